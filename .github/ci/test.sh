@@ -11,6 +11,8 @@ cargo test --manifest-path ./embassy-sync/Cargo.toml
 cargo test --manifest-path ./embassy-embedded-hal/Cargo.toml 
 cargo test --manifest-path ./embassy-hal-internal/Cargo.toml 
 cargo test --manifest-path ./embassy-time/Cargo.toml --features generic-queue
+cargo test --manifest-path ./embassy-net-driver-channel/Cargo.toml
+cargo test --manifest-path ./embassy-net/Cargo.toml --features std,tcp,udp,dns,dns-cache,icmp,raw,dhcpv4,dhcpv4-server,slaac,dhcpv6,proto-ipv4,proto-ipv6,medium-ethernet,medium-ip,igmp,mdns,sntp,vlan,tls
 
 cargo test --manifest-path ./embassy-boot/boot/Cargo.toml
 cargo test --manifest-path ./embassy-boot/boot/Cargo.toml --features ed25519-dalek
@@ -9,33 +9,38 @@ use core::mem::MaybeUninit;
 use core::task::{Context, Poll};
 
 pub use embassy_net_driver as driver;
-use embassy_net_driver::{Capabilities, LinkState};
+use embassy_net_driver::{Capabilities, LinkState, Stats};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 use embassy_sync::waitqueue::WakerRegistration;
 use embassy_sync::zerocopy_channel;
 
-pub struct State<const MTU: usize, const N_RX: usize, const N_TX: usize> {
+/// The buffers of a channel.
+///
+/// Each packet to transmit has a `HEADER` bytes buffer for a device-specific header, separate from
+/// the packet itself, see [`PacketBuf::frame`].
+pub struct State<const MTU: usize, const N_RX: usize, const N_TX: usize, const HEADER: usize = 0> {
     rx: [PacketBuf<MTU>; N_RX],
-    tx: [PacketBuf<MTU>; N_TX],
-    inner: MaybeUninit<StateInner<'static, MTU>>,
+    tx: [PacketBuf<MTU, HEADER>; N_TX],
+    inner: MaybeUninit<StateInner<'static, MTU, HEADER>>,
 }
 
-impl<const MTU: usize, const N_RX: usize, const N_TX: usize> State<MTU, N_RX, N_TX> {
-    const NEW_PACKET: PacketBuf<MTU> = PacketBuf::new();
+impl<const MTU: usize, const N_RX: usize, const N_TX: usize, const HEADER: usize> State<MTU, N_RX, N_TX, HEADER> {
+    const NEW_RX_PACKET: PacketBuf<MTU> = PacketBuf::new();
+    const NEW_TX_PACKET: PacketBuf<MTU, HEADER> = PacketBuf::new();
 
     pub const fn new() -> Self {
         Self {
-            rx: [Self::NEW_PACKET; N_RX],
-            tx: [Self::NEW_PACKET; N_TX],
+            rx: [Self::NEW_RX_PACKET; N_RX],
+            tx: [Self::NEW_TX_PACKET; N_TX],
             inner: MaybeUninit::uninit(),
         }
     }
 }
 
-struct StateInner<'d, const MTU: usize> {
+struct StateInner<'d, const MTU: usize, const HEADER: usize> {
     rx: zerocopy_channel::Channel<'d, NoopRawMutex, PacketBuf<MTU>>,
-    tx: zerocopy_channel::Channel<'d, NoopRawMutex, PacketBuf<MTU>>,
+    tx: zerocopy_channel::Channel<'d, NoopRawMutex, PacketBuf<MTU, HEADER>>,
     shared: Mutex<NoopRawMutex, RefCell<Shared>>,
 }
 
@@ -44,10 +49,11 @@ struct Shared {
     link_state: LinkState,
     waker: WakerRegistration,
     hardware_address: driver::HardwareAddress,
+    stats: Stats,
 }
 
-pub struct Runner<'d, const MTU: usize> {
-    tx_chan: zerocopy_channel::Receiver<'d, NoopRawMutex, PacketBuf<MTU>>,
+pub struct Runner<'d, const MTU: usize, const HEADER: usize = 0> {
+    tx_chan: zerocopy_channel::Receiver<'d, NoopRawMutex, PacketBuf<MTU, HEADER>>,
     rx_chan: zerocopy_channel::Sender<'d, NoopRawMutex, PacketBuf<MTU>>,
     shared: &'d Mutex<NoopRawMutex, RefCell<Shared>>,
 }
@@ -59,26 +65,31 @@ pub struct StateRunner<'d> {
 
 pub struct RxRunner<'d, const MTU: usize> {
     rx_chan: zerocopy_channel::Sender<'d, NoopRawMutex, PacketBuf<MTU>>,
+    shared: &'d Mutex<NoopRawMutex, RefCell<Shared>>,
 }
 
-pub struct TxRunner<'d, const MTU: usize> {
-    tx_chan: zerocopy_channel::Receiver<'d, NoopRawMutex, PacketBuf<MTU>>,
+pub struct TxRunner<'d, const MTU: usize, const HEADER: usize = 0> {
+    tx_chan: zerocopy_channel::Receiver<'d, NoopRawMutex, PacketBuf<MTU, HEADER>>,
 }
 
-impl<'d, const MTU: usize> Runner<'d, MTU> {
-    pub fn split(self) -> (StateRunner<'d>, RxRunner<'d, MTU>, TxRunner<'d, MTU>) {
+impl<'d, const MTU: usize, const HEADER: usize> Runner<'d, MTU, HEADER> {
+    pub fn split(self) -> (StateRunner<'d>, RxRunner<'d, MTU>, TxRunner<'d, MTU, HEADER>) {
         (
             StateRunner { shared: self.shared },
-            RxRunner { rx_chan: self.rx_chan },
+            RxRunner {
+                rx_chan: self.rx_chan,
+                shared: self.shared,
+            },
             TxRunner { tx_chan: self.tx_chan },
         )
     }
 
-    pub fn borrow_split(&mut self) -> (StateRunner<'_>, RxRunner<'_, MTU>, TxRunner<'_, MTU>) {
+    pub fn borrow_split(&mut self) -> (StateRunner<'_>, RxRunner<'_, MTU>, TxRunner<'_, MTU, HEADER>) {
         (
             StateRunner { shared: self.shared },
             RxRunner {
                 rx_chan: self.rx_chan.borrow(),
+                shared: self.shared,
             },
             TxRunner {
                 tx_chan: self.tx_chan.borrow(),
@@ -106,13 +117,27 @@ impl<'d, const MTU: usize> Runner<'d, MTU> {
         });
     }
 
+    /// Update the driver [`Stats`], for example to add MAC-level error counters.
+    pub fn update_stats(&mut self, f: impl FnOnce(&mut Stats)) {
+        self.shared.lock(|s| f(&mut s.borrow_mut().stats));
+    }
+
     pub async fn rx_buf(&mut self) -> &mut [u8] {
         let p = self.rx_chan.send().await;
         &mut p.buf
     }
 
+    /// Get a buffer to receive a packet into, if one is free.
+    ///
+    /// If none is, the packet is counted as dropped in the driver [`Stats`].
     pub fn try_rx_buf(&mut self) -> Option<&mut [u8]> {
-        let p = self.rx_chan.try_send()?;
+        let Some(p) = self.rx_chan.try_send() else {
+            self.shared.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                s.stats.rx_dropped = s.stats.rx_dropped.wrapping_add(1);
+            });
+            return None;
+        };
         Some(&mut p.buf)
     }
 
@@ -129,19 +154,45 @@ impl<'d, const MTU: usize> Runner<'d, MTU> {
         self.rx_chan.send_done();
     }
 
+    /// Get free buffers to receive several packets into, waiting until there is at least one.
+    ///
+    /// Set the length of the packets received with [`PacketBuf::set_len`], then pass the first
+    /// `n` buffers to the stack with `rx_done_many(n)`. The buffers returned are contiguous in the
+    /// queue, so there might be fewer than the free buffers.
+    pub async fn rx_bufs(&mut self) -> &mut [PacketBuf<MTU>] {
+        self.rx_chan.send_many().await
+    }
+
+    /// Get free buffers to receive several packets into, if there are any.
+    ///
+    /// See [`rx_bufs`](Self::rx_bufs). Unlike [`try_rx_buf`](Self::try_rx_buf), nothing is counted as
+    /// dropped when there are none.
+    pub fn try_rx_bufs(&mut self) -> &mut [PacketBuf<MTU>] {
+        self.rx_chan.try_send_many()
+    }
+
+    pub fn poll_rx_bufs(&mut self, cx: &mut Context) -> Poll<&mut [PacketBuf<MTU>]> {
+        self.rx_chan.poll_send_many(cx)
+    }
+
+    /// Pass the first `n` buffers returned by [`rx_bufs`](Self::rx_bufs) to the stack.
+    pub fn rx_done_many(&mut self, n: usize) {
+        self.rx_chan.send_done_many(n);
+    }
+
     pub async fn tx_buf(&mut self) -> &mut [u8] {
         let p = self.tx_chan.receive().await;
-        &mut p.buf[..p.len]
+        p.packet_mut()
     }
 
     pub fn try_tx_buf(&mut self) -> Option<&mut [u8]> {
         let p = self.tx_chan.try_receive()?;
-        Some(&mut p.buf[..p.len])
+        Some(p.packet_mut())
     }
 
     pub fn poll_tx_buf(&mut self, cx: &mut Context) -> Poll<&mut [u8]> {
         match self.tx_chan.poll_receive(cx) {
-            Poll::Ready(p) => Poll::Ready(&mut p.buf[..p.len]),
+            Poll::Ready(p) => Poll::Ready(p.packet_mut()),
             Poll::Pending => Poll::Pending,
         }
     }
@@ -149,6 +200,31 @@ impl<'d, const MTU: usize> Runner<'d, MTU> {
     pub fn tx_done(&mut self) {
         self.tx_chan.receive_done();
     }
+
+    /// Get several packets to transmit, waiting until there is at least one.
+    ///
+    /// Each packet can be transmitted along with its header with [`PacketBuf::frame`]. After
+    /// transmitting the first `n` packets, release them with `tx_done_many(n)`. The packets
+    /// returned are contiguous in the queue, so there might be fewer than the packets waiting.
+    pub async fn tx_bufs(&mut self) -> &mut [PacketBuf<MTU, HEADER>] {
+        self.tx_chan.receive_many().await
+    }
+
+    /// Get several packets to transmit, if there are any.
+    ///
+    /// See [`tx_bufs`](Self::tx_bufs).
+    pub fn try_tx_bufs(&mut self) -> &mut [PacketBuf<MTU, HEADER>] {
+        self.tx_chan.try_receive_many()
+    }
+
+    pub fn poll_tx_bufs(&mut self, cx: &mut Context) -> Poll<&mut [PacketBuf<MTU, HEADER>]> {
+        self.tx_chan.poll_receive_many(cx)
+    }
+
+    /// Release the first `n` packets returned by [`tx_bufs`](Self::tx_bufs).
+    pub fn tx_done_many(&mut self, n: usize) {
+        self.tx_chan.receive_done_many(n);
+    }
 }
 
 impl<'d> StateRunner<'d> {
@@ -167,6 +243,11 @@ impl<'d> StateRunner<'d> {
             s.waker.wake();
         });
     }
+
+    /// Update the driver [`Stats`], for example to add MAC-level error counters.
+    pub fn update_stats(&self, f: impl FnOnce(&mut Stats)) {
+        self.shared.lock(|s| f(&mut s.borrow_mut().stats));
+    }
 }
 
 impl<'d, const MTU: usize> RxRunner<'d, MTU> {
@@ -175,8 +256,17 @@ impl<'d, const MTU: usize> RxRunner<'d, MTU> {
         &mut p.buf
     }
 
+    /// Get a buffer to receive a packet into, if one is free.
+    ///
+    /// If none is, the packet is counted as dropped in the driver [`Stats`].
     pub fn try_rx_buf(&mut self) -> Option<&mut [u8]> {
-        let p = self.rx_chan.try_send()?;
+        let Some(p) = self.rx_chan.try_send() else {
+            self.shared.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                s.stats.rx_dropped = s.stats.rx_dropped.wrapping_add(1);
+            });
+            return None;
+        };
         Some(&mut p.buf)
     }
 
@@ -192,22 +282,48 @@ impl<'d, const MTU: usize> RxRunner<'d, MTU> {
         p.len = len;
         self.rx_chan.send_done();
     }
+
+    /// Get free buffers to receive several packets into, waiting until there is at least one.
+    ///
+    /// Set the length of the packets received with [`PacketBuf::set_len`], then pass the first
+    /// `n` buffers to the stack with `rx_done_many(n)`. The buffers returned are contiguous in the
+    /// queue, so there might be fewer than the free buffers.
+    pub async fn rx_bufs(&mut self) -> &mut [PacketBuf<MTU>] {
+        self.rx_chan.send_many().await
+    }
+
+    /// Get free buffers to receive several packets into, if there are any.
+    ///
+    /// See [`rx_bufs`](Self::rx_bufs). Unlike [`try_rx_buf`](Self::try_rx_buf), nothing is counted as
+    /// dropped when there are none.
+    pub fn try_rx_bufs(&mut self) -> &mut [PacketBuf<MTU>] {
+        self.rx_chan.try_send_many()
+    }
+
+    pub fn poll_rx_bufs(&mut self, cx: &mut Context) -> Poll<&mut [PacketBuf<MTU>]> {
+        self.rx_chan.poll_send_many(cx)
+    }
+
+    /// Pass the first `n` buffers returned by [`rx_bufs`](Self::rx_bufs) to the stack.
+    pub fn rx_done_many(&mut self, n: usize) {
+        self.rx_chan.send_done_many(n);
+    }
 }
 
-impl<'d, const MTU: usize> TxRunner<'d, MTU> {
+impl<'d, const MTU: usize, const HEADER: usize> TxRunner<'d, MTU, HEADER> {
     pub async fn tx_buf(&mut self) -> &mut [u8] {
         let p = self.tx_chan.receive().await;
-        &mut p.buf[..p.len]
+        p.packet_mut()
     }
 
     pub fn try_tx_buf(&mut self) -> Option<&mut [u8]> {
         let p = self.tx_chan.try_receive()?;
-        Some(&mut p.buf[..p.len])
+        Some(p.packet_mut())
     }
 
     pub fn poll_tx_buf(&mut self, cx: &mut Context) -> Poll<&mut [u8]> {
         match self.tx_chan.poll_receive(cx) {
-            Poll::Ready(p) => Poll::Ready(&mut p.buf[..p.len]),
+            Poll::Ready(p) => Poll::Ready(p.packet_mut()),
             Poll::Pending => Poll::Pending,
         }
     }
@@ -215,20 +331,45 @@ impl<'d, const MTU: usize> TxRunner<'d, MTU> {
     pub fn tx_done(&mut self) {
         self.tx_chan.receive_done();
     }
+
+    /// Get several packets to transmit, waiting until there is at least one.
+    ///
+    /// Each packet can be transmitted along with its header with [`PacketBuf::frame`]. After
+    /// transmitting the first `n` packets, release them with `tx_done_many(n)`. The packets
+    /// returned are contiguous in the queue, so there might be fewer than the packets waiting.
+    pub async fn tx_bufs(&mut self) -> &mut [PacketBuf<MTU, HEADER>] {
+        self.tx_chan.receive_many().await
+    }
+
+    /// Get several packets to transmit, if there are any.
+    ///
+    /// See [`tx_bufs`](Self::tx_bufs).
+    pub fn try_tx_bufs(&mut self) -> &mut [PacketBuf<MTU, HEADER>] {
+        self.tx_chan.try_receive_many()
+    }
+
+    pub fn poll_tx_bufs(&mut self, cx: &mut Context) -> Poll<&mut [PacketBuf<MTU, HEADER>]> {
+        self.tx_chan.poll_receive_many(cx)
+    }
+
+    /// Release the first `n` packets returned by [`tx_bufs`](Self::tx_bufs).
+    pub fn tx_done_many(&mut self, n: usize) {
+        self.tx_chan.receive_done_many(n);
+    }
 }
 
-pub fn new<'d, const MTU: usize, const N_RX: usize, const N_TX: usize>(
-    state: &'d mut State<MTU, N_RX, N_TX>,
+pub fn new<'d, const MTU: usize, const N_RX: usize, const N_TX: usize, const HEADER: usize>(
+    state: &'d mut State<MTU, N_RX, N_TX, HEADER>,
     hardware_address: driver::HardwareAddress,
-) -> (Runner<'d, MTU>, Device<'d, MTU>) {
+) -> (Runner<'d, MTU, HEADER>, Device<'d, MTU, HEADER>) {
     let mut caps = Capabilities::default();
     caps.max_transmission_unit = MTU;
 
     // safety: this is a self-referential struct, however:
     // - it can't move while the `'d` borrow is active.
     // - when the borrow ends, the dangling references inside the MaybeUninit will never be used again.
-    let state_uninit: *mut MaybeUninit<StateInner<'d, MTU>> =
-        (&mut state.inner as *mut MaybeUninit<StateInner<'static, MTU>>).cast();
+    let state_uninit: *mut MaybeUninit<StateInner<'d, MTU, HEADER>> =
+        (&mut state.inner as *mut MaybeUninit<StateInner<'static, MTU, HEADER>>).cast();
     let state = unsafe { &mut *state_uninit }.write(StateInner {
         rx: zerocopy_channel::Channel::new(&mut state.rx[..]),
         tx: zerocopy_channel::Channel::new(&mut state.tx[..]),
@@ -236,6 +377,7 @@ pub fn new<'d, const MTU: usize, const N_RX: usize, const N_TX: usize>(
             link_state: LinkState::Down,
             hardware_address,
             waker: WakerRegistration::new(),
+            stats: Stats::default(),
         })),
     });
 
@@ -257,27 +399,79 @@ pub fn new<'d, const MTU: usize, const N_RX: usize, const N_TX: usize>(
     )
 }
 
-pub struct PacketBuf<const MTU: usize> {
+/// A packet buffer.
+///
+/// Packets to transmit also have a `HEADER` bytes buffer, for a device-specific header sent in front
+/// of the packet.
+pub struct PacketBuf<const MTU: usize, const HEADER: usize = 0> {
+    header: [u8; HEADER],
     len: usize,
     buf: [u8; MTU],
 }
 
-impl<const MTU: usize> PacketBuf<MTU> {
+impl<const MTU: usize, const HEADER: usize> PacketBuf<MTU, HEADER> {
     pub const fn new() -> Self {
-        Self { len: 0, buf: [0; MTU] }
+        Self {
+            header: [0; HEADER],
+            len: 0,
+            buf: [0; MTU],
+        }
+    }
+
+    /// Get the length of the packet.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the packet is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Set the length of a packet received into [`buf_mut`](Self::buf_mut).
+    pub fn set_len(&mut self, len: usize) {
+        assert!(len <= MTU);
+        self.len = len;
+    }
+
+    /// Get the whole buffer, to receive a packet into.
+    pub fn buf_mut(&mut self) -> &mut [u8; MTU] {
+        &mut self.buf
+    }
+
+    /// Get the packet.
+    pub fn packet(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Get the packet, mutably.
+    pub fn packet_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[..self.len]
+    }
+
+    /// Get the header of a packet to transmit, to write it.
+    pub fn header_mut(&mut self) -> &mut [u8; HEADER] {
+        &mut self.header
+    }
+
+    /// Get the frame to transmit, split across the header buffer and the packet buffer.
+    ///
+    /// The two buffers can be sent in one scatter/gather transfer, without copying the packet.
+    pub fn frame(&self) -> (&[u8; HEADER], &[u8]) {
+        (&self.header, self.packet())
     }
 }
 
-pub struct Device<'d, const MTU: usize> {
+pub struct Device<'d, const MTU: usize, const HEADER: usize = 0> {
     rx: zerocopy_channel::Receiver<'d, NoopRawMutex, PacketBuf<MTU>>,
-    tx: zerocopy_channel::Sender<'d, NoopRawMutex, PacketBuf<MTU>>,
+    tx: zerocopy_channel::Sender<'d, NoopRawMutex, PacketBuf<MTU, HEADER>>,
     shared: &'d Mutex<NoopRawMutex, RefCell<Shared>>,
     caps: Capabilities,
 }
 
-impl<'d, const MTU: usize> embassy_net_driver::Driver for Device<'d, MTU> {
+impl<'d, const MTU: usize, const HEADER: usize> embassy_net_driver::Driver for Device<'d, MTU, HEADER> {
     type RxToken<'a> = RxToken<'a, MTU> where Self: 'a ;
-    type TxToken<'a> = TxToken<'a, MTU> where Self: 'a ;
+    type TxToken<'a> = TxToken<'a, MTU, HEADER> where Self: 'a ;
 
     fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
         if self.rx.poll_receive(cx).is_ready() && self.tx.poll_send(cx).is_ready() {
@@ -305,6 +499,10 @@ impl<'d, const MTU: usize> embassy_net_driver::Driver for Device<'d, MTU> {
         self.shared.lock(|s| s.borrow().hardware_address)
     }
 
+    fn stats(&self) -> Stats {
+        self.shared.lock(|s| s.borrow().stats.clone())
+    }
+
     fn link_state(&mut self, cx: &mut Context) -> LinkState {
         self.shared.lock(|s| {
             let s = &mut *s.borrow_mut();
@@ -331,20 +529,190 @@ impl<'a, const MTU: usize> embassy_net_driver::RxToken for RxToken<'a, MTU> {
     }
 }
 
-pub struct TxToken<'a, const MTU: usize> {
-    tx: zerocopy_channel::Sender<'a, NoopRawMutex, PacketBuf<MTU>>,
+pub struct TxToken<'a, const MTU: usize, const HEADER: usize = 0> {
+    tx: zerocopy_channel::Sender<'a, NoopRawMutex, PacketBuf<MTU, HEADER>>,
 }
 
-impl<'a, const MTU: usize> embassy_net_driver::TxToken for TxToken<'a, MTU> {
+impl<'a, const MTU: usize, const HEADER: usize> embassy_net_driver::TxToken for TxToken<'a, MTU, HEADER> {
     fn consume<R, F>(mut self, len: usize, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R,
     {
         // NOTE(unwrap): we checked the queue wasn't full when creating the token.
         let pkt = unwrap!(self.tx.try_send());
-        let r = f(&mut pkt.buf[..len]);
         pkt.len = len;
+        let r = f(pkt.packet_mut());
         self.tx.send_done();
         r
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+
+    use embassy_net_driver::{Driver, RxToken as _, TxToken as _};
+
+    use super::*;
+
+    fn noop_waker() -> Waker {
+        const VTABLE: RawWakerVTable =
+            RawWakerVTable::new(|_| RawWaker::new(core::ptr::null(), &VTABLE), |_| {}, |_| {}, |_| {});
+        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+    }
+
+    const ADDR: driver::HardwareAddress = driver::HardwareAddress::Ethernet([2, 0, 0, 0, 0, 1]);
+
+    /// Receive a packet from the device, and check it's `expected`.
+    fn receive<const MTU: usize, const HEADER: usize>(device: &mut Device<'_, MTU, HEADER>, expected: &[u8]) {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let (rx, _) = device.receive(&mut cx).expect("no packet received");
+        rx.consume(|buf| assert_eq!(buf, expected));
+    }
+
+    /// Send a packet of `len` bytes filled with `byte` to the device.
+    fn transmit<const MTU: usize, const HEADER: usize>(device: &mut Device<'_, MTU, HEADER>, len: usize, byte: u8) {
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let tx = device.transmit(&mut cx).expect("transmit queue full");
+        tx.consume(len, |buf| buf.fill(byte));
+    }
+
+    #[test]
+    fn rx_bufs_wrap_around() {
+        let mut state = State::<64, 3, 1>::new();
+        let (mut runner, mut device) = new(&mut state, ADDR);
+
+        let bufs = runner.try_rx_bufs();
+        assert_eq!(bufs.len(), 3);
+        for (i, buf) in bufs[..2].iter_mut().enumerate() {
+            buf.buf_mut()[..4].fill(i as u8);
+            buf.set_len(4);
+        }
+        runner.rx_done_many(2);
+        receive(&mut device, &[0; 4]);
+        receive(&mut device, &[1; 4]);
+
+        // The last buffer of the queue, then the first two ones.
+        let bufs = runner.try_rx_bufs();
+        assert_eq!(bufs.len(), 1);
+        bufs[0].buf_mut()[..2].fill(2);
+        bufs[0].set_len(2);
+        runner.rx_done_many(1);
+
+        let bufs = runner.try_rx_bufs();
+        assert_eq!(bufs.len(), 2);
+        bufs[0].buf_mut().fill(3);
+        bufs[0].set_len(64);
+        bufs[1].set_len(0);
+        runner.rx_done_many(2);
+        assert!(runner.try_rx_bufs().is_empty());
+
+        receive(&mut device, &[2; 2]);
+        receive(&mut device, &[3; 64]);
+        receive(&mut device, &[]);
+        // All free again, but the next buffer is the last one of the queue.
+        assert_eq!(runner.try_rx_bufs().len(), 1);
+    }
+
+    #[test]
+    fn rx_done_single_and_many() {
+        let mut state = State::<64, 2, 1>::new();
+        let (mut runner, mut device) = new(&mut state, ADDR);
+
+        unwrap!(runner.try_rx_buf())[..3].copy_from_slice(b"one");
+        runner.rx_done(3);
+        let bufs = runner.try_rx_bufs();
+        assert_eq!(bufs.len(), 1);
+        bufs[0].buf_mut()[..3].copy_from_slice(b"two");
+        bufs[0].set_len(3);
+        runner.rx_done_many(1);
+
+        // Full, the packet is counted as dropped.
+        assert!(runner.try_rx_buf().is_none());
+        assert!(runner.try_rx_bufs().is_empty());
+        assert_eq!(device.stats().rx_dropped, 1);
+
+        receive(&mut device, b"one");
+        receive(&mut device, b"two");
+    }
+
+    #[test]
+    fn tx_bufs_wrap_around() {
+        let mut state = State::<64, 1, 3>::new();
+        let (mut runner, mut device) = new(&mut state, ADDR);
+
+        transmit(&mut device, 10, 0);
+        transmit(&mut device, 20, 1);
+        transmit(&mut device, 30, 2);
+        let waker = noop_waker();
+        assert!(device.transmit(&mut Context::from_waker(&waker)).is_none());
+
+        let bufs = runner.try_tx_bufs();
+        assert_eq!(bufs.len(), 3);
+        assert_eq!(bufs[0].packet(), &[0; 10]);
+        assert_eq!(bufs[1].packet(), &[1; 20]);
+        runner.tx_done_many(2);
+
+        transmit(&mut device, 40, 3);
+        transmit(&mut device, 50, 4);
+
+        // The last packet of the queue, then the first two ones.
+        let bufs = runner.try_tx_bufs();
+        assert_eq!(bufs.len(), 1);
+        assert_eq!(bufs[0].packet(), &[2; 30]);
+        runner.tx_done_many(1);
+
+        let bufs = runner.try_tx_bufs();
+        assert_eq!(bufs.len(), 2);
+        assert_eq!(bufs[0].packet(), &[3; 40]);
+        assert_eq!(bufs[1].packet(), &[4; 50]);
+        runner.tx_done_many(2);
+        assert!(runner.try_tx_bufs().is_empty());
+    }
+
+    #[test]
+    fn tx_header() {
+        let mut state = State::<64, 1, 2, 4>::new();
+        let (mut runner, mut device) = new(&mut state, ADDR);
+
+        // The header has its own buffer, the whole MTU is left for the packet.
+        assert_eq!(device.capabilities().max_transmission_unit, 64);
+        transmit(&mut device, 64, 0xaa);
+        transmit(&mut device, 1, 0xbb);
+
+        let bufs = runner.try_tx_bufs();
+        assert_eq!(bufs.len(), 2);
+        for (i, buf) in bufs.iter_mut().enumerate() {
+            buf.header_mut().copy_from_slice(&[i as u8; 4]);
+        }
+        assert_eq!(bufs[0].frame(), (&[0; 4], &[0xaa; 64][..]));
+        assert_eq!(bufs[1].frame(), (&[1; 4], &[0xbb][..]));
+        runner.tx_done_many(2);
+
+        // The single packet API only sees the packet.
+        transmit(&mut device, 3, 0xcc);
+        assert_eq!(unwrap!(runner.try_tx_buf()), &[0xcc; 3]);
+        runner.tx_done();
+    }
+
+    #[test]
+    fn set_len() {
+        let mut buf = PacketBuf::<8>::new();
+        assert!(buf.is_empty());
+        buf.buf_mut().copy_from_slice(b"abcdefgh");
+        buf.set_len(3);
+        assert_eq!(buf.len(), 3);
+        assert_eq!(buf.packet(), b"abc");
+        buf.packet_mut()[0] = b'x';
+        buf.set_len(8);
+        assert_eq!(buf.packet(), b"xbcdefgh");
+    }
+
+    #[test]
+    #[should_panic]
+    fn set_len_above_mtu() {
+        PacketBuf::<8>::new().set_len(9);
+    }
+}
@@ -80,6 +80,24 @@ pub trait Driver {
     /// what kind of packet the sent/received bytes are, and determines some behaviors of
     /// the interface. For example, ARP/NDISC address resolution is only done for Ethernet mediums.
     fn hardware_address(&self) -> HardwareAddress;
+
+    /// Get the driver's statistics counters.
+    ///
+    /// Drivers that keep counters, for example for errors reported by the MAC, can return them here.
+    /// The default implementation returns all counters as zero.
+    fn stats(&self) -> Stats {
+        Stats::default()
+    }
+
+    /// Set the multicast Ethernet addresses to receive frames for.
+    ///
+    /// The stack calls this with the addresses of all the multicast groups it joined, every time
+    /// it joins or leaves one. Drivers with [`MacFilterCapabilities`] must let frames to these
+    /// addresses through, using perfect filters, the hash table, or by receiving all multicast
+    /// frames if there are too many. The default implementation does nothing.
+    fn set_multicast_filter(&mut self, addresses: &[[u8; 6]]) {
+        let _ = addresses;
+    }
 }
 
 impl<T: ?Sized + Driver> Driver for &mut T {
@@ -105,6 +123,12 @@ impl<T: ?Sized + Driver> Driver for &mut T {
     fn hardware_address(&self) -> HardwareAddress {
         T::hardware_address(self)
     }
+    fn stats(&self) -> Stats {
+        T::stats(self)
+    }
+    fn set_multicast_filter(&mut self, addresses: &[[u8; 6]]) {
+        T::set_multicast_filter(self, addresses)
+    }
 }
 
 /// A token to receive a single network packet.
@@ -116,6 +140,13 @@ pub trait RxToken {
     fn consume<R, F>(self, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R;
+
+    /// Get the metadata of the packet, such as a VLAN tag stripped by the hardware.
+    ///
+    /// The default implementation returns empty metadata.
+    fn meta(&self) -> RxMeta {
+        RxMeta::default()
+    }
 }
 
 /// A token to transmit a single network packet.
@@ -129,6 +160,37 @@ pub trait TxToken {
     fn consume<R, F>(self, len: usize, f: F) -> R
     where
         F: FnOnce(&mut [u8]) -> R;
+
+    /// Set the metadata of the packet, such as a VLAN tag for the hardware to insert.
+    ///
+    /// This must be called before [`consume`](TxToken::consume). It is only called with metadata
+    /// the driver advertised support for in its [`Capabilities`], so the default implementation
+    /// ignores it.
+    fn set_meta(&mut self, meta: TxMeta) {
+        let _ = meta;
+    }
+}
+
+/// Metadata of a received packet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct RxMeta {
+    /// Tag control information of the VLAN tag stripped from the frame by the hardware, if any.
+    ///
+    /// Only set by drivers with [`VlanCapabilities::stripping`].
+    pub vlan_tci: Option<u16>,
+}
+
+/// Metadata of a packet to transmit.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct TxMeta {
+    /// Tag control information of the VLAN tag for the hardware to insert in the frame, if any.
+    ///
+    /// Only set for drivers with [`VlanCapabilities::insertion`].
+    pub vlan_tci: Option<u16>,
 }
 
 /// A description of device capabilities.
@@ -167,6 +229,45 @@ pub struct Capabilities {
     /// If the network device is capable of verifying or computing checksums for some protocols,
     /// it can request that the stack not do so in software to improve performance.
     pub checksum: ChecksumCapabilities,
+
+    /// VLAN tag offload.
+    pub vlan: VlanCapabilities,
+
+    /// Hardware filtering of received frames by destination MAC address.
+    pub mac_filter: MacFilterCapabilities,
+
+    /// Maximum size of the TCP segments the device can split into MTU-sized packets, if it supports
+    /// TCP segmentation offload.
+    ///
+    /// This is the size of the IP packet given to the device, including the IP and TCP headers.
+    pub tcp_segmentation: Option<usize>,
+}
+
+/// A description of VLAN tag offload.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct VlanCapabilities {
+    /// The device can insert the VLAN tag given in [`TxMeta::vlan_tci`] in transmitted frames.
+    pub insertion: bool,
+    /// The device strips VLAN tags from received frames, and reports them in [`RxMeta::vlan_tci`].
+    pub stripping: bool,
+}
+
+/// A description of hardware filtering of received frames by destination MAC address.
+///
+/// The multicast filters are set with [`Driver::set_multicast_filter`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct MacFilterCapabilities {
+    /// The device drops unicast frames that aren't for its own address.
+    pub unicast: bool,
+    /// Number of multicast addresses the device can match exactly.
+    pub multicast_filters: usize,
+    /// The device has a multicast hash table, which lets through the frames to all the addresses
+    /// with the same hash as a wanted address.
+    pub multicast_hash: bool,
 }
 
 /// A description of checksum behavior for every supported protocol.
@@ -206,6 +307,23 @@ impl Default for Checksum {
     }
 }
 
+/// Statistics counters of a network device.
+///
+/// The counters wrap around on overflow.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Stats {
+    /// Received packets dropped by the driver, for example because its receive buffers were full.
+    pub rx_dropped: u32,
+    /// Packets dropped by the driver before transmitting them.
+    pub tx_dropped: u32,
+    /// Frames received with errors, such as a bad CRC or an invalid length.
+    pub rx_errors: u32,
+    /// Frames that failed to be transmitted, such as because of collisions or underruns.
+    pub tx_errors: u32,
+}
+
 /// The link state of a network device.
 #[derive(PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
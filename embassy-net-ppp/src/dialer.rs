@@ -0,0 +1,442 @@
+//! AT command dialer, to get a modem into data mode before running PPP.
+//!
+//! [`Chat`] runs a script of send/expect steps, like `chat(8)`. [`Dialer`] uses it to set up the
+//! APN and dial, and to hang up. [`Runner::run_with_dialer`](crate::Runner::run_with_dialer) puts
+//! everything together, dialing again whenever the PPP link goes down.
+
+use embassy_time::{with_timeout, Duration, Timer};
+use embedded_io_async::{BufRead, Write};
+
+/// Maximum length of the strings to expect.
+const MAX_EXPECT_LEN: usize = 64;
+
+/// Responses aborting a script by default.
+pub const DEFAULT_ABORT: &[&[u8]] = &[b"ERROR", b"NO CARRIER", b"BUSY", b"NO DIALTONE", b"NO ANSWER"];
+
+/// A step of a [`Chat`] script.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Step<'a> {
+    /// Send bytes to the modem.
+    Send(&'a [u8]),
+    /// Wait until the modem sends these bytes.
+    ///
+    /// Everything received up to and including them is discarded.
+    Expect(&'a [u8]),
+    /// Set the timeout of the following [`Expect`](Step::Expect) steps.
+    Timeout(Duration),
+    /// Wait for some time.
+    Delay(Duration),
+}
+
+/// Error returned by a [`Chat`] script.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ChatError<E> {
+    /// Reading from the serial port failed.
+    Read(E),
+    /// Writing to the serial port failed.
+    Write(E),
+    /// Reading from the serial port got EOF.
+    Eof,
+    /// The expected response wasn't received in time.
+    Timeout,
+    /// The modem sent an abort response, the index of which in the abort list is given.
+    Aborted(usize),
+}
+
+/// Runner for scripts of AT commands.
+#[derive(Debug, Clone)]
+pub struct Chat<'a> {
+    abort: &'a [&'a [u8]],
+    timeout: Duration,
+}
+
+impl Default for Chat<'static> {
+    fn default() -> Self {
+        Self::new(DEFAULT_ABORT)
+    }
+}
+
+impl<'a> Chat<'a> {
+    /// Create a new chat runner, aborting scripts when the modem sends any of `abort`.
+    ///
+    /// The initial timeout of [`Expect`](Step::Expect) steps is 5 seconds.
+    pub fn new(abort: &'a [&'a [u8]]) -> Self {
+        for a in abort {
+            assert!(a.len() <= MAX_EXPECT_LEN, "abort string too long");
+        }
+        Self {
+            abort,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Run a script.
+    ///
+    /// The timeout set by [`Step::Timeout`] lasts until the end of the script.
+    pub async fn run<RW: BufRead + Write>(&self, rw: &mut RW, script: &[Step<'_>]) -> Result<(), ChatError<RW::Error>> {
+        let mut timeout = self.timeout;
+        for step in script {
+            match *step {
+                Step::Send(data) => self.send(rw, &[data]).await?,
+                Step::Expect(expected) => self.expect(rw, expected, timeout).await?,
+                Step::Timeout(t) => timeout = t,
+                Step::Delay(d) => Timer::after(d).await,
+            }
+        }
+        Ok(())
+    }
+
+    async fn send<RW: BufRead + Write>(&self, rw: &mut RW, parts: &[&[u8]]) -> Result<(), ChatError<RW::Error>> {
+        for part in parts {
+            rw.write_all(part).await.map_err(ChatError::Write)?;
+        }
+        rw.flush().await.map_err(ChatError::Write)
+    }
+
+    async fn expect<RW: BufRead + Write>(
+        &self,
+        rw: &mut RW,
+        expected: &[u8],
+        timeout: Duration,
+    ) -> Result<(), ChatError<RW::Error>> {
+        assert!(expected.len() <= MAX_EXPECT_LEN, "expected string too long");
+
+        let mut window = Window::new();
+        let fut = async {
+            loop {
+                let data = match rw.fill_buf().await {
+                    Ok([]) => return Err(ChatError::Eof),
+                    Ok(data) => data,
+                    Err(e) => return Err(ChatError::Read(e)),
+                };
+
+                // Stop right after the match, what follows might be the first PPP frame.
+                let mut n = 0;
+                let mut res = None;
+                for &b in data {
+                    n += 1;
+                    window.push(b);
+                    if window.ends_with(expected) {
+                        res = Some(Ok(()));
+                    } else if let Some(i) = self.abort.iter().position(|a| window.ends_with(a)) {
+                        res = Some(Err(ChatError::Aborted(i)));
+                    }
+                    if res.is_some() {
+                        break;
+                    }
+                }
+                rw.consume(n);
+
+                if let Some(res) = res {
+                    return res;
+                }
+            }
+        };
+        with_timeout(timeout, fut).await.map_err(|_| ChatError::Timeout)?
+    }
+}
+
+/// The last bytes received.
+struct Window {
+    buf: [u8; MAX_EXPECT_LEN],
+    len: usize,
+}
+
+impl Window {
+    fn new() -> Self {
+        Self {
+            buf: [0; MAX_EXPECT_LEN],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.len == MAX_EXPECT_LEN {
+            self.buf.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.buf[self.len] = b;
+        self.len += 1;
+    }
+
+    fn ends_with(&self, s: &[u8]) -> bool {
+        !s.is_empty() && self.buf[..self.len].ends_with(s)
+    }
+}
+
+/// Dialer configuration.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct DialConfig<'a> {
+    /// Commands to send after resetting the modem and before dialing, each must be answered with `OK`.
+    ///
+    /// They must include the final `\r`.
+    pub init: &'a [&'a [u8]],
+    /// Access point name to set with `AT+CGDCONT` for context 1, if any.
+    pub apn: Option<&'a [u8]>,
+    /// PDP type to set with the APN: `IP`, `IPV6` or `IPV4V6`.
+    pub pdp_type: &'a [u8],
+    /// Number to dial with `ATD`.
+    pub number: &'a [u8],
+    /// Responses aborting dialing.
+    pub abort: &'a [&'a [u8]],
+    /// Timeout for the answers to commands.
+    pub timeout: Duration,
+    /// Timeout for the `CONNECT` answer after dialing.
+    pub connect_timeout: Duration,
+    /// Delay before dialing again after hanging up or a failed attempt.
+    pub redial_delay: Duration,
+}
+
+impl Default for DialConfig<'_> {
+    fn default() -> Self {
+        Self {
+            init: &[],
+            apn: None,
+            pdp_type: b"IP",
+            number: b"*99#",
+            abort: DEFAULT_ABORT,
+            timeout: Duration::from_secs(5),
+            connect_timeout: Duration::from_secs(60),
+            redial_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Modem dialer.
+#[derive(Debug, Clone)]
+pub struct Dialer<'a> {
+    config: DialConfig<'a>,
+    chat: Chat<'a>,
+}
+
+impl<'a> Dialer<'a> {
+    /// Create a new dialer.
+    pub fn new(config: DialConfig<'a>) -> Self {
+        let mut chat = Chat::new(config.abort);
+        chat.timeout = config.timeout;
+        Self { config, chat }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &DialConfig<'a> {
+        &self.config
+    }
+
+    /// Dial, and return once the modem answered `CONNECT` and is in data mode.
+    pub async fn dial<RW: BufRead + Write>(&self, rw: &mut RW) -> Result<(), ChatError<RW::Error>> {
+        let chat = &self.chat;
+        let timeout = self.config.timeout;
+
+        // Reset the modem to its profile, with echo off.
+        chat.send(rw, &[b"ATZ\r"]).await?;
+        chat.expect(rw, b"OK", timeout).await?;
+        chat.send(rw, &[b"ATE0\r"]).await?;
+        chat.expect(rw, b"OK", timeout).await?;
+
+        for cmd in self.config.init {
+            chat.send(rw, &[cmd]).await?;
+            chat.expect(rw, b"OK", timeout).await?;
+        }
+
+        if let Some(apn) = self.config.apn {
+            chat.send(rw, &[b"AT+CGDCONT=1,\"", self.config.pdp_type, b"\",\"", apn, b"\"\r"])
+                .await?;
+            chat.expect(rw, b"OK", timeout).await?;
+        }
+
+        chat.send(rw, &[b"ATD", self.config.number, b"\r"]).await?;
+        chat.expect(rw, b"CONNECT", self.config.connect_timeout).await?;
+        // Skip the rest of the line, such as the connection speed.
+        chat.expect(rw, b"\n", timeout).await
+    }
+
+    /// Hang up, getting the modem back to command mode with the `+++` escape sequence first.
+    ///
+    /// The modem doesn't have to be in data mode. Only I/O errors are returned.
+    pub async fn hangup<RW: BufRead + Write>(&self, rw: &mut RW) -> Result<(), ChatError<RW::Error>> {
+        // The escape sequence must be surrounded by a guard time without data.
+        let guard = Duration::from_millis(1100);
+        let script = [
+            Step::Delay(guard),
+            Step::Send(b"+++"),
+            Step::Delay(guard),
+            Step::Send(b"ATH\r"),
+            Step::Expect(b"OK"),
+        ];
+        match self.chat.run(rw, &script).await {
+            Err(e @ (ChatError::Read(_) | ChatError::Write(_) | ChatError::Eof)) => Err(e),
+            // Modems answer differently depending on whether they were in data mode.
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::convert::Infallible;
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+    use embassy_time::Instant;
+    use embedded_io_async::ErrorType;
+
+    use super::*;
+
+    /// A modem sending canned responses, and recording what's written to it.
+    struct FakeModem<'a> {
+        responses: &'a [u8],
+        /// Largest chunk returned by `fill_buf`, to exercise matches across reads.
+        chunk: usize,
+        /// Once the responses are exhausted, wait forever instead of returning EOF.
+        stall: bool,
+        written: Vec<u8>,
+    }
+
+    impl<'a> FakeModem<'a> {
+        fn new(responses: &'a [u8]) -> Self {
+            Self {
+                responses,
+                chunk: 3,
+                stall: false,
+                written: Vec::new(),
+            }
+        }
+
+        fn count(&self, s: &[u8]) -> usize {
+            self.written.windows(s.len()).filter(|w| *w == s).count()
+        }
+    }
+
+    impl ErrorType for FakeModem<'_> {
+        type Error = Infallible;
+    }
+
+    impl BufRead for FakeModem<'_> {
+        async fn fill_buf(&mut self) -> Result<&[u8], Infallible> {
+            if self.responses.is_empty() && self.stall {
+                core::future::pending::<()>().await;
+            }
+            let n = self.responses.len().min(self.chunk);
+            Ok(&self.responses[..n])
+        }
+
+        fn consume(&mut self, amt: usize) {
+            self.responses = &self.responses[amt..];
+        }
+    }
+
+    impl Write for FakeModem<'_> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    fn config() -> DialConfig<'static> {
+        DialConfig {
+            apn: Some(b"internet"),
+            timeout: Duration::from_millis(100),
+            connect_timeout: Duration::from_millis(200),
+            redial_delay: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn connect() {
+        let mut modem = FakeModem::new(b"\r\nOK\r\n\r\nOK\r\nOK\r\n\r\nCONNECT 150000000\r\n\x7e\xff\x7d\x23");
+        let dialer = Dialer::new(config());
+        block_on(dialer.dial(&mut modem)).unwrap();
+        assert_eq!(
+            modem.written,
+            b"ATZ\rATE0\rAT+CGDCONT=1,\"IP\",\"internet\"\rATD*99#\r".to_vec()
+        );
+        // The first PPP frame is left for the PPP runner.
+        assert_eq!(modem.responses, b"\x7e\xff\x7d\x23");
+    }
+
+    #[test]
+    fn abort() {
+        let mut modem = FakeModem::new(b"OK\r\nOK\r\nOK\r\nNO CARRIER\r\n");
+        let dialer = Dialer::new(config());
+        let res = block_on(dialer.dial(&mut modem));
+        assert!(matches!(res, Err(ChatError::Aborted(1))));
+
+        let mut modem = FakeModem::new(b"OK\r\nERROR\r\n");
+        let res = block_on(dialer.dial(&mut modem));
+        assert!(matches!(res, Err(ChatError::Aborted(0))));
+        assert_eq!(modem.count(b"ATD"), 0);
+    }
+
+    #[test]
+    fn timeout() {
+        let mut modem = FakeModem::new(b"OK\r\nOK\r\nOK\r\n");
+        modem.stall = true;
+        let dialer = Dialer::new(config());
+        let start = Instant::now();
+        let res = block_on(dialer.dial(&mut modem));
+        assert!(matches!(res, Err(ChatError::Timeout)));
+        // The CONNECT timeout applies after dialing.
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert_eq!(modem.count(b"ATD*99#\r"), 1);
+
+        let mut modem = FakeModem::new(b"");
+        let res = block_on(dialer.dial(&mut modem));
+        assert!(matches!(res, Err(ChatError::Eof)));
+    }
+
+    #[test]
+    fn chat_script() {
+        let mut modem = FakeModem::new(b"first\r\nsecond\r\n");
+        modem.stall = true;
+        let chat = Chat::new(&[b"NOPE"]);
+        let script = [
+            Step::Send(b"hello\r"),
+            Step::Expect(b"first"),
+            Step::Timeout(Duration::from_millis(50)),
+            Step::Expect(b"second"),
+            Step::Expect(b"third"),
+        ];
+        let res = block_on(chat.run(&mut modem, &script));
+        assert!(matches!(res, Err(ChatError::Timeout)));
+        assert_eq!(modem.written, b"hello\r".to_vec());
+    }
+
+    #[test]
+    fn window() {
+        let mut window = Window::new();
+        for &b in [b'x'; 100].iter().chain(b"CONNECT") {
+            window.push(b);
+        }
+        assert!(window.ends_with(b"CONNECT"));
+        assert!(!window.ends_with(b""));
+        assert!(!window.ends_with(b"OK"));
+    }
+
+    #[test]
+    fn redial() {
+        // Two failed attempts, each followed by a hangup, then the modem goes away.
+        let responses = b"OK\r\nOK\r\nOK\r\nNO CARRIER\r\nOK\r\nOK\r\nOK\r\nOK\r\nBUSY\r\nOK\r\n";
+        let mut modem = FakeModem::new(responses);
+        let dialer = Dialer::new(config());
+        let mut state = crate::State::<1, 1>::new();
+        let (_device, mut runner) = crate::new(&mut state);
+        let config = ppproto::Config {
+            username: b"",
+            password: b"",
+        };
+
+        let res =
+            block_on(runner.run_with_dialer(&mut modem, &dialer, &config, &crate::Options::default(), |_| {}, |_| {}));
+        assert!(matches!(res, Err(crate::RunError::Eof)));
+        assert_eq!(modem.count(b"ATD*99#\r"), 2);
+        assert_eq!(modem.count(b"+++"), 2);
+        assert_eq!(modem.count(b"ATH\r"), 2);
+        assert_eq!(modem.count(b"ATZ\r"), 3);
+    }
+}
@@ -0,0 +1,317 @@
+//! HDLC-like framing (RFC 1662).
+//!
+//! `ppproto` does its own framing, but it only handles LCP, PAP, IPCP and IPv4. The runner splits the
+//! serial stream into frames itself so it can handle the other protocols, and passes the rest on to `ppproto`.
+
+use embedded_io_async::Write;
+
+const FLAG: u8 = 0x7e;
+const ESCAPE: u8 = 0x7d;
+
+const FCS_INIT: u16 = 0xffff;
+const FCS_GOOD: u16 = 0xf0b8;
+
+fn fcs_update(mut fcs: u16, b: u8) -> u16 {
+    fcs ^= b as u16;
+    for _ in 0..8 {
+        fcs = if fcs & 1 != 0 { (fcs >> 1) ^ 0x8408 } else { fcs >> 1 };
+    }
+    fcs
+}
+
+fn needs_escape(b: u8) -> bool {
+    // All control characters are escaped, which is valid whatever ACCM the peer asked for.
+    b == FLAG || b == ESCAPE || b < 0x20
+}
+
+/// Error for a frame that was received but must be discarded.
+pub(crate) struct FrameError;
+
+/// Reassembles frames from the serial byte stream.
+pub(crate) struct Deframer<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    escape: bool,
+    overflow: bool,
+}
+
+impl<const N: usize> Deframer<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            escape: false,
+            overflow: false,
+        }
+    }
+
+    /// Feed a byte from the serial port.
+    ///
+    /// Returns `true` when this byte ends a frame, which must then be taken with [`frame`](Self::frame)
+    /// before pushing more bytes.
+    pub(crate) fn push(&mut self, b: u8) -> bool {
+        match (b, self.escape) {
+            (FLAG, true) => {
+                // Abort sequence, discard the frame.
+                self.len = 0;
+                self.overflow = false;
+                self.escape = false;
+                false
+            }
+            // Flags between frames end empty frames, which are ignored.
+            (FLAG, false) => self.len != 0 || self.overflow,
+            (ESCAPE, false) => {
+                self.escape = true;
+                false
+            }
+            (b, escape) => {
+                self.escape = false;
+                let b = if escape { b ^ 0x20 } else { b };
+                if self.len == N {
+                    self.overflow = true;
+                } else {
+                    self.buf[self.len] = b;
+                    self.len += 1;
+                }
+                false
+            }
+        }
+    }
+
+    /// Take the frame completed by the last call to [`push`](Self::push).
+    ///
+    /// The address, control and protocol fields are included, the FCS is checked and stripped.
+    pub(crate) fn frame(&mut self) -> Result<&[u8], FrameError> {
+        let len = core::mem::replace(&mut self.len, 0);
+        let overflow = core::mem::replace(&mut self.overflow, false);
+        self.escape = false;
+
+        if overflow || len < 4 {
+            return Err(FrameError);
+        }
+        let fcs = self.buf[..len].iter().fold(FCS_INIT, |fcs, &b| fcs_update(fcs, b));
+        if fcs != FCS_GOOD {
+            return Err(FrameError);
+        }
+        Ok(&self.buf[..len - 2])
+    }
+}
+
+/// Split a frame returned by [`Deframer::frame`] into its protocol and information fields.
+///
+/// Handles both compressed and uncompressed address, control and protocol fields.
+pub(crate) fn parse(frame: &[u8]) -> Option<(u16, &[u8])> {
+    let frame = frame.strip_prefix(&[0xff, 0x03]).unwrap_or(frame);
+    match *frame {
+        [p, ref rest @ ..] if p & 1 == 1 => Some((p as u16, rest)),
+        [hi, lo, ref rest @ ..] if lo & 1 == 1 => Some((u16::from_be_bytes([hi, lo]), rest)),
+        _ => None,
+    }
+}
+
+/// Encode again a frame returned by [`Deframer::frame`], calling `f` with chunks of the encoded bytes.
+///
+/// The encoded frame starts and ends with a flag.
+pub(crate) fn encode_raw(frame: &[u8], mut f: impl FnMut(&[u8])) {
+    let mut out = Chunk::new();
+    let mut fcs = FCS_INIT;
+    out.push_raw(FLAG);
+    for &b in frame {
+        fcs = fcs_update(fcs, b);
+        if out.is_full() {
+            f(out.take());
+        }
+        out.push(b);
+    }
+    for b in (!fcs).to_le_bytes() {
+        if out.is_full() {
+            f(out.take());
+        }
+        out.push(b);
+    }
+    out.push_raw(FLAG);
+    f(out.take());
+}
+
+/// Write a frame with the given protocol to `w`.
+///
+/// The information field is the concatenation of `info`. The frame is encoded in chunks, to avoid
+/// needing a buffer of twice the MTU for the worst case escaping.
+pub(crate) async fn write_frame<W: Write>(w: &mut W, protocol: u16, info: &[&[u8]]) -> Result<(), W::Error> {
+    let header = [0xff, 0x03, (protocol >> 8) as u8, protocol as u8];
+    let mut out = Chunk::new();
+    let mut fcs = FCS_INIT;
+    out.push_raw(FLAG);
+    for &b in header.iter().chain(info.iter().flat_map(|i| i.iter())) {
+        fcs = fcs_update(fcs, b);
+        if out.is_full() {
+            w.write_all(out.take()).await?;
+        }
+        out.push(b);
+    }
+    for b in (!fcs).to_le_bytes() {
+        if out.is_full() {
+            w.write_all(out.take()).await?;
+        }
+        out.push(b);
+    }
+    out.push_raw(FLAG);
+    w.write_all(out.take()).await
+}
+
+const CHUNK_LEN: usize = 64;
+
+struct Chunk {
+    buf: [u8; CHUNK_LEN],
+    len: usize,
+}
+
+impl Chunk {
+    const fn new() -> Self {
+        Self {
+            buf: [0; CHUNK_LEN],
+            len: 0,
+        }
+    }
+
+    /// Whether there might not be room left for an escaped byte and the closing flag.
+    fn is_full(&self) -> bool {
+        self.len + 3 > CHUNK_LEN
+    }
+
+    /// Get the bytes in the chunk, and empty it.
+    fn take(&mut self) -> &[u8] {
+        let len = core::mem::replace(&mut self.len, 0);
+        &self.buf[..len]
+    }
+
+    fn push_raw(&mut self, b: u8) {
+        self.buf[self.len] = b;
+        self.len += 1;
+    }
+
+    fn push(&mut self, b: u8) {
+        if needs_escape(b) {
+            self.push_raw(ESCAPE);
+            self.push_raw(b ^ 0x20);
+        } else {
+            self.push_raw(b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deframe encoded bytes, expecting exactly one frame.
+    fn deframe(encoded: &[u8]) -> Result<([u8; 64], usize), FrameError> {
+        let mut deframer = Deframer::<64>::new();
+        let (last, rest) = encoded.split_last().unwrap();
+        for &b in rest {
+            assert!(!deframer.push(b));
+        }
+        assert!(deframer.push(*last));
+        let frame = deframer.frame()?;
+        let mut out = [0; 64];
+        out[..frame.len()].copy_from_slice(frame);
+        Ok((out, frame.len()))
+    }
+
+    fn encode(frame: &[u8]) -> ([u8; 256], usize) {
+        let mut out = [0; 256];
+        let mut len = 0;
+        encode_raw(frame, |chunk| {
+            out[len..][..chunk.len()].copy_from_slice(chunk);
+            len += chunk.len();
+        });
+        (out, len)
+    }
+
+    #[test]
+    fn fcs() {
+        // Check value of CRC-16/X-25, the FCS-16 of RFC 1662.
+        let fcs = b"123456789".iter().fold(FCS_INIT, |fcs, &b| fcs_update(fcs, b));
+        assert_eq!(!fcs, 0x906e);
+
+        // The FCS of a frame followed by its own FCS is the good FCS.
+        let frame = [0xff, 0x03, 0xc0, 0x21, 0x01];
+        let fcs = !frame.iter().fold(FCS_INIT, |fcs, &b| fcs_update(fcs, b));
+        let fcs = frame
+            .iter()
+            .chain(fcs.to_le_bytes().iter())
+            .fold(FCS_INIT, |fcs, &b| fcs_update(fcs, b));
+        assert_eq!(fcs, FCS_GOOD);
+    }
+
+    #[test]
+    fn escaping() {
+        let frame = [0xff, 0x03, 0x00, 0x21, FLAG, ESCAPE, 0x11, 0x20, 0x7f];
+        let (encoded, len) = encode(&frame);
+        let encoded = &encoded[..len];
+
+        assert_eq!(encoded[0], FLAG);
+        assert_eq!(encoded[len - 1], FLAG);
+        let body = &encoded[1..len - 1];
+        assert!(!body.contains(&FLAG));
+        assert!(body.iter().all(|&b| b >= 0x20));
+        assert_eq!(&body[..6], &[0xff, ESCAPE, 0x23, ESCAPE, 0x20, 0x21]);
+        assert_eq!(&body[6..13], &[ESCAPE, 0x5e, ESCAPE, 0x5d, ESCAPE, 0x31, 0x20]);
+
+        let (decoded, n) = deframe(encoded).ok().unwrap();
+        assert_eq!(&decoded[..n], &frame);
+    }
+
+    #[test]
+    fn bad_frames() {
+        let (mut encoded, len) = encode(&[0xff, 0x03, 0x00, 0x21, 0x45]);
+        encoded[5] ^= 1;
+        assert!(deframe(&encoded[..len]).is_err());
+
+        // Too short to have an FCS.
+        assert!(deframe(&[FLAG, 0x21, 0x01, FLAG]).is_err());
+
+        // Too long for the buffer.
+        let (encoded, len) = encode(&[0x45; 100]);
+        assert!(deframe(&encoded[..len]).is_err());
+
+        // An abort sequence discards the frame, the next one is received.
+        let mut deframer = Deframer::<64>::new();
+        for b in [FLAG, 0xff, 0x03, ESCAPE, FLAG] {
+            assert!(!deframer.push(b));
+        }
+        let (encoded, len) = encode(&[0xff, 0x03, 0x00, 0x21, 0x45]);
+        let done = encoded[1..len].iter().filter(|&&b| deframer.push(b)).count();
+        assert_eq!(done, 1);
+        assert_eq!(deframer.frame().ok(), Some(&[0xff, 0x03, 0x00, 0x21, 0x45][..]));
+    }
+
+    #[test]
+    fn parse_compressed_fields() {
+        let info = [1, 2, 3];
+        assert_eq!(parse(&[0xff, 0x03, 0xc0, 0x21, 1, 2, 3]), Some((0xc021, &info[..])));
+        // Address and control field compression.
+        assert_eq!(parse(&[0xc0, 0x21, 1, 2, 3]), Some((0xc021, &info[..])));
+        // Protocol field compression.
+        assert_eq!(parse(&[0xff, 0x03, 0x21, 1, 2, 3]), Some((0x0021, &info[..])));
+        assert_eq!(parse(&[0x57, 1, 2, 3]), Some((0x0057, &info[..])));
+        // Protocol numbers are odd.
+        assert_eq!(parse(&[0xff, 0x03, 0x00, 0x20, 1]), None);
+        assert_eq!(parse(&[0xff, 0x03]), None);
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let info = [0x45; 200];
+        let mut out = [0; 512];
+        let mut w = &mut out[..];
+        embassy_futures::block_on(write_frame(&mut w, 0x0021, &[&info[..100], &info[100..]])).unwrap();
+        let len = 512 - w.len();
+
+        let mut deframer = Deframer::<256>::new();
+        let done = out[..len].iter().position(|&b| deframer.push(b));
+        assert_eq!(done, Some(len - 1));
+        let frame = deframer.frame().ok().unwrap();
+        assert_eq!(parse(frame), Some((0x0021, &info[..])));
+    }
+}
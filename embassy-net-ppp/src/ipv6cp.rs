@@ -0,0 +1,532 @@
+//! IPv6 Control Protocol (RFC 5072).
+//!
+//! Only the Interface-Identifier option is negotiated, IPv6 header compression is rejected.
+
+use embassy_time::{Duration, Instant};
+
+/// PPP protocol number of IPv6CP.
+pub(crate) const PROTOCOL: u16 = 0x8057;
+/// PPP protocol number of IPv6.
+pub(crate) const PROTOCOL_IPV6: u16 = 0x0057;
+
+const CONFIGURE_REQUEST: u8 = 1;
+const CONFIGURE_ACK: u8 = 2;
+const CONFIGURE_NAK: u8 = 3;
+const CONFIGURE_REJECT: u8 = 4;
+const TERMINATE_REQUEST: u8 = 5;
+const TERMINATE_ACK: u8 = 6;
+const CODE_REJECT: u8 = 7;
+
+const OPTION_INTERFACE_IDENTIFIER: u8 = 1;
+
+const RESTART_INTERVAL: Duration = Duration::from_secs(3);
+const MAX_CONFIGURE: u8 = 10;
+
+/// Status of the IPv6 link, once IPv6CP negotiation is done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Ipv6Status {
+    /// Our interface identifier.
+    pub our_interface_id: [u8; 8],
+    /// The peer's interface identifier.
+    pub peer_interface_id: [u8; 8],
+}
+
+impl Ipv6Status {
+    /// Our link-local address, derived from our interface identifier.
+    pub fn link_local_address(&self) -> [u8; 16] {
+        link_local(self.our_interface_id)
+    }
+
+    /// The peer's link-local address, derived from its interface identifier.
+    ///
+    /// This is the address to use as default gateway.
+    pub fn peer_link_local_address(&self) -> [u8; 16] {
+        link_local(self.peer_interface_id)
+    }
+}
+
+fn link_local(interface_id: [u8; 8]) -> [u8; 16] {
+    let mut addr = [0; 16];
+    addr[0] = 0xfe;
+    addr[1] = 0x80;
+    addr[8..].copy_from_slice(&interface_id);
+    addr
+}
+
+/// Option negotiation automaton state, from RFC 1661.
+///
+/// The Closing and Stopping states are skipped, since there's nothing to wait for when
+/// going down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// The lower layer (LCP) is down.
+    Initial,
+    /// Negotiation failed or was terminated by the peer, waiting for a Configure-Request from it.
+    Stopped,
+    ReqSent,
+    AckReceived,
+    AckSent,
+    Opened,
+}
+
+pub(crate) struct Ipv6cp {
+    state: State,
+    our_id: [u8; 8],
+    peer_id: [u8; 8],
+    /// Identifier of the last Configure-Request sent.
+    identifier: u8,
+    restart_counter: u8,
+    /// When to send the next Configure-Request.
+    deadline: Option<Instant>,
+}
+
+impl Ipv6cp {
+    /// Create a new IPv6CP instance.
+    ///
+    /// If `interface_id` is all zeros, the peer is asked to suggest one.
+    pub(crate) fn new(interface_id: [u8; 8]) -> Self {
+        Self {
+            state: State::Initial,
+            our_id: interface_id,
+            peer_id: [0; 8],
+            identifier: 0,
+            restart_counter: 0,
+            deadline: None,
+        }
+    }
+
+    /// Get the IPv6 link status, if it is up.
+    pub(crate) fn status(&self) -> Option<Ipv6Status> {
+        (self.state == State::Opened).then_some(Ipv6Status {
+            our_interface_id: self.our_id,
+            peer_interface_id: self.peer_id,
+        })
+    }
+
+    /// The lower layer went up, start negotiating.
+    pub(crate) fn up(&mut self, now: Instant) {
+        if self.state == State::Initial {
+            self.send_request(now);
+            self.state = State::ReqSent;
+        }
+    }
+
+    /// The lower layer went down.
+    pub(crate) fn down(&mut self) {
+        self.state = State::Initial;
+        self.deadline = None;
+    }
+
+    /// When [`poll`](Self::poll) must be called next.
+    pub(crate) fn poll_at(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    fn send_request(&mut self, now: Instant) {
+        self.restart_counter = MAX_CONFIGURE;
+        self.deadline = Some(now);
+    }
+
+    /// Write the Configure-Request to send to `buf` if it's time to send it, and return its length.
+    pub(crate) fn poll(&mut self, now: Instant, buf: &mut [u8; 14]) -> Option<usize> {
+        if !matches!(self.deadline, Some(d) if d <= now) {
+            return None;
+        }
+
+        if self.restart_counter == 0 {
+            warn!("IPv6CP: no answer from peer, giving up");
+            self.state = State::Stopped;
+            self.deadline = None;
+            return None;
+        }
+        self.restart_counter -= 1;
+        self.deadline = Some(now + RESTART_INTERVAL);
+        if self.state == State::AckReceived {
+            self.state = State::ReqSent;
+        }
+
+        self.identifier = self.identifier.wrapping_add(1);
+        buf[0] = CONFIGURE_REQUEST;
+        buf[1] = self.identifier;
+        buf[2..4].copy_from_slice(&14u16.to_be_bytes());
+        buf[4] = OPTION_INTERFACE_IDENTIFIER;
+        buf[5] = 10;
+        buf[6..14].copy_from_slice(&self.our_id);
+        Some(14)
+    }
+
+    /// Handle a received IPv6CP packet.
+    ///
+    /// If a reply must be sent, it is written to `reply` and its length is returned.
+    pub(crate) fn receive(&mut self, now: Instant, pkt: &[u8], reply: &mut [u8]) -> Option<usize> {
+        let [code, identifier, len_hi, len_lo, ..] = *pkt else {
+            return None;
+        };
+        let len = u16::from_be_bytes([len_hi, len_lo]) as usize;
+        if len < 4 || len > pkt.len() {
+            return None;
+        }
+        let pkt = &pkt[..len];
+        let data = &pkt[4..];
+
+        if self.state == State::Initial {
+            // Silently discarded until LCP is up, the peer will retransmit.
+            return None;
+        }
+
+        match code {
+            CONFIGURE_REQUEST => {
+                let (reply_code, reply_len) = self.check_request(data, &mut reply[4..])?;
+                let good = reply_code == CONFIGURE_ACK;
+                self.state = match (self.state, good) {
+                    (State::Stopped | State::Opened, good) => {
+                        self.send_request(now);
+                        if good {
+                            State::AckSent
+                        } else {
+                            State::ReqSent
+                        }
+                    }
+                    (State::AckReceived, true) => {
+                        self.deadline = None;
+                        State::Opened
+                    }
+                    (State::AckReceived, false) => State::AckReceived,
+                    (_, true) => State::AckSent,
+                    (_, false) => State::ReqSent,
+                };
+                Some(header(reply, reply_code, identifier, reply_len))
+            }
+            CONFIGURE_ACK if identifier == self.identifier => {
+                self.state = match self.state {
+                    State::ReqSent => {
+                        self.restart_counter = MAX_CONFIGURE;
+                        State::AckReceived
+                    }
+                    State::AckSent => {
+                        self.deadline = None;
+                        State::Opened
+                    }
+                    State::AckReceived | State::Opened => {
+                        self.send_request(now);
+                        State::ReqSent
+                    }
+                    state => state,
+                };
+                None
+            }
+            CONFIGURE_NAK | CONFIGURE_REJECT if identifier == self.identifier => {
+                if code == CONFIGURE_REJECT {
+                    warn!("IPv6CP: peer rejected the interface identifier");
+                    self.state = State::Stopped;
+                    self.deadline = None;
+                    return None;
+                }
+                for (kind, value) in options(data) {
+                    if kind == OPTION_INTERFACE_IDENTIFIER && value.len() == 8 {
+                        self.our_id.copy_from_slice(value);
+                    }
+                }
+                self.state = match self.state {
+                    State::Stopped => State::Stopped,
+                    State::AckSent => {
+                        self.deadline = Some(now);
+                        State::AckSent
+                    }
+                    _ => {
+                        self.deadline = Some(now);
+                        State::ReqSent
+                    }
+                };
+                None
+            }
+            TERMINATE_REQUEST => {
+                self.state = match self.state {
+                    State::Opened | State::Stopped => {
+                        self.deadline = None;
+                        State::Stopped
+                    }
+                    _ => State::ReqSent,
+                };
+                Some(header(reply, TERMINATE_ACK, identifier, 0))
+            }
+            TERMINATE_ACK => {
+                if matches!(self.state, State::AckReceived | State::Opened) {
+                    self.send_request(now);
+                    self.state = State::ReqSent;
+                }
+                None
+            }
+            CONFIGURE_ACK | CONFIGURE_NAK | CONFIGURE_REJECT | CODE_REJECT => None,
+            _ => {
+                // Reject unknown codes, truncating the rejected packet to fit.
+                let n = pkt.len().min(reply.len() - 4);
+                reply[4..][..n].copy_from_slice(&pkt[..n]);
+                Some(header(reply, CODE_REJECT, identifier, n))
+            }
+        }
+    }
+
+    /// Check the options of a Configure-Request from the peer.
+    ///
+    /// Writes the options of the reply to `buf`, and returns the reply code and the options length.
+    fn check_request(&mut self, data: &[u8], buf: &mut [u8]) -> Option<(u8, usize)> {
+        // Options must be well-formed.
+        let total: usize = options(data).map(|(_, v)| v.len() + 2).sum();
+        if total != data.len() {
+            return None;
+        }
+
+        // Reject everything but the interface identifier.
+        let mut n = 0;
+        for (kind, value) in options(data) {
+            if kind != OPTION_INTERFACE_IDENTIFIER || value.len() != 8 {
+                n += put_option(&mut buf[n..], kind, value)?;
+            }
+        }
+        if n != 0 {
+            return Some((CONFIGURE_REJECT, n));
+        }
+
+        let peer_id = options(data).find_map(|(_, v)| v.try_into().ok());
+        if let Some(peer_id) = peer_id {
+            if peer_id == [0; 8] || peer_id == self.our_id {
+                // Suggest an identifier different from ours.
+                let mut suggestion = if self.our_id == [0; 8] { [0; 8] } else { self.our_id };
+                suggestion[7] ^= 1;
+                n += put_option(buf, OPTION_INTERFACE_IDENTIFIER, &suggestion)?;
+                return Some((CONFIGURE_NAK, n));
+            }
+            self.peer_id = peer_id;
+        }
+
+        // A Configure-Ack repeats the options as received.
+        buf.get_mut(..data.len())?.copy_from_slice(data);
+        Some((CONFIGURE_ACK, data.len()))
+    }
+}
+
+/// Iterate over the `(type, value)` options of a configure packet, stopping at the first malformed one.
+///
+/// The option format is shared by all the control protocols, LCP included.
+pub(crate) fn options(mut data: &[u8]) -> impl Iterator<Item = (u8, &[u8])> {
+    core::iter::from_fn(move || {
+        let [kind, len, ..] = *data else {
+            return None;
+        };
+        let len = len as usize;
+        if len < 2 || len > data.len() {
+            return None;
+        }
+        let value = &data[2..len];
+        data = &data[len..];
+        Some((kind, value))
+    })
+}
+
+fn put_option(buf: &mut [u8], kind: u8, value: &[u8]) -> Option<usize> {
+    let len = value.len() + 2;
+    let buf = buf.get_mut(..len)?;
+    buf[0] = kind;
+    buf[1] = len as u8;
+    buf[2..].copy_from_slice(value);
+    Some(len)
+}
+
+/// Write a packet header to `buf`, for `data_len` bytes of data after it, and return the packet length.
+fn header(buf: &mut [u8], code: u8, identifier: u8, data_len: usize) -> usize {
+    let len = data_len + 4;
+    buf[0] = code;
+    buf[1] = identifier;
+    buf[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUR_ID: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+    const PEER_ID: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 2];
+
+    fn at(secs: u64) -> Instant {
+        Instant::from_secs(secs)
+    }
+
+    /// Build a configure packet with an Interface-Identifier option.
+    fn configure(code: u8, identifier: u8, id: [u8; 8]) -> [u8; 14] {
+        let mut pkt = [0; 14];
+        header(&mut pkt, code, identifier, 10);
+        put_option(&mut pkt[4..], OPTION_INTERFACE_IDENTIFIER, &id).unwrap();
+        pkt
+    }
+
+    /// Start negotiating, and return the identifier of the Configure-Request sent.
+    fn start(ipv6cp: &mut Ipv6cp) -> u8 {
+        ipv6cp.up(at(0));
+        let mut req = [0; 14];
+        assert_eq!(ipv6cp.poll(at(0), &mut req), Some(14));
+        assert_eq!(req[0], CONFIGURE_REQUEST);
+        assert_eq!(&req[6..], &ipv6cp.our_id);
+        req[1]
+    }
+
+    fn open(ipv6cp: &mut Ipv6cp) {
+        let identifier = start(ipv6cp);
+        let mut reply = [0; 64];
+        assert_eq!(
+            ipv6cp.receive(at(0), &configure(CONFIGURE_ACK, identifier, OUR_ID), &mut reply),
+            None
+        );
+        assert_eq!(ipv6cp.state, State::AckReceived);
+        let n = ipv6cp
+            .receive(at(0), &configure(CONFIGURE_REQUEST, 42, PEER_ID), &mut reply)
+            .unwrap();
+        assert_eq!(&reply[..n], &configure(CONFIGURE_ACK, 42, PEER_ID));
+        assert_eq!(ipv6cp.state, State::Opened);
+    }
+
+    #[test]
+    fn negotiation() {
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        assert_eq!(ipv6cp.status(), None);
+        open(&mut ipv6cp);
+
+        let status = ipv6cp.status().unwrap();
+        assert_eq!(status.our_interface_id, OUR_ID);
+        assert_eq!(status.peer_interface_id, PEER_ID);
+        assert_eq!(&status.peer_link_local_address()[..2], &[0xfe, 0x80]);
+        assert_eq!(ipv6cp.poll_at(), None);
+    }
+
+    #[test]
+    fn peer_request_first() {
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        let identifier = start(&mut ipv6cp);
+        let mut reply = [0; 64];
+        ipv6cp.receive(at(0), &configure(CONFIGURE_REQUEST, 7, PEER_ID), &mut reply);
+        assert_eq!(ipv6cp.state, State::AckSent);
+        ipv6cp.receive(at(0), &configure(CONFIGURE_ACK, identifier, OUR_ID), &mut reply);
+        assert_eq!(ipv6cp.state, State::Opened);
+
+        // Acks for older requests are ignored.
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        let identifier = start(&mut ipv6cp);
+        ipv6cp.receive(
+            at(0),
+            &configure(CONFIGURE_ACK, identifier.wrapping_sub(1), OUR_ID),
+            &mut reply,
+        );
+        assert_eq!(ipv6cp.state, State::ReqSent);
+    }
+
+    #[test]
+    fn discarded_before_up() {
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        let mut reply = [0; 64];
+        assert_eq!(
+            ipv6cp.receive(at(0), &configure(CONFIGURE_REQUEST, 1, PEER_ID), &mut reply),
+            None
+        );
+        assert_eq!(ipv6cp.state, State::Initial);
+        assert_eq!(ipv6cp.poll_at(), None);
+    }
+
+    #[test]
+    fn nak_duplicate_peer_id() {
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        start(&mut ipv6cp);
+        let mut reply = [0; 64];
+        let n = ipv6cp
+            .receive(at(0), &configure(CONFIGURE_REQUEST, 3, OUR_ID), &mut reply)
+            .unwrap();
+        let mut suggestion = OUR_ID;
+        suggestion[7] ^= 1;
+        assert_eq!(&reply[..n], &configure(CONFIGURE_NAK, 3, suggestion));
+        assert_eq!(ipv6cp.state, State::ReqSent);
+    }
+
+    #[test]
+    fn reject_unknown_options() {
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        start(&mut ipv6cp);
+        // IPv6-Compression-Protocol.
+        let mut pkt = [0; 8];
+        header(&mut pkt, CONFIGURE_REQUEST, 4, 4);
+        put_option(&mut pkt[4..], 2, &[0x00, 0x4f]).unwrap();
+        let mut reply = [0; 64];
+        let n = ipv6cp.receive(at(0), &pkt, &mut reply).unwrap();
+        assert_eq!(reply[0], CONFIGURE_REJECT);
+        assert_eq!(&reply[4..n], &pkt[4..]);
+
+        // Malformed options are ignored.
+        pkt[5] = 10;
+        assert_eq!(ipv6cp.receive(at(0), &pkt, &mut reply), None);
+    }
+
+    #[test]
+    fn nak_changes_our_id() {
+        let mut ipv6cp = Ipv6cp::new([0; 8]);
+        let identifier = start(&mut ipv6cp);
+        let mut reply = [0; 64];
+        ipv6cp.receive(at(1), &configure(CONFIGURE_NAK, identifier, OUR_ID), &mut reply);
+        assert_eq!(ipv6cp.poll_at(), Some(at(1)));
+
+        let mut req = [0; 14];
+        assert_eq!(ipv6cp.poll(at(1), &mut req), Some(14));
+        assert_eq!(req[1], identifier.wrapping_add(1));
+        assert_eq!(&req[6..], &OUR_ID);
+    }
+
+    #[test]
+    fn gives_up_without_answer() {
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        start(&mut ipv6cp);
+        let mut req = [0; 14];
+        let mut sent = 1;
+        while let Some(deadline) = ipv6cp.poll_at() {
+            assert_eq!(deadline, at(0) + RESTART_INTERVAL * sent);
+            // Too early.
+            assert_eq!(ipv6cp.poll(deadline - Duration::from_millis(1), &mut req), None);
+            if ipv6cp.poll(deadline, &mut req).is_some() {
+                sent += 1;
+            }
+        }
+        assert_eq!(sent, MAX_CONFIGURE as u32);
+        assert_eq!(ipv6cp.state, State::Stopped);
+    }
+
+    #[test]
+    fn terminate() {
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        open(&mut ipv6cp);
+        let mut reply = [0; 64];
+        let mut pkt = [0; 4];
+        header(&mut pkt, TERMINATE_REQUEST, 9, 0);
+        let n = ipv6cp.receive(at(5), &pkt, &mut reply).unwrap();
+        assert_eq!(&reply[..n], &[TERMINATE_ACK, 9, 0, 4]);
+        assert_eq!(ipv6cp.status(), None);
+
+        // A new request from the peer restarts negotiation.
+        ipv6cp.receive(at(6), &configure(CONFIGURE_REQUEST, 10, PEER_ID), &mut reply);
+        assert_eq!(ipv6cp.state, State::AckSent);
+        assert_eq!(ipv6cp.poll_at(), Some(at(6)));
+
+        // Going down forgets everything.
+        ipv6cp.down();
+        assert_eq!(ipv6cp.state, State::Initial);
+        assert_eq!(ipv6cp.poll_at(), None);
+    }
+
+    #[test]
+    fn code_reject() {
+        let mut ipv6cp = Ipv6cp::new(OUR_ID);
+        start(&mut ipv6cp);
+        let pkt = [42, 1, 0, 6, 0xaa, 0xbb];
+        let mut reply = [0; 8];
+        let n = ipv6cp.receive(at(0), &pkt, &mut reply).unwrap();
+        // Truncated to fit the reply buffer.
+        assert_eq!(&reply[..n], &[CODE_REJECT, 1, 0, 8, 42, 1, 0, 6]);
+    }
+}
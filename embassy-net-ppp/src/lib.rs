@@ -5,24 +5,44 @@
 // must be first
 mod fmt;
 
+pub mod dialer;
+mod hdlc;
+mod ipv6cp;
+
+use core::cell::Cell;
 use core::convert::Infallible;
 use core::mem::MaybeUninit;
 
-use embassy_futures::select::{select, Either};
+use dialer::{ChatError, Dialer};
+use embassy_futures::select::{select3, Either3};
 use embassy_net_driver_channel as ch;
 use embassy_net_driver_channel::driver::LinkState;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_io_async::{BufRead, Write};
+pub use ipv6cp::Ipv6Status;
 use ppproto::pppos::{BufferFullError, PPPoS, PPPoSAction};
 pub use ppproto::{Config, Ipv4Status};
 
 const MTU: usize = 1500;
 
+/// PPP protocol number of LCP.
+const PROTOCOL_LCP: u16 = 0xc021;
+/// PPP protocol number of IPv4 packets.
+const PROTOCOL_IPV4: u16 = 0x0021;
+const LCP_CONFIGURE_ACK: u8 = 2;
+const LCP_ECHO_REQUEST: u8 = 9;
+const LCP_ECHO_REPLY: u8 = 10;
+const LCP_OPTION_MAGIC_NUMBER: u8 = 5;
+
 /// Type alias for the embassy-net driver.
 pub type Device<'d> = embassy_net_driver_channel::Device<'d, MTU>;
 
 /// Internal state for the embassy-net integration.
 pub struct State<const N_RX: usize, const N_TX: usize> {
     ch_state: ch::State<MTU, N_RX, N_TX>,
+    quality: Mutex<NoopRawMutex, Cell<LinkQuality>>,
 }
 
 impl<const N_RX: usize, const N_TX: usize> State<N_RX, N_TX> {
@@ -30,6 +50,7 @@ impl<const N_RX: usize, const N_TX: usize> State<N_RX, N_TX> {
     pub const fn new() -> Self {
         Self {
             ch_state: ch::State::new(),
+            quality: Mutex::new(Cell::new(LinkQuality::new())),
         }
     }
 }
@@ -39,6 +60,88 @@ impl<const N_RX: usize, const N_TX: usize> State<N_RX, N_TX> {
 /// You must call `.run()` in a background task for the driver to operate.
 pub struct Runner<'d> {
     ch: ch::Runner<'d, MTU>,
+    quality: &'d Mutex<NoopRawMutex, Cell<LinkQuality>>,
+}
+
+/// Options for [`Runner::run_with_options`].
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Options {
+    /// Negotiate IPv6 with IPv6CP, in addition to IPv4.
+    pub ipv6: bool,
+    /// Interface identifier to ask for in IPv6CP.
+    ///
+    /// If all zeros, the peer is asked to suggest one.
+    pub interface_id: [u8; 8],
+    /// Send LCP Echo-Requests to check the peer is still there.
+    pub keepalive: Option<Keepalive>,
+}
+
+/// LCP keepalive configuration.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Keepalive {
+    /// Interval between LCP Echo-Requests.
+    pub interval: Duration,
+    /// Time without receiving anything from the peer after which the link is considered dead.
+    pub timeout: Duration,
+}
+
+impl Default for Keepalive {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Link quality counters.
+///
+/// The counters start at zero when the driver is created, and wrap around on overflow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct LinkQuality {
+    /// Number of valid frames received.
+    pub rx_frames: u32,
+    /// Number of frames received with a bad FCS, or too long.
+    pub rx_errors: u32,
+    /// Number of frames sent.
+    pub tx_frames: u32,
+    /// Number of LCP Echo-Requests sent.
+    pub echo_requests: u32,
+    /// Number of LCP Echo-Replies received for them.
+    pub echo_replies: u32,
+    /// Round-trip time of the last answered LCP Echo-Request.
+    pub echo_rtt: Option<Duration>,
+}
+
+impl LinkQuality {
+    const fn new() -> Self {
+        Self {
+            rx_frames: 0,
+            rx_errors: 0,
+            tx_frames: 0,
+            echo_requests: 0,
+            echo_replies: 0,
+            echo_rtt: None,
+        }
+    }
+}
+
+/// Handle to read the [`LinkQuality`] counters of a [`Runner`] while it runs.
+#[derive(Clone, Copy)]
+pub struct LinkQualityReader<'d> {
+    quality: &'d Mutex<NoopRawMutex, Cell<LinkQuality>>,
+}
+
+impl<'d> LinkQualityReader<'d> {
+    /// Get the current counters.
+    pub fn get(&self) -> LinkQuality {
+        self.quality.lock(|q| q.get())
+    }
 }
 
 /// Error returned by [`Runner::run`].
@@ -53,9 +156,16 @@ pub enum RunError<E> {
     Eof,
     /// PPP protocol was terminated by the peer
     Terminated,
+    /// Nothing was received from the peer within the [`Keepalive`] timeout.
+    KeepaliveTimeout,
 }
 
 impl<'d> Runner<'d> {
+    /// Get a handle to read the link quality counters.
+    pub fn link_quality(&self) -> LinkQualityReader<'d> {
+        LinkQualityReader { quality: self.quality }
+    }
+
     /// You must call this in a background task for the driver to operate.
     ///
     /// If reading/writing to the underlying serial port fails, the link state
@@ -67,25 +177,60 @@ impl<'d> Runner<'d> {
     /// After this function returns or is canceled, you can call it again to establish
     /// a new PPP connection.
     pub async fn run<RW: BufRead + Write>(
+        &mut self,
+        rw: RW,
+        config: ppproto::Config<'_>,
+        on_ipv4_up: impl FnMut(Ipv4Status),
+    ) -> Result<Infallible, RunError<RW::Error>> {
+        self.run_with_options(rw, config, &Options::default(), on_ipv4_up, |_| {})
+            .await
+    }
+
+    /// Like [`run`](Self::run), with IPv6 and keepalive options.
+    ///
+    /// `on_ipv6_up` is called when IPv6CP negotiation is done. The link state is Up as long
+    /// as either IPv4 or IPv6 is up.
+    pub async fn run_with_options<RW: BufRead + Write>(
         &mut self,
         mut rw: RW,
         config: ppproto::Config<'_>,
+        options: &Options,
         mut on_ipv4_up: impl FnMut(Ipv4Status),
+        mut on_ipv6_up: impl FnMut(Ipv6Status),
     ) -> Result<Infallible, RunError<RW::Error>> {
         let mut ppp = PPPoS::new(config);
         ppp.open().unwrap();
 
+        let quality = self.quality;
         let (state_chan, mut rx_chan, mut tx_chan) = self.ch.borrow_split();
         state_chan.set_link_state(LinkState::Down);
         let _ondrop = OnDrop::new(|| state_chan.set_link_state(LinkState::Down));
 
         let mut rx_buf = [0; 2048];
         let mut tx_buf = [0; 2048];
+        let mut deframer = hdlc::Deframer::<2048>::new();
+        let mut ipv6cp = ipv6cp::Ipv6cp::new(options.interface_id);
+        let mut ctrl_buf = [0; 64];
 
         let mut needs_poll = true;
-        let mut was_up = false;
+        let mut lcp_up = false;
+        let mut ipv4_up = false;
+        let mut ipv6_up = false;
+
+        // Keepalive state.
+        let mut last_rx = Instant::now();
+        let mut next_echo = Instant::MAX;
+        let mut echo_identifier = 0u8;
+        let mut echo_sent_at: Option<Instant> = None;
+        // Our magic number, zero until the peer acks one (RFC 1661).
+        let mut magic_number = 0u32;
 
         loop {
+            let mut deadline = ipv6cp.poll_at().unwrap_or(Instant::MAX);
+            if let (Some(keepalive), true) = (options.keepalive, lcp_up) {
+                deadline = deadline.min(next_echo).min(last_rx + keepalive.timeout);
+            }
+
             let rx_fut = async {
                 let buf = rx_chan.rx_buf().await;
                 let rx_data = match needs_poll {
@@ -99,22 +244,112 @@ impl<'d> Runner<'d> {
                 Ok((buf, rx_data))
             };
             let tx_fut = tx_chan.tx_buf();
-            match select(rx_fut, tx_fut).await {
-                Either::First(r) => {
+            let timer_fut = Timer::at(deadline);
+            match select3(rx_fut, tx_fut, timer_fut).await {
+                Either3::First(r) => {
                     needs_poll = false;
 
                     let (buf, rx_data) = r?;
-                    let n = ppp.consume(rx_data, &mut rx_buf);
+
+                    // Split the received data into frames, handling one frame at a time.
+                    let mut n = 0;
+                    let mut frame_done = false;
+                    for &b in rx_data {
+                        n += 1;
+                        if deframer.push(b) {
+                            frame_done = true;
+                            break;
+                        }
+                    }
                     rw.consume(n);
 
+                    // IP packets go straight to the rx channel. Control frames not handled here are passed
+                    // on to ppproto, which is polled every time anyway.
+                    let mut to_ppp = &[][..];
+                    let mut rx_len = None;
+                    if frame_done {
+                        match deframer.frame() {
+                            Ok(frame) => {
+                                last_rx = Instant::now();
+                                update(quality, |q| q.rx_frames = q.rx_frames.wrapping_add(1));
+                                match hdlc::parse(frame) {
+                                    Some((PROTOCOL_IPV4, pkt)) if ipv4_up => {
+                                        if pkt.len() <= buf.len() {
+                                            buf[..pkt.len()].copy_from_slice(pkt);
+                                            rx_len = Some(pkt.len());
+                                        }
+                                    }
+                                    Some((ipv6cp::PROTOCOL_IPV6, pkt)) if ipv6_up => {
+                                        if pkt.len() <= buf.len() {
+                                            buf[..pkt.len()].copy_from_slice(pkt);
+                                            rx_len = Some(pkt.len());
+                                        }
+                                    }
+                                    // IPv4 packets are dropped until IPCP is up, like ppproto does.
+                                    Some((PROTOCOL_IPV4, _)) => {}
+                                    Some((ipv6cp::PROTOCOL, pkt)) if options.ipv6 => {
+                                        if let Some(n) = ipv6cp.receive(last_rx, pkt, &mut ctrl_buf) {
+                                            hdlc::write_frame(&mut rw, ipv6cp::PROTOCOL, &[&ctrl_buf[..n]])
+                                                .await
+                                                .map_err(RunError::Write)?;
+                                            update(quality, |q| q.tx_frames = q.tx_frames.wrapping_add(1));
+                                        }
+                                    }
+                                    Some((PROTOCOL_LCP, [LCP_ECHO_REPLY, identifier, ..])) => {
+                                        if let Some(sent_at) = echo_sent_at.filter(|_| *identifier == echo_identifier) {
+                                            echo_sent_at = None;
+                                            let rtt = last_rx - sent_at;
+                                            update(quality, |q| {
+                                                q.echo_replies = q.echo_replies.wrapping_add(1);
+                                                q.echo_rtt = Some(rtt);
+                                            });
+                                        }
+                                    }
+                                    Some((PROTOCOL_LCP, pkt @ [LCP_CONFIGURE_ACK, ..])) => {
+                                        // LCP is negotiated by ppproto, the acked magic number is picked up on the way.
+                                        if let Some(magic) = acked_magic_number(pkt) {
+                                            magic_number = magic;
+                                        }
+                                        to_ppp = frame;
+                                    }
+                                    _ => to_ppp = frame,
+                                }
+                            }
+                            Err(hdlc::FrameError) => {
+                                update(quality, |q| q.rx_errors = q.rx_errors.wrapping_add(1));
+                                state_chan.update_stats(|s| s.rx_errors = s.rx_errors.wrapping_add(1));
+                            }
+                        }
+                    }
+
+                    if !to_ppp.is_empty() {
+                        // ppproto only takes HDLC-encoded bytes, so the frame is encoded again for it.
+                        hdlc::encode_raw(to_ppp, |mut chunk| {
+                            while !chunk.is_empty() {
+                                let n = ppp.consume(chunk, &mut rx_buf);
+                                if n == 0 {
+                                    break;
+                                }
+                                chunk = &chunk[n..];
+                            }
+                        });
+                    }
+
                     match ppp.poll(&mut tx_buf, &mut rx_buf) {
                         PPPoSAction::None => {}
                         PPPoSAction::Received(rg) => {
                             let pkt = &rx_buf[rg];
                             buf[..pkt.len()].copy_from_slice(pkt);
-                            rx_chan.rx_done(pkt.len());
+                            rx_len = Some(pkt.len());
+                        }
+                        PPPoSAction::Transmit(n) => {
+                            rw.write_all(&tx_buf[..n]).await.map_err(RunError::Write)?;
+                            update(quality, |q| q.tx_frames = q.tx_frames.wrapping_add(1));
                         }
-                        PPPoSAction::Transmit(n) => rw.write_all(&tx_buf[..n]).await.map_err(RunError::Write)?,
+                    }
+
+                    if let Some(n) = rx_len {
+                        rx_chan.rx_done(n);
                     }
 
                     let status = ppp.status();
@@ -123,30 +358,168 @@ impl<'d> Runner<'d> {
                             return Err(RunError::Terminated);
                         }
                         ppproto::Phase::Open => {
-                            if !was_up {
+                            if !ipv4_up {
                                 on_ipv4_up(status.ipv4.unwrap());
                             }
-                            was_up = true;
-                            state_chan.set_link_state(LinkState::Up);
+                            ipv4_up = true;
                         }
-                        _ => {
-                            was_up = false;
-                            state_chan.set_link_state(LinkState::Down);
+                        _ => ipv4_up = false,
+                    }
+
+                    // IPv6CP and the keepalive run once LCP is up and authentication is done.
+                    let up = matches!(status.phase, ppproto::Phase::Network | ppproto::Phase::Open);
+                    if up != lcp_up {
+                        lcp_up = up;
+                        let now = Instant::now();
+                        if up {
+                            if options.ipv6 {
+                                ipv6cp.up(now);
+                            }
+                            last_rx = now;
+                            next_echo = now;
+                            echo_sent_at = None;
+                        } else {
+                            ipv6cp.down();
+                            magic_number = 0;
                         }
                     }
                 }
-                Either::Second(pkt) => {
-                    match ppp.send(pkt, &mut tx_buf) {
-                        Ok(n) => rw.write_all(&tx_buf[..n]).await.map_err(RunError::Write)?,
-                        Err(BufferFullError) => unreachable!(),
+                Either3::Second(pkt) => {
+                    if pkt.first().map(|b| b >> 4) == Some(6) {
+                        // IPv6 packets are dropped until IPv6CP is up.
+                        if ipv6_up {
+                            hdlc::write_frame(&mut rw, ipv6cp::PROTOCOL_IPV6, &[pkt])
+                                .await
+                                .map_err(RunError::Write)?;
+                            update(quality, |q| q.tx_frames = q.tx_frames.wrapping_add(1));
+                        }
+                    } else {
+                        match ppp.send(pkt, &mut tx_buf) {
+                            Ok(n) => rw.write_all(&tx_buf[..n]).await.map_err(RunError::Write)?,
+                            Err(BufferFullError) => unreachable!(),
+                        }
+                        update(quality, |q| q.tx_frames = q.tx_frames.wrapping_add(1));
                     }
                     tx_chan.tx_done();
                 }
+                Either3::Third(()) => {}
+            }
+
+            // Timers.
+            let now = Instant::now();
+            if let (Some(keepalive), true) = (options.keepalive, lcp_up) {
+                if now >= last_rx + keepalive.timeout {
+                    warn!("PPP: no answer from peer, closing link");
+                    return Err(RunError::KeepaliveTimeout);
+                }
+                if now >= next_echo {
+                    next_echo = now + keepalive.interval;
+                    echo_identifier = echo_identifier.wrapping_add(1);
+                    echo_sent_at = Some(now);
+                    let req = [LCP_ECHO_REQUEST, echo_identifier, 0, 8];
+                    hdlc::write_frame(&mut rw, PROTOCOL_LCP, &[&req, &magic_number.to_be_bytes()])
+                        .await
+                        .map_err(RunError::Write)?;
+                    update(quality, |q| {
+                        q.tx_frames = q.tx_frames.wrapping_add(1);
+                        q.echo_requests = q.echo_requests.wrapping_add(1);
+                    });
+                }
+            }
+            let mut req = [0; 14];
+            if let Some(n) = ipv6cp.poll(now, &mut req) {
+                hdlc::write_frame(&mut rw, ipv6cp::PROTOCOL, &[&req[..n]])
+                    .await
+                    .map_err(RunError::Write)?;
+                update(quality, |q| q.tx_frames = q.tx_frames.wrapping_add(1));
+            }
+
+            let status = ipv6cp.status();
+            if status.is_some() != ipv6_up {
+                ipv6_up = status.is_some();
+                if let Some(status) = status {
+                    on_ipv6_up(status);
+                }
+            }
+
+            state_chan.set_link_state(if ipv4_up || ipv6_up {
+                LinkState::Up
+            } else {
+                LinkState::Down
+            });
+        }
+    }
+
+    /// Like [`run_with_options`](Self::run_with_options), dialing with an AT command modem first.
+    ///
+    /// When the peer terminates the PPP connection or stops answering keepalives, or when dialing
+    /// fails, the modem is hung up and dialed again after the redial delay. Only I/O errors are returned.
+    pub async fn run_with_dialer<RW: BufRead + Write>(
+        &mut self,
+        mut rw: RW,
+        dialer: &Dialer<'_>,
+        config: &ppproto::Config<'_>,
+        options: &Options,
+        mut on_ipv4_up: impl FnMut(Ipv4Status),
+        mut on_ipv6_up: impl FnMut(Ipv6Status),
+    ) -> Result<Infallible, RunError<RW::Error>> {
+        loop {
+            match dialer.dial(&mut rw).await {
+                Ok(()) => {
+                    info!("Modem connected, starting PPP");
+                    let config = ppproto::Config {
+                        username: config.username,
+                        password: config.password,
+                    };
+                    match self
+                        .run_with_options(&mut rw, config, options, &mut on_ipv4_up, &mut on_ipv6_up)
+                        .await
+                    {
+                        Ok(never) => match never {},
+                        Err(RunError::Terminated) => warn!("PPP terminated, dialing again"),
+                        Err(RunError::KeepaliveTimeout) => warn!("PPP peer silent, dialing again"),
+                        Err(e) => return Err(e),
+                    }
+                }
+                Err(ChatError::Read(e)) => return Err(RunError::Read(e)),
+                Err(ChatError::Write(e)) => return Err(RunError::Write(e)),
+                Err(ChatError::Eof) => return Err(RunError::Eof),
+                Err(ChatError::Timeout) => warn!("Dialing timed out"),
+                Err(ChatError::Aborted(i)) => warn!("Dialing aborted by modem response {}", i),
+            }
+
+            match dialer.hangup(&mut rw).await {
+                Ok(()) => {}
+                Err(ChatError::Read(e)) => return Err(RunError::Read(e)),
+                Err(ChatError::Write(e)) => return Err(RunError::Write(e)),
+                Err(_) => return Err(RunError::Eof),
             }
+            Timer::after(dialer.config().redial_delay).await;
         }
     }
 }
 
+/// Get the magic number in an LCP Configure-Ack, which is ours since the peer acks our Configure-Requests.
+fn acked_magic_number(pkt: &[u8]) -> Option<u32> {
+    let [_, _, len_hi, len_lo, ref options @ ..] = *pkt else {
+        return None;
+    };
+    let len = u16::from_be_bytes([len_hi, len_lo]) as usize;
+    let options = options.get(..len.checked_sub(4)?)?;
+    ipv6cp::options(options).find_map(|(kind, value)| match (kind, value) {
+        (LCP_OPTION_MAGIC_NUMBER, &[a, b, c, d]) => Some(u32::from_be_bytes([a, b, c, d])),
+        _ => None,
+    })
+}
+
+fn update(quality: &Mutex<NoopRawMutex, Cell<LinkQuality>>, f: impl FnOnce(&mut LinkQuality)) {
+    quality.lock(|q| {
+        let mut quality = q.get();
+        f(&mut quality);
+        q.set(quality);
+    })
+}
+
 /// Create a PPP embassy-net driver instance.
 ///
 /// This returns two structs:
@@ -154,7 +527,13 @@ impl<'d> Runner<'d> {
 /// - a `Runner`. You must call `.run()` on it in a background task.
 pub fn new<'a, const N_RX: usize, const N_TX: usize>(state: &'a mut State<N_RX, N_TX>) -> (Device<'a>, Runner<'a>) {
     let (runner, device) = ch::new(&mut state.ch_state, ch::driver::HardwareAddress::Ip);
-    (device, Runner { ch: runner })
+    (
+        device,
+        Runner {
+            ch: runner,
+            quality: &state.quality,
+        },
+    )
 }
 
 struct OnDrop<F: FnOnce()> {
@@ -172,3 +551,23 @@ impl<F: FnOnce()> Drop for OnDrop<F> {
         unsafe { self.f.as_ptr().read()() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magic_number_from_configure_ack() {
+        // ACCM, then Magic-Number.
+        let ack = [2, 1, 0, 16, 2, 6, 0, 0, 0, 0, 5, 6, 0x12, 0x34, 0x56, 0x78, 0xee];
+        assert_eq!(acked_magic_number(&ack), Some(0x12345678));
+        // The length field excludes the padding, and options past it.
+        assert_eq!(
+            acked_magic_number(&[2, 1, 0, 10, 2, 6, 0, 0, 0, 0, 5, 6, 1, 2, 3, 4]),
+            None
+        );
+        // Without the option, or with a malformed one.
+        assert_eq!(acked_magic_number(&[2, 1, 0, 4]), None);
+        assert_eq!(acked_magic_number(&[2, 1, 0, 8, 5, 4, 1, 2]), None);
+    }
+}
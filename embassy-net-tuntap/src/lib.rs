@@ -8,15 +8,73 @@ use embassy_net_driver::{self, Capabilities, Driver, HardwareAddress, LinkState}
 use log::*;
 
 pub const SIOCGIFMTU: libc::c_ulong = 0x8921;
+pub const SIOCSIFMTU: libc::c_ulong = 0x8922;
 pub const _SIOCGIFINDEX: libc::c_ulong = 0x8933;
 pub const _ETH_P_ALL: libc::c_short = 0x0003;
 pub const TUNSETIFF: libc::c_ulong = 0x400454CA;
-pub const _IFF_TUN: libc::c_int = 0x0001;
+pub const IFF_TUN: libc::c_int = 0x0001;
 pub const IFF_TAP: libc::c_int = 0x0002;
+pub const IFF_MULTI_QUEUE: libc::c_int = 0x0100;
 pub const IFF_NO_PI: libc::c_int = 0x1000;
 
 const ETHERNET_HEADER_LEN: usize = 14;
 
+/// Hardware address reported for TAP interfaces by default.
+const DEFAULT_HARDWARE_ADDRESS: [u8; 6] = [0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+
+/// Kind of interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// TUN interface, exchanging IP packets. The device uses the IP medium.
+    Tun,
+    /// TAP interface, exchanging Ethernet frames. The device uses the Ethernet medium.
+    Tap,
+}
+
+/// Interface configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// Kind of interface.
+    pub mode: Mode,
+    /// IP MTU to set on the interface. If `None`, the current MTU of the interface is used.
+    ///
+    /// Setting the MTU requires the `CAP_NET_ADMIN` capability.
+    pub mtu: Option<usize>,
+    /// Open the interface with `IFF_MULTI_QUEUE`, so it can be opened several times, such as
+    /// by several devices or processes. The interface must have been created with multi-queue
+    /// support if it already exists.
+    pub multi_queue: bool,
+    /// Hardware address reported by the device, in TAP mode.
+    pub hardware_address: [u8; 6],
+}
+
+impl Config {
+    /// Configuration for a TUN interface.
+    pub fn tun() -> Self {
+        Self {
+            mode: Mode::Tun,
+            ..Self::tap()
+        }
+    }
+
+    /// Configuration for a TAP interface.
+    pub fn tap() -> Self {
+        Self {
+            mode: Mode::Tap,
+            mtu: None,
+            multi_queue: false,
+            hardware_address: DEFAULT_HARDWARE_ADDRESS,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::tap()
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 #[allow(non_camel_case_types)]
@@ -51,6 +109,7 @@ fn ifreq_ioctl(lower: libc::c_int, ifreq: &mut ifreq, cmd: libc::c_ulong) -> io:
 pub struct TunTap {
     fd: libc::c_int,
     mtu: usize,
+    mode: Mode,
 }
 
 impl AsRawFd for TunTap {
@@ -60,7 +119,20 @@ impl AsRawFd for TunTap {
 }
 
 impl TunTap {
+    /// Open the TAP interface with the given name, creating it if it doesn't exist.
     pub fn new(name: &str) -> io::Result<TunTap> {
+        Self::with_config(name, &Config::tap())
+    }
+
+    /// Open the interface with the given name and configuration, creating it if it doesn't exist.
+    ///
+    /// Persistent interfaces, such as ones created with `ip tuntap add`, are opened if the
+    /// configuration matches the way they were created.
+    pub fn with_config(name: &str, config: &Config) -> io::Result<TunTap> {
+        if name.len() >= libc::IF_NAMESIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "interface name too long"));
+        }
+
         unsafe {
             let fd = libc::open(
                 "/dev/net/tun\0".as_ptr() as *const libc::c_char,
@@ -70,8 +142,21 @@ impl TunTap {
                 return Err(io::Error::last_os_error());
             }
 
+            // From here on, `fd` is closed on error by dropping `tuntap`.
+            let mut tuntap = TunTap {
+                fd,
+                mtu: 0,
+                mode: config.mode,
+            };
+
             let mut ifreq = ifreq_for(name);
-            ifreq.ifr_data = IFF_TAP | IFF_NO_PI;
+            ifreq.ifr_data = match config.mode {
+                Mode::Tun => IFF_TUN,
+                Mode::Tap => IFF_TAP,
+            } | IFF_NO_PI;
+            if config.multi_queue {
+                ifreq.ifr_data |= IFF_MULTI_QUEUE;
+            }
             ifreq_ioctl(fd, &mut ifreq, TUNSETIFF)?;
 
             let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, libc::IPPROTO_IP);
@@ -79,17 +164,37 @@ impl TunTap {
                 return Err(io::Error::last_os_error());
             }
 
-            let ip_mtu = ifreq_ioctl(socket, &mut ifreq, SIOCGIFMTU);
+            let ip_mtu = match config.mtu {
+                Some(mtu) => {
+                    let mut ifreq = ifreq_for(name);
+                    ifreq.ifr_data = mtu as libc::c_int;
+                    ifreq_ioctl(socket, &mut ifreq, SIOCSIFMTU).map(|_| mtu)
+                }
+                None => ifreq_ioctl(socket, &mut ifreq_for(name), SIOCGIFMTU).map(|mtu| mtu as usize),
+            };
             libc::close(socket);
-            let ip_mtu = ip_mtu? as usize;
+            let ip_mtu = ip_mtu?;
 
-            // SIOCGIFMTU returns the IP MTU (typically 1500 bytes.)
-            // smoltcp counts the entire Ethernet packet in the MTU, so add the Ethernet header size to it.
-            let mtu = ip_mtu + ETHERNET_HEADER_LEN;
+            // The interface MTU is the IP MTU (typically 1500 bytes.)
+            // In TAP mode, smoltcp counts the entire Ethernet packet in the MTU, so add the Ethernet header size to it.
+            tuntap.mtu = match config.mode {
+                Mode::Tun => ip_mtu,
+                Mode::Tap => ip_mtu + ETHERNET_HEADER_LEN,
+            };
 
-            Ok(TunTap { fd, mtu })
+            Ok(tuntap)
         }
     }
+
+    /// Get the kind of the interface.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Get the MTU of the interface, including the Ethernet header in TAP mode.
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
 }
 
 impl Drop for TunTap {
@@ -128,12 +233,26 @@ impl io::Write for TunTap {
 
 pub struct TunTapDevice {
     device: Async<TunTap>,
+    hardware_address: HardwareAddress,
 }
 
 impl TunTapDevice {
+    /// Open the TAP interface with the given name, creating it if it doesn't exist.
     pub fn new(name: &str) -> io::Result<TunTapDevice> {
+        Self::with_config(name, Config::tap())
+    }
+
+    /// Open the interface with the given name and configuration, creating it if it doesn't exist.
+    ///
+    /// See [`TunTap::with_config`].
+    pub fn with_config(name: &str, config: Config) -> io::Result<TunTapDevice> {
+        let hardware_address = match config.mode {
+            Mode::Tun => HardwareAddress::Ip,
+            Mode::Tap => HardwareAddress::Ethernet(config.hardware_address),
+        };
         Ok(Self {
-            device: Async::new(TunTap::new(name)?)?,
+            device: Async::new(TunTap::with_config(name, &config)?)?,
+            hardware_address,
         })
     }
 }
@@ -182,7 +301,7 @@ impl Driver for TunTapDevice {
     }
 
     fn hardware_address(&self) -> HardwareAddress {
-        HardwareAddress::Ethernet([0x02, 0x03, 0x04, 0x05, 0x06, 0x07])
+        self.hardware_address
     }
 }
 
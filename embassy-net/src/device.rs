@@ -4,6 +4,8 @@ use embassy_net_driver::{Capabilities, Checksum, Driver, RxToken, TxToken};
 use smoltcp::phy::{self, Medium};
 use smoltcp::time::Instant;
 
+use crate::stats::{self, Counters};
+
 pub(crate) struct DriverAdapter<'d, 'c, T>
 where
     T: Driver,
@@ -12,36 +14,55 @@ where
     pub cx: Option<&'d mut Context<'c>>,
     pub inner: &'d mut T,
     pub medium: Medium,
+    pub stats: &'d Counters,
 }
 
 impl<'d, 'c, T> phy::Device for DriverAdapter<'d, 'c, T>
 where
     T: Driver,
 {
-    type RxToken<'a> = RxTokenAdapter<T::RxToken<'a>> where Self: 'a;
-    type TxToken<'a> = TxTokenAdapter<T::TxToken<'a>> where Self: 'a;
+    type RxToken<'a> = RxTokenAdapter<'a, T::RxToken<'a>> where Self: 'a;
+    type TxToken<'a> = TxTokenAdapter<'a, T::TxToken<'a>> where Self: 'a;
 
     fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
-        self.inner
-            .receive(unwrap!(self.cx.as_deref_mut()))
-            .map(|(rx, tx)| (RxTokenAdapter(rx), TxTokenAdapter(tx)))
+        let stats = self.stats;
+        let medium = self.medium;
+        let verify_ipv4 = stats::verifies_ipv4(medium) && convert(self.inner.capabilities().checksum.ipv4).rx();
+        let cx = unwrap!(self.cx.as_deref_mut());
+        let (rx, tx) = self.inner.receive(cx)?;
+        if rx.meta().vlan_tci.is_some_and(|tci| tci & 0x0fff != 0) {
+            // The hardware stripped a VLAN tag, this frame isn't for the untagged interface. Drop it, and
+            // get polled again for the next one. Priority-tagged frames (VLAN ID 0) are kept.
+            rx.consume(|_| ());
+            cx.waker().wake_by_ref();
+            return None;
+        }
+        Some((
+            RxTokenAdapter {
+                inner: rx,
+                medium,
+                verify_ipv4,
+                stats,
+            },
+            TxTokenAdapter {
+                inner: tx,
+                medium,
+                stats,
+            },
+        ))
     }
 
     /// Construct a transmit token.
     fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
-        self.inner.transmit(unwrap!(self.cx.as_deref_mut())).map(TxTokenAdapter)
+        let stats = self.stats;
+        let medium = self.medium;
+        self.inner
+            .transmit(unwrap!(self.cx.as_deref_mut()))
+            .map(|inner| TxTokenAdapter { inner, medium, stats })
     }
 
     /// Get a description of device capabilities.
     fn capabilities(&self) -> phy::DeviceCapabilities {
-        fn convert(c: Checksum) -> phy::Checksum {
-            match c {
-                Checksum::Both => phy::Checksum::Both,
-                Checksum::Tx => phy::Checksum::Tx,
-                Checksum::Rx => phy::Checksum::Rx,
-                Checksum::None => phy::Checksum::None,
-            }
-        }
         let caps: Capabilities = self.inner.capabilities();
         let mut smolcaps = phy::DeviceCapabilities::default();
 
@@ -49,6 +70,14 @@ where
         smolcaps.max_burst_size = caps.max_burst_size;
         smolcaps.medium = self.medium;
         smolcaps.checksum.ipv4 = convert(caps.checksum.ipv4);
+        if stats::verifies_ipv4(self.medium) {
+            // The receive tokens verify the IPv4 header checksum, to count the invalid ones.
+            smolcaps.checksum.ipv4 = match smolcaps.checksum.ipv4 {
+                phy::Checksum::Both => phy::Checksum::Tx,
+                phy::Checksum::Rx => phy::Checksum::None,
+                c => c,
+            };
+        }
         smolcaps.checksum.tcp = convert(caps.checksum.tcp);
         smolcaps.checksum.udp = convert(caps.checksum.udp);
         #[cfg(feature = "proto-ipv4")]
@@ -64,11 +93,26 @@ where
     }
 }
 
-pub(crate) struct RxTokenAdapter<T>(T)
+fn convert(c: Checksum) -> phy::Checksum {
+    match c {
+        Checksum::Both => phy::Checksum::Both,
+        Checksum::Tx => phy::Checksum::Tx,
+        Checksum::Rx => phy::Checksum::Rx,
+        Checksum::None => phy::Checksum::None,
+    }
+}
+
+pub(crate) struct RxTokenAdapter<'d, T>
 where
-    T: RxToken;
+    T: RxToken,
+{
+    inner: T,
+    medium: Medium,
+    verify_ipv4: bool,
+    stats: &'d Counters,
+}
 
-impl<T> phy::RxToken for RxTokenAdapter<T>
+impl<'d, T> phy::RxToken for RxTokenAdapter<'d, T>
 where
     T: RxToken,
 {
@@ -76,15 +120,27 @@ where
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        self.0.consume(|buf| f(buf))
+        self.inner.consume(|buf| {
+            if self.stats.rx(self.medium, self.verify_ipv4, buf) {
+                f(buf)
+            } else {
+                // smoltcp drops empty frames.
+                f(&mut [])
+            }
+        })
     }
 }
 
-pub(crate) struct TxTokenAdapter<T>(T)
+pub(crate) struct TxTokenAdapter<'d, T>
 where
-    T: TxToken;
+    T: TxToken,
+{
+    inner: T,
+    medium: Medium,
+    stats: &'d Counters,
+}
 
-impl<T> phy::TxToken for TxTokenAdapter<T>
+impl<'d, T> phy::TxToken for TxTokenAdapter<'d, T>
 where
     T: TxToken,
 {
@@ -92,6 +148,10 @@ where
     where
         F: FnOnce(&mut [u8]) -> R,
     {
-        self.0.consume(len, |buf| f(buf))
+        self.inner.consume(len, |buf| {
+            let r = f(buf);
+            self.stats.tx(self.medium, buf);
+            r
+        })
     }
 }
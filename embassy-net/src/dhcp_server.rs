@@ -0,0 +1,662 @@
+//! DHCPv4 server.
+//!
+//! [`DhcpServer`] hands out addresses from a pool to the clients on the link, for devices
+//! that are the only host on their network: a WiFi access point, or a USB network gadget.
+//!
+//! The stack the server runs on must have a static IPv4 configuration: its address is used
+//! as the server identifier, and its netmask is given to the clients.
+//!
+//! ## Example
+//! ```ignore
+//! let mut config = dhcp_server::Config::new(Ipv4Address::new(192, 168, 4, 10), 16);
+//! config.router = Some(Ipv4Address::new(192, 168, 4, 1));
+//! let mut leases = [None; 16];
+//! let server = DhcpServer::new(stack, config, &mut leases, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+//! server.run().await;
+//! ```
+
+use core::cell::RefCell;
+
+use embassy_net_driver::Driver;
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use smoltcp::wire::{DhcpMessageType, DhcpPacket, DhcpRepr, DHCP_MAX_DNS_SERVER_COUNT};
+
+use crate::udp::{PacketMetadata, UdpSocket};
+use crate::{EthernetAddress, IpEndpoint, Ipv4Address, Stack};
+
+/// Maximum length of the DHCP messages we receive and send (RFC 2131 section 2).
+const MAX_MESSAGE_LEN: usize = 576;
+
+/// How long an offered address is reserved for the client it was offered to.
+const OFFER_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long an address declined by a client isn't handed out.
+const DECLINE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// DHCP server configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Config {
+    /// First address of the pool of addresses handed out to clients.
+    pub pool_start: Ipv4Address,
+    /// Number of addresses in the pool.
+    pub pool_size: u32,
+    /// Lease duration.
+    pub lease_duration: Duration,
+    /// Default gateway given to the clients.
+    pub router: Option<Ipv4Address>,
+    /// DNS servers given to the clients.
+    pub dns_servers: Vec<Ipv4Address, DHCP_MAX_DNS_SERVER_COUNT>,
+    /// Server port. This is almost always 67. Do not change unless you know what you're doing.
+    pub server_port: u16,
+    /// Client port. This is almost always 68. Do not change unless you know what you're doing.
+    pub client_port: u16,
+}
+
+impl Config {
+    /// Create a new configuration handing out `pool_size` addresses starting at `pool_start`,
+    /// with a lease duration of one hour, and no router or DNS servers.
+    pub fn new(pool_start: Ipv4Address, pool_size: u32) -> Self {
+        Self {
+            pool_start,
+            pool_size,
+            lease_duration: Duration::from_secs(60 * 60),
+            router: None,
+            dns_servers: Vec::new(),
+            server_port: 67,
+            client_port: 68,
+        }
+    }
+
+    fn contains(&self, addr: Ipv4Address) -> bool {
+        let offset = u32::from_be_bytes(addr.0).wrapping_sub(u32::from_be_bytes(self.pool_start.0));
+        offset < self.pool_size
+    }
+
+    fn addresses(&self) -> impl Iterator<Item = Ipv4Address> {
+        let start = u32::from_be_bytes(self.pool_start.0);
+        (0..self.pool_size).map(move |i| Ipv4Address(start.wrapping_add(i).to_be_bytes()))
+    }
+}
+
+/// An address lease.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Lease {
+    /// Hardware address of the client.
+    pub hardware_address: EthernetAddress,
+    /// Address leased to the client.
+    pub address: Ipv4Address,
+    /// When the lease expires.
+    pub expires: Instant,
+    /// Whether the client has accepted the address. Addresses that are only offered
+    /// are released after a short time.
+    pub bound: bool,
+    /// Whether the client declined the address because another host uses it. The address
+    /// isn't handed out to any client until the entry expires.
+    pub declined: bool,
+}
+
+/// A DHCPv4 server.
+pub struct DhcpServer<'a, D: Driver> {
+    stack: &'a Stack<D>,
+    socket: UdpSocket<'a>,
+    config: Config,
+    leases: RefCell<&'a mut [Option<Lease>]>,
+}
+
+impl<'a, D: Driver> DhcpServer<'a, D> {
+    /// Create a new DHCP server using the provided stack and buffers.
+    ///
+    /// The lease table is kept in `leases`, so its length is the maximum number of clients
+    /// served at the same time.
+    pub fn new(
+        stack: &'a Stack<D>,
+        config: Config,
+        leases: &'a mut [Option<Lease>],
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+        unwrap!(socket.bind(config.server_port));
+
+        Self {
+            stack,
+            socket,
+            config,
+            leases: RefCell::new(leases),
+        }
+    }
+
+    /// Call `f` for each lease in the lease table, including the expired ones that
+    /// haven't been reused yet.
+    pub fn for_each_lease(&self, f: impl FnMut(&Lease)) {
+        self.leases.borrow().iter().flatten().for_each(f);
+    }
+
+    /// Get the lease of the client with the given hardware address, if it's bound and not expired.
+    pub fn lease(&self, hardware_address: EthernetAddress) -> Option<Lease> {
+        let now = Instant::now();
+        self.leases
+            .borrow()
+            .iter()
+            .flatten()
+            .find(|l| l.hardware_address == hardware_address && l.bound && l.expires > now)
+            .copied()
+    }
+
+    /// Run the server.
+    ///
+    /// This answers the requests of DHCP clients forever.
+    pub async fn run(&self) -> ! {
+        let mut rx = [0; MAX_MESSAGE_LEN];
+        let mut tx = [0; MAX_MESSAGE_LEN];
+        loop {
+            let n = match self.socket.recv_from(&mut rx).await {
+                Ok((n, _)) => n,
+                Err(_) => continue,
+            };
+
+            let Some((len, to)) = self.handle(&rx[..n], &mut tx) else {
+                continue;
+            };
+            if let Err(e) = self.socket.send_to(&tx[..len], to).await {
+                warn!("DHCP server: failed to send reply: {:?}", e);
+            }
+        }
+    }
+
+    /// Handle a request, writing the reply to `tx` if there's one.
+    ///
+    /// Returns the length of the reply and where to send it.
+    fn handle(&self, rx: &[u8], tx: &mut [u8]) -> Option<(usize, IpEndpoint)> {
+        let packet = DhcpPacket::new_checked(rx).ok()?;
+        let request = DhcpRepr::parse(&packet).ok()?;
+
+        let Some(stack_config) = self.stack.config_v4() else {
+            debug!("DHCP server: ignoring request, the stack has no IPv4 configuration");
+            return None;
+        };
+        let server_addr = stack_config.address.address();
+        let hardware_address = request.client_hardware_address;
+        let now = Instant::now();
+
+        let (message_type, your_ip) = match request.message_type {
+            DhcpMessageType::Discover => {
+                let addr = self.offer(hardware_address, request.requested_ip, server_addr, now)?;
+                debug!("DHCP server: offering {} to {}", addr, hardware_address);
+                (DhcpMessageType::Offer, addr)
+            }
+            DhcpMessageType::Request => {
+                if request.server_identifier.is_some_and(|id| id != server_addr) {
+                    // The client chose another server.
+                    self.release(hardware_address, None);
+                    return None;
+                }
+                let addr = request
+                    .requested_ip
+                    .or(Some(request.client_ip).filter(|a| !a.is_unspecified()))?;
+                if self.bind(hardware_address, addr, server_addr, now) {
+                    debug!("DHCP server: leased {} to {}", addr, hardware_address);
+                    (DhcpMessageType::Ack, addr)
+                } else {
+                    debug!("DHCP server: refusing {} to {}", addr, hardware_address);
+                    (DhcpMessageType::Nak, Ipv4Address::UNSPECIFIED)
+                }
+            }
+            DhcpMessageType::Decline => {
+                // The client found out the address is used by another host (RFC 2131 section 4.3.3).
+                let addr = request.requested_ip?;
+                warn!("DHCP server: {} declined {}, it's in use", hardware_address, addr);
+                self.decline(hardware_address, addr, now);
+                return None;
+            }
+            DhcpMessageType::Release => {
+                self.release(
+                    hardware_address,
+                    Some(request.client_ip).filter(|a| !a.is_unspecified()),
+                );
+                return None;
+            }
+            DhcpMessageType::Inform => (DhcpMessageType::Ack, Ipv4Address::UNSPECIFIED),
+            _ => return None,
+        };
+
+        // NAKs carry no configuration, and INFORM replies no lease (RFC 2131 table 3).
+        let ack = message_type == DhcpMessageType::Ack;
+        let nak = message_type == DhcpMessageType::Nak;
+        let inform = request.message_type == DhcpMessageType::Inform;
+        let reply = DhcpRepr {
+            message_type,
+            transaction_id: request.transaction_id,
+            secs: 0,
+            client_hardware_address: hardware_address,
+            client_ip: if ack {
+                request.client_ip
+            } else {
+                Ipv4Address::UNSPECIFIED
+            },
+            your_ip,
+            server_ip: Ipv4Address::UNSPECIFIED,
+            router: self.config.router.filter(|_| !nak),
+            subnet_mask: (!nak).then(|| stack_config.address.netmask()),
+            relay_agent_ip: request.relay_agent_ip,
+            broadcast: request.broadcast,
+            requested_ip: None,
+            client_identifier: None,
+            server_identifier: Some(server_addr),
+            parameter_request_list: None,
+            dns_servers: (!nak && !self.config.dns_servers.is_empty()).then(|| self.config.dns_servers.clone()),
+            max_size: None,
+            lease_duration: (!nak && !inform).then(|| self.config.lease_duration.as_secs() as u32),
+            renew_duration: None,
+            rebind_duration: None,
+            additional_options: &[],
+        };
+
+        let len = reply.buffer_len();
+        let mut packet = DhcpPacket::new_unchecked(tx.get_mut(..len)?);
+        reply.emit(&mut packet).ok()?;
+
+        // Replies go to the relay agent the request came through, if any. Clients that have an
+        // address (renewing, rebinding or INFORM) get a unicast reply. The others can't answer ARP
+        // requests for their address yet, so the reply is broadcast, as are NAKs (RFC 2131 section 4.1).
+        let to = if !request.relay_agent_ip.is_unspecified() {
+            IpEndpoint::new(request.relay_agent_ip.into(), self.config.server_port)
+        } else if !nak && !request.client_ip.is_unspecified() {
+            IpEndpoint::new(request.client_ip.into(), self.config.client_port)
+        } else {
+            IpEndpoint::new(Ipv4Address::BROADCAST.into(), self.config.client_port)
+        };
+        Some((len, to))
+    }
+
+    /// Pick an address to offer to a client, and reserve it.
+    fn offer(
+        &self,
+        hardware_address: EthernetAddress,
+        requested: Option<Ipv4Address>,
+        server_addr: Ipv4Address,
+        now: Instant,
+    ) -> Option<Ipv4Address> {
+        let mut leases = self.leases.borrow_mut();
+
+        // Offer the client its previous address if it's still available.
+        let previous = leases
+            .iter()
+            .flatten()
+            .find(|l| l.hardware_address == hardware_address && !l.declined)
+            .map(|l| l.address);
+        if let Some(addr) = previous.filter(|&a| self.is_available(&leases, a, hardware_address, server_addr, now)) {
+            let lease = unwrap!(leases
+                .iter_mut()
+                .flatten()
+                .find(|l| l.hardware_address == hardware_address && !l.declined));
+            if !lease.bound || lease.expires <= now {
+                lease.bound = false;
+                lease.expires = now + OFFER_TIMEOUT;
+            }
+            return Some(addr);
+        }
+
+        let addr = requested
+            .filter(|&a| self.is_available(&leases, a, hardware_address, server_addr, now))
+            .or_else(|| {
+                self.config
+                    .addresses()
+                    .find(|&a| self.is_available(&leases, a, hardware_address, server_addr, now))
+            });
+        let Some(addr) = addr else {
+            warn!("DHCP server: address pool exhausted");
+            return None;
+        };
+
+        let lease = Lease {
+            hardware_address,
+            address: addr,
+            expires: now + OFFER_TIMEOUT,
+            bound: false,
+            declined: false,
+        };
+        if !insert(&mut leases, lease, now) {
+            warn!("DHCP server: lease table full");
+            return None;
+        }
+        Some(addr)
+    }
+
+    /// Lease an address to a client, if it's available.
+    fn bind(
+        &self,
+        hardware_address: EthernetAddress,
+        addr: Ipv4Address,
+        server_addr: Ipv4Address,
+        now: Instant,
+    ) -> bool {
+        let mut leases = self.leases.borrow_mut();
+        if !self.is_available(&leases, addr, hardware_address, server_addr, now) {
+            return false;
+        }
+
+        let lease = Lease {
+            hardware_address,
+            address: addr,
+            expires: now + self.config.lease_duration,
+            bound: true,
+            declined: false,
+        };
+        insert(&mut leases, lease, now)
+    }
+
+    /// Mark an address declined by a client as unavailable, replacing the client's lease of it.
+    fn decline(&self, hardware_address: EthernetAddress, addr: Ipv4Address, now: Instant) {
+        if !self.config.contains(addr) {
+            return;
+        }
+
+        let mut leases = self.leases.borrow_mut();
+        let index = leases
+            .iter()
+            .position(|e| e.is_some_and(|l| l.hardware_address == hardware_address && l.address == addr))
+            .or_else(|| free_entry(&leases, now));
+        match index {
+            Some(i) => {
+                leases[i] = Some(Lease {
+                    hardware_address,
+                    address: addr,
+                    expires: now + DECLINE_TIMEOUT,
+                    bound: false,
+                    declined: true,
+                })
+            }
+            None => warn!("DHCP server: lease table full"),
+        }
+    }
+
+    /// Release the lease of a client.
+    fn release(&self, hardware_address: EthernetAddress, addr: Option<Ipv4Address>) {
+        for entry in self.leases.borrow_mut().iter_mut() {
+            if entry.is_some_and(|l| {
+                l.hardware_address == hardware_address && !l.declined && (addr.is_none() || addr == Some(l.address))
+            }) {
+                *entry = None;
+            }
+        }
+    }
+
+    /// Whether `addr` is in the pool, and isn't leased or offered to another client, or declined.
+    fn is_available(
+        &self,
+        leases: &[Option<Lease>],
+        addr: Ipv4Address,
+        hardware_address: EthernetAddress,
+        server_addr: Ipv4Address,
+        now: Instant,
+    ) -> bool {
+        self.config.contains(addr)
+            && addr != server_addr
+            && !leases
+                .iter()
+                .flatten()
+                .any(|l| l.address == addr && (l.hardware_address != hardware_address || l.declined) && l.expires > now)
+    }
+}
+
+/// Insert a lease, replacing the client's previous one. If the client has no lease yet, an empty
+/// or expired entry is used.
+///
+/// Returns false if the table is full.
+fn insert(leases: &mut [Option<Lease>], lease: Lease, now: Instant) -> bool {
+    let index = leases
+        .iter()
+        .position(|e| e.is_some_and(|l| l.hardware_address == lease.hardware_address && !l.declined))
+        .or_else(|| free_entry(leases, now));
+
+    match index {
+        Some(i) => {
+            leases[i] = Some(lease);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Find an empty entry, or else the entry that expired first.
+fn free_entry(leases: &[Option<Lease>], now: Instant) -> Option<usize> {
+    leases.iter().position(|e| e.is_none()).or_else(|| {
+        leases
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| e.filter(|l| l.expires <= now).map(|l| (i, l.expires)))
+            .min_by_key(|(_, expires)| *expires)
+            .map(|(i, _)| i)
+    })
+}
+
+#[cfg(all(test, feature = "medium-ip"))]
+mod tests {
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::loopback::{self, Loopback};
+
+    const TRANSACTION_ID: u32 = 0x1234;
+    const RELAY: Ipv4Address = Ipv4Address([10, 0, 0, 1]);
+
+    fn mac(n: u8) -> EthernetAddress {
+        EthernetAddress([2, 0, 0, 0, 0, n])
+    }
+
+    fn addr(n: u8) -> Ipv4Address {
+        Ipv4Address([192, 168, 0, n])
+    }
+
+    /// A server handing out 192.168.0.10 and 192.168.0.11, on a stack with the address 192.168.0.1.
+    fn server(stack: &Stack<Loopback>, leases: usize) -> DhcpServer<'_, Loopback> {
+        DhcpServer::new(
+            stack,
+            Config::new(addr(10), 2),
+            Box::leak(std::vec![None; leases].into_boxed_slice()),
+            Box::leak(Box::new([PacketMetadata::EMPTY; 1])),
+            Box::leak(Box::new([0; MAX_MESSAGE_LEN])),
+            Box::leak(Box::new([PacketMetadata::EMPTY; 1])),
+            Box::leak(Box::new([0; MAX_MESSAGE_LEN])),
+        )
+    }
+
+    struct Message {
+        message_type: DhcpMessageType,
+        client: u8,
+        requested_ip: Option<Ipv4Address>,
+        client_ip: Ipv4Address,
+        relay_agent_ip: Ipv4Address,
+    }
+
+    impl Message {
+        fn new(message_type: DhcpMessageType, client: u8) -> Self {
+            Self {
+                message_type,
+                client,
+                requested_ip: None,
+                client_ip: Ipv4Address::UNSPECIFIED,
+                relay_agent_ip: Ipv4Address::UNSPECIFIED,
+            }
+        }
+
+        fn requested_ip(self, requested_ip: Ipv4Address) -> Self {
+            Self {
+                requested_ip: Some(requested_ip),
+                ..self
+            }
+        }
+
+        fn client_ip(self, client_ip: Ipv4Address) -> Self {
+            Self { client_ip, ..self }
+        }
+
+        fn relay_agent_ip(self, relay_agent_ip: Ipv4Address) -> Self {
+            Self { relay_agent_ip, ..self }
+        }
+
+        /// Have `server` handle the message, and get its reply and where it's sent.
+        fn send(&self, server: &DhcpServer<'_, Loopback>) -> Option<(DhcpMessageType, Ipv4Address, IpEndpoint)> {
+            let request = DhcpRepr {
+                message_type: self.message_type,
+                transaction_id: TRANSACTION_ID,
+                secs: 0,
+                client_hardware_address: mac(self.client),
+                client_ip: self.client_ip,
+                your_ip: Ipv4Address::UNSPECIFIED,
+                server_ip: Ipv4Address::UNSPECIFIED,
+                router: None,
+                subnet_mask: None,
+                relay_agent_ip: self.relay_agent_ip,
+                broadcast: false,
+                requested_ip: self.requested_ip,
+                client_identifier: None,
+                server_identifier: None,
+                parameter_request_list: None,
+                dns_servers: None,
+                max_size: None,
+                lease_duration: None,
+                renew_duration: None,
+                rebind_duration: None,
+                additional_options: &[],
+            };
+            let mut rx: Vec<u8> = std::vec![0; request.buffer_len()];
+            request.emit(&mut DhcpPacket::new_unchecked(&mut rx[..])).unwrap();
+
+            let mut tx = [0; MAX_MESSAGE_LEN];
+            let (len, to) = server.handle(&rx, &mut tx)?;
+            let packet = DhcpPacket::new_checked(&tx[..len]).unwrap();
+            let reply = DhcpRepr::parse(&packet).unwrap();
+            assert_eq!(reply.transaction_id, TRANSACTION_ID);
+            assert_eq!(reply.client_hardware_address, mac(self.client));
+            Some((reply.message_type, reply.your_ip, to))
+        }
+    }
+
+    fn broadcast() -> IpEndpoint {
+        IpEndpoint::new(Ipv4Address::BROADCAST.into(), 68)
+    }
+
+    /// Get an address leased to `client`.
+    fn lease(server: &DhcpServer<'_, Loopback>, client: u8) -> Ipv4Address {
+        let (message_type, offered, _) = Message::new(DhcpMessageType::Discover, client).send(server).unwrap();
+        assert_eq!(message_type, DhcpMessageType::Offer);
+        let (message_type, leased, _) = Message::new(DhcpMessageType::Request, client)
+            .requested_ip(offered)
+            .send(server)
+            .unwrap();
+        assert_eq!(message_type, DhcpMessageType::Ack);
+        assert_eq!(leased, offered);
+        leased
+    }
+
+    #[test]
+    fn pool_exhausted() {
+        let stacks = loopback::stacks::<2>();
+        let server = server(&stacks.0, 4);
+
+        assert_eq!(lease(&server, 1), addr(10));
+        // An offered address is reserved too.
+        let (_, offered, _) = Message::new(DhcpMessageType::Discover, 2).send(&server).unwrap();
+        assert_eq!(offered, addr(11));
+
+        assert_eq!(Message::new(DhcpMessageType::Discover, 3).send(&server), None);
+        let (message_type, _, _) = Message::new(DhcpMessageType::Request, 3)
+            .requested_ip(addr(10))
+            .send(&server)
+            .unwrap();
+        assert_eq!(message_type, DhcpMessageType::Nak);
+
+        // A released address is handed out again.
+        assert_eq!(
+            Message::new(DhcpMessageType::Release, 1)
+                .client_ip(addr(10))
+                .send(&server),
+            None
+        );
+        assert_eq!(lease(&server, 3), addr(10));
+        assert_eq!(server.lease(mac(3)).map(|l| l.address), Some(addr(10)));
+        assert_eq!(server.lease(mac(1)), None);
+    }
+
+    #[test]
+    fn lease_table_full() {
+        let stacks = loopback::stacks::<2>();
+        let server = server(&stacks.0, 1);
+
+        assert_eq!(lease(&server, 1), addr(10));
+        assert_eq!(Message::new(DhcpMessageType::Discover, 2).send(&server), None);
+    }
+
+    #[test]
+    fn declined_address_unavailable() {
+        let stacks = loopback::stacks::<2>();
+        let server = server(&stacks.0, 4);
+
+        assert_eq!(lease(&server, 1), addr(10));
+        assert_eq!(
+            Message::new(DhcpMessageType::Decline, 1)
+                .requested_ip(addr(10))
+                .send(&server),
+            None
+        );
+        assert_eq!(server.lease(mac(1)), None);
+
+        // Neither the client that declined it nor another one get the address.
+        assert_eq!(lease(&server, 1), addr(11));
+        let (message_type, _, _) = Message::new(DhcpMessageType::Request, 2)
+            .requested_ip(addr(10))
+            .send(&server)
+            .unwrap();
+        assert_eq!(message_type, DhcpMessageType::Nak);
+        assert_eq!(Message::new(DhcpMessageType::Discover, 2).send(&server), None);
+
+        // Releasing its lease doesn't make the declined address available again.
+        Message::new(DhcpMessageType::Release, 1).send(&server);
+        assert_eq!(lease(&server, 2), addr(11));
+    }
+
+    #[test]
+    fn reply_destination() {
+        let stacks = loopback::stacks::<2>();
+        let server = server(&stacks.0, 4);
+
+        // Clients without an address get broadcast replies.
+        let (_, offered, to) = Message::new(DhcpMessageType::Discover, 1).send(&server).unwrap();
+        assert_eq!(to, broadcast());
+        let (_, _, to) = Message::new(DhcpMessageType::Request, 1)
+            .requested_ip(offered)
+            .send(&server)
+            .unwrap();
+        assert_eq!(to, broadcast());
+
+        // Renewing clients get unicast replies.
+        let (message_type, _, to) = Message::new(DhcpMessageType::Request, 1)
+            .client_ip(offered)
+            .send(&server)
+            .unwrap();
+        assert_eq!(message_type, DhcpMessageType::Ack);
+        assert_eq!(to, IpEndpoint::new(offered.into(), 68));
+
+        // NAKs are broadcast, even to a client with an address.
+        let (message_type, _, to) = Message::new(DhcpMessageType::Request, 2)
+            .client_ip(offered)
+            .send(&server)
+            .unwrap();
+        assert_eq!(message_type, DhcpMessageType::Nak);
+        assert_eq!(to, broadcast());
+
+        // Replies to relayed requests go to the relay agent.
+        let (_, _, to) = Message::new(DhcpMessageType::Discover, 3)
+            .relay_agent_ip(RELAY)
+            .send(&server)
+            .unwrap();
+        assert_eq!(to, IpEndpoint::new(RELAY.into(), 67));
+    }
+}
@@ -4,13 +4,61 @@
 //! Prefer using [`Stack::dns_query`](crate::Stack::dns_query) directly if you're
 //! not using `embedded-nal-async`.
 
+#[cfg(all(feature = "udp", feature = "proto-ipv4"))]
+use core::fmt::Write as _;
+
+#[cfg(feature = "dns-cache")]
+use embassy_time::Instant;
+#[cfg(feature = "udp")]
+use embassy_time::{with_timeout, Duration};
 use heapless::Vec;
 pub use smoltcp::socket::dns::{DnsQuery, Socket};
 pub(crate) use smoltcp::socket::dns::{GetQueryResultError, StartQueryError};
 pub use smoltcp::wire::{DnsQueryType, IpAddress};
 
+#[cfg(feature = "udp")]
+use crate::udp::{PacketMetadata, UdpSocket};
+#[cfg(feature = "udp")]
+use crate::IpEndpoint;
 use crate::{Driver, Stack};
 
+#[cfg(feature = "udp")]
+const DNS_PORT: u16 = 53;
+#[cfg(feature = "udp")]
+const HEADER_LEN: usize = 12;
+#[cfg(feature = "udp")]
+const TYPE_CNAME: u16 = 5;
+#[cfg(feature = "udp")]
+const TYPE_SOA: u16 = 6;
+#[cfg(feature = "udp")]
+const TYPE_PTR: u16 = 12;
+#[cfg(feature = "udp")]
+const CLASS_IN: u16 = 1;
+#[cfg(feature = "udp")]
+const FLAG_RESPONSE: u16 = 0x8000;
+#[cfg(feature = "udp")]
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+#[cfg(feature = "udp")]
+const RCODE_MASK: u16 = 0x000f;
+#[cfg(feature = "udp")]
+const RCODE_NO_ERROR: u16 = 0;
+#[cfg(feature = "udp")]
+const RCODE_NXDOMAIN: u16 = 3;
+/// Maximum length of the wire format of a name.
+#[cfg(feature = "udp")]
+const MAX_NAME_LEN: usize = 255;
+/// Maximum length of the wire format of a reverse lookup name, for IPv6.
+#[cfg(feature = "udp")]
+const MAX_PTR_NAME_LEN: usize = 74;
+#[cfg(feature = "udp")]
+const MAX_RESPONSE_LEN: usize = 512;
+/// How long to wait for a response from each DNS server.
+#[cfg(feature = "udp")]
+const QUERY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Maximum number of compression pointers followed in a name, to bail out of loops.
+#[cfg(feature = "udp")]
+const MAX_POINTERS: usize = 16;
+
 /// Errors returned by DnsSocket.
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -103,9 +151,713 @@ where
 
     async fn get_host_by_address(
         &self,
-        _addr: embedded_nal_async::IpAddr,
-        _result: &mut [u8],
+        addr: embedded_nal_async::IpAddr,
+        result: &mut [u8],
     ) -> Result<usize, Self::Error> {
-        todo!()
+        use embedded_nal_async::IpAddr;
+        let addr = match addr {
+            #[cfg(feature = "proto-ipv4")]
+            IpAddr::V4(addr) => IpAddress::Ipv4(crate::Ipv4Address(addr.octets())),
+            #[cfg(feature = "proto-ipv6")]
+            IpAddr::V6(addr) => IpAddress::Ipv6(crate::Ipv6Address(addr.octets())),
+            #[allow(unreachable_patterns)]
+            _ => return Err(Error::Failed),
+        };
+        #[cfg(feature = "udp")]
+        return self.query_ptr(addr, result).await;
+        // Reverse lookups are sent over a UDP socket.
+        #[cfg(not(feature = "udp"))]
+        {
+            let _ = (addr, result);
+            Err(Error::Failed)
+        }
+    }
+}
+
+#[cfg(feature = "udp")]
+impl<'a, D> DnsSocket<'a, D>
+where
+    D: Driver + 'static,
+{
+    /// Look up the host name of `addr`, with a reverse (PTR) query.
+    ///
+    /// The name is written to `result` without a trailing dot, and its length is returned.
+    ///
+    /// The query is sent over a UDP socket created for it, so a socket must be free in the
+    /// [`StackResources`](crate::StackResources). If none is, [`Error::Failed`] is returned.
+    pub async fn query_ptr(&self, addr: IpAddress, result: &mut [u8]) -> Result<usize, Error> {
+        let mut question = [0; MAX_PTR_NAME_LEN + 4];
+        let name_len = encode_reverse_name(addr, &mut question);
+        let question = finish_question(&mut question, name_len, TYPE_PTR);
+
+        let mut response = [0; MAX_RESPONSE_LEN];
+        let n = udp_query(self.stack, question, &mut response).await?;
+        let msg = &response[..n];
+
+        let mut len = None;
+        let response = parse_response(msg, TYPE_PTR, |record| {
+            if len.is_none() {
+                len = Some(decode_name(msg, record.data, result)?);
+            }
+            Ok(())
+        })?;
+        match response {
+            Response::Answer { .. } => len.ok_or(Error::Failed),
+            Response::NxDomain { .. } => Err(Error::Failed),
+        }
+    }
+}
+
+/// Look up the addresses of `name` with a query over a UDP socket, to know how long the answer can be cached.
+///
+/// Returns the answer along with the number of seconds it can be cached for, or `None` if the query can't be made
+/// this way because no socket is free or `qtype` isn't an address type.
+#[cfg(feature = "dns-cache")]
+pub(crate) async fn query_addrs<D: Driver>(
+    stack: &Stack<D>,
+    name: &str,
+    qtype: DnsQueryType,
+) -> Option<(
+    Result<Vec<IpAddress, { smoltcp::config::DNS_MAX_RESULT_COUNT }>, Error>,
+    Option<u32>,
+)> {
+    let addr_len = match qtype {
+        #[cfg(feature = "proto-ipv4")]
+        DnsQueryType::A => 4,
+        #[cfg(feature = "proto-ipv6")]
+        DnsQueryType::Aaaa => 16,
+        _ => return None,
+    };
+    if !stack.socket.borrow().has_free_sockets(1) {
+        return None;
+    }
+
+    let mut question = [0; MAX_NAME_LEN + 4];
+    let name_len = match encode_name(name, &mut question[..MAX_NAME_LEN]) {
+        Ok(len) => len,
+        Err(e) => return Some((Err(e), None)),
+    };
+    let question = finish_question(&mut question, name_len, qtype.into());
+
+    let mut response = [0; MAX_RESPONSE_LEN];
+    let n = match udp_query(stack, question, &mut response).await {
+        Ok(n) => n,
+        Err(e) => return Some((Err(e), None)),
+    };
+    let msg = &response[..n];
+
+    let mut addrs = Vec::new();
+    let response = parse_response(msg, qtype.into(), |record| {
+        let data = &msg[record.data..record.end()];
+        if data.len() != addr_len {
+            return Err(Error::Failed);
+        }
+        let addr = match data.len() {
+            #[cfg(feature = "proto-ipv4")]
+            4 => IpAddress::Ipv4(crate::Ipv4Address::from_bytes(data)),
+            #[cfg(feature = "proto-ipv6")]
+            16 => IpAddress::Ipv6(crate::Ipv6Address::from_bytes(data)),
+            _ => unreachable!(),
+        };
+        // Like with the DNS socket, extra addresses are dropped.
+        let _ = addrs.push(addr);
+        Ok(())
+    });
+    Some(match response {
+        Ok(Response::Answer { ttl }) => (Ok(addrs), Some(ttl)),
+        Ok(Response::NxDomain { ttl }) => (Err(Error::Failed), ttl),
+        Err(e) => (Err(e), None),
+    })
+}
+
+/// Send a query with `question` to the DNS servers of `stack` in turn, and write the first response to `response`.
+///
+/// Returns the length of the response. Responses not coming from the server or not repeating the question are ignored.
+#[cfg(feature = "udp")]
+async fn udp_query<D: Driver>(
+    stack: &Stack<D>,
+    question: &[u8],
+    response: &mut [u8; MAX_RESPONSE_LEN],
+) -> Result<usize, Error> {
+    if !stack.socket.borrow().has_free_sockets(1) {
+        warn!("dns: no free socket for the query");
+        return Err(Error::Failed);
+    }
+
+    let mut query = [0; HEADER_LEN + MAX_NAME_LEN + 4];
+    let query = &mut query[..HEADER_LEN + question.len()];
+    query[2..4].copy_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    query[4..6].copy_from_slice(&1u16.to_be_bytes());
+    query[HEADER_LEN..].copy_from_slice(question);
+
+    let mut rx_meta = [PacketMetadata::EMPTY; 1];
+    let mut rx_buffer = [0; MAX_RESPONSE_LEN];
+    let mut tx_meta = [PacketMetadata::EMPTY; 1];
+    let mut tx_buffer = [0; HEADER_LEN + MAX_NAME_LEN + 4];
+    let mut socket = UdpSocket::new(stack, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+    // Random source port and IDs, so that spoofed responses can't be matched to the query.
+    let port = stack.socket.borrow_mut().rand_local_port();
+    unwrap!(socket.bind(port));
+
+    for server in stack.dns_servers() {
+        let server = IpEndpoint::new(server, DNS_PORT);
+        let id = stack.socket.borrow_mut().rand_u16();
+        query[0..2].copy_from_slice(&id.to_be_bytes());
+        if socket.send_to(query, server).await.is_err() {
+            continue;
+        }
+
+        let received = with_timeout(QUERY_TIMEOUT, async {
+            loop {
+                if let Ok((n, from)) = socket.recv_from(response).await {
+                    if from == server && is_response_to(query, &response[..n]) {
+                        break n;
+                    }
+                }
+            }
+        })
+        .await;
+
+        if let Ok(n) = received {
+            return Ok(n);
+        }
+    }
+
+    Err(Error::Failed)
+}
+
+/// Write the type and class of a question after its name, and get the whole question.
+#[cfg(feature = "udp")]
+fn finish_question(buf: &mut [u8], name_len: usize, qtype: u16) -> &[u8] {
+    buf[name_len..name_len + 2].copy_from_slice(&qtype.to_be_bytes());
+    buf[name_len + 2..name_len + 4].copy_from_slice(&CLASS_IN.to_be_bytes());
+    &buf[..name_len + 4]
+}
+
+/// Write the wire format of `name` to `buf`, and return its length.
+#[cfg(feature = "dns-cache")]
+fn encode_name(name: &str, buf: &mut [u8]) -> Result<usize, Error> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    let mut len = 0;
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(Error::InvalidName);
+        }
+        let dest = buf.get_mut(len..len + 1 + label.len()).ok_or(Error::NameTooLong)?;
+        dest[0] = label.len() as u8;
+        dest[1..].copy_from_slice(label.as_bytes());
+        len += 1 + label.len();
+    }
+    *buf.get_mut(len).ok_or(Error::NameTooLong)? = 0;
+    Ok(len + 1)
+}
+
+/// Write the wire format of the reverse lookup name of `addr` to `buf`, and return its length.
+#[cfg(feature = "udp")]
+fn encode_reverse_name(addr: IpAddress, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    let mut push_label = |label: &str| {
+        buf[len] = label.len() as u8;
+        buf[len + 1..len + 1 + label.len()].copy_from_slice(label.as_bytes());
+        len += 1 + label.len();
+    };
+
+    match addr {
+        #[cfg(feature = "proto-ipv4")]
+        IpAddress::Ipv4(addr) => {
+            for b in addr.0.iter().rev() {
+                let mut label = heapless::String::<3>::new();
+                unwrap!(write!(label, "{}", b).ok());
+                push_label(&label);
+            }
+            push_label("in-addr");
+        }
+        #[cfg(feature = "proto-ipv6")]
+        IpAddress::Ipv6(addr) => {
+            const HEX: &[u8; 16] = b"0123456789abcdef";
+            for b in addr.0.iter().rev() {
+                for nibble in [b & 0x0f, b >> 4] {
+                    let c = [HEX[nibble as usize]];
+                    push_label(unwrap!(core::str::from_utf8(&c).ok()));
+                }
+            }
+            push_label("ip6");
+        }
+    }
+    push_label("arpa");
+    buf[len] = 0;
+    len + 1
+}
+
+#[cfg(feature = "udp")]
+fn u16_at(msg: &[u8], pos: usize) -> Result<u16, Error> {
+    let b = msg.get(pos..pos + 2).ok_or(Error::Failed)?;
+    Ok(u16::from_be_bytes([b[0], b[1]]))
+}
+
+#[cfg(feature = "udp")]
+fn u32_at(msg: &[u8], pos: usize) -> Result<u32, Error> {
+    let b = msg.get(pos..pos + 4).ok_or(Error::Failed)?;
+    Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Get whether `msg` is a response to `query`, with the same ID and question.
+#[cfg(feature = "udp")]
+fn is_response_to(query: &[u8], msg: &[u8]) -> bool {
+    let question_end = |msg: &[u8]| skip_name(msg, HEADER_LEN).map(|pos| pos + 4);
+    let matches = || -> Result<bool, Error> {
+        let end = question_end(msg)?;
+        let query_end = question_end(query)?;
+        Ok(msg.get(0..2) == query.get(0..2)
+            && u16_at(msg, 2)? & FLAG_RESPONSE != 0
+            && u16_at(msg, 4)? == 1
+            && name_eq(query, HEADER_LEN, msg, HEADER_LEN)?
+            && msg.get(end - 4..end) == query.get(query_end - 4..query_end))
+    };
+    matches().unwrap_or(false)
+}
+
+/// Outcome of a query.
+#[cfg(feature = "udp")]
+#[derive(Debug, PartialEq, Eq)]
+enum Response {
+    /// Records answering the question were found, and can be cached for `ttl` seconds.
+    Answer { ttl: u32 },
+    /// The name doesn't exist. This can be cached for `ttl` seconds, if the server gave the SOA record of the zone.
+    NxDomain { ttl: Option<u32> },
+}
+
+/// A resource record, with the positions of its name and data in the message.
+#[cfg(feature = "udp")]
+struct Record {
+    name: usize,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    data: usize,
+    len: usize,
+}
+
+#[cfg(feature = "udp")]
+impl Record {
+    fn parse(msg: &[u8], name: usize) -> Result<Self, Error> {
+        let pos = skip_name(msg, name)?;
+        let data = pos + 10;
+        let len = u16_at(msg, pos + 8)? as usize;
+        msg.get(data..data + len).ok_or(Error::Failed)?;
+        let ttl = u32_at(msg, pos + 4)?;
+        Ok(Self {
+            name,
+            rtype: u16_at(msg, pos)?,
+            class: u16_at(msg, pos + 2)?,
+            // TTLs with the top bit set are treated as zero (RFC 2181).
+            ttl: if ttl > i32::MAX as u32 { 0 } else { ttl },
+            data,
+            len,
+        })
+    }
+
+    fn end(&self) -> usize {
+        self.data + self.len
+    }
+}
+
+/// Parse the response to a query for records of type `rtype`, calling `f` with each record answering the
+/// question, after following CNAMEs.
+///
+/// Responses that are neither answers nor NXDOMAIN, such as server failures or names without records of that
+/// type, are errors.
+#[cfg(feature = "udp")]
+fn parse_response(msg: &[u8], rtype: u16, mut f: impl FnMut(&Record) -> Result<(), Error>) -> Result<Response, Error> {
+    let flags = u16_at(msg, 2)?;
+    let questions = u16_at(msg, 4)?;
+    let answers = u16_at(msg, 6)?;
+    let authorities = u16_at(msg, 8)?;
+
+    let mut pos = HEADER_LEN;
+    for _ in 0..questions {
+        pos = skip_name(msg, pos)? + 4;
+    }
+
+    match flags & RCODE_MASK {
+        RCODE_NO_ERROR => {}
+        RCODE_NXDOMAIN => {
+            for _ in 0..answers {
+                pos = Record::parse(msg, pos)?.end();
+            }
+            // Negative answers are cached for the TTL of the SOA record, capped by its minimum field (RFC 2308).
+            let mut ttl = None;
+            for _ in 0..authorities {
+                let record = Record::parse(msg, pos)?;
+                if record.rtype == TYPE_SOA && record.len >= 20 {
+                    let minimum = u32_at(msg, record.end() - 4)?;
+                    ttl = Some(record.ttl.min(minimum));
+                }
+                pos = record.end();
+            }
+            return Ok(Response::NxDomain { ttl });
+        }
+        _ => return Err(Error::Failed),
+    }
+
+    let mut name = HEADER_LEN;
+    let mut ttl = u32::MAX;
+    let mut found = false;
+    for _ in 0..answers {
+        let record = Record::parse(msg, pos)?;
+        pos = record.end();
+        if record.class != CLASS_IN || !name_eq(msg, record.name, msg, name)? {
+            continue;
+        }
+        if record.rtype == TYPE_CNAME {
+            name = record.data;
+        } else if record.rtype == rtype {
+            f(&record)?;
+            found = true;
+        } else {
+            continue;
+        }
+        ttl = ttl.min(record.ttl);
+    }
+
+    if found {
+        Ok(Response::Answer { ttl })
+    } else {
+        Err(Error::Failed)
+    }
+}
+
+/// Get the position following the name at `pos`.
+#[cfg(feature = "udp")]
+fn skip_name(msg: &[u8], mut pos: usize) -> Result<usize, Error> {
+    loop {
+        let len = *msg.get(pos).ok_or(Error::Failed)?;
+        match len {
+            0 => return Ok(pos + 1),
+            // A compression pointer ends the name.
+            0xc0..=0xff => return Ok(pos + 2),
+            0x40..=0xbf => return Err(Error::Failed),
+            _ => pos += 1 + len as usize,
+        }
+    }
+}
+
+/// Read the label at `pos` and move past it, following compression pointers. Returns `None` at the end of the name.
+#[cfg(feature = "udp")]
+fn read_label<'m>(msg: &'m [u8], pos: &mut usize, pointers: &mut usize) -> Result<Option<&'m [u8]>, Error> {
+    loop {
+        let len = *msg.get(*pos).ok_or(Error::Failed)? as usize;
+        match len {
+            0 => return Ok(None),
+            0xc0..=0xff => {
+                *pointers += 1;
+                if *pointers > MAX_POINTERS {
+                    return Err(Error::Failed);
+                }
+                let low = *msg.get(*pos + 1).ok_or(Error::Failed)? as usize;
+                *pos = (len & 0x3f) << 8 | low;
+            }
+            0x40..=0xbf => return Err(Error::Failed),
+            _ => {
+                let label = msg.get(*pos + 1..*pos + 1 + len).ok_or(Error::Failed)?;
+                *pos += 1 + len;
+                return Ok(Some(label));
+            }
+        }
+    }
+}
+
+/// Compare the name at `a_pos` in `a` with the one at `b_pos` in `b`, ignoring case.
+#[cfg(feature = "udp")]
+fn name_eq(a: &[u8], mut a_pos: usize, b: &[u8], mut b_pos: usize) -> Result<bool, Error> {
+    let (mut a_pointers, mut b_pointers) = (0, 0);
+    loop {
+        match (
+            read_label(a, &mut a_pos, &mut a_pointers)?,
+            read_label(b, &mut b_pos, &mut b_pointers)?,
+        ) {
+            (None, None) => return Ok(true),
+            (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => {}
+            _ => return Ok(false),
+        }
+    }
+}
+
+/// Write the name at `pos` to `out` in dotted form, and return its length.
+#[cfg(feature = "udp")]
+fn decode_name(msg: &[u8], mut pos: usize, out: &mut [u8]) -> Result<usize, Error> {
+    let mut len = 0;
+    let mut pointers = 0;
+    while let Some(label) = read_label(msg, &mut pos, &mut pointers)? {
+        let dot = (len != 0) as usize;
+        let dest = out.get_mut(len..len + dot + label.len()).ok_or(Error::NameTooLong)?;
+        if dot != 0 {
+            dest[0] = b'.';
+        }
+        dest[dot..].copy_from_slice(label);
+        len += dot + label.len();
+    }
+    Ok(len)
+}
+
+/// Maximum length of the names whose answers are cached. Answers for longer names aren't.
+#[cfg(feature = "dns-cache")]
+const MAX_CACHED_NAME_LEN: usize = 64;
+/// Longest time answers are cached for, whatever their TTL.
+#[cfg(feature = "dns-cache")]
+const MAX_TTL: u32 = 24 * 60 * 60;
+
+/// An answer in the DNS cache.
+#[cfg(feature = "dns-cache")]
+pub(crate) struct CacheEntry {
+    name: heapless::String<MAX_CACHED_NAME_LEN>,
+    qtype: DnsQueryType,
+    /// The addresses, or `None` if the name doesn't exist.
+    addrs: Option<Vec<IpAddress, { smoltcp::config::DNS_MAX_RESULT_COUNT }>>,
+    expires: Instant,
+}
+
+/// A cache of the answers to DNS queries, including nonexistent names.
+#[cfg(feature = "dns-cache")]
+pub(crate) struct Cache {
+    entries: &'static mut [Option<CacheEntry>],
+}
+
+#[cfg(feature = "dns-cache")]
+impl Cache {
+    pub(crate) fn new(entries: &'static mut [Option<CacheEntry>]) -> Self {
+        Self { entries }
+    }
+
+    /// Get the cached answer to a query, if it hasn't expired.
+    pub(crate) fn get(
+        &mut self,
+        name: &str,
+        qtype: DnsQueryType,
+    ) -> Option<Result<Vec<IpAddress, { smoltcp::config::DNS_MAX_RESULT_COUNT }>, Error>> {
+        let now = Instant::now();
+        for slot in self.entries.iter_mut() {
+            if slot.as_ref().is_some_and(|e| e.expires <= now) {
+                *slot = None;
+            }
+        }
+
+        let name = name.strip_suffix('.').unwrap_or(name);
+        let entry = self
+            .entries
+            .iter()
+            .flatten()
+            .find(|e| e.qtype == qtype && e.name.eq_ignore_ascii_case(name))?;
+        Some(entry.addrs.clone().ok_or(Error::Failed))
+    }
+
+    /// Cache the answer to a query for `ttl` seconds. Failed queries are cached as nonexistent names.
+    ///
+    /// When the cache is full, the entry that expires first is replaced.
+    pub(crate) fn insert(
+        &mut self,
+        name: &str,
+        qtype: DnsQueryType,
+        result: &Result<Vec<IpAddress, { smoltcp::config::DNS_MAX_RESULT_COUNT }>, Error>,
+        ttl: u32,
+    ) {
+        if ttl == 0 {
+            return;
+        }
+        let name = name.strip_suffix('.').unwrap_or(name);
+        let Ok(name) = heapless::String::try_from(name) else {
+            return;
+        };
+
+        let entry = CacheEntry {
+            name,
+            qtype,
+            addrs: result.as_ref().ok().cloned(),
+            expires: Instant::now() + Duration::from_secs(ttl.min(MAX_TTL) as u64),
+        };
+        let existing = self
+            .entries
+            .iter()
+            .position(|e| matches!(e, Some(e) if e.qtype == qtype && e.name.eq_ignore_ascii_case(&entry.name)));
+        let free = self.entries.iter().position(|e| e.is_none());
+        let oldest = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| Some((i, e.as_ref()?.expires)))
+            .min_by_key(|(_, expires)| *expires)
+            .map(|(i, _)| i);
+        if let Some(i) = existing.or(free).or(oldest) {
+            self.entries[i] = Some(entry);
+        }
+    }
+
+    /// Remove all entries from the cache.
+    pub(crate) fn clear(&mut self) {
+        self.entries.fill_with(|| None);
+    }
+}
+
+#[cfg(all(test, feature = "udp"))]
+mod tests {
+    use super::*;
+
+    type Msg = Vec<u8, MAX_RESPONSE_LEN>;
+
+    fn header(id: u16, flags: u16, counts: [u16; 4]) -> Msg {
+        let mut msg = Msg::new();
+        msg.extend_from_slice(&id.to_be_bytes()).unwrap();
+        msg.extend_from_slice(&flags.to_be_bytes()).unwrap();
+        for count in counts {
+            msg.extend_from_slice(&count.to_be_bytes()).unwrap();
+        }
+        msg
+    }
+
+    fn push_name(msg: &mut Msg, name: &str) {
+        for label in name.split('.') {
+            msg.push(label.len() as u8).unwrap();
+            msg.extend_from_slice(label.as_bytes()).unwrap();
+        }
+        msg.push(0).unwrap();
+    }
+
+    fn push_record(msg: &mut Msg, name: &[u8], rtype: u16, ttl: u32, data: &[u8]) {
+        msg.extend_from_slice(name).unwrap();
+        msg.extend_from_slice(&rtype.to_be_bytes()).unwrap();
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes()).unwrap();
+        msg.extend_from_slice(&ttl.to_be_bytes()).unwrap();
+        msg.extend_from_slice(&(data.len() as u16).to_be_bytes()).unwrap();
+        msg.extend_from_slice(data).unwrap();
+    }
+
+    fn query(name: &str, qtype: u16) -> Msg {
+        let mut msg = header(0x1234, FLAG_RECURSION_DESIRED, [1, 0, 0, 0]);
+        push_name(&mut msg, name);
+        msg.extend_from_slice(&qtype.to_be_bytes()).unwrap();
+        msg.extend_from_slice(&CLASS_IN.to_be_bytes()).unwrap();
+        msg
+    }
+
+    fn response(query: &Msg, rcode: u16, counts: [u16; 3]) -> Msg {
+        let mut msg = header(0x1234, FLAG_RESPONSE | rcode, [1, counts[0], counts[1], counts[2]]);
+        msg.extend_from_slice(&query[HEADER_LEN..]).unwrap();
+        msg
+    }
+
+    /// A pointer to the name of the question.
+    const QUESTION_NAME: [u8; 2] = [0xc0, HEADER_LEN as u8];
+
+    #[test]
+    fn answer_follows_cname() {
+        let query = query("www.example.com", 1);
+        let mut msg = response(&query, RCODE_NO_ERROR, [3, 0, 0]);
+        // An unrelated record, which must be skipped.
+        let mut other = Msg::new();
+        push_name(&mut other, "other.example.com");
+        push_record(&mut msg, &other, 1, 10, &[10, 0, 0, 1]);
+        // The CNAME target is "web." followed by a pointer to "example.com".
+        let target = msg.len() + 2 + 10;
+        let cname = [3, b'w', b'e', b'b', 0xc0, HEADER_LEN as u8 + 4];
+        push_record(&mut msg, &QUESTION_NAME, TYPE_CNAME, 300, &cname);
+        push_record(&mut msg, &[0xc0, target as u8], 1, 60, &[93, 184, 216, 34]);
+
+        assert!(is_response_to(&query, &msg));
+        let mut found = Vec::<_, 4>::new();
+        let response = parse_response(&msg, 1, |r| {
+            found.extend_from_slice(&msg[r.data..r.end()]).unwrap();
+            Ok(())
+        });
+        assert_eq!(response, Ok(Response::Answer { ttl: 60 }));
+        assert_eq!(found, [93, 184, 216, 34]);
+    }
+
+    #[test]
+    fn nxdomain_uses_soa_minimum() {
+        let query = query("nope.example.com", 1);
+        let mut msg = response(&query, RCODE_NXDOMAIN, [0, 1, 0]);
+        let mut soa = Msg::new();
+        push_name(&mut soa, "ns.example.com");
+        push_name(&mut soa, "admin.example.com");
+        for field in [1u32, 7200, 3600, 1209600, 30] {
+            soa.extend_from_slice(&field.to_be_bytes()).unwrap();
+        }
+        push_record(&mut msg, &[0xc0, HEADER_LEN as u8 + 5], TYPE_SOA, 900, &soa);
+
+        assert_eq!(
+            parse_response(&msg, 1, |_| Ok(())),
+            Ok(Response::NxDomain { ttl: Some(30) })
+        );
+
+        // Without a SOA record, the answer mustn't be cached.
+        let msg = response(&query, RCODE_NXDOMAIN, [0, 0, 0]);
+        assert_eq!(
+            parse_response(&msg, 1, |_| Ok(())),
+            Ok(Response::NxDomain { ttl: None })
+        );
+    }
+
+    #[test]
+    fn server_failure_is_an_error() {
+        let query = query("www.example.com", 1);
+        let msg = response(&query, 2, [0, 0, 0]);
+        assert_eq!(parse_response(&msg, 1, |_| Ok(())), Err(Error::Failed));
+
+        // No records of the requested type.
+        let mut msg = response(&query, RCODE_NO_ERROR, [1, 0, 0]);
+        push_record(&mut msg, &QUESTION_NAME, 28, 60, &[0; 16]);
+        assert_eq!(parse_response(&msg, 1, |_| Ok(())), Err(Error::Failed));
+    }
+
+    #[test]
+    fn response_must_match_question() {
+        let query = query("www.example.com", 1);
+        let msg = response(&query, RCODE_NO_ERROR, [0, 0, 0]);
+        assert!(is_response_to(&query, &msg));
+
+        // Case doesn't matter.
+        let mut upper = msg.clone();
+        upper[HEADER_LEN + 1..HEADER_LEN + 4].copy_from_slice(b"WWW");
+        assert!(is_response_to(&query, &upper));
+
+        assert!(!is_response_to(
+            &query,
+            &response(&self::query("www.example.org", 1), 0, [0, 0, 0])
+        ));
+        assert!(!is_response_to(
+            &query,
+            &response(&self::query("www.example.com", 28), 0, [0, 0, 0])
+        ));
+
+        let mut other_id = msg.clone();
+        other_id[0] = 0;
+        assert!(!is_response_to(&query, &other_id));
+
+        // The query itself isn't a response.
+        assert!(!is_response_to(&query, &query));
+    }
+
+    #[test]
+    fn compression_loop() {
+        let query = query("www.example.com", 12);
+        let mut msg = response(&query, RCODE_NO_ERROR, [1, 0, 0]);
+        let data = msg.len() + 2 + 10;
+        push_record(&mut msg, &QUESTION_NAME, TYPE_PTR, 60, &[0xc0, data as u8]);
+        let mut out = [0; 64];
+        let res = parse_response(&msg, TYPE_PTR, |r| decode_name(&msg, r.data, &mut out).map(|_| ()));
+        assert_eq!(res, Err(Error::Failed));
+    }
+
+    #[cfg(feature = "dns-cache")]
+    #[test]
+    fn encode_names() {
+        let mut buf = [0; MAX_NAME_LEN];
+        assert_eq!(encode_name("example.com.", &mut buf), Ok(13));
+        assert_eq!(&buf[..13], b"\x07example\x03com\x00");
+        assert_eq!(encode_name("example..com", &mut buf), Err(Error::InvalidName));
+        let long = [b'a'; 64];
+        let long = core::str::from_utf8(&long).unwrap();
+        assert_eq!(encode_name(long, &mut buf), Err(Error::InvalidName));
+        assert_eq!(encode_name(&long[..60], &mut buf[..32]), Err(Error::NameTooLong));
     }
 }
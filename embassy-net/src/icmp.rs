@@ -0,0 +1,631 @@
+//! ICMP sockets.
+//!
+//! Besides the raw [`IcmpSocket`], this module provides [`Stack::ping`], which sends ICMP echo
+//! requests to a host and collects statistics about the replies.
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::mem;
+use core::task::{Context, Poll};
+
+use embassy_net_driver::Driver;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+use smoltcp::iface::{Interface, SocketHandle};
+use smoltcp::phy::ChecksumCapabilities;
+pub use smoltcp::socket::icmp::{Endpoint, PacketMetadata};
+use smoltcp::socket::{icmp, raw};
+#[cfg(feature = "proto-ipv4")]
+use smoltcp::wire::{Icmpv4Message, Icmpv4Packet, Icmpv4Repr, Ipv4Packet};
+#[cfg(feature = "proto-ipv6")]
+use smoltcp::wire::{Icmpv6Message, Icmpv6Packet, Icmpv6Repr, Ipv6Address, Ipv6Packet};
+use smoltcp::wire::{IpAddress, IpProtocol, IpVersion};
+
+use crate::{SocketStack, Stack};
+
+/// Error returned by [`IcmpSocket::bind`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BindError {
+    /// The socket was already open.
+    InvalidState,
+    /// The endpoint is not specified.
+    InvalidEndpoint,
+}
+
+/// Error returned by [`IcmpSocket::recv_from`] and [`IcmpSocket::send_to`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// No route to host.
+    NoRoute,
+}
+
+/// An ICMP socket.
+///
+/// The socket receives the ICMP messages matching the endpoint it's bound to, and sends
+/// complete ICMP messages (header included) to remote hosts.
+pub struct IcmpSocket<'a> {
+    stack: &'a RefCell<SocketStack>,
+    handle: SocketHandle,
+}
+
+impl<'a> IcmpSocket<'a> {
+    /// Create a new ICMP socket using the provided stack and buffers.
+    pub fn new<D: Driver>(
+        stack: &'a Stack<D>,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        let s = &mut *stack.socket.borrow_mut();
+
+        let rx_meta: &'static mut [PacketMetadata] = unsafe { mem::transmute(rx_meta) };
+        let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
+        let tx_meta: &'static mut [PacketMetadata] = unsafe { mem::transmute(tx_meta) };
+        let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        let handle = s.sockets.add(icmp::Socket::new(
+            icmp::PacketBuffer::new(rx_meta, rx_buffer),
+            icmp::PacketBuffer::new(tx_meta, tx_buffer),
+        ));
+
+        Self {
+            stack: &stack.socket,
+            handle,
+        }
+    }
+
+    /// Bind the socket to an endpoint.
+    ///
+    /// Binding to [`Endpoint::Ident`] receives the echo replies with that identifier, binding
+    /// to [`Endpoint::Udp`] receives the ICMP errors caused by packets sent from that UDP port.
+    pub fn bind<T>(&mut self, endpoint: T) -> Result<(), BindError>
+    where
+        T: Into<Endpoint>,
+    {
+        match self.with_mut(|s, _| s.bind(endpoint)) {
+            Ok(()) => Ok(()),
+            Err(icmp::BindError::InvalidState) => Err(BindError::InvalidState),
+            Err(icmp::BindError::Unaddressable) => Err(BindError::InvalidEndpoint),
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&icmp::Socket, &Interface) -> R) -> R {
+        let s = &*self.stack.borrow();
+        let socket = s.sockets.get::<icmp::Socket>(self.handle);
+        f(socket, &s.iface)
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(&mut icmp::Socket, &mut Interface) -> R) -> R {
+        let s = &mut *self.stack.borrow_mut();
+        let socket = s.sockets.get_mut::<icmp::Socket>(self.handle);
+        let res = f(socket, &mut s.iface);
+        s.waker.wake();
+        res
+    }
+
+    /// Receive an ICMP message.
+    ///
+    /// This method will wait until a message is received.
+    ///
+    /// Returns the number of bytes received and the address of the remote host.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, IpAddress), Error> {
+        poll_fn(move |cx| self.poll_recv_from(buf, cx)).await
+    }
+
+    /// Receive an ICMP message.
+    ///
+    /// When no message is available, this method will return `Poll::Pending` and
+    /// register the current task to be notified when a message is received.
+    ///
+    /// When a message is received, this method will return `Poll::Ready` with the
+    /// number of bytes received and the address of the remote host.
+    pub fn poll_recv_from(&self, buf: &mut [u8], cx: &mut Context<'_>) -> Poll<Result<(usize, IpAddress), Error>> {
+        self.with_mut(|s, _| match s.recv_slice(buf) {
+            Ok((n, addr)) => Poll::Ready(Ok((n, addr))),
+            // No data ready
+            Err(icmp::RecvError::Exhausted) => {
+                s.register_recv_waker(cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+
+    /// Send an ICMP message to the specified remote host.
+    ///
+    /// This method will wait until the message has been queued for sending.
+    ///
+    /// When the remote host is not reachable, this method will return `Err(Error::NoRoute)`
+    pub async fn send_to<T>(&self, buf: &[u8], remote_addr: T) -> Result<(), Error>
+    where
+        T: Into<IpAddress>,
+    {
+        let remote_addr: IpAddress = remote_addr.into();
+        poll_fn(move |cx| self.poll_send_to(buf, remote_addr, cx)).await
+    }
+
+    /// Send an ICMP message to the specified remote host.
+    ///
+    /// When the message has been queued for sending, this method will return `Poll::Ready(Ok())`.
+    ///
+    /// When the socket's send buffer is full, this method will return `Poll::Pending`
+    /// and register the current task to be notified when the buffer has space available.
+    ///
+    /// When the remote host is not reachable, this method will return `Poll::Ready(Err(Error::NoRoute))`.
+    pub fn poll_send_to<T>(&self, buf: &[u8], remote_addr: T, cx: &mut Context<'_>) -> Poll<Result<(), Error>>
+    where
+        T: Into<IpAddress>,
+    {
+        self.with_mut(|s, _| match s.send_slice(buf, remote_addr.into()) {
+            // Entire message has been queued
+            Ok(()) => Poll::Ready(Ok(())),
+            Err(icmp::SendError::BufferFull) => {
+                s.register_send_waker(cx.waker());
+                Poll::Pending
+            }
+            Err(icmp::SendError::Unaddressable) => Poll::Ready(Err(Error::NoRoute)),
+        })
+    }
+
+    /// Send an ICMP message of `size` bytes to the specified remote host, filling it in with `f`.
+    ///
+    /// This method will wait until there is space for the message in the send buffer.
+    pub async fn send_to_with<T, F, R>(&self, size: usize, remote_addr: T, f: F) -> Result<R, Error>
+    where
+        T: Into<IpAddress>,
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let remote_addr: IpAddress = remote_addr.into();
+        let mut f = Some(f);
+        poll_fn(move |cx| {
+            self.with_mut(|s, _| match s.send(size, remote_addr) {
+                Ok(buf) => Poll::Ready(Ok(unwrap!(f.take())(buf))),
+                Err(icmp::SendError::BufferFull) => {
+                    s.register_send_waker(cx.waker());
+                    Poll::Pending
+                }
+                Err(icmp::SendError::Unaddressable) => Poll::Ready(Err(Error::NoRoute)),
+            })
+        })
+        .await
+    }
+
+    /// Set the hop limit field in the IP header of sent packets.
+    pub fn set_hop_limit(&mut self, hop_limit: Option<u8>) {
+        self.with_mut(|s, _| s.set_hop_limit(hop_limit))
+    }
+
+    /// Get the hop limit field in the IP header of sent packets.
+    pub fn hop_limit(&self) -> Option<u8> {
+        self.with(|s, _| s.hop_limit())
+    }
+
+    /// Returns whether the socket is open.
+    pub fn is_open(&self) -> bool {
+        self.with(|s, _| s.is_open())
+    }
+
+    /// Returns whether the socket is ready to send data, i.e. it has enough buffer space to hold a packet.
+    pub fn may_send(&self) -> bool {
+        self.with(|s, _| s.can_send())
+    }
+
+    /// Returns whether the socket is ready to receive data, i.e. it has received a packet that's now in the buffer.
+    pub fn may_recv(&self) -> bool {
+        self.with(|s, _| s.can_recv())
+    }
+
+    /// Return the maximum number packets the socket can receive.
+    pub fn packet_recv_capacity(&self) -> usize {
+        self.with(|s, _| s.packet_recv_capacity())
+    }
+
+    /// Return the maximum number packets the socket can send.
+    pub fn packet_send_capacity(&self) -> usize {
+        self.with(|s, _| s.packet_send_capacity())
+    }
+
+    /// Return the maximum number of bytes inside the recv buffer.
+    pub fn payload_recv_capacity(&self) -> usize {
+        self.with(|s, _| s.payload_recv_capacity())
+    }
+
+    /// Return the maximum number of bytes inside the transmit buffer.
+    pub fn payload_send_capacity(&self) -> usize {
+        self.with(|s, _| s.payload_send_capacity())
+    }
+}
+
+impl Drop for IcmpSocket<'_> {
+    fn drop(&mut self) {
+        self.stack.borrow_mut().sockets.remove(self.handle);
+    }
+}
+
+/// Maximum payload length of the echo requests sent by [`Stack::ping`].
+pub const MAX_PING_PAYLOAD_LEN: usize = 64;
+
+// ICMP echo header, plus the largest IPv4 header (IPv6 headers are only 40 bytes).
+const ECHO_HEADER_LEN: usize = 8;
+const MAX_IP_HEADER_LEN: usize = 60;
+const PING_RX_PACKETS: usize = 4;
+const PING_PACKET_LEN: usize = MAX_IP_HEADER_LEN + ECHO_HEADER_LEN + MAX_PING_PAYLOAD_LEN;
+
+/// Parameters for [`Stack::ping`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PingParams {
+    /// Number of echo requests to send.
+    pub count: u16,
+    /// Interval between two echo requests.
+    pub interval: Duration,
+    /// How long to wait for the reply to an echo request.
+    pub timeout: Duration,
+    /// Length of the echo request payload, at most [`MAX_PING_PAYLOAD_LEN`].
+    pub payload_len: usize,
+    /// Hop limit (TTL) of the echo requests. If not set, the default of 64 is used.
+    pub hop_limit: Option<u8>,
+}
+
+impl Default for PingParams {
+    fn default() -> Self {
+        Self {
+            count: 4,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            payload_len: 32,
+            hop_limit: None,
+        }
+    }
+}
+
+/// Statistics returned by [`Stack::ping`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PingStats {
+    /// Number of echo requests sent.
+    pub transmitted: u16,
+    /// Number of echo requests that were replied to before their timeout.
+    pub received: u16,
+    /// Number of replies that were late (after their timeout) or duplicated.
+    pub duplicates: u16,
+    /// Sequence number of the last reply received in time.
+    pub last_seq_no: Option<u16>,
+    /// Hop limit (TTL) of the last reply received in time.
+    pub last_hop_limit: Option<u8>,
+    /// Shortest round-trip time.
+    pub rtt_min: Option<Duration>,
+    /// Longest round-trip time.
+    pub rtt_max: Option<Duration>,
+    /// Average round-trip time.
+    pub rtt_avg: Option<Duration>,
+}
+
+impl PingStats {
+    /// Number of echo requests that were not replied to in time.
+    pub fn lost(&self) -> u16 {
+        self.transmitted - self.received
+    }
+}
+
+/// Error returned by [`Stack::ping`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PingError {
+    /// The payload length is larger than [`MAX_PING_PAYLOAD_LEN`].
+    PayloadTooLong,
+    /// No route to host.
+    NoRoute,
+    /// The stack doesn't have the two free sockets ping needs.
+    NoFreeSocket,
+}
+
+/// Echo reply received by the raw socket, with the fields of the IP header ping cares about.
+struct EchoReply {
+    ident: u16,
+    seq_no: u16,
+    hop_limit: u8,
+}
+
+/// Raw socket receiving all ICMP messages, so that the IP header of echo replies can be inspected.
+struct EchoReplySocket<'a> {
+    stack: &'a RefCell<SocketStack>,
+    handle: SocketHandle,
+}
+
+impl<'a> EchoReplySocket<'a> {
+    fn new<D: Driver>(
+        stack: &'a Stack<D>,
+        version: IpVersion,
+        rx_meta: &'a mut [raw::PacketMetadata],
+        rx_buffer: &'a mut [u8],
+    ) -> Self {
+        let s = &mut *stack.socket.borrow_mut();
+
+        let rx_meta: &'static mut [raw::PacketMetadata] = unsafe { mem::transmute(rx_meta) };
+        let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
+        let protocol = match version {
+            #[cfg(feature = "proto-ipv4")]
+            IpVersion::Ipv4 => IpProtocol::Icmp,
+            #[cfg(feature = "proto-ipv6")]
+            IpVersion::Ipv6 => IpProtocol::Icmpv6,
+        };
+        let handle = s.sockets.add(raw::Socket::new(
+            version,
+            protocol,
+            raw::PacketBuffer::new(rx_meta, rx_buffer),
+            raw::PacketBuffer::new(&mut [][..], &mut [][..]),
+        ));
+
+        Self {
+            stack: &stack.socket,
+            handle,
+        }
+    }
+
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<EchoReply> {
+        let s = &mut *self.stack.borrow_mut();
+        let socket = s.sockets.get_mut::<raw::Socket>(self.handle);
+        let version = socket.ip_version();
+        loop {
+            match socket.recv() {
+                Ok(packet) => {
+                    if let Some(reply) = parse_echo_reply(version, packet) {
+                        return Poll::Ready(reply);
+                    }
+                }
+                Err(raw::RecvError::Exhausted) => {
+                    socket.register_recv_waker(cx.waker());
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+impl Drop for EchoReplySocket<'_> {
+    fn drop(&mut self) {
+        self.stack.borrow_mut().sockets.remove(self.handle);
+    }
+}
+
+fn parse_echo_reply(version: IpVersion, packet: &[u8]) -> Option<EchoReply> {
+    match version {
+        #[cfg(feature = "proto-ipv4")]
+        IpVersion::Ipv4 => {
+            let ip = Ipv4Packet::new_checked(packet).ok()?;
+            let icmp = Icmpv4Packet::new_checked(ip.payload()).ok()?;
+            if icmp.msg_type() != Icmpv4Message::EchoReply {
+                return None;
+            }
+            Some(EchoReply {
+                ident: icmp.echo_ident(),
+                seq_no: icmp.echo_seq_no(),
+                hop_limit: ip.hop_limit(),
+            })
+        }
+        #[cfg(feature = "proto-ipv6")]
+        IpVersion::Ipv6 => {
+            let ip = Ipv6Packet::new_checked(packet).ok()?;
+            let icmp = Icmpv6Packet::new_checked(ip.payload()).ok()?;
+            if icmp.msg_type() != Icmpv6Message::EchoReply {
+                return None;
+            }
+            Some(EchoReply {
+                ident: icmp.echo_ident(),
+                seq_no: icmp.echo_seq_no(),
+                hop_limit: ip.hop_limit(),
+            })
+        }
+    }
+}
+
+impl<D: Driver> Stack<D> {
+    /// Send ICMP echo requests to a host, and wait for its echo replies.
+    ///
+    /// The echo requests are sent one after the other, every [`PingParams::interval`], and each
+    /// one waits at most [`PingParams::timeout`] for its reply. Replies that arrive after their
+    /// timeout are counted in [`PingStats::duplicates`].
+    ///
+    /// Hosts that don't reply at all are reported with [`PingStats::received`] set to 0, not as
+    /// an error.
+    ///
+    /// Ping uses an ICMP socket and a raw socket of the stack while it runs, so the stack's
+    /// [`StackResources`](crate::StackResources) must have room for them.
+    pub async fn ping<T>(&self, addr: T, params: &PingParams) -> Result<PingStats, PingError>
+    where
+        T: Into<IpAddress>,
+    {
+        let addr = addr.into();
+        if params.payload_len > MAX_PING_PAYLOAD_LEN {
+            return Err(PingError::PayloadTooLong);
+        }
+        if !self.socket.borrow().has_free_sockets(2) {
+            return Err(PingError::NoFreeSocket);
+        }
+
+        let version = addr.version();
+        let ident = self.socket.borrow_mut().get_local_port();
+
+        let mut rx_meta = [raw::PacketMetadata::EMPTY; PING_RX_PACKETS];
+        let mut rx_buffer = [0; PING_RX_PACKETS * PING_PACKET_LEN];
+        let replies = EchoReplySocket::new(self, version, &mut rx_meta, &mut rx_buffer);
+
+        // Echo replies are read from the raw socket, which also gives us their hop limit. The ICMP socket
+        // is only used to send the requests, so it doesn't need a receive buffer.
+        let mut tx_meta = [PacketMetadata::EMPTY; 1];
+        let mut tx_buffer = [0; ECHO_HEADER_LEN + MAX_PING_PAYLOAD_LEN];
+        let mut socket = IcmpSocket::new(self, &mut [], &mut [], &mut tx_meta, &mut tx_buffer);
+        unwrap!(socket.bind(Endpoint::Ident(ident)));
+        socket.set_hop_limit(params.hop_limit);
+
+        let mut stats = PingStats::default();
+        let mut rtt_total = Duration::from_ticks(0);
+        let mut data = [0; MAX_PING_PAYLOAD_LEN];
+        for (i, b) in data.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        let data = &data[..params.payload_len];
+
+        for seq_no in 0..params.count {
+            let size = ECHO_HEADER_LEN + data.len();
+            socket
+                .send_to_with(size, addr, |buf| emit_echo_request(version, ident, seq_no, data, buf))
+                .await
+                .map_err(|_| PingError::NoRoute)?;
+            let sent_at = Instant::now();
+            stats.transmitted += 1;
+
+            let wait_reply = async {
+                loop {
+                    let reply = poll_fn(|cx| replies.poll_recv(cx)).await;
+                    if reply.ident != ident {
+                        continue;
+                    }
+                    if reply.seq_no != seq_no {
+                        stats.duplicates += 1;
+                        continue;
+                    }
+                    return reply;
+                }
+            };
+
+            if let Ok(reply) = with_timeout(params.timeout, wait_reply).await {
+                let rtt = Instant::now() - sent_at;
+                stats.received += 1;
+                stats.last_seq_no = Some(reply.seq_no);
+                stats.last_hop_limit = Some(reply.hop_limit);
+                stats.rtt_min = Some(stats.rtt_min.map_or(rtt, |min| min.min(rtt)));
+                stats.rtt_max = Some(stats.rtt_max.map_or(rtt, |max| max.max(rtt)));
+                rtt_total += rtt;
+                stats.rtt_avg = Some(rtt_total / stats.received as u32);
+            }
+
+            if seq_no + 1 < params.count {
+                Timer::at(sent_at + params.interval).await;
+            }
+        }
+
+        Ok(stats)
+    }
+}
+
+fn emit_echo_request(version: IpVersion, ident: u16, seq_no: u16, data: &[u8], buf: &mut [u8]) {
+    match version {
+        #[cfg(feature = "proto-ipv4")]
+        IpVersion::Ipv4 => {
+            let repr = Icmpv4Repr::EchoRequest { ident, seq_no, data };
+            repr.emit(&mut Icmpv4Packet::new_unchecked(buf), &ChecksumCapabilities::default());
+        }
+        #[cfg(feature = "proto-ipv6")]
+        IpVersion::Ipv6 => {
+            // The checksum covers the IPv6 pseudo-header, the socket fills it in when sending.
+            let repr = Icmpv6Repr::EchoRequest { ident, seq_no, data };
+            repr.emit(
+                &IpAddress::Ipv6(Ipv6Address::UNSPECIFIED),
+                &IpAddress::Ipv6(Ipv6Address::UNSPECIFIED),
+                &mut Icmpv6Packet::new_unchecked(buf),
+                &ChecksumCapabilities::ignored(),
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "medium-ip", feature = "proto-ipv4"))]
+mod tests {
+    use std::vec::Vec;
+
+    use embassy_futures::select::{select, Either};
+
+    use super::*;
+    use crate::loopback::{self, ADDR_B};
+    use crate::Ipv4Address;
+
+    fn params(count: u16) -> PingParams {
+        PingParams {
+            count,
+            interval: Duration::from_millis(50),
+            timeout: Duration::from_millis(20),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replies() {
+        let stacks = loopback::stacks::<4>();
+        let stats = loopback::run(&stacks, stacks.0.ping(ADDR_B, &params(3))).unwrap();
+
+        assert_eq!((stats.transmitted, stats.received, stats.lost()), (3, 3, 0));
+        assert_eq!(stats.duplicates, 0);
+        assert_eq!(stats.last_seq_no, Some(2));
+        assert_eq!(stats.last_hop_limit, Some(64));
+        assert!(stats.rtt_min.is_some());
+        assert!(stats.rtt_min <= stats.rtt_avg && stats.rtt_avg <= stats.rtt_max);
+    }
+
+    #[test]
+    fn lost() {
+        let stacks = loopback::stacks::<4>();
+        let stats = loopback::run(&stacks, stacks.0.ping(Ipv4Address::new(192, 168, 0, 3), &params(3))).unwrap();
+
+        assert_eq!((stats.transmitted, stats.received, stats.lost()), (3, 0, 3));
+        assert_eq!(stats.duplicates, 0);
+        assert_eq!(stats.last_seq_no, None);
+        assert_eq!(stats.last_hop_limit, None);
+        assert_eq!(stats.rtt_avg, None);
+    }
+
+    #[test]
+    fn duplicates() {
+        let stacks = loopback::stacks::<4>();
+        let ident = stacks.0.socket.borrow().next_local_port;
+
+        let stats = loopback::run(&stacks, async {
+            let mut rx_meta = [PacketMetadata::EMPTY; 4];
+            let mut rx_buffer = [0; 1024];
+            let mut tx_meta = [PacketMetadata::EMPTY; 4];
+            let mut tx_buffer = [0; 1024];
+            let mut socket = IcmpSocket::new(&stacks.1, &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+            socket.bind(Endpoint::Ident(ident)).unwrap();
+
+            // Besides the reply of stack B itself, reply to every echo request a second time, and
+            // as if it was sent by another ping.
+            let replier = async {
+                let mut buf = [0; 256];
+                loop {
+                    let (n, addr) = socket.recv_from(&mut buf).await.unwrap();
+                    let mut packet = Icmpv4Packet::new_checked(&mut buf[..n]).unwrap();
+                    packet.set_msg_type(Icmpv4Message::EchoReply);
+                    packet.fill_checksum();
+                    socket.send_to(&buf[..n], addr).await.unwrap();
+
+                    let mut packet = Icmpv4Packet::new_unchecked(&mut buf[..n]);
+                    packet.set_echo_ident(ident + 1);
+                    packet.fill_checksum();
+                    socket.send_to(&buf[..n], addr).await.unwrap();
+                }
+            };
+            match select(stacks.0.ping(ADDR_B, &params(3)), replier).await {
+                Either::First(stats) => stats.unwrap(),
+                Either::Second(()) => unreachable!(),
+            }
+        });
+
+        // The second reply to the last request arrives after ping returns.
+        assert_eq!((stats.transmitted, stats.received, stats.lost()), (3, 3, 0));
+        assert_eq!(stats.duplicates, 2);
+        assert_eq!(stats.last_seq_no, Some(2));
+    }
+
+    #[test]
+    fn no_free_socket() {
+        let stacks = loopback::stacks::<4>();
+        let mut sockets = Vec::new();
+        while stacks.0.socket.borrow().has_free_sockets(2) {
+            sockets.push(IcmpSocket::new(&stacks.0, &mut [], &mut [], &mut [], &mut []));
+        }
+
+        let ping = loopback::run(&stacks, stacks.0.ping(ADDR_B, &params(1)));
+        assert_eq!(ping, Err(PingError::NoFreeSocket));
+    }
+}
@@ -12,13 +12,40 @@ compile_error!("You must enable at least one of the following features: proto-ip
 pub(crate) mod fmt;
 
 mod device;
+#[cfg(feature = "dhcpv4-server")]
+pub mod dhcp_server;
 #[cfg(feature = "dns")]
 pub mod dns;
+#[cfg(feature = "icmp")]
+pub mod icmp;
+#[cfg(all(
+    test,
+    feature = "medium-ip",
+    feature = "proto-ipv4",
+    any(feature = "tcp", feature = "icmp", feature = "dhcpv4-server")
+))]
+mod loopback;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+#[cfg(feature = "pcap")]
+pub mod pcap;
+#[cfg(feature = "raw")]
+pub mod raw;
+pub mod router;
+#[cfg(feature = "slaac")]
+mod slaac;
+#[cfg(feature = "sntp")]
+pub mod sntp;
+mod stats;
 #[cfg(feature = "tcp")]
 pub mod tcp;
 mod time;
+#[cfg(feature = "tls")]
+pub mod tls;
 #[cfg(feature = "udp")]
 pub mod udp;
+#[cfg(feature = "vlan")]
+pub mod vlan;
 
 use core::cell::RefCell;
 use core::future::{poll_fn, Future};
@@ -31,6 +58,8 @@ use embassy_time::{Instant, Timer};
 use futures::pin_mut;
 #[allow(unused_imports)]
 use heapless::Vec;
+#[cfg(feature = "slaac")]
+pub use slaac::SlaacConfig;
 #[cfg(feature = "igmp")]
 pub use smoltcp::iface::MulticastError;
 #[allow(unused_imports)]
@@ -51,8 +80,10 @@ pub use smoltcp::wire::{IpAddress, IpCidr, IpEndpoint};
 pub use smoltcp::wire::{Ipv4Address, Ipv4Cidr};
 #[cfg(feature = "proto-ipv6")]
 pub use smoltcp::wire::{Ipv6Address, Ipv6Cidr};
+pub use stats::Stats;
 
 use crate::device::DriverAdapter;
+use crate::stats::Counters;
 use crate::time::{instant_from_smoltcp, instant_to_smoltcp};
 
 const LOCAL_PORT_MIN: u16 = 1025;
@@ -61,14 +92,31 @@ const LOCAL_PORT_MAX: u16 = 65535;
 const MAX_QUERIES: usize = 4;
 #[cfg(feature = "dhcpv4-hostname")]
 const MAX_HOSTNAME_LEN: usize = 32;
+/// Size of the buffer received DHCP packets are kept in to read extra options from.
+#[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+const DHCP_PACKET_LEN: usize = 576;
+#[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+const MAX_NTP_SERVERS: usize = 3;
+/// DHCP options requested from the server: subnet mask, router, DNS servers and NTP servers.
+#[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+const DHCP_PARAMETER_REQUEST_LIST: &[u8] = &[1, 3, 6, 42];
 
 /// Memory resources needed for a network stack.
-pub struct StackResources<const SOCK: usize> {
+///
+/// `SOCK` is the number of sockets, and `DNS_CACHE` the number of answers kept in the DNS cache
+/// when the `dns-cache` feature is enabled.
+pub struct StackResources<const SOCK: usize, const DNS_CACHE: usize = 4> {
     sockets: [SocketStorage<'static>; SOCK],
     #[cfg(feature = "dns")]
     queries: [Option<dns::DnsQuery>; MAX_QUERIES],
+    #[cfg(feature = "dns-cache")]
+    dns_cache: [Option<dns::CacheEntry>; DNS_CACHE],
     #[cfg(feature = "dhcpv4-hostname")]
     hostname: core::cell::UnsafeCell<HostnameResources>,
+    #[cfg(feature = "slaac")]
+    slaac: core::cell::UnsafeCell<slaac::SlaacResources>,
+    #[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+    dhcp_packet: core::cell::UnsafeCell<[u8; DHCP_PACKET_LEN]>,
 }
 
 #[cfg(feature = "dhcpv4-hostname")]
@@ -77,20 +125,28 @@ struct HostnameResources {
     data: [u8; MAX_HOSTNAME_LEN],
 }
 
-impl<const SOCK: usize> StackResources<SOCK> {
+impl<const SOCK: usize, const DNS_CACHE: usize> StackResources<SOCK, DNS_CACHE> {
     /// Create a new set of stack resources.
     pub const fn new() -> Self {
         #[cfg(feature = "dns")]
         const INIT: Option<dns::DnsQuery> = None;
+        #[cfg(feature = "dns-cache")]
+        const INIT_CACHE: Option<dns::CacheEntry> = None;
         Self {
             sockets: [SocketStorage::EMPTY; SOCK],
             #[cfg(feature = "dns")]
             queries: [INIT; MAX_QUERIES],
+            #[cfg(feature = "dns-cache")]
+            dns_cache: [INIT_CACHE; DNS_CACHE],
             #[cfg(feature = "dhcpv4-hostname")]
             hostname: core::cell::UnsafeCell::new(HostnameResources {
                 option: smoltcp::wire::DhcpOption { kind: 0, data: &[] },
                 data: [0; MAX_HOSTNAME_LEN],
             }),
+            #[cfg(feature = "slaac")]
+            slaac: core::cell::UnsafeCell::new(slaac::SlaacResources::new()),
+            #[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+            dhcp_packet: core::cell::UnsafeCell::new([0; DHCP_PACKET_LEN]),
         }
     }
 }
@@ -192,6 +248,16 @@ impl Config {
         }
     }
 
+    /// IPv6 configuration with stateless address autoconfiguration.
+    #[cfg(feature = "slaac")]
+    pub fn slaac(config: SlaacConfig) -> Self {
+        Self {
+            #[cfg(feature = "proto-ipv4")]
+            ipv4: ConfigV4::None,
+            ipv6: ConfigV6::Slaac(config),
+        }
+    }
+
     /// IPv4 configuration with dynamic addressing.
     ///
     /// # Example
@@ -232,6 +298,13 @@ pub enum ConfigV6 {
     None,
     /// Use a static IPv6 address configuration.
     Static(StaticConfigV6),
+    /// Use stateless address autoconfiguration (SLAAC) to obtain an IPv6 address configuration
+    /// from router advertisements.
+    ///
+    /// The address is built from the advertised prefix and the MAC address, so this requires
+    /// an Ethernet device.
+    #[cfg(feature = "slaac")]
+    Slaac(SlaacConfig),
 }
 
 /// A network stack.
@@ -251,20 +324,41 @@ struct Inner<D: Driver> {
     static_v6: Option<StaticConfigV6>,
     #[cfg(feature = "dhcpv4")]
     dhcp_socket: Option<SocketHandle>,
+    #[cfg(feature = "slaac")]
+    slaac: Option<slaac::Slaac>,
     config_waker: WakerRegistration,
     #[cfg(feature = "dns")]
     dns_socket: SocketHandle,
     #[cfg(feature = "dns")]
     dns_waker: WakerRegistration,
+    #[cfg(feature = "dns")]
+    dns_servers: Vec<IpAddress, 6>,
+    #[cfg(feature = "dns-cache")]
+    dns_cache: dns::Cache,
     #[cfg(feature = "dhcpv4-hostname")]
     hostname: &'static mut core::cell::UnsafeCell<HostnameResources>,
+    #[cfg(all(feature = "dhcpv4-hostname", feature = "mdns"))]
+    dhcp_hostname: Option<heapless::String<MAX_HOSTNAME_LEN>>,
+    #[cfg(feature = "slaac")]
+    slaac_resources: &'static mut core::cell::UnsafeCell<slaac::SlaacResources>,
+    #[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+    dhcp_packet: &'static mut core::cell::UnsafeCell<[u8; DHCP_PACKET_LEN]>,
+    #[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+    ntp_servers: Vec<Ipv4Address, MAX_NTP_SERVERS>,
+    #[cfg(feature = "igmp")]
+    multicast_groups: Vec<IpAddress, { smoltcp::config::IFACE_MAX_MULTICAST_GROUP_COUNT }>,
 }
 
 pub(crate) struct SocketStack {
     pub(crate) sockets: SocketSet<'static>,
     pub(crate) iface: Interface,
     pub(crate) waker: WakerRegistration,
+    pub(crate) stats: Counters,
+    #[cfg(any(all(feature = "dns", feature = "udp"), feature = "icmp"))]
+    max_sockets: usize,
     next_local_port: u16,
+    #[cfg(all(feature = "dns", feature = "udp"))]
+    rand_state: u64,
 }
 
 fn to_smoltcp_hardware_address(addr: driver::HardwareAddress) -> (HardwareAddress, Medium) {
@@ -289,10 +383,10 @@ fn to_smoltcp_hardware_address(addr: driver::HardwareAddress) -> (HardwareAddres
 
 impl<D: Driver> Stack<D> {
     /// Create a new network stack.
-    pub fn new<const SOCK: usize>(
+    pub fn new<const SOCK: usize, const DNS_CACHE: usize>(
         mut device: D,
         config: Config,
-        resources: &'static mut StackResources<SOCK>,
+        resources: &'static mut StackResources<SOCK, DNS_CACHE>,
         random_seed: u64,
     ) -> Self {
         let (hardware_addr, medium) = to_smoltcp_hardware_address(device.hardware_address());
@@ -305,6 +399,7 @@ impl<D: Driver> Stack<D> {
                 inner: &mut device,
                 cx: None,
                 medium,
+                stats: &Counters::new(),
             },
             instant_to_smoltcp(Instant::now()),
         );
@@ -318,7 +413,13 @@ impl<D: Driver> Stack<D> {
             sockets,
             iface,
             waker: WakerRegistration::new(),
+            stats: Counters::new(),
+            #[cfg(any(all(feature = "dns", feature = "udp"), feature = "icmp"))]
+            max_sockets: SOCK,
             next_local_port,
+            // Don't produce the same numbers as the generator of smoltcp, seeded with the same seed.
+            #[cfg(all(feature = "dns", feature = "udp"))]
+            rand_state: !random_seed,
         };
 
         let mut inner = Inner {
@@ -330,6 +431,8 @@ impl<D: Driver> Stack<D> {
             static_v6: None,
             #[cfg(feature = "dhcpv4")]
             dhcp_socket: None,
+            #[cfg(feature = "slaac")]
+            slaac: None,
             config_waker: WakerRegistration::new(),
             #[cfg(feature = "dns")]
             dns_socket: socket.sockets.add(dns::Socket::new(
@@ -338,8 +441,22 @@ impl<D: Driver> Stack<D> {
             )),
             #[cfg(feature = "dns")]
             dns_waker: WakerRegistration::new(),
+            #[cfg(feature = "dns")]
+            dns_servers: Vec::new(),
+            #[cfg(feature = "dns-cache")]
+            dns_cache: dns::Cache::new(&mut resources.dns_cache),
             #[cfg(feature = "dhcpv4-hostname")]
             hostname: &mut resources.hostname,
+            #[cfg(all(feature = "dhcpv4-hostname", feature = "mdns"))]
+            dhcp_hostname: None,
+            #[cfg(feature = "slaac")]
+            slaac_resources: &mut resources.slaac,
+            #[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+            dhcp_packet: &mut resources.dhcp_packet,
+            #[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+            ntp_servers: Vec::new(),
+            #[cfg(feature = "igmp")]
+            multicast_groups: Vec::new(),
         };
 
         #[cfg(feature = "proto-ipv4")]
@@ -372,6 +489,11 @@ impl<D: Driver> Stack<D> {
         self.with(|_s, i| i.link_up)
     }
 
+    /// Get the statistics of the network interface, including the ones reported by the driver.
+    pub fn stats(&self) -> Stats {
+        self.with(|s, i| s.stats.snapshot(i.device.stats()))
+    }
+
     /// Get whether the network stack has a valid IP configuration.
     /// This is true if the network stack has a static IP configuration or if DHCP has completed
     pub fn is_config_up(&self) -> bool {
@@ -457,7 +579,22 @@ impl<D: Driver> Stack<D> {
         self.with(|_, i| i.static_v4.clone())
     }
 
+    /// Get the hostname sent to the DHCP server, if using DHCP with a hostname.
+    #[cfg(all(feature = "dhcpv4-hostname", feature = "mdns"))]
+    pub(crate) fn dhcp_hostname(&self) -> Option<heapless::String<MAX_HOSTNAME_LEN>> {
+        self.with(|_, i| i.dhcp_hostname.clone())
+    }
+
+    /// Get the NTP servers handed out by the DHCP server.
+    #[cfg(all(feature = "dhcpv4", feature = "sntp"))]
+    pub(crate) fn dhcp_ntp_servers(&self) -> Vec<Ipv4Address, MAX_NTP_SERVERS> {
+        self.with(|_, i| i.ntp_servers.clone())
+    }
+
     /// Get the current IPv6 configuration.
+    ///
+    /// If using SLAAC, this will be None until a router has advertised a prefix
+    /// to autoconfigure an address from, or Some if it has.
     #[cfg(feature = "proto-ipv6")]
     pub fn config_v6(&self) -> Option<StaticConfigV6> {
         self.with(|_, i| i.static_v6.clone())
@@ -517,6 +654,21 @@ impl<D: Driver> Stack<D> {
             _ => {}
         }
 
+        #[cfg(feature = "dns-cache")]
+        {
+            if let Some(res) = self.with_mut(|_, i| i.dns_cache.get(name, qtype)) {
+                return res;
+            }
+            // The DNS socket doesn't report the TTL of the records, so the query is made over a UDP socket
+            // when one is free, and without the cache otherwise.
+            if let Some((res, ttl)) = dns::query_addrs(self, name, qtype).await {
+                if let Some(ttl) = ttl {
+                    self.with_mut(|_, i| i.dns_cache.insert(name, qtype, &res, ttl));
+                }
+                return res;
+            }
+        }
+
         let query = poll_fn(|cx| {
             self.with_mut(|s, i| {
                 let socket = s.sockets.get_mut::<dns::Socket>(i.dns_socket);
@@ -592,6 +744,20 @@ impl<D: Driver> Stack<D> {
 
         res
     }
+
+    /// Remove all answers from the DNS cache, so the next queries are sent to the DNS servers.
+    ///
+    /// The cache is also flushed automatically when the DNS servers change, such as when DHCP hands out new ones.
+    #[cfg(feature = "dns-cache")]
+    pub fn flush_dns_cache(&self) {
+        self.with_mut(|_, i| i.dns_cache.clear())
+    }
+
+    /// Get the DNS servers currently in use.
+    #[cfg(all(feature = "dns", feature = "udp"))]
+    pub(crate) fn dns_servers(&self) -> Vec<IpAddress, 6> {
+        self.with(|_, i| i.dns_servers.clone())
+    }
 }
 
 #[cfg(feature = "igmp")]
@@ -622,13 +788,21 @@ impl<D: Driver> Stack<D> {
                 cx: Some(cx),
                 inner: &mut i.device,
                 medium,
+                stats: &s.stats,
             };
 
             match s
                 .iface
                 .join_multicast_group(&mut smoldev, addr, instant_to_smoltcp(Instant::now()))
             {
-                Ok(announce_sent) => Poll::Ready(Ok(announce_sent)),
+                Ok(announce_sent) => {
+                    if !i.multicast_groups.contains(&addr) {
+                        // Can't be full, the interface has as many groups.
+                        unwrap!(i.multicast_groups.push(addr));
+                        i.update_multicast_filter();
+                    }
+                    Poll::Ready(Ok(announce_sent))
+                }
                 Err(MulticastError::Exhausted) => Poll::Pending,
                 Err(other) => Poll::Ready(Err(other)),
             }
@@ -661,13 +835,20 @@ impl<D: Driver> Stack<D> {
                 cx: Some(cx),
                 inner: &mut i.device,
                 medium,
+                stats: &s.stats,
             };
 
             match s
                 .iface
                 .leave_multicast_group(&mut smoldev, addr, instant_to_smoltcp(Instant::now()))
             {
-                Ok(leave_sent) => Poll::Ready(Ok(leave_sent)),
+                Ok(leave_sent) => {
+                    if let Some(pos) = i.multicast_groups.iter().position(|a| *a == addr) {
+                        i.multicast_groups.swap_remove(pos);
+                        i.update_multicast_filter();
+                    }
+                    Poll::Ready(Ok(leave_sent))
+                }
                 Err(MulticastError::Exhausted) => Poll::Pending,
                 Err(other) => Poll::Ready(Err(other)),
             }
@@ -687,9 +868,56 @@ impl SocketStack {
         self.next_local_port = if res >= LOCAL_PORT_MAX { LOCAL_PORT_MIN } else { res + 1 };
         res
     }
+
+    /// Get whether `n` sockets can be added without panicking because the socket set is full.
+    #[cfg(any(all(feature = "dns", feature = "udp"), feature = "icmp"))]
+    pub(crate) fn has_free_sockets(&self, n: usize) -> bool {
+        self.sockets.iter().count() + n <= self.max_sockets
+    }
+
+    /// Get a random number derived from the random seed of the stack, like smoltcp does.
+    ///
+    /// Used for the values that an off-path attacker must not be able to guess, such as DNS query IDs.
+    #[cfg(all(feature = "dns", feature = "udp"))]
+    pub(crate) fn rand_u16(&mut self) -> u16 {
+        // sPCG32 from https://www.pcg-random.org/paper.html
+        const M: u64 = 0xbb2efcec3c39611d;
+        const A: u64 = 0x7590ef39;
+
+        let s = self.rand_state.wrapping_mul(M).wrapping_add(A);
+        self.rand_state = s;
+        let n = (s >> (29 - (s >> 61))) as u32;
+        (n ^ (n >> 16)) as u16
+    }
+
+    /// Get a random local port.
+    #[cfg(all(feature = "dns", feature = "udp"))]
+    pub(crate) fn rand_local_port(&mut self) -> u16 {
+        loop {
+            let port = self.rand_u16();
+            if port >= LOCAL_PORT_MIN {
+                return port;
+            }
+        }
+    }
 }
 
 impl<D: Driver> Inner<D> {
+    /// Give the driver the Ethernet addresses of the joined multicast groups, for its MAC filter.
+    #[cfg(feature = "igmp")]
+    fn update_multicast_filter(&mut self) {
+        let macs: Vec<[u8; 6], { smoltcp::config::IFACE_MAX_MULTICAST_GROUP_COUNT }> = self
+            .multicast_groups
+            .iter()
+            .map(|addr| match addr {
+                IpAddress::Ipv4(a) => [0x01, 0x00, 0x5e, a.0[1] & 0x7f, a.0[2], a.0[3]],
+                #[cfg(feature = "proto-ipv6")]
+                IpAddress::Ipv6(a) => [0x33, 0x33, a.0[12], a.0[13], a.0[14], a.0[15]],
+            })
+            .collect();
+        self.device.set_multicast_filter(&macs);
+    }
+
     #[cfg(feature = "proto-ipv4")]
     pub fn set_config_v4(&mut self, _s: &mut SocketStack, config: ConfigV4) {
         // Handle static config.
@@ -706,7 +934,15 @@ impl<D: Driver> Inner<D> {
             ConfigV4::Dhcp(c) => {
                 // Create the socket if it doesn't exist.
                 if self.dhcp_socket.is_none() {
-                    let socket = smoltcp::socket::dhcpv4::Socket::new();
+                    #[allow(unused_mut)]
+                    let mut socket = smoltcp::socket::dhcpv4::Socket::new();
+                    #[cfg(feature = "sntp")]
+                    {
+                        socket.set_parameter_request_list(DHCP_PARAMETER_REQUEST_LIST);
+                        // safety: the buffer lives forever, new borrows the StackResources for 'static,
+                        // and no other socket uses it: a previous DHCP socket was removed from the set.
+                        socket.set_receive_packet_buffer(unsafe { &mut *self.dhcp_packet.get() });
+                    }
                     let handle = _s.sockets.add(socket);
                     self.dhcp_socket = Some(handle);
                 }
@@ -719,6 +955,10 @@ impl<D: Driver> Inner<D> {
                 socket.set_retry_config(c.retry_config);
 
                 socket.set_outgoing_options(&[]);
+                #[cfg(all(feature = "dhcpv4-hostname", feature = "mdns"))]
+                {
+                    self.dhcp_hostname = c.hostname.clone();
+                }
                 #[cfg(feature = "dhcpv4-hostname")]
                 if let Some(h) = c.hostname {
                     // safety: we just did set_outgoing_options([]) so we know the socket is no longer holding a reference.
@@ -744,16 +984,51 @@ impl<D: Driver> Inner<D> {
                     _s.sockets.remove(socket);
                     self.dhcp_socket = None;
                 }
+                #[cfg(all(feature = "dhcpv4-hostname", feature = "mdns"))]
+                {
+                    self.dhcp_hostname = None;
+                }
+                #[cfg(feature = "sntp")]
+                self.ntp_servers.clear();
             }
         }
     }
 
     #[cfg(feature = "proto-ipv6")]
     pub fn set_config_v6(&mut self, _s: &mut SocketStack, config: ConfigV6) {
-        self.static_v6 = match config {
+        // Handle static config.
+        self.static_v6 = match config.clone() {
             ConfigV6::None => None,
+            #[cfg(feature = "slaac")]
+            ConfigV6::Slaac(_) => None,
             ConfigV6::Static(c) => Some(c),
         };
+
+        // Handle SLAAC config.
+        #[cfg(feature = "slaac")]
+        match config {
+            ConfigV6::Slaac(c) => {
+                // Create the sockets if they don't exist.
+                match &mut self.slaac {
+                    Some(slaac) => {
+                        slaac.set_config(c);
+                        slaac.reset();
+                    }
+                    None => {
+                        // safety: the resources are only used by the SLAAC sockets, which don't exist.
+                        // we know the resources live forever, new borrows the StackResources for 'static.
+                        let resources = unsafe { &mut *self.slaac_resources.get() };
+                        self.slaac = Some(unsafe { slaac::Slaac::new(&mut _s.sockets, resources, c) });
+                    }
+                }
+            }
+            _ => {
+                // Remove SLAAC sockets if any.
+                if let Some(slaac) = self.slaac.take() {
+                    slaac.remove(&mut _s.sockets);
+                }
+            }
+        }
     }
 
     fn apply_static_config(&mut self, s: &mut SocketStack) {
@@ -799,6 +1074,19 @@ impl<D: Driver> Inner<D> {
             info!("IPv6: DOWN");
         }
 
+        // SLAAC needs a link-local address to talk to routers, even before it's configured.
+        #[cfg(feature = "slaac")]
+        if self.slaac.is_some() {
+            #[allow(irrefutable_let_patterns)]
+            if let (HardwareAddress::Ethernet(mac), _) = to_smoltcp_hardware_address(self.device.hardware_address()) {
+                let link_local = slaac::link_local_address(mac);
+                debug!("   Link-local:      {:?}", link_local);
+                if addrs.push(IpCidr::Ipv6(link_local)).is_err() {
+                    warn!("No room for the IPv6 link-local address, increase smoltcp's iface-max-addr-count");
+                }
+            }
+        }
+
         // Apply addresses
         s.iface.update_ip_addrs(|a| *a = addrs);
 
@@ -818,9 +1106,15 @@ impl<D: Driver> Inner<D> {
 
         // Apply DNS servers
         #[cfg(feature = "dns")]
-        s.sockets
-            .get_mut::<smoltcp::socket::dns::Socket>(self.dns_socket)
-            .update_servers(&dns_servers[..]);
+        if dns_servers != self.dns_servers {
+            s.sockets
+                .get_mut::<smoltcp::socket::dns::Socket>(self.dns_socket)
+                .update_servers(&dns_servers[..]);
+            // Answers from the previous servers may not be valid anymore, e.g. if we moved to another network.
+            #[cfg(feature = "dns-cache")]
+            self.dns_cache.clear();
+            self.dns_servers = dns_servers;
+        }
 
         self.config_waker.wake();
     }
@@ -850,6 +1144,7 @@ impl<D: Driver> Inner<D> {
             cx: Some(cx),
             inner: &mut self.device,
             medium,
+            stats: &s.stats,
         };
         s.iface.poll(timestamp, &mut smoldev, &mut s.sockets);
 
@@ -877,9 +1172,21 @@ impl<D: Driver> Inner<D> {
                     None => {}
                     Some(dhcpv4::Event::Deconfigured) => {
                         self.static_v4 = None;
+                        #[cfg(feature = "sntp")]
+                        self.ntp_servers.clear();
                         apply_config = true;
                     }
                     Some(dhcpv4::Event::Configured(config)) => {
+                        #[cfg(feature = "sntp")]
+                        {
+                            self.ntp_servers.clear();
+                            let option = config.packet.as_ref().and_then(|p| p.options().find(|o| o.kind == 42));
+                            for addr in option.iter().flat_map(|o| o.data.chunks_exact(4)) {
+                                if self.ntp_servers.push(Ipv4Address::from_bytes(addr)).is_err() {
+                                    break;
+                                }
+                            }
+                        }
                         self.static_v4 = Some(StaticConfigV4 {
                             address: config.address,
                             gateway: config.router,
@@ -891,6 +1198,36 @@ impl<D: Driver> Inner<D> {
             } else if old_link_up {
                 socket.reset();
                 self.static_v4 = None;
+                #[cfg(feature = "sntp")]
+                self.ntp_servers.clear();
+                apply_config = true;
+            }
+        }
+
+        #[cfg(feature = "slaac")]
+        if let Some(slaac) = &mut self.slaac {
+            if self.link_up {
+                if old_link_up != self.link_up {
+                    slaac.reset();
+                }
+                #[allow(irrefutable_let_patterns)]
+                if let (HardwareAddress::Ethernet(mac), _) = to_smoltcp_hardware_address(self.device.hardware_address())
+                {
+                    match slaac.poll(&mut s.sockets, mac) {
+                        None => {}
+                        Some(slaac::Event::Deconfigured) => {
+                            self.static_v6 = None;
+                            apply_config = true;
+                        }
+                        Some(slaac::Event::Configured(config)) => {
+                            self.static_v6 = Some(config);
+                            apply_config = true;
+                        }
+                    }
+                }
+            } else if old_link_up {
+                slaac.reset();
+                self.static_v6 = None;
                 apply_config = true;
             }
         }
@@ -899,8 +1236,15 @@ impl<D: Driver> Inner<D> {
             self.apply_static_config(s);
         }
 
-        if let Some(poll_at) = s.iface.poll_at(timestamp, &mut s.sockets) {
-            let t = Timer::at(instant_from_smoltcp(poll_at));
+        #[allow(unused_mut)]
+        let mut poll_at = s.iface.poll_at(timestamp, &mut s.sockets).map(instant_from_smoltcp);
+        #[cfg(feature = "slaac")]
+        if let Some(slaac_poll_at) = self.slaac.as_ref().filter(|_| self.link_up).and_then(|s| s.poll_at()) {
+            poll_at = Some(poll_at.map_or(slaac_poll_at, |t| t.min(slaac_poll_at)));
+        }
+
+        if let Some(poll_at) = poll_at {
+            let t = Timer::at(poll_at);
             pin_mut!(t);
             if t.poll(cx).is_ready() {
                 cx.waker().wake_by_ref();
@@ -0,0 +1,133 @@
+//! Two stacks connected to each other in memory, to test sockets end to end.
+
+// Not all the tests use all the helpers, depending on the features.
+#![allow(dead_code)]
+
+use core::future::Future;
+use core::task::{Context, Waker};
+use std::boxed::Box;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::vec::Vec;
+
+use embassy_futures::block_on;
+use embassy_futures::select::{select3, Either3};
+use embassy_net_driver::{Capabilities, Driver, HardwareAddress, LinkState, RxToken, TxToken};
+
+use crate::{Config, Ipv4Address, Ipv4Cidr, Stack, StackResources, StaticConfigV4};
+
+/// Address of the first stack returned by [`stacks`].
+pub(crate) const ADDR_A: Ipv4Address = Ipv4Address::new(192, 168, 0, 1);
+/// Address of the second stack returned by [`stacks`].
+pub(crate) const ADDR_B: Ipv4Address = Ipv4Address::new(192, 168, 0, 2);
+
+#[derive(Default)]
+struct Queue {
+    packets: VecDeque<Vec<u8>>,
+    waker: Option<Waker>,
+}
+
+/// A driver whose packets are received by the other driver of the pair.
+pub(crate) struct Loopback {
+    rx: Rc<RefCell<Queue>>,
+    tx: Rc<RefCell<Queue>>,
+}
+
+/// Create two drivers connected to each other.
+fn pair() -> (Loopback, Loopback) {
+    let a = Rc::new(RefCell::new(Queue::default()));
+    let b = Rc::new(RefCell::new(Queue::default()));
+    (
+        Loopback {
+            rx: a.clone(),
+            tx: b.clone(),
+        },
+        Loopback { rx: b, tx: a },
+    )
+}
+
+/// Create two stacks connected to each other, with the addresses [`ADDR_A`] and [`ADDR_B`].
+pub(crate) fn stacks<const SOCK: usize>() -> (Stack<Loopback>, Stack<Loopback>) {
+    let (a, b) = pair();
+    let stack = |device, address| {
+        let config = Config::ipv4_static(StaticConfigV4 {
+            address: Ipv4Cidr::new(address, 24),
+            gateway: None,
+            dns_servers: Default::default(),
+        });
+        let resources = Box::leak(Box::new(StackResources::<SOCK>::new()));
+        Stack::new(device, config, resources, u64::from(address.0[3]))
+    };
+    (stack(a, ADDR_A), stack(b, ADDR_B))
+}
+
+/// Run `f` to completion, while running both stacks.
+pub(crate) fn run<F: Future>(stacks: &(Stack<Loopback>, Stack<Loopback>), f: F) -> F::Output {
+    match block_on(select3(stacks.0.run(), stacks.1.run(), f)) {
+        Either3::Third(r) => r,
+    }
+}
+
+impl Driver for Loopback {
+    type RxToken<'a> = LoopbackRxToken;
+    type TxToken<'a> = LoopbackTxToken<'a>;
+
+    fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let mut rx = self.rx.borrow_mut();
+        match rx.packets.pop_front() {
+            Some(packet) => Some((LoopbackRxToken(packet), LoopbackTxToken(&self.tx))),
+            None => {
+                rx.waker = Some(cx.waker().clone());
+                None
+            }
+        }
+    }
+
+    fn transmit(&mut self, _cx: &mut Context) -> Option<Self::TxToken<'_>> {
+        Some(LoopbackTxToken(&self.tx))
+    }
+
+    fn link_state(&mut self, _cx: &mut Context) -> LinkState {
+        LinkState::Up
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let mut caps = Capabilities::default();
+        caps.max_transmission_unit = 1500;
+        caps
+    }
+
+    fn hardware_address(&self) -> HardwareAddress {
+        HardwareAddress::Ip
+    }
+}
+
+pub(crate) struct LoopbackRxToken(Vec<u8>);
+
+impl RxToken for LoopbackRxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+pub(crate) struct LoopbackTxToken<'a>(&'a Rc<RefCell<Queue>>);
+
+impl<'a> TxToken for LoopbackTxToken<'a> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut packet = std::vec![0; len];
+        let r = f(&mut packet);
+        let mut tx = self.0.borrow_mut();
+        tx.packets.push_back(packet);
+        if let Some(waker) = tx.waker.take() {
+            waker.wake();
+        }
+        r
+    }
+}
@@ -0,0 +1,758 @@
+//! mDNS responder and DNS-SD service advertisement.
+//!
+//! [`MdnsResponder`] answers multicast DNS queries for `<hostname>.local` with the addresses
+//! of the stack, and advertises the [`Service`]s registered with it using DNS-SD, so devices
+//! can be found on the local network without knowing their address.
+//!
+//! Only mDNS over IPv4 is supported: `smoltcp` can't join IPv6 multicast groups yet, so queries
+//! sent to `ff02::fb` aren't received. The IPv6 address of the stack is still advertised with an
+//! AAAA record.
+//!
+//! ## Example
+//! ```ignore
+//! let mut rx_meta = [PacketMetadata::EMPTY; 4];
+//! let mut rx_buffer = [0; 1024];
+//! let mut tx_meta = [PacketMetadata::EMPTY; 4];
+//! let mut tx_buffer = [0; 2048];
+//! let mdns = MdnsResponder::<_, 2>::new(stack, Some("sensor"), &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+//! mdns.register(Service::new("Living room sensor", "_http._tcp", 80)).unwrap();
+//! mdns.run().await;
+//! ```
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::iter;
+use core::task::Poll;
+
+use embassy_net_driver::Driver;
+use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::{Duration, Instant, Timer};
+use futures::future::{select, Either};
+use futures::pin_mut;
+use heapless::{String, Vec};
+
+use crate::udp::{PacketMetadata, UdpSocket};
+use crate::{IpEndpoint, Ipv4Address, Stack};
+
+/// IPv4 multicast address mDNS queries are sent to.
+pub const MDNS_ADDR: Ipv4Address = Ipv4Address([224, 0, 0, 251]);
+/// UDP port of mDNS.
+pub const MDNS_PORT: u16 = 5353;
+
+/// Maximum length of a DNS label, such as a hostname or a service instance name.
+pub const MAX_LABEL_LEN: usize = 63;
+const MAX_NAME_LEN: usize = 255;
+
+const MAX_QUERY_LEN: usize = 512;
+const MAX_RESPONSE_LEN: usize = 1024;
+const MAX_RECORDS: usize = 16;
+
+/// TTL of records containing a hostname (RFC 6762 section 10).
+const HOST_TTL: u32 = 120;
+/// TTL of other records (RFC 6762 section 10).
+const OTHER_TTL: u32 = 4500;
+/// Maximum TTL of records in responses to legacy unicast queries (RFC 6762 section 6.7).
+const LEGACY_TTL: u32 = 10;
+
+/// Number of unsolicited announcements (RFC 6762 section 8.3).
+const ANNOUNCE_COUNT: u8 = 2;
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+
+const HEADER_LEN: usize = 12;
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const OPCODE_MASK: u16 = 0x7800;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+#[cfg(feature = "proto-ipv6")]
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+/// Top bit of the class: "cache flush" in records, "unicast response" in questions.
+const CLASS_TOP_BIT: u16 = 0x8000;
+
+const SERVICES_NAME: [&str; 4] = ["_services", "_dns-sd", "_udp", "local"];
+
+/// A service advertised with DNS-SD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Service<'a> {
+    /// Instance name, shown to users when browsing services. For example `Living room sensor`.
+    pub instance: &'a str,
+    /// Service type, made of the service name and the transport protocol. For example `_http._tcp`.
+    pub service_type: &'a str,
+    /// Port the service listens on.
+    pub port: u16,
+    /// Entries of the TXT record, usually `key=value` pairs.
+    pub txt: &'a [&'a str],
+}
+
+impl<'a> Service<'a> {
+    /// Create a new service, with an empty TXT record.
+    pub fn new(instance: &'a str, service_type: &'a str, port: u16) -> Self {
+        Self {
+            instance,
+            service_type,
+            port,
+            txt: &[],
+        }
+    }
+
+    fn type_name(&self) -> impl Iterator<Item = &'a str> + Clone {
+        self.service_type.split('.').chain(iter::once("local"))
+    }
+
+    fn instance_name(&self) -> impl Iterator<Item = &'a str> + Clone {
+        iter::once(self.instance).chain(self.type_name())
+    }
+
+    fn is_valid(&self) -> bool {
+        let labels_valid = self.instance_name().all(|l| !l.is_empty() && l.len() <= MAX_LABEL_LEN);
+        let txt_valid = self.txt.iter().all(|t| t.len() <= u8::MAX as usize);
+        labels_valid && txt_valid
+    }
+}
+
+/// Error returned by [`MdnsResponder::register`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RegisterError {
+    /// The maximum number of services are registered already.
+    TooManyServices,
+    /// A service with the same instance name and type is registered already.
+    Duplicate,
+    /// A label of the service name is empty or longer than [`MAX_LABEL_LEN`], or a TXT entry
+    /// is longer than 255 bytes.
+    InvalidName,
+}
+
+enum Event {
+    Query(usize, IpEndpoint),
+    Announce,
+    Goodbye,
+}
+
+struct State<'a, const SERVICES: usize> {
+    services: Vec<Service<'a>, SERVICES>,
+    /// Unregistered services whose records must be withdrawn.
+    goodbyes: Vec<Service<'a>, SERVICES>,
+    announcements: u8,
+    waker: WakerRegistration,
+}
+
+/// An mDNS responder.
+///
+/// `SERVICES` is the maximum number of services that can be registered.
+pub struct MdnsResponder<'a, D: Driver, const SERVICES: usize = 4> {
+    stack: &'a Stack<D>,
+    socket: UdpSocket<'a>,
+    hostname: Option<&'a str>,
+    state: RefCell<State<'a, SERVICES>>,
+}
+
+impl<'a, D: Driver, const SERVICES: usize> MdnsResponder<'a, D, SERVICES> {
+    /// Create a new mDNS responder using the provided stack and buffers.
+    ///
+    /// The responder answers queries for `<hostname>.local`. When `hostname` is `None`, the
+    /// [`DhcpConfig::hostname`](crate::DhcpConfig::hostname) of the stack is used, if any.
+    ///
+    /// Panics if `hostname` is empty or longer than [`MAX_LABEL_LEN`].
+    pub fn new(
+        stack: &'a Stack<D>,
+        hostname: Option<&'a str>,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        if let Some(hostname) = hostname {
+            assert!(!hostname.is_empty() && hostname.len() <= MAX_LABEL_LEN);
+        }
+
+        let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+        unwrap!(socket.bind(MDNS_PORT));
+
+        Self {
+            stack,
+            socket,
+            hostname,
+            state: RefCell::new(State {
+                services: Vec::new(),
+                goodbyes: Vec::new(),
+                announcements: 0,
+                waker: WakerRegistration::new(),
+            }),
+        }
+    }
+
+    /// Get the hostname the responder answers queries for, without the `.local` suffix.
+    pub fn hostname(&self) -> Option<String<MAX_LABEL_LEN>> {
+        if let Some(hostname) = self.hostname {
+            return Some(unwrap!(String::try_from(hostname).ok()));
+        }
+
+        #[cfg(feature = "dhcpv4-hostname")]
+        if let Some(hostname) = self.stack.dhcp_hostname() {
+            return String::try_from(hostname.as_str()).ok();
+        }
+
+        None
+    }
+
+    /// Register a service to advertise.
+    ///
+    /// The service is announced on the network as soon as possible.
+    pub fn register(&self, service: Service<'a>) -> Result<(), RegisterError> {
+        if !service.is_valid() {
+            return Err(RegisterError::InvalidName);
+        }
+
+        let mut state = self.state.borrow_mut();
+        if state
+            .services
+            .iter()
+            .any(|s| s.instance == service.instance && s.service_type == service.service_type)
+        {
+            return Err(RegisterError::Duplicate);
+        }
+        state
+            .services
+            .push(service)
+            .map_err(|_| RegisterError::TooManyServices)?;
+
+        state.announcements = ANNOUNCE_COUNT;
+        state.waker.wake();
+        Ok(())
+    }
+
+    /// Stop advertising a service.
+    ///
+    /// A goodbye is sent for the records of the service, so other hosts remove them from their
+    /// caches right away.
+    ///
+    /// Returns the removed service, if any.
+    pub fn unregister(&self, instance: &str, service_type: &str) -> Option<Service<'a>> {
+        let mut state = self.state.borrow_mut();
+        let index = state
+            .services
+            .iter()
+            .position(|s| s.instance == instance && s.service_type == service_type)?;
+        let service = state.services.remove(index);
+        if state.goodbyes.push(service).is_err() {
+            warn!("mDNS: too many goodbyes pending, dropping one");
+        }
+        state.waker.wake();
+        Some(service)
+    }
+
+    /// Run the responder.
+    ///
+    /// This waits for the stack to have an IP configuration, joins the mDNS multicast group,
+    /// announces the hostname and the registered services, and then answers queries forever.
+    pub async fn run(&self) -> ! {
+        self.stack.wait_config_up().await;
+        if let Err(e) = self.stack.join_multicast_group(MDNS_ADDR).await {
+            warn!("mDNS: failed to join multicast group: {:?}", e);
+        }
+
+        self.state.borrow_mut().announcements = ANNOUNCE_COUNT;
+        let mut next_announcement = Instant::now();
+
+        let mut rx = [0; MAX_QUERY_LEN];
+        let mut tx = [0; MAX_RESPONSE_LEN];
+        loop {
+            let event = {
+                let recv = self.socket.recv_from(&mut rx);
+                let announce = async {
+                    let goodbye = poll_fn(|cx| {
+                        let mut state = self.state.borrow_mut();
+                        if !state.goodbyes.is_empty() {
+                            Poll::Ready(true)
+                        } else if state.announcements > 0 {
+                            Poll::Ready(false)
+                        } else {
+                            state.waker.register(cx.waker());
+                            Poll::Pending
+                        }
+                    })
+                    .await;
+                    if !goodbye {
+                        Timer::at(next_announcement).await;
+                    }
+                    goodbye
+                };
+                pin_mut!(recv);
+                pin_mut!(announce);
+                match select(recv, announce).await {
+                    Either::Left((Ok((n, from)), _)) => Event::Query(n, from),
+                    Either::Left((Err(_), _)) => continue,
+                    Either::Right((true, _)) => Event::Goodbye,
+                    Either::Right((false, _)) => Event::Announce,
+                }
+            };
+
+            let response = match event {
+                Event::Query(n, from) => self.respond(&rx[..n], from, &mut tx),
+                Event::Announce => {
+                    let mut state = self.state.borrow_mut();
+                    state.announcements -= 1;
+                    next_announcement = Instant::now() + ANNOUNCE_INTERVAL;
+                    drop(state);
+                    self.announce(&mut tx)
+                }
+                Event::Goodbye => self.goodbye(&mut tx),
+            };
+
+            if let Some((len, to)) = response {
+                if let Err(e) = self.socket.send_to(&tx[..len], to).await {
+                    warn!("mDNS: failed to send response: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Build an unsolicited response with all our records.
+    fn announce(&self, tx: &mut [u8]) -> Option<(usize, IpEndpoint)> {
+        let state = self.state.borrow();
+        let hostname = self.hostname();
+        let mut records = Records::default();
+
+        self.host_records(hostname.is_some(), &mut records.answers);
+        for (i, service) in state.services.iter().enumerate() {
+            if state.services[..i]
+                .iter()
+                .all(|s| s.service_type != service.service_type)
+            {
+                records.answer(Record::ServiceType(i));
+            }
+            records.answer(Record::Ptr(i));
+            if hostname.is_some() {
+                records.answer(Record::Srv(i));
+            }
+            records.answer(Record::Txt(i));
+        }
+
+        if records.answers.is_empty() {
+            return None;
+        }
+
+        trace!("mDNS: announcing {} records", records.answers.len());
+        let len = self.emit(tx, &records, hostname.as_deref(), &state.services, None, false)?;
+        Some((len, IpEndpoint::new(MDNS_ADDR.into(), MDNS_PORT)))
+    }
+
+    /// Build an unsolicited response withdrawing the records of the unregistered services, with
+    /// a TTL of 0 (RFC 6762 section 10.1).
+    fn goodbye(&self, tx: &mut [u8]) -> Option<(usize, IpEndpoint)> {
+        let mut state = self.state.borrow_mut();
+        let hostname = self.hostname();
+        let mut records = Records::default();
+
+        for (i, service) in state.goodbyes.iter().enumerate() {
+            // The service type is only withdrawn when no service of that type is left.
+            let last_of_type = !state.services.iter().any(|s| s.service_type == service.service_type);
+            if last_of_type
+                && state.goodbyes[..i]
+                    .iter()
+                    .all(|s| s.service_type != service.service_type)
+            {
+                records.answer(Record::ServiceType(i));
+            }
+            records.answer(Record::Ptr(i));
+            if hostname.is_some() {
+                records.answer(Record::Srv(i));
+            }
+            records.answer(Record::Txt(i));
+        }
+
+        trace!("mDNS: sending goodbye for {} services", state.goodbyes.len());
+        let len = self.emit(tx, &records, hostname.as_deref(), &state.goodbyes, None, true);
+        state.goodbyes.clear();
+        Some((len?, IpEndpoint::new(MDNS_ADDR.into(), MDNS_PORT)))
+    }
+
+    /// Build the response to a query, if we have any answers for it.
+    fn respond(&self, query: &[u8], from: IpEndpoint, tx: &mut [u8]) -> Option<(usize, IpEndpoint)> {
+        if query.len() < HEADER_LEN {
+            return None;
+        }
+        let flags = read_u16(query, 2)?;
+        if flags & (FLAG_RESPONSE | OPCODE_MASK) != 0 {
+            return None;
+        }
+        let question_count = read_u16(query, 4)?;
+
+        let state = self.state.borrow();
+        let hostname = self.hostname();
+        let mut records = Records::default();
+
+        // Queries not sent from the mDNS port come from simple resolvers, which expect
+        // a conventional unicast DNS response (RFC 6762 section 6.7).
+        let legacy = from.port != MDNS_PORT;
+        let mut unicast = legacy;
+
+        let mut pos = HEADER_LEN;
+        for _ in 0..question_count {
+            let mut name = Vec::<u8, MAX_NAME_LEN>::new();
+            pos = read_name(query, pos, &mut name)?;
+            let qtype = read_u16(query, pos)?;
+            let qclass = read_u16(query, pos + 2)?;
+            pos += 4;
+
+            if qclass & CLASS_TOP_BIT != 0 {
+                unicast = true;
+            }
+            if !matches!(qclass & !CLASS_TOP_BIT, CLASS_IN | CLASS_ANY) {
+                continue;
+            }
+
+            self.answer(&name, qtype, hostname.as_deref(), &state.services, &mut records);
+        }
+
+        if records.answers.is_empty() {
+            return None;
+        }
+        records.additionals.retain(|r| !records.answers.contains(r));
+
+        let questions = legacy.then(|| (read_u16(query, 0).unwrap_or(0), question_count, &query[HEADER_LEN..pos]));
+        let len = self.emit(tx, &records, hostname.as_deref(), &state.services, questions, false)?;
+        let to = match unicast {
+            true => from,
+            false => IpEndpoint::new(MDNS_ADDR.into(), MDNS_PORT),
+        };
+        Some((len, to))
+    }
+
+    fn answer(&self, name: &[u8], qtype: u16, hostname: Option<&str>, services: &[Service<'a>], records: &mut Records) {
+        if let Some(hostname) = hostname {
+            if name_eq(name, [hostname, "local"]) {
+                let mut host = Vec::<Record, 2>::new();
+                self.host_records(true, &mut host);
+                for r in host {
+                    if qtype == TYPE_ANY || qtype == r.rtype() {
+                        records.answer(r);
+                    }
+                }
+            }
+        }
+
+        let any = qtype == TYPE_ANY;
+        let services_query = name_eq(name, SERVICES_NAME) && (any || qtype == TYPE_PTR);
+        for (i, service) in services.iter().enumerate() {
+            if services_query && services[..i].iter().all(|s| s.service_type != service.service_type) {
+                records.answer(Record::ServiceType(i));
+            }
+
+            if name_eq(name, service.type_name()) && (any || qtype == TYPE_PTR) {
+                records.answer(Record::Ptr(i));
+                if hostname.is_some() {
+                    records.additional(Record::Srv(i));
+                }
+                records.additional(Record::Txt(i));
+                self.host_records(hostname.is_some(), &mut records.additionals);
+            }
+
+            if name_eq(name, service.instance_name()) {
+                if hostname.is_some() && (any || qtype == TYPE_SRV) {
+                    records.answer(Record::Srv(i));
+                    self.host_records(true, &mut records.additionals);
+                }
+                if any || qtype == TYPE_TXT {
+                    records.answer(Record::Txt(i));
+                }
+            }
+        }
+    }
+
+    /// Add the address records of the host, if it has a hostname.
+    fn host_records<const N: usize>(&self, has_hostname: bool, records: &mut Vec<Record, N>) {
+        if !has_hostname {
+            return;
+        }
+        if let Some(config) = self.stack.config_v4() {
+            push_unique(records, Record::A(config.address.address()));
+        }
+        #[cfg(feature = "proto-ipv6")]
+        if let Some(config) = self.stack.config_v6() {
+            push_unique(records, Record::Aaaa(config.address.address()));
+        }
+    }
+
+    /// Write a response with the given records. `questions` holds the ID, question count and
+    /// questions to copy from a legacy unicast query. With `goodbye`, the records have a TTL of 0.
+    ///
+    /// Returns the length of the response.
+    fn emit(
+        &self,
+        buf: &mut [u8],
+        records: &Records,
+        hostname: Option<&str>,
+        services: &[Service<'a>],
+        questions: Option<(u16, u16, &[u8])>,
+        goodbye: bool,
+    ) -> Option<usize> {
+        let mut w = Writer { buf, pos: 0 };
+        let (id, question_count, question_data) = questions.unwrap_or((0, 0, &[]));
+        let legacy = questions.is_some();
+
+        w.u16(id)?;
+        w.u16(FLAG_RESPONSE | FLAG_AUTHORITATIVE)?;
+        w.u16(question_count)?;
+        // The answer and additional counts are filled in below.
+        w.u16(0)?;
+        w.u16(0)?;
+        w.u16(0)?;
+        // The questions are copied right after the header, where they were in the query,
+        // so compressed names in them stay valid.
+        w.bytes(question_data)?;
+
+        let mut answer_count = 0;
+        for r in &records.answers {
+            if r.emit(&mut w, hostname, services, legacy, goodbye).is_none() {
+                warn!("mDNS: response too long, dropping records");
+                break;
+            }
+            answer_count += 1;
+        }
+        let mut additional_count = 0;
+        if answer_count == records.answers.len() {
+            for r in &records.additionals {
+                if r.emit(&mut w, hostname, services, legacy, goodbye).is_none() {
+                    break;
+                }
+                additional_count += 1;
+            }
+        }
+
+        if answer_count == 0 {
+            return None;
+        }
+        w.buf[6..8].copy_from_slice(&(answer_count as u16).to_be_bytes());
+        w.buf[10..12].copy_from_slice(&(additional_count as u16).to_be_bytes());
+        Some(w.pos)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Record {
+    A(Ipv4Address),
+    #[cfg(feature = "proto-ipv6")]
+    Aaaa(crate::Ipv6Address),
+    /// `_services._dns-sd._udp.local` PTR to the type of the service with the given index.
+    ServiceType(usize),
+    /// Service type PTR to the instance of the service with the given index.
+    Ptr(usize),
+    Srv(usize),
+    Txt(usize),
+}
+
+impl Record {
+    fn rtype(&self) -> u16 {
+        match self {
+            Record::A(_) => TYPE_A,
+            #[cfg(feature = "proto-ipv6")]
+            Record::Aaaa(_) => TYPE_AAAA,
+            Record::ServiceType(_) | Record::Ptr(_) => TYPE_PTR,
+            Record::Srv(_) => TYPE_SRV,
+            Record::Txt(_) => TYPE_TXT,
+        }
+    }
+
+    fn emit(
+        &self,
+        w: &mut Writer,
+        hostname: Option<&str>,
+        services: &[Service],
+        legacy: bool,
+        goodbye: bool,
+    ) -> Option<()> {
+        // Records only we can answer for get the cache flush bit, shared PTR records don't.
+        let (ttl, unique) = match self {
+            Record::A(_) | Record::Srv(_) => (HOST_TTL, true),
+            #[cfg(feature = "proto-ipv6")]
+            Record::Aaaa(_) => (HOST_TTL, true),
+            Record::Txt(_) => (OTHER_TTL, true),
+            Record::ServiceType(_) | Record::Ptr(_) => (OTHER_TTL, false),
+        };
+        let ttl = if goodbye { 0 } else { ttl };
+        let (ttl, class) = match legacy {
+            true => (ttl.min(LEGACY_TTL), CLASS_IN),
+            false if unique => (ttl, CLASS_IN | CLASS_TOP_BIT),
+            false => (ttl, CLASS_IN),
+        };
+
+        match *self {
+            Record::A(addr) => {
+                w.name([hostname?, "local"])?;
+                w.record_header(TYPE_A, class, ttl)?;
+                w.rdata(|w| w.bytes(addr.as_bytes()))
+            }
+            #[cfg(feature = "proto-ipv6")]
+            Record::Aaaa(addr) => {
+                w.name([hostname?, "local"])?;
+                w.record_header(TYPE_AAAA, class, ttl)?;
+                w.rdata(|w| w.bytes(addr.as_bytes()))
+            }
+            Record::ServiceType(i) => {
+                w.name(SERVICES_NAME)?;
+                w.record_header(TYPE_PTR, class, ttl)?;
+                w.rdata(|w| w.name(services[i].type_name()))
+            }
+            Record::Ptr(i) => {
+                w.name(services[i].type_name())?;
+                w.record_header(TYPE_PTR, class, ttl)?;
+                w.rdata(|w| w.name(services[i].instance_name()))
+            }
+            Record::Srv(i) => {
+                w.name(services[i].instance_name())?;
+                w.record_header(TYPE_SRV, class, ttl)?;
+                w.rdata(|w| {
+                    w.u16(0)?; // priority
+                    w.u16(0)?; // weight
+                    w.u16(services[i].port)?;
+                    w.name([hostname?, "local"])
+                })
+            }
+            Record::Txt(i) => {
+                w.name(services[i].instance_name())?;
+                w.record_header(TYPE_TXT, class, ttl)?;
+                w.rdata(|w| {
+                    // An empty TXT record is a single empty string (RFC 6763 section 6.1).
+                    if services[i].txt.is_empty() {
+                        return w.u8(0);
+                    }
+                    for entry in services[i].txt {
+                        w.u8(entry.len() as u8)?;
+                        w.bytes(entry.as_bytes())?;
+                    }
+                    Some(())
+                })
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Records {
+    answers: Vec<Record, MAX_RECORDS>,
+    additionals: Vec<Record, MAX_RECORDS>,
+}
+
+impl Records {
+    fn answer(&mut self, record: Record) {
+        push_unique(&mut self.answers, record)
+    }
+
+    fn additional(&mut self, record: Record) {
+        push_unique(&mut self.additionals, record)
+    }
+}
+
+fn push_unique<const N: usize>(records: &mut Vec<Record, N>, record: Record) {
+    if !records.contains(&record) && records.push(record).is_err() {
+        warn!("mDNS: too many records in response");
+    }
+}
+
+struct Writer<'b> {
+    buf: &'b mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn bytes(&mut self, data: &[u8]) -> Option<()> {
+        let end = self.pos + data.len();
+        self.buf.get_mut(self.pos..end)?.copy_from_slice(data);
+        self.pos = end;
+        Some(())
+    }
+
+    fn u8(&mut self, val: u8) -> Option<()> {
+        self.bytes(&[val])
+    }
+
+    fn u16(&mut self, val: u16) -> Option<()> {
+        self.bytes(&val.to_be_bytes())
+    }
+
+    fn u32(&mut self, val: u32) -> Option<()> {
+        self.bytes(&val.to_be_bytes())
+    }
+
+    /// Write an uncompressed name made of the given labels.
+    fn name<'l>(&mut self, labels: impl IntoIterator<Item = &'l str>) -> Option<()> {
+        for label in labels {
+            self.u8(label.len() as u8)?;
+            self.bytes(label.as_bytes())?;
+        }
+        self.u8(0)
+    }
+
+    fn record_header(&mut self, rtype: u16, class: u16, ttl: u32) -> Option<()> {
+        self.u16(rtype)?;
+        self.u16(class)?;
+        self.u32(ttl)
+    }
+
+    /// Write record data prefixed by its length.
+    fn rdata(&mut self, f: impl FnOnce(&mut Self) -> Option<()>) -> Option<()> {
+        let start = self.pos;
+        self.u16(0)?;
+        f(self)?;
+        let len = (self.pos - start - 2) as u16;
+        self.buf[start..start + 2].copy_from_slice(&len.to_be_bytes());
+        Some(())
+    }
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(buf.get(pos..pos + 2)?.try_into().ok()?))
+}
+
+/// Read the name at `pos` in `packet` into `name`, following compression pointers.
+///
+/// The name is stored uncompressed, as length-prefixed labels ending with an empty label.
+/// Returns the position after the name.
+fn read_name<const N: usize>(packet: &[u8], mut pos: usize, name: &mut Vec<u8, N>) -> Option<usize> {
+    let mut end = None;
+    // Bound the number of pointers followed, so pointer loops can't hang us.
+    for _ in 0..MAX_NAME_LEN {
+        let len = *packet.get(pos)? as usize;
+        match len {
+            0 => {
+                name.push(0).ok()?;
+                return Some(end.unwrap_or(pos + 1));
+            }
+            l if l & 0xC0 == 0xC0 => {
+                let pointer = read_u16(packet, pos)? & 0x3FFF;
+                end.get_or_insert(pos + 2);
+                pos = pointer as usize;
+            }
+            l if l <= MAX_LABEL_LEN => {
+                name.extend_from_slice(packet.get(pos..pos + 1 + l)?).ok()?;
+                pos += 1 + l;
+            }
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Compare a name read with [`read_name`] to the given labels, ignoring ASCII case.
+fn name_eq<'l>(name: &[u8], labels: impl IntoIterator<Item = &'l str>) -> bool {
+    let mut pos = 0;
+    for label in labels {
+        let Some(&len) = name.get(pos) else { return false };
+        let len = len as usize;
+        if len != label.len() || !name[pos + 1..pos + 1 + len].eq_ignore_ascii_case(label.as_bytes()) {
+            return false;
+        }
+        pos += 1 + len;
+    }
+    name.get(pos) == Some(&0)
+}
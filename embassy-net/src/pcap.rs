@@ -0,0 +1,267 @@
+//! Packet capture in pcap format.
+//!
+//! [`PcapDriver`] wraps a [`Driver`] and copies every frame it receives and transmits,
+//! with a pcap record header, to a [`Pipe`]. [`run`] writes the capture from the pipe to a
+//! sink such as a UART, a USB serial port or, with the `std` feature, a [`PcapFile`]. The
+//! resulting stream can be opened with Wireshark.
+//!
+//! Frames that don't fit in the pipe because the sink can't keep up are left out of the
+//! capture, and counted in [`PcapDriver::dropped`].
+//!
+//! ## Example
+//! ```ignore
+//! static PIPE: Pipe<NoopRawMutex, 4096> = Pipe::new();
+//!
+//! let device = PcapDriver::new(device, &PIPE);
+//! let stack = Stack::new(device, config, resources, seed);
+//!
+//! // In another task:
+//! pcap::run(&PIPE, PcapFile::create("capture.pcap")?).await
+//! ```
+
+use core::cell::Cell;
+use core::convert::Infallible;
+use core::task::Context;
+
+use embassy_net_driver::{Capabilities, Driver, HardwareAddress, LinkState, RxMeta, RxToken, Stats, TxMeta, TxToken};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::pipe::Pipe;
+use embassy_time::Instant;
+use embedded_io_async::Write;
+
+/// `LINKTYPE_ETHERNET`
+const LINKTYPE_ETHERNET: u32 = 1;
+/// `LINKTYPE_RAW`, IPv4 or IPv6 packets without a link-layer header.
+const LINKTYPE_RAW: u32 = 101;
+/// `LINKTYPE_IEEE802_15_4_NOFCS`
+const LINKTYPE_IEEE802_15_4_NOFCS: u32 = 230;
+
+/// Maximum length of the captured frames, in the pcap file header.
+const SNAPLEN: u32 = 65535;
+
+const FILE_HEADER_LEN: usize = 24;
+const RECORD_HEADER_LEN: usize = 16;
+
+/// A [`Driver`] that captures the frames going through another driver.
+///
+/// The pipe capacity `N` must be large enough for the pcap file header and at least one record header.
+/// Frames longer than the pipe capacity are truncated in the capture.
+pub struct PcapDriver<'a, D: Driver, M: RawMutex, const N: usize> {
+    inner: D,
+    pipe: &'a Pipe<M, N>,
+    dropped: Cell<u32>,
+}
+
+impl<'a, D: Driver, M: RawMutex, const N: usize> PcapDriver<'a, D, M, N> {
+    /// Create a new capturing driver, writing the capture of the frames of `inner` to `pipe`.
+    ///
+    /// The pcap file header is written to `pipe` right away, so it must be empty.
+    pub fn new(inner: D, pipe: &'a Pipe<M, N>) -> Self {
+        assert!(N >= FILE_HEADER_LEN + RECORD_HEADER_LEN, "pipe too small for capture");
+        assert!(pipe.is_empty(), "pipe not empty");
+
+        let linktype = match inner.hardware_address() {
+            HardwareAddress::Ethernet(_) => LINKTYPE_ETHERNET,
+            HardwareAddress::Ieee802154(_) => LINKTYPE_IEEE802_15_4_NOFCS,
+            HardwareAddress::Ip => LINKTYPE_RAW,
+            addr => panic!("Unsupported medium {:?} for capture.", addr),
+        };
+
+        let mut header = [0; FILE_HEADER_LEN];
+        header[0..4].copy_from_slice(&0xa1b2c3d4u32.to_le_bytes());
+        header[4..6].copy_from_slice(&2u16.to_le_bytes());
+        header[6..8].copy_from_slice(&4u16.to_le_bytes());
+        // Time zone offset and timestamp accuracy are left to 0.
+        header[16..20].copy_from_slice(&SNAPLEN.to_le_bytes());
+        header[20..24].copy_from_slice(&linktype.to_le_bytes());
+        write_all(pipe, &header);
+
+        Self {
+            inner,
+            pipe,
+            dropped: Cell::new(0),
+        }
+    }
+
+    /// Get the number of frames left out of the capture because the pipe was full.
+    pub fn dropped(&self) -> u32 {
+        self.dropped.get()
+    }
+
+    /// Get the wrapped driver.
+    pub fn inner(&mut self) -> &mut D {
+        &mut self.inner
+    }
+}
+
+fn write_all<M: RawMutex, const N: usize>(pipe: &Pipe<M, N>, mut buf: &[u8]) {
+    // The pipe wraps around, so a single write might not take all of `buf` even if it fits.
+    while !buf.is_empty() {
+        let n = unwrap!(pipe.try_write(buf).ok());
+        buf = &buf[n..];
+    }
+}
+
+fn capture<M: RawMutex, const N: usize>(pipe: &Pipe<M, N>, dropped: &Cell<u32>, frame: &[u8]) {
+    let len = frame.len().min(N - RECORD_HEADER_LEN);
+    if pipe.free_capacity() < RECORD_HEADER_LEN + len {
+        dropped.set(dropped.get().wrapping_add(1));
+        return;
+    }
+
+    let micros = Instant::now().as_micros();
+    let mut header = [0; RECORD_HEADER_LEN];
+    header[0..4].copy_from_slice(&((micros / 1_000_000) as u32).to_le_bytes());
+    header[4..8].copy_from_slice(&((micros % 1_000_000) as u32).to_le_bytes());
+    header[8..12].copy_from_slice(&(len as u32).to_le_bytes());
+    header[12..16].copy_from_slice(&(frame.len() as u32).to_le_bytes());
+    write_all(pipe, &header);
+    write_all(pipe, &frame[..len]);
+}
+
+impl<'a, D: Driver, M: RawMutex, const N: usize> Driver for PcapDriver<'a, D, M, N> {
+    type RxToken<'b> = PcapRxToken<'b, D::RxToken<'b>, M, N> where Self: 'b;
+    type TxToken<'b> = PcapTxToken<'b, D::TxToken<'b>, M, N> where Self: 'b;
+
+    fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let (pipe, dropped) = (self.pipe, &self.dropped);
+        self.inner.receive(cx).map(|(rx, tx)| {
+            (
+                PcapRxToken {
+                    inner: rx,
+                    pipe,
+                    dropped,
+                },
+                PcapTxToken {
+                    inner: tx,
+                    pipe,
+                    dropped,
+                },
+            )
+        })
+    }
+
+    fn transmit(&mut self, cx: &mut Context) -> Option<Self::TxToken<'_>> {
+        let (pipe, dropped) = (self.pipe, &self.dropped);
+        self.inner
+            .transmit(cx)
+            .map(|inner| PcapTxToken { inner, pipe, dropped })
+    }
+
+    fn link_state(&mut self, cx: &mut Context) -> LinkState {
+        self.inner.link_state(cx)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+
+    fn hardware_address(&self) -> HardwareAddress {
+        self.inner.hardware_address()
+    }
+
+    fn stats(&self) -> Stats {
+        self.inner.stats()
+    }
+
+    fn set_multicast_filter(&mut self, addresses: &[[u8; 6]]) {
+        self.inner.set_multicast_filter(addresses)
+    }
+}
+
+/// RX token of a [`PcapDriver`].
+pub struct PcapRxToken<'a, T: RxToken, M: RawMutex, const N: usize> {
+    inner: T,
+    pipe: &'a Pipe<M, N>,
+    dropped: &'a Cell<u32>,
+}
+
+impl<'a, T: RxToken, M: RawMutex, const N: usize> RxToken for PcapRxToken<'a, T, M, N> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.inner.consume(|buf| {
+            capture(self.pipe, self.dropped, buf);
+            f(buf)
+        })
+    }
+
+    fn meta(&self) -> RxMeta {
+        self.inner.meta()
+    }
+}
+
+/// TX token of a [`PcapDriver`].
+pub struct PcapTxToken<'a, T: TxToken, M: RawMutex, const N: usize> {
+    inner: T,
+    pipe: &'a Pipe<M, N>,
+    dropped: &'a Cell<u32>,
+}
+
+impl<'a, T: TxToken, M: RawMutex, const N: usize> TxToken for PcapTxToken<'a, T, M, N> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        self.inner.consume(len, |buf| {
+            let r = f(buf);
+            capture(self.pipe, self.dropped, buf);
+            r
+        })
+    }
+
+    fn set_meta(&mut self, meta: TxMeta) {
+        self.inner.set_meta(meta)
+    }
+}
+
+/// Write the capture from `pipe` to `sink`.
+///
+/// The sink is flushed every time data is written to it, so the capture can be followed live.
+/// This function only returns if writing to the sink fails.
+pub async fn run<M: RawMutex, const N: usize, W: Write>(
+    pipe: &Pipe<M, N>,
+    mut sink: W,
+) -> Result<Infallible, W::Error> {
+    let mut buf = [0; 256];
+    loop {
+        let n = pipe.read(&mut buf).await;
+        sink.write_all(&buf[..n]).await?;
+        sink.flush().await?;
+    }
+}
+
+/// A file to write a capture to with [`run`].
+///
+/// Writes block the executor, which is fine for tests and debugging on a host.
+#[cfg(feature = "std")]
+pub struct PcapFile {
+    file: std::fs::File,
+}
+
+#[cfg(feature = "std")]
+impl PcapFile {
+    /// Create the capture file, truncating it if it exists.
+    pub fn create(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self {
+            file: std::fs::File::create(path)?,
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl embedded_io_async::ErrorType for PcapFile {
+    type Error = std::io::Error;
+}
+
+#[cfg(feature = "std")]
+impl Write for PcapFile {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        std::io::Write::write(&mut self.file, buf)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        std::io::Write::flush(&mut self.file)
+    }
+}
@@ -0,0 +1,187 @@
+//! Raw sockets.
+//!
+//! Raw sockets send and receive whole IP packets of a given IP version and protocol number,
+//! header included. They can be used to implement protocols that `embassy-net` doesn't
+//! handle itself, such as VRRP or OSPF.
+
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::mem;
+use core::task::{Context, Poll};
+
+use embassy_net_driver::Driver;
+use smoltcp::iface::{Interface, SocketHandle};
+use smoltcp::socket::raw;
+pub use smoltcp::socket::raw::PacketMetadata;
+pub use smoltcp::wire::{IpProtocol, IpVersion};
+
+use crate::{SocketStack, Stack};
+
+/// Error returned by [`RawSocket::recv`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RecvError {
+    /// The provided buffer was too small to hold the packet.
+    ///
+    /// The packet is dropped.
+    Truncated,
+}
+
+/// A raw socket.
+pub struct RawSocket<'a> {
+    stack: &'a RefCell<SocketStack>,
+    handle: SocketHandle,
+}
+
+impl<'a> RawSocket<'a> {
+    /// Create a new raw socket using the provided stack and buffers.
+    ///
+    /// The socket receives all packets with the given IP version and protocol number.
+    /// Note that these packets are still processed by the stack as well: for example, a raw
+    /// socket for ICMP doesn't prevent the stack from answering echo requests.
+    pub fn new<D: Driver>(
+        stack: &'a Stack<D>,
+        ip_version: IpVersion,
+        ip_protocol: IpProtocol,
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        let s = &mut *stack.socket.borrow_mut();
+
+        let rx_meta: &'static mut [PacketMetadata] = unsafe { mem::transmute(rx_meta) };
+        let rx_buffer: &'static mut [u8] = unsafe { mem::transmute(rx_buffer) };
+        let tx_meta: &'static mut [PacketMetadata] = unsafe { mem::transmute(tx_meta) };
+        let tx_buffer: &'static mut [u8] = unsafe { mem::transmute(tx_buffer) };
+        let handle = s.sockets.add(raw::Socket::new(
+            ip_version,
+            ip_protocol,
+            raw::PacketBuffer::new(rx_meta, rx_buffer),
+            raw::PacketBuffer::new(tx_meta, tx_buffer),
+        ));
+
+        Self {
+            stack: &stack.socket,
+            handle,
+        }
+    }
+
+    fn with<R>(&self, f: impl FnOnce(&raw::Socket, &Interface) -> R) -> R {
+        let s = &*self.stack.borrow();
+        let socket = s.sockets.get::<raw::Socket>(self.handle);
+        f(socket, &s.iface)
+    }
+
+    fn with_mut<R>(&self, f: impl FnOnce(&mut raw::Socket, &mut Interface) -> R) -> R {
+        let s = &mut *self.stack.borrow_mut();
+        let socket = s.sockets.get_mut::<raw::Socket>(self.handle);
+        let res = f(socket, &mut s.iface);
+        s.waker.wake();
+        res
+    }
+
+    /// Receive a packet.
+    ///
+    /// This method will wait until a packet is received.
+    ///
+    /// Returns the number of bytes received. The received data starts with the IP header.
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize, RecvError> {
+        poll_fn(move |cx| self.poll_recv(buf, cx)).await
+    }
+
+    /// Receive a packet.
+    ///
+    /// When no packet is available, this method will return `Poll::Pending` and
+    /// register the current task to be notified when a packet is received.
+    ///
+    /// When a packet is received, this method will return `Poll::Ready` with the
+    /// number of bytes received.
+    pub fn poll_recv(&self, buf: &mut [u8], cx: &mut Context<'_>) -> Poll<Result<usize, RecvError>> {
+        self.with_mut(|s, _| match s.recv() {
+            Ok(packet) if packet.len() > buf.len() => Poll::Ready(Err(RecvError::Truncated)),
+            Ok(packet) => {
+                buf[..packet.len()].copy_from_slice(packet);
+                Poll::Ready(Ok(packet.len()))
+            }
+            // No data ready
+            Err(raw::RecvError::Exhausted) => {
+                s.register_recv_waker(cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+
+    /// Send a packet.
+    ///
+    /// The packet must start with the IP header. Its protocol must match the protocol of
+    /// the socket, or the packet is dropped. For IPv4, the header checksum is filled in by the stack.
+    ///
+    /// This method will wait until the packet has been queued for sending.
+    pub async fn send(&self, buf: &[u8]) {
+        poll_fn(move |cx| self.poll_send(buf, cx)).await
+    }
+
+    /// Send a packet.
+    ///
+    /// When the packet has been queued for sending, this method will return `Poll::Ready(())`.
+    ///
+    /// When the socket's send buffer is full, this method will return `Poll::Pending`
+    /// and register the current task to be notified when the buffer has space available.
+    pub fn poll_send(&self, buf: &[u8], cx: &mut Context<'_>) -> Poll<()> {
+        self.with_mut(|s, _| match s.send_slice(buf) {
+            // Entire packet has been queued
+            Ok(()) => Poll::Ready(()),
+            Err(raw::SendError::BufferFull) => {
+                s.register_send_waker(cx.waker());
+                Poll::Pending
+            }
+        })
+    }
+
+    /// Returns the IP version of the socket.
+    pub fn ip_version(&self) -> IpVersion {
+        self.with(|s, _| s.ip_version())
+    }
+
+    /// Returns the IP protocol of the socket.
+    pub fn ip_protocol(&self) -> IpProtocol {
+        self.with(|s, _| s.ip_protocol())
+    }
+
+    /// Returns whether the socket is ready to send data, i.e. it has enough buffer space to hold a packet.
+    pub fn may_send(&self) -> bool {
+        self.with(|s, _| s.can_send())
+    }
+
+    /// Returns whether the socket is ready to receive data, i.e. it has received a packet that's now in the buffer.
+    pub fn may_recv(&self) -> bool {
+        self.with(|s, _| s.can_recv())
+    }
+
+    /// Return the maximum number packets the socket can receive.
+    pub fn packet_recv_capacity(&self) -> usize {
+        self.with(|s, _| s.packet_recv_capacity())
+    }
+
+    /// Return the maximum number packets the socket can send.
+    pub fn packet_send_capacity(&self) -> usize {
+        self.with(|s, _| s.packet_send_capacity())
+    }
+
+    /// Return the maximum number of bytes inside the recv buffer.
+    pub fn payload_recv_capacity(&self) -> usize {
+        self.with(|s, _| s.payload_recv_capacity())
+    }
+
+    /// Return the maximum number of bytes inside the transmit buffer.
+    pub fn payload_send_capacity(&self) -> usize {
+        self.with(|s, _| s.payload_send_capacity())
+    }
+}
+
+impl Drop for RawSocket<'_> {
+    fn drop(&mut self) {
+        self.stack.borrow_mut().sockets.remove(self.handle);
+    }
+}
@@ -0,0 +1,250 @@
+//! Picking the interface to reach a destination through, among several stacks.
+//!
+//! A [`Router`] groups several independent [`Stack`]s, each with its own driver,
+//! [`Config`](crate::Config), resources and task calling [`Stack::run`], and keeps a routing table
+//! to pick the one to reach a destination through. It is not a stack itself: packets are not
+//! forwarded between the interfaces, and a socket only ever uses the stack it was created on.
+//!
+//! The router only picks the interface: sockets are still created on a [`Stack`], the one of the
+//! [`Interface`] returned by [`Router::route`] for the remote address.
+//!
+//! ## Example
+//! ```ignore
+//! let router = Router::<2>::new([Interface::new("eth0", eth_stack), Interface::new("ppp0", ppp_stack)]);
+//! // Use the modem for everything that isn't on the local network.
+//! router.add_route(Route::new(IpCidr::new(Ipv4Address::UNSPECIFIED.into(), 0), "ppp0")).unwrap();
+//!
+//! let mut socket = match router.route(remote.addr).map(|i| i.name()) {
+//!     Some("eth0") => TcpSocket::new(eth_stack, &mut rx_buffer, &mut tx_buffer),
+//!     Some("ppp0") => TcpSocket::new(ppp_stack, &mut rx_buffer, &mut tx_buffer),
+//!     _ => return Err(Error::NoRoute),
+//! };
+//! socket.connect(remote).await?;
+//! ```
+
+use core::cell::RefCell;
+
+use embassy_net_driver::Driver;
+use heapless::Vec;
+
+use crate::{IpAddress, IpCidr, SocketStack, Stack, Stats};
+
+/// The parts of a [`Stack`] a [`Router`] uses, whatever its driver.
+trait RoutedStack {
+    fn socket_stack(&self) -> &RefCell<SocketStack>;
+    fn is_link_up(&self) -> bool;
+    fn is_config_up(&self) -> bool;
+    fn stats(&self) -> Stats;
+}
+
+impl<D: Driver> RoutedStack for Stack<D> {
+    fn socket_stack(&self) -> &RefCell<SocketStack> {
+        &self.socket
+    }
+
+    fn is_link_up(&self) -> bool {
+        self.is_link_up()
+    }
+
+    fn is_config_up(&self) -> bool {
+        self.is_config_up()
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats()
+    }
+}
+
+/// A network interface of a [`Router`], backed by a [`Stack`].
+pub struct Interface<'a> {
+    name: &'static str,
+    stack: &'a dyn RoutedStack,
+}
+
+impl<'a> Interface<'a> {
+    /// Create a new interface with the given name, using the given stack.
+    pub fn new<D: Driver>(name: &'static str, stack: &'a Stack<D>) -> Self {
+        Self { name, stack }
+    }
+
+    /// Get the name of the interface.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Get whether the link of the interface is up.
+    pub fn is_link_up(&self) -> bool {
+        self.stack.is_link_up()
+    }
+
+    /// Get whether the interface has a valid IP configuration.
+    pub fn is_config_up(&self) -> bool {
+        self.stack.is_config_up()
+    }
+
+    /// Get the statistics of the interface.
+    pub fn stats(&self) -> Stats {
+        self.stack.stats()
+    }
+
+    /// Get whether the interface can currently be used for routing: its link is up and it has a valid IP configuration.
+    fn is_up(&self) -> bool {
+        self.is_link_up() && self.is_config_up()
+    }
+
+    /// Get the prefix length of the interface address whose subnet contains `addr`, if any.
+    fn on_link_prefix_len(&self, addr: &IpAddress) -> Option<u8> {
+        let s = self.stack.socket_stack().borrow();
+        s.iface
+            .ip_addrs()
+            .iter()
+            .filter(|cidr| cidr.contains_addr(addr))
+            .map(|cidr| cidr.prefix_len())
+            .max()
+    }
+}
+
+/// A route in the routing table of a [`Router`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Route {
+    /// Destination network of the route.
+    pub destination: IpCidr,
+    /// Name of the interface to reach the destination network through.
+    pub interface: &'static str,
+    /// Metric of the route. Among the routes to equally specific networks, the one with
+    /// the lowest metric is used.
+    pub metric: u32,
+}
+
+impl Route {
+    /// Create a new route with a metric of 0.
+    pub fn new(destination: IpCidr, interface: &'static str) -> Self {
+        Self {
+            destination,
+            interface,
+            metric: 0,
+        }
+    }
+}
+
+/// Error returned by [`Router::add_route`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum RouteError {
+    /// There's no interface with the name given in the route.
+    UnknownInterface,
+    /// The routing table is full.
+    TableFull,
+}
+
+/// A routing table over several stacks, picking the one to reach a destination through.
+///
+/// `N` is the number of interfaces, and `ROUTES` the capacity of the routing table.
+pub struct Router<'a, const N: usize, const ROUTES: usize = 8> {
+    interfaces: [Interface<'a>; N],
+    routes: RefCell<Vec<Route, ROUTES>>,
+}
+
+impl<'a, const N: usize, const ROUTES: usize> Router<'a, N, ROUTES> {
+    /// Create a new router over the given interfaces, with an empty routing table.
+    ///
+    /// Panics if two interfaces have the same name.
+    pub fn new(interfaces: [Interface<'a>; N]) -> Self {
+        for (i, a) in interfaces.iter().enumerate() {
+            assert!(
+                interfaces[..i].iter().all(|b| b.name != a.name),
+                "duplicate interface name"
+            );
+        }
+
+        Self {
+            interfaces,
+            routes: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Get the interfaces of the stack.
+    pub fn interfaces(&self) -> &[Interface<'a>] {
+        &self.interfaces
+    }
+
+    /// Get the interface with the given name.
+    pub fn interface(&self, name: &str) -> Option<&Interface<'a>> {
+        self.interfaces.iter().find(|i| i.name == name)
+    }
+
+    /// Add a route to the routing table.
+    ///
+    /// A route to the same destination through the same interface is replaced.
+    pub fn add_route(&self, route: Route) -> Result<(), RouteError> {
+        if self.interface(route.interface).is_none() {
+            return Err(RouteError::UnknownInterface);
+        }
+
+        let mut routes = self.routes.borrow_mut();
+        if let Some(r) = routes
+            .iter_mut()
+            .find(|r| r.destination == route.destination && r.interface == route.interface)
+        {
+            *r = route;
+            return Ok(());
+        }
+        routes.push(route).map_err(|_| RouteError::TableFull)
+    }
+
+    /// Remove the route to `destination` through the given interface from the routing table.
+    ///
+    /// Returns the removed route, if any.
+    pub fn remove_route(&self, destination: IpCidr, interface: &str) -> Option<Route> {
+        let mut routes = self.routes.borrow_mut();
+        let index = routes
+            .iter()
+            .position(|r| r.destination == destination && r.interface == interface)?;
+        Some(routes.swap_remove(index))
+    }
+
+    /// Remove all routes from the routing table.
+    pub fn clear_routes(&self) {
+        self.routes.borrow_mut().clear();
+    }
+
+    /// Get the routes in the routing table.
+    pub fn routes(&self) -> Vec<Route, ROUTES> {
+        self.routes.borrow().clone()
+    }
+
+    /// Get the interface to reach `addr` through.
+    ///
+    /// Only interfaces whose link is up and that have a valid IP configuration are considered.
+    /// The subnets the interfaces have an address in act as routes with a metric of 0, in addition
+    /// to the routes in the routing table. The most specific route is used, and the one with the lowest
+    /// metric among equally specific ones.
+    ///
+    /// Returns `None` if there's no route to `addr`.
+    pub fn route(&self, addr: IpAddress) -> Option<&Interface<'a>> {
+        // (prefix length, metric, interface)
+        let mut best: Option<(u8, u32, &Interface<'a>)> = None;
+        let mut consider = |prefix_len: u8, metric: u32, iface| match best {
+            Some((p, m, _)) if p > prefix_len || (p == prefix_len && m <= metric) => {}
+            _ => best = Some((prefix_len, metric, iface)),
+        };
+
+        for iface in self.interfaces.iter().filter(|i| i.is_up()) {
+            if let Some(prefix_len) = iface.on_link_prefix_len(&addr) {
+                consider(prefix_len, 0, iface);
+            }
+        }
+
+        for route in self.routes.borrow().iter() {
+            if !route.destination.contains_addr(&addr) {
+                continue;
+            }
+            if let Some(iface) = self.interface(route.interface).filter(|i| i.is_up()) {
+                consider(route.destination.prefix_len(), route.metric, iface);
+            }
+        }
+
+        best.map(|(_, _, iface)| iface)
+    }
+}
@@ -0,0 +1,858 @@
+//! IPv6 stateless address autoconfiguration (SLAAC), and stateless DHCPv6.
+//!
+//! Router advertisements are received through a raw ICMPv6 socket. The address is built from the
+//! advertised /64 prefix and the EUI-64 interface identifier derived from the MAC address, the
+//! default route from the advertising router. DNS servers are taken from the RDNSS option, or,
+//! with the `dhcpv6` feature, requested with a DHCPv6 Information-Request when the router sets the
+//! "other configuration" flag.
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use smoltcp::iface::{SocketHandle, SocketSet};
+use smoltcp::phy::ChecksumCapabilities;
+use smoltcp::socket::raw;
+#[cfg(feature = "dhcpv6")]
+use smoltcp::socket::udp;
+use smoltcp::wire::{
+    EthernetAddress, Icmpv6Repr, IpProtocol, IpVersion, Ipv6Address, Ipv6Cidr, Ipv6Packet, Ipv6Repr, NdiscRepr,
+    RawHardwareAddress,
+};
+#[cfg(feature = "dhcpv6")]
+use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
+
+use crate::StaticConfigV6;
+
+/// Interval between router solicitations (RFC 4861 `RTR_SOLICITATION_INTERVAL`).
+const RS_INTERVAL: Duration = Duration::from_secs(4);
+/// Router solicitations sent before waiting for unsolicited advertisements (RFC 4861 `MAX_RTR_SOLICITATIONS`).
+const RS_MAX_COUNT: u8 = 3;
+
+const RX_PACKETS: usize = 4;
+const RX_BUFFER_LEN: usize = 1024;
+// IPv6 header, router solicitation, source link-layer address option.
+const RS_LEN: usize = 40 + 8 + 8;
+
+const ICMPV6_ROUTER_ADVERT: u8 = 134;
+const RA_HEADER_LEN: usize = 16;
+const RA_FLAG_MANAGED: u8 = 0x80;
+const RA_FLAG_OTHER: u8 = 0x40;
+const OPT_PREFIX_INFORMATION: u8 = 3;
+const OPT_RDNSS: u8 = 25;
+const PREFIX_FLAG_AUTONOMOUS: u8 = 0x40;
+
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_CLIENT_PORT: u16 = 546;
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_SERVER_PORT: u16 = 547;
+/// All_DHCP_Relay_Agents_and_Servers, `ff02::1:2`.
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_SERVERS: Ipv6Address = Ipv6Address::new(0xff02, 0, 0, 0, 0, 0, 1, 2);
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_MAX_RETRIES: u8 = 5;
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_RX_BUFFER_LEN: usize = 256;
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_TX_BUFFER_LEN: usize = 64;
+
+/// SLAAC configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SlaacConfig {
+    /// Request DNS servers with stateless DHCPv6 when the router advertisement has the
+    /// "managed" or "other configuration" flag set, and doesn't list DNS servers itself.
+    #[cfg(feature = "dhcpv6")]
+    pub dhcpv6: bool,
+}
+
+// Only derivable when there are no options, i.e. without the `dhcpv6` feature.
+#[allow(clippy::derivable_impls)]
+impl Default for SlaacConfig {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "dhcpv6")]
+            dhcpv6: true,
+        }
+    }
+}
+
+pub(crate) struct SlaacResources {
+    rx_meta: [raw::PacketMetadata; RX_PACKETS],
+    rx_buffer: [u8; RX_BUFFER_LEN],
+    tx_meta: [raw::PacketMetadata; 1],
+    tx_buffer: [u8; RS_LEN],
+    #[cfg(feature = "dhcpv6")]
+    dhcp_rx_meta: [udp::PacketMetadata; 1],
+    #[cfg(feature = "dhcpv6")]
+    dhcp_rx_buffer: [u8; DHCPV6_RX_BUFFER_LEN],
+    #[cfg(feature = "dhcpv6")]
+    dhcp_tx_meta: [udp::PacketMetadata; 1],
+    #[cfg(feature = "dhcpv6")]
+    dhcp_tx_buffer: [u8; DHCPV6_TX_BUFFER_LEN],
+}
+
+impl SlaacResources {
+    pub(crate) const fn new() -> Self {
+        Self {
+            rx_meta: [raw::PacketMetadata::EMPTY; RX_PACKETS],
+            rx_buffer: [0; RX_BUFFER_LEN],
+            tx_meta: [raw::PacketMetadata::EMPTY; 1],
+            tx_buffer: [0; RS_LEN],
+            #[cfg(feature = "dhcpv6")]
+            dhcp_rx_meta: [udp::PacketMetadata::EMPTY; 1],
+            #[cfg(feature = "dhcpv6")]
+            dhcp_rx_buffer: [0; DHCPV6_RX_BUFFER_LEN],
+            #[cfg(feature = "dhcpv6")]
+            dhcp_tx_meta: [udp::PacketMetadata::EMPTY; 1],
+            #[cfg(feature = "dhcpv6")]
+            dhcp_tx_buffer: [0; DHCPV6_TX_BUFFER_LEN],
+        }
+    }
+}
+
+pub(crate) enum Event {
+    Configured(StaticConfigV6),
+    Deconfigured,
+}
+
+/// Information learnt from a router advertisement.
+struct RouterAdvert {
+    router: Ipv6Address,
+    router_lifetime: Duration,
+    prefix: Option<(Ipv6Address, Duration)>,
+    dns_servers: Vec<Ipv6Address, 3>,
+    other_config: bool,
+}
+
+#[cfg(feature = "dhcpv6")]
+struct Dhcpv6 {
+    handle: SocketHandle,
+    transaction_id: u32,
+    retries: u8,
+    next_request: Option<Instant>,
+}
+
+pub(crate) struct Slaac {
+    handle: SocketHandle,
+    #[cfg_attr(not(feature = "dhcpv6"), allow(dead_code))]
+    config: SlaacConfig,
+    solicitations: u8,
+    next_solicitation: Option<Instant>,
+    current: Option<StaticConfigV6>,
+    address_valid_until: Instant,
+    router_valid_until: Instant,
+    #[cfg(feature = "dhcpv6")]
+    dhcp: Dhcpv6,
+}
+
+impl Slaac {
+    /// Create the sockets used for SLAAC.
+    ///
+    /// Safety: the resources must not be used by another `Slaac` until this one is removed.
+    pub(crate) unsafe fn new(
+        sockets: &mut SocketSet<'static>,
+        resources: &'static mut SlaacResources,
+        config: SlaacConfig,
+    ) -> Self {
+        let handle = sockets.add(raw::Socket::new(
+            IpVersion::Ipv6,
+            IpProtocol::Icmpv6,
+            raw::PacketBuffer::new(&mut resources.rx_meta[..], &mut resources.rx_buffer[..]),
+            raw::PacketBuffer::new(&mut resources.tx_meta[..], &mut resources.tx_buffer[..]),
+        ));
+
+        #[cfg(feature = "dhcpv6")]
+        let dhcp = {
+            let mut socket = udp::Socket::new(
+                udp::PacketBuffer::new(&mut resources.dhcp_rx_meta[..], &mut resources.dhcp_rx_buffer[..]),
+                udp::PacketBuffer::new(&mut resources.dhcp_tx_meta[..], &mut resources.dhcp_tx_buffer[..]),
+            );
+            unwrap!(socket.bind(IpListenEndpoint {
+                addr: None,
+                port: DHCPV6_CLIENT_PORT,
+            }));
+            Dhcpv6 {
+                handle: sockets.add(socket),
+                transaction_id: 0,
+                retries: 0,
+                next_request: None,
+            }
+        };
+
+        Self {
+            handle,
+            config,
+            solicitations: 0,
+            next_solicitation: Some(Instant::now()),
+            current: None,
+            address_valid_until: Instant::MAX,
+            router_valid_until: Instant::MAX,
+            #[cfg(feature = "dhcpv6")]
+            dhcp,
+        }
+    }
+
+    /// Remove the sockets used for SLAAC, releasing the resources.
+    pub(crate) fn remove(self, sockets: &mut SocketSet<'static>) {
+        sockets.remove(self.handle);
+        #[cfg(feature = "dhcpv6")]
+        sockets.remove(self.dhcp.handle);
+    }
+
+    pub(crate) fn set_config(&mut self, config: SlaacConfig) {
+        self.config = config;
+    }
+
+    /// Forget the current configuration, and start soliciting routers again.
+    pub(crate) fn reset(&mut self) {
+        self.solicitations = 0;
+        self.next_solicitation = Some(Instant::now());
+        self.current = None;
+        #[cfg(feature = "dhcpv6")]
+        {
+            self.dhcp.next_request = None;
+        }
+    }
+
+    /// Get the next instant at which `poll` must be called.
+    pub(crate) fn poll_at(&self) -> Option<Instant> {
+        let mut at = self.next_solicitation;
+        if self.current.is_some() {
+            let expiry = self.address_valid_until.min(self.router_valid_until);
+            at = Some(at.map_or(expiry, |at| at.min(expiry)));
+        }
+        #[cfg(feature = "dhcpv6")]
+        if let Some(t) = self.dhcp.next_request {
+            at = Some(at.map_or(t, |at| at.min(t)));
+        }
+        at
+    }
+
+    pub(crate) fn poll(&mut self, sockets: &mut SocketSet<'static>, mac: EthernetAddress) -> Option<Event> {
+        let now = Instant::now();
+        let mut changed = false;
+
+        // Process received router advertisements.
+        let socket = sockets.get_mut::<raw::Socket>(self.handle);
+        while let Ok(packet) = socket.recv() {
+            if let Some(ra) = parse_router_advert(packet) {
+                changed |= self.process_router_advert(ra, mac, now);
+            }
+        }
+
+        // Expire the address and the default route.
+        if self.current.is_some() && now >= self.address_valid_until {
+            debug!("SLAAC: address lifetime expired");
+            self.reset();
+            changed = true;
+        }
+        if let Some(current) = &mut self.current {
+            if current.gateway.is_some() && now >= self.router_valid_until {
+                debug!("SLAAC: router lifetime expired");
+                current.gateway = None;
+                self.router_valid_until = Instant::MAX;
+                changed = true;
+            }
+        }
+
+        // Solicit routers until one advertises itself.
+        if self.next_solicitation.is_some_and(|t| now >= t) {
+            let socket = sockets.get_mut::<raw::Socket>(self.handle);
+            if socket.can_send() {
+                trace!("SLAAC: sending router solicitation");
+                unwrap!(socket.send(RS_LEN).map(|buf| emit_router_solicit(mac, buf)).ok());
+                self.solicitations += 1;
+            }
+            self.next_solicitation = if self.solicitations < RS_MAX_COUNT {
+                Some(now + RS_INTERVAL)
+            } else {
+                None
+            };
+        }
+
+        #[cfg(feature = "dhcpv6")]
+        {
+            changed |= self.poll_dhcpv6(sockets, mac, now);
+        }
+
+        if !changed {
+            return None;
+        }
+        match &self.current {
+            Some(current) => Some(Event::Configured(current.clone())),
+            None => Some(Event::Deconfigured),
+        }
+    }
+
+    fn process_router_advert(&mut self, ra: RouterAdvert, mac: EthernetAddress, now: Instant) -> bool {
+        let Some((prefix, valid_lifetime)) = ra.prefix else {
+            // Without a prefix to autoconfigure from, only update the router of the current configuration.
+            return match &mut self.current {
+                Some(current) if current.gateway == Some(ra.router) && ra.router_lifetime.as_ticks() == 0 => {
+                    current.gateway = None;
+                    true
+                }
+                _ => false,
+            };
+        };
+
+        if valid_lifetime.as_ticks() == 0 {
+            // The router withdraws the prefix.
+            return match &self.current {
+                Some(current) if current.address.address() == eui64_address(prefix, mac) => {
+                    self.current = None;
+                    true
+                }
+                _ => false,
+            };
+        }
+
+        self.next_solicitation = None;
+        self.address_valid_until = now.checked_add(valid_lifetime).unwrap_or(Instant::MAX);
+
+        let gateway = if ra.router_lifetime.as_ticks() > 0 {
+            self.router_valid_until = now.checked_add(ra.router_lifetime).unwrap_or(Instant::MAX);
+            Some(ra.router)
+        } else {
+            self.router_valid_until = Instant::MAX;
+            None
+        };
+
+        let mut dns_servers = ra.dns_servers;
+        if dns_servers.is_empty() {
+            // Keep the servers learnt earlier, from DHCPv6 for example.
+            if let Some(current) = &self.current {
+                dns_servers = current.dns_servers.clone();
+            }
+        }
+
+        #[cfg(feature = "dhcpv6")]
+        if self.config.dhcpv6 && ra.other_config && dns_servers.is_empty() && self.dhcp.next_request.is_none() {
+            self.dhcp.transaction_id = (now.as_ticks() as u32) & 0x00ff_ffff;
+            self.dhcp.retries = 0;
+            self.dhcp.next_request = Some(now);
+        }
+        #[cfg(not(feature = "dhcpv6"))]
+        let _ = ra.other_config;
+
+        let config = StaticConfigV6 {
+            address: Ipv6Cidr::new(eui64_address(prefix, mac), 64),
+            gateway,
+            dns_servers,
+        };
+        if self.current.as_ref() == Some(&config) {
+            return false;
+        }
+        self.current = Some(config);
+        true
+    }
+
+    #[cfg(feature = "dhcpv6")]
+    fn poll_dhcpv6(&mut self, sockets: &mut SocketSet<'static>, mac: EthernetAddress, now: Instant) -> bool {
+        let socket = sockets.get_mut::<udp::Socket>(self.dhcp.handle);
+        let mut changed = false;
+
+        while let Ok((data, _)) = socket.recv() {
+            if self.dhcp.next_request.is_none() {
+                continue;
+            }
+            if let Some(dns_servers) = parse_dhcpv6_reply(data, self.dhcp.transaction_id) {
+                debug!("DHCPv6: received reply");
+                self.dhcp.next_request = None;
+                if let Some(current) = &mut self.current {
+                    if current.dns_servers != dns_servers {
+                        current.dns_servers = dns_servers;
+                        changed = true;
+                    }
+                }
+            }
+        }
+
+        if let Some(t) = self.dhcp.next_request {
+            if now >= t && socket.can_send() {
+                if self.dhcp.retries >= DHCPV6_MAX_RETRIES {
+                    debug!("DHCPv6: no reply from any server");
+                    self.dhcp.next_request = None;
+                } else {
+                    trace!("DHCPv6: sending information-request");
+                    let mut buf = [0; DHCPV6_TX_BUFFER_LEN];
+                    let len = emit_dhcpv6_information_request(self.dhcp.transaction_id, mac, &mut buf);
+                    let remote = IpEndpoint::new(IpAddress::Ipv6(DHCPV6_SERVERS), DHCPV6_SERVER_PORT);
+                    unwrap!(socket.send_slice(&buf[..len], remote).ok());
+                    self.dhcp.retries += 1;
+                    self.dhcp.next_request = Some(now + DHCPV6_RETRY_INTERVAL);
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+/// Get the EUI-64 interface identifier for a MAC address.
+fn eui64(mac: EthernetAddress) -> [u8; 8] {
+    let m = mac.0;
+    [m[0] ^ 0x02, m[1], m[2], 0xff, 0xfe, m[3], m[4], m[5]]
+}
+
+fn eui64_address(prefix: Ipv6Address, mac: EthernetAddress) -> Ipv6Address {
+    let mut addr = prefix.0;
+    addr[8..].copy_from_slice(&eui64(mac));
+    Ipv6Address(addr)
+}
+
+/// Get the link-local address used by SLAAC for a MAC address.
+pub(crate) fn link_local_address(mac: EthernetAddress) -> Ipv6Cidr {
+    Ipv6Cidr::new(eui64_address(Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 0), mac), 64)
+}
+
+fn emit_router_solicit(mac: EthernetAddress, buf: &mut [u8]) {
+    let src_addr = link_local_address(mac).address();
+    let dst_addr = Ipv6Address::LINK_LOCAL_ALL_ROUTERS;
+    let icmp_repr = Icmpv6Repr::Ndisc(NdiscRepr::RouterSolicit {
+        lladdr: Some(RawHardwareAddress::from(mac)),
+    });
+    let ip_repr = Ipv6Repr {
+        src_addr,
+        dst_addr,
+        next_header: IpProtocol::Icmpv6,
+        payload_len: icmp_repr.buffer_len(),
+        hop_limit: 255,
+    };
+    let mut packet = Ipv6Packet::new_unchecked(buf);
+    ip_repr.emit(&mut packet);
+    icmp_repr.emit(
+        &src_addr.into(),
+        &dst_addr.into(),
+        &mut smoltcp::wire::Icmpv6Packet::new_unchecked(packet.payload_mut()),
+        &ChecksumCapabilities::default(),
+    );
+}
+
+fn parse_router_advert(packet: &[u8]) -> Option<RouterAdvert> {
+    let ip = Ipv6Packet::new_checked(packet).ok()?;
+    // RFC 4861 section 6.1.2: advertisements must come from a link-local address, and can't have been forwarded.
+    if !ip.src_addr().is_link_local() || ip.hop_limit() != 255 {
+        return None;
+    }
+    let icmp = ip.payload();
+    if icmp.len() < RA_HEADER_LEN || icmp[0] != ICMPV6_ROUTER_ADVERT || icmp[1] != 0 {
+        return None;
+    }
+
+    let flags = icmp[5];
+    let mut ra = RouterAdvert {
+        router: ip.src_addr(),
+        router_lifetime: Duration::from_secs(u16::from_be_bytes([icmp[6], icmp[7]]) as u64),
+        prefix: None,
+        dns_servers: Vec::new(),
+        other_config: flags & (RA_FLAG_MANAGED | RA_FLAG_OTHER) != 0,
+    };
+
+    let mut options = &icmp[RA_HEADER_LEN..];
+    while options.len() >= 2 {
+        let len = options[1] as usize * 8;
+        if len == 0 || len > options.len() {
+            return None;
+        }
+        let (option, rest) = options.split_at(len);
+        options = rest;
+
+        match option[0] {
+            OPT_PREFIX_INFORMATION if len == 32 => {
+                let prefix_len = option[2];
+                let autonomous = option[3] & PREFIX_FLAG_AUTONOMOUS != 0;
+                let valid_lifetime = u32::from_be_bytes([option[4], option[5], option[6], option[7]]);
+                let prefix = Ipv6Address::from_bytes(&option[16..32]);
+                // Only /64 prefixes can be combined with an EUI-64 interface identifier.
+                if autonomous && prefix_len == 64 && !prefix.is_link_local() && ra.prefix.is_none() {
+                    ra.prefix = Some((prefix, Duration::from_secs(valid_lifetime as u64)));
+                }
+            }
+            OPT_RDNSS if len >= 24 => {
+                for addr in option[8..].chunks_exact(16) {
+                    if ra.dns_servers.push(Ipv6Address::from_bytes(addr)).is_err() {
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Some(ra)
+}
+
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_INFORMATION_REQUEST: u8 = 11;
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_REPLY: u8 = 7;
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_OPT_CLIENTID: u16 = 1;
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_OPT_ORO: u16 = 6;
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_OPT_ELAPSED_TIME: u16 = 8;
+#[cfg(feature = "dhcpv6")]
+const DHCPV6_OPT_DNS_SERVERS: u16 = 23;
+
+#[cfg(feature = "dhcpv6")]
+fn emit_dhcpv6_information_request(transaction_id: u32, mac: EthernetAddress, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    let mut push = |data: &[u8]| {
+        buf[len..][..data.len()].copy_from_slice(data);
+        len += data.len();
+    };
+
+    push(&[DHCPV6_INFORMATION_REQUEST]);
+    push(&transaction_id.to_be_bytes()[1..]);
+    // Client identifier: DUID-LL (type 3), hardware type Ethernet (1).
+    push(&DHCPV6_OPT_CLIENTID.to_be_bytes());
+    push(&10u16.to_be_bytes());
+    push(&[0, 3, 0, 1]);
+    push(&mac.0);
+    // Option request: DNS servers.
+    push(&DHCPV6_OPT_ORO.to_be_bytes());
+    push(&2u16.to_be_bytes());
+    push(&DHCPV6_OPT_DNS_SERVERS.to_be_bytes());
+    // Elapsed time.
+    push(&DHCPV6_OPT_ELAPSED_TIME.to_be_bytes());
+    push(&2u16.to_be_bytes());
+    push(&[0, 0]);
+
+    len
+}
+
+#[cfg(feature = "dhcpv6")]
+fn parse_dhcpv6_reply(data: &[u8], transaction_id: u32) -> Option<Vec<Ipv6Address, 3>> {
+    if data.len() < 4 || data[0] != DHCPV6_REPLY || data[1..4] != transaction_id.to_be_bytes()[1..] {
+        return None;
+    }
+
+    let mut dns_servers = Vec::new();
+    let mut options = &data[4..];
+    while options.len() >= 4 {
+        let code = u16::from_be_bytes([options[0], options[1]]);
+        let len = u16::from_be_bytes([options[2], options[3]]) as usize;
+        if options.len() < 4 + len {
+            return None;
+        }
+        if code == DHCPV6_OPT_DNS_SERVERS {
+            for addr in options[4..4 + len].chunks_exact(16) {
+                if dns_servers.push(Ipv6Address::from_bytes(addr)).is_err() {
+                    break;
+                }
+            }
+        }
+        options = &options[4 + len..];
+    }
+
+    Some(dns_servers)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec as StdVec;
+
+    use super::*;
+
+    const MAC: EthernetAddress = EthernetAddress([0x00, 0x1b, 0x21, 0x3c, 0x4d, 0x5e]);
+    const ROUTER: Ipv6Address = Ipv6Address::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+    const PREFIX: Ipv6Address = Ipv6Address::new(0x2001, 0xdb8, 0, 1, 0, 0, 0, 0);
+    const DNS_SERVERS: [Ipv6Address; 4] = [
+        Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x53),
+        Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x54),
+        Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x55),
+        Ipv6Address::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x56),
+    ];
+
+    /// Build a router advertisement with a router lifetime of 1800s.
+    fn router_advert(src_addr: Ipv6Address, hop_limit: u8, flags: u8, options: &[&[u8]]) -> StdVec<u8> {
+        let mut icmp = std::vec![
+            ICMPV6_ROUTER_ADVERT,
+            0,
+            0,
+            0,
+            64,
+            flags,
+            0x07,
+            0x08,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0
+        ];
+        for option in options {
+            icmp.extend_from_slice(option);
+        }
+        let ip_repr = Ipv6Repr {
+            src_addr,
+            dst_addr: Ipv6Address::LINK_LOCAL_ALL_NODES,
+            next_header: IpProtocol::Icmpv6,
+            payload_len: icmp.len(),
+            hop_limit,
+        };
+        let mut packet = std::vec![0; ip_repr.buffer_len()];
+        ip_repr.emit(&mut Ipv6Packet::new_unchecked(&mut packet[..]));
+        packet.extend_from_slice(&icmp);
+        packet
+    }
+
+    fn prefix_option(prefix: Ipv6Address, prefix_len: u8, flags: u8, valid_lifetime: u32) -> StdVec<u8> {
+        let mut option = std::vec![OPT_PREFIX_INFORMATION, 4, prefix_len, flags];
+        option.extend_from_slice(&valid_lifetime.to_be_bytes());
+        // Preferred lifetime, reserved.
+        option.extend_from_slice(&[0; 8]);
+        option.extend_from_slice(prefix.as_bytes());
+        option
+    }
+
+    fn rdnss_option(servers: &[Ipv6Address]) -> StdVec<u8> {
+        let mut option = std::vec![OPT_RDNSS, 1 + 2 * servers.len() as u8, 0, 0, 0, 0, 0x0e, 0x10];
+        for server in servers {
+            option.extend_from_slice(server.as_bytes());
+        }
+        option
+    }
+
+    #[test]
+    fn eui64_address() {
+        assert_eq!(eui64(MAC), [0x02, 0x1b, 0x21, 0xff, 0xfe, 0x3c, 0x4d, 0x5e]);
+        // The universal/local bit is inverted, not set.
+        assert_eq!(
+            eui64(EthernetAddress([0x02, 0, 0, 0, 0, 1])),
+            [0, 0, 0, 0xff, 0xfe, 0, 0, 1]
+        );
+
+        assert_eq!(
+            super::eui64_address(PREFIX, MAC),
+            Ipv6Address::new(0x2001, 0xdb8, 0, 1, 0x021b, 0x21ff, 0xfe3c, 0x4d5e)
+        );
+        assert_eq!(
+            link_local_address(MAC),
+            Ipv6Cidr::new(Ipv6Address::new(0xfe80, 0, 0, 0, 0x021b, 0x21ff, 0xfe3c, 0x4d5e), 64)
+        );
+    }
+
+    #[test]
+    fn router_advert_options() {
+        let packet = router_advert(
+            ROUTER,
+            255,
+            RA_FLAG_OTHER,
+            &[
+                // Source link-layer address, ignored.
+                &[1, 1, 0x02, 0, 0, 0, 0, 1],
+                &prefix_option(PREFIX, 64, PREFIX_FLAG_AUTONOMOUS, 86400),
+                &rdnss_option(&DNS_SERVERS[..2]),
+            ],
+        );
+        let ra = parse_router_advert(&packet).unwrap();
+        assert_eq!(ra.router, ROUTER);
+        assert_eq!(ra.router_lifetime, Duration::from_secs(1800));
+        assert_eq!(ra.prefix, Some((PREFIX, Duration::from_secs(86400))));
+        assert_eq!(ra.dns_servers[..], DNS_SERVERS[..2]);
+        assert!(ra.other_config);
+
+        let ra = parse_router_advert(&router_advert(ROUTER, 255, RA_FLAG_MANAGED, &[])).unwrap();
+        assert_eq!(ra.prefix, None);
+        assert!(ra.dns_servers.is_empty());
+        assert!(ra.other_config);
+        assert!(
+            !parse_router_advert(&router_advert(ROUTER, 255, 0, &[]))
+                .unwrap()
+                .other_config
+        );
+    }
+
+    #[test]
+    fn router_advert_prefixes() {
+        let other = Ipv6Address::new(0x2001, 0xdb8, 0, 2, 0, 0, 0, 0);
+        let prefix = |options: &[&[u8]]| {
+            parse_router_advert(&router_advert(ROUTER, 255, 0, options))
+                .unwrap()
+                .prefix
+        };
+
+        // Only autonomous /64 prefixes that aren't link-local are used.
+        assert_eq!(prefix(&[&prefix_option(PREFIX, 64, 0, 86400)]), None);
+        assert_eq!(
+            prefix(&[&prefix_option(PREFIX, 48, PREFIX_FLAG_AUTONOMOUS, 86400)]),
+            None
+        );
+        assert_eq!(
+            prefix(&[&prefix_option(ROUTER, 64, PREFIX_FLAG_AUTONOMOUS, 86400)]),
+            None
+        );
+
+        // The first usable prefix is used.
+        assert_eq!(
+            prefix(&[
+                &prefix_option(other, 48, PREFIX_FLAG_AUTONOMOUS, 60),
+                &prefix_option(PREFIX, 64, PREFIX_FLAG_AUTONOMOUS, 86400),
+                &prefix_option(other, 64, PREFIX_FLAG_AUTONOMOUS, 60),
+            ]),
+            Some((PREFIX, Duration::from_secs(86400)))
+        );
+    }
+
+    #[test]
+    fn router_advert_dns_servers() {
+        let dns_servers = |options: &[&[u8]]| {
+            parse_router_advert(&router_advert(ROUTER, 255, 0, options))
+                .unwrap()
+                .dns_servers
+        };
+
+        // Servers past the third are dropped.
+        assert_eq!(dns_servers(&[&rdnss_option(&DNS_SERVERS)])[..], DNS_SERVERS[..3]);
+        assert_eq!(
+            dns_servers(&[&rdnss_option(&DNS_SERVERS[..1]), &rdnss_option(&DNS_SERVERS[1..])])[..],
+            DNS_SERVERS[..3]
+        );
+        // An RDNSS option without any server is ignored.
+        assert!(dns_servers(&[&rdnss_option(&[])]).is_empty());
+    }
+
+    #[test]
+    fn router_advert_malformed() {
+        let options: &[&[u8]] = &[&prefix_option(PREFIX, 64, PREFIX_FLAG_AUTONOMOUS, 86400)];
+        assert!(parse_router_advert(&router_advert(ROUTER, 255, 0, options)).is_some());
+
+        // Not from a link-local address, or forwarded.
+        assert!(parse_router_advert(&router_advert(PREFIX, 255, 0, options)).is_none());
+        assert!(parse_router_advert(&router_advert(ROUTER, 254, 0, options)).is_none());
+
+        // Not a router advertisement.
+        let mut packet = router_advert(ROUTER, 255, 0, options);
+        packet[40] = 133;
+        assert!(parse_router_advert(&packet).is_none());
+        let mut packet = router_advert(ROUTER, 255, 0, options);
+        packet[41] = 1;
+        assert!(parse_router_advert(&packet).is_none());
+
+        // Truncated header.
+        let packet = router_advert(ROUTER, 255, 0, &[]);
+        let mut truncated = packet[..packet.len() - 1].to_vec();
+        truncated[5] -= 1;
+        assert!(parse_router_advert(&truncated).is_none());
+        // Shorter than the IPv6 payload length.
+        assert!(parse_router_advert(&packet[..packet.len() - 1]).is_none());
+
+        // Options with a zero length, or longer than the packet.
+        assert!(parse_router_advert(&router_advert(ROUTER, 255, 0, &[&[1, 0, 0, 0, 0, 0, 0, 0]])).is_none());
+        assert!(parse_router_advert(&router_advert(ROUTER, 255, 0, &[&options[0][..24]])).is_none());
+        let mut option = rdnss_option(&DNS_SERVERS[..1]);
+        option[1] = 4;
+        assert!(parse_router_advert(&router_advert(ROUTER, 255, 0, &[&option])).is_none());
+
+        // A prefix option of the wrong length is ignored.
+        let mut option = prefix_option(PREFIX, 64, PREFIX_FLAG_AUTONOMOUS, 86400);
+        option[1] = 3;
+        let ra = parse_router_advert(&router_advert(ROUTER, 255, 0, &[&option[..24]])).unwrap();
+        assert_eq!(ra.prefix, None);
+        // A trailing byte too short for an option is ignored.
+        let ra = parse_router_advert(&router_advert(ROUTER, 255, 0, &[options[0], &[0]])).unwrap();
+        assert_eq!(ra.prefix, Some((PREFIX, Duration::from_secs(86400))));
+    }
+
+    #[cfg(feature = "dhcpv6")]
+    const TRANSACTION_ID: u32 = 0x123456;
+
+    #[cfg(feature = "dhcpv6")]
+    fn dhcpv6_message(msg_type: u8, transaction_id: u32, options: &[(u16, &[u8])]) -> StdVec<u8> {
+        let mut data = std::vec![msg_type];
+        data.extend_from_slice(&transaction_id.to_be_bytes()[1..]);
+        for (code, option) in options {
+            data.extend_from_slice(&code.to_be_bytes());
+            data.extend_from_slice(&(option.len() as u16).to_be_bytes());
+            data.extend_from_slice(option);
+        }
+        data
+    }
+
+    #[cfg(feature = "dhcpv6")]
+    fn dns_servers_option(servers: &[Ipv6Address]) -> StdVec<u8> {
+        servers.iter().flat_map(|s| s.as_bytes().iter().copied()).collect()
+    }
+
+    #[cfg(feature = "dhcpv6")]
+    #[test]
+    fn dhcpv6_information_request() {
+        let mut buf = [0; DHCPV6_TX_BUFFER_LEN];
+        let len = emit_dhcpv6_information_request(TRANSACTION_ID, MAC, &mut buf);
+        let duid: &[u8] = &[0, 3, 0, 1, 0x00, 0x1b, 0x21, 0x3c, 0x4d, 0x5e];
+        assert_eq!(
+            buf[..len],
+            dhcpv6_message(
+                DHCPV6_INFORMATION_REQUEST,
+                TRANSACTION_ID,
+                &[
+                    (DHCPV6_OPT_CLIENTID, duid),
+                    (DHCPV6_OPT_ORO, &DHCPV6_OPT_DNS_SERVERS.to_be_bytes()),
+                    (DHCPV6_OPT_ELAPSED_TIME, &[0, 0]),
+                ]
+            )[..]
+        );
+    }
+
+    #[cfg(feature = "dhcpv6")]
+    #[test]
+    fn dhcpv6_reply() {
+        let servers = dns_servers_option(&DNS_SERVERS[..2]);
+        let reply = dhcpv6_message(
+            DHCPV6_REPLY,
+            TRANSACTION_ID,
+            &[(DHCPV6_OPT_CLIENTID, &[0, 3, 0, 1]), (DHCPV6_OPT_DNS_SERVERS, &servers)],
+        );
+        assert_eq!(
+            parse_dhcpv6_reply(&reply, TRANSACTION_ID).unwrap()[..],
+            DNS_SERVERS[..2]
+        );
+
+        // Servers past the third are dropped.
+        let servers = dns_servers_option(&DNS_SERVERS);
+        let reply = dhcpv6_message(DHCPV6_REPLY, TRANSACTION_ID, &[(DHCPV6_OPT_DNS_SERVERS, &servers)]);
+        assert_eq!(
+            parse_dhcpv6_reply(&reply, TRANSACTION_ID).unwrap()[..],
+            DNS_SERVERS[..3]
+        );
+
+        // A reply without DNS servers.
+        let reply = dhcpv6_message(DHCPV6_REPLY, TRANSACTION_ID, &[]);
+        assert!(parse_dhcpv6_reply(&reply, TRANSACTION_ID).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "dhcpv6")]
+    #[test]
+    fn dhcpv6_reply_malformed() {
+        let servers = dns_servers_option(&DNS_SERVERS[..1]);
+        let options: &[(u16, &[u8])] = &[(DHCPV6_OPT_DNS_SERVERS, &servers)];
+
+        // Not a reply, or not to our request.
+        let reply = dhcpv6_message(DHCPV6_INFORMATION_REQUEST, TRANSACTION_ID, options);
+        assert!(parse_dhcpv6_reply(&reply, TRANSACTION_ID).is_none());
+        let reply = dhcpv6_message(DHCPV6_REPLY, TRANSACTION_ID, options);
+        assert!(parse_dhcpv6_reply(&reply, TRANSACTION_ID + 1).is_none());
+
+        // Truncated header and options.
+        assert!(parse_dhcpv6_reply(&reply[..3], TRANSACTION_ID).is_none());
+        assert!(parse_dhcpv6_reply(&reply[..reply.len() - 1], TRANSACTION_ID).is_none());
+        // An option header cut short is ignored.
+        assert!(parse_dhcpv6_reply(&reply[..6], TRANSACTION_ID).unwrap().is_empty());
+
+        // A trailing partial address is ignored.
+        let mut servers = dns_servers_option(&DNS_SERVERS[..1]);
+        servers.extend_from_slice(&[0; 8]);
+        let reply = dhcpv6_message(DHCPV6_REPLY, TRANSACTION_ID, &[(DHCPV6_OPT_DNS_SERVERS, &servers)]);
+        assert_eq!(
+            parse_dhcpv6_reply(&reply, TRANSACTION_ID).unwrap()[..],
+            DNS_SERVERS[..1]
+        );
+    }
+}
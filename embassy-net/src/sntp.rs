@@ -0,0 +1,409 @@
+//! SNTP client.
+//!
+//! [`Sntp::query`] gets the time from an NTP server, as a [`Timestamp`] tying a UNIX time to
+//! the [`Instant`] it was valid at. [`Sntp::run`] queries the servers periodically and keeps
+//! a [`Clock`] synchronized, so the UNIX time of any [`Instant`] can be known. Servers handed
+//! out by the DHCP server are used before the configured ones.
+//!
+//! ## Example
+//! ```ignore
+//! static CLOCK: Clock<CriticalSectionRawMutex> = Clock::new();
+//!
+//! let mut rx_meta = [PacketMetadata::EMPTY; 1];
+//! let mut rx_buffer = [0; 128];
+//! let mut tx_meta = [PacketMetadata::EMPTY; 1];
+//! let mut tx_buffer = [0; 128];
+//! let sntp = Sntp::new(stack, &["pool.ntp.org"], &mut rx_meta, &mut rx_buffer, &mut tx_meta, &mut tx_buffer);
+//! sntp.run(&CLOCK, Duration::from_secs(1024)).await;
+//!
+//! // Anywhere else:
+//! if let Some(unix_micros) = CLOCK.now() { ... }
+//! ```
+
+use core::cell::Cell;
+
+use embassy_net_driver::Driver;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_time::{with_timeout, Duration, Instant, Timer};
+
+use crate::dns::{DnsQueryType, DnsSocket};
+use crate::udp::{self, PacketMetadata, UdpSocket};
+use crate::{IpAddress, IpEndpoint, Stack};
+
+/// UDP port of NTP.
+pub const NTP_PORT: u16 = 123;
+
+const PACKET_LEN: usize = 48;
+const VERSION: u8 = 4;
+const MODE_CLIENT: u8 = 3;
+const MODE_SERVER: u8 = 4;
+const LEAP_UNSYNCHRONIZED: u8 = 3;
+
+/// Seconds between the NTP epoch (1900) and the UNIX epoch (1970).
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// How long to wait for a response to a query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait before retrying when no server responded.
+const RETRY_INTERVAL: Duration = Duration::from_secs(16);
+
+/// Minimum time between two samples for them to be used to estimate the drift of the clock.
+const MIN_DRIFT_INTERVAL: Duration = Duration::from_secs(60);
+/// Maximum drift of the clock, in parts per billion. Larger estimates are clamped.
+const MAX_DRIFT_PPB: i64 = 500_000;
+/// Offset from the servers past which the clock is stepped, and the drift estimate reset.
+///
+/// Such an offset isn't caused by drift, but by the clock being changed or the [`Instant`] clock being stopped.
+const STEP_THRESHOLD_MICROS: i64 = 1_000_000;
+
+/// SNTP errors.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    /// The server name couldn't be resolved.
+    Dns(crate::dns::Error),
+    /// The query couldn't be sent.
+    Send(udp::Error),
+    /// The server didn't respond in time.
+    Timeout,
+    /// The response was malformed or didn't match the query.
+    InvalidResponse,
+    /// The server isn't synchronized, or asked us to stop querying it.
+    Unsynchronized,
+    /// No server is configured or handed out by DHCP.
+    NoServer,
+}
+
+impl From<crate::dns::Error> for Error {
+    fn from(e: crate::dns::Error) -> Self {
+        Self::Dns(e)
+    }
+}
+
+impl From<udp::Error> for Error {
+    fn from(e: udp::Error) -> Self {
+        Self::Send(e)
+    }
+}
+
+/// A UNIX time, and the [`Instant`] it was valid at.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Timestamp {
+    /// Microseconds since the UNIX epoch.
+    pub unix_micros: u64,
+    /// Instant the time was valid at.
+    pub instant: Instant,
+}
+
+impl Timestamp {
+    /// Get the seconds since the UNIX epoch.
+    pub fn unix_secs(&self) -> u64 {
+        self.unix_micros / 1_000_000
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ClockState {
+    reference: Timestamp,
+    /// How much faster the UNIX time advances than [`Instant`]s, in parts per billion.
+    drift_ppb: i64,
+}
+
+impl ClockState {
+    fn to_unix(self, instant: Instant) -> u64 {
+        let elapsed = if instant >= self.reference.instant {
+            (instant - self.reference.instant).as_micros() as i64
+        } else {
+            -((self.reference.instant - instant).as_micros() as i64)
+        };
+        let elapsed = elapsed + (elapsed as i128 * self.drift_ppb as i128 / 1_000_000_000) as i64;
+        self.reference.unix_micros.saturating_add_signed(elapsed)
+    }
+}
+
+/// A clock converting [`Instant`]s to UNIX time, synchronized by [`Sntp::run`].
+///
+/// Between synchronizations, the drift of the [`Instant`] clock compared to the servers is compensated.
+pub struct Clock<M: RawMutex> {
+    state: Mutex<M, Cell<Option<ClockState>>>,
+}
+
+impl<M: RawMutex> Clock<M> {
+    /// Create a new, unsynchronized clock.
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(Cell::new(None)),
+        }
+    }
+
+    /// Get whether the clock was synchronized at least once.
+    pub fn is_synchronized(&self) -> bool {
+        self.state.lock(|s| s.get().is_some())
+    }
+
+    /// Get the current time, in microseconds since the UNIX epoch.
+    ///
+    /// Returns `None` if the clock was never synchronized.
+    pub fn now(&self) -> Option<u64> {
+        self.to_unix(Instant::now())
+    }
+
+    /// Get the UNIX time of `instant`, in microseconds since the UNIX epoch.
+    ///
+    /// Returns `None` if the clock was never synchronized.
+    pub fn to_unix(&self, instant: Instant) -> Option<u64> {
+        self.state.lock(|s| s.get().map(|s| s.to_unix(instant)))
+    }
+
+    /// Synchronize the clock to `timestamp`, and update the drift estimate.
+    pub fn update(&self, timestamp: Timestamp) {
+        self.state.lock(|s| {
+            let new = match s.get() {
+                None => ClockState {
+                    reference: timestamp,
+                    drift_ppb: 0,
+                },
+                Some(old) => {
+                    let mut drift_ppb = old.drift_ppb;
+                    let elapsed = timestamp.instant.saturating_duration_since(old.reference.instant);
+                    let error = timestamp.unix_micros as i64 - old.to_unix(timestamp.instant) as i64;
+                    if error.unsigned_abs() > STEP_THRESHOLD_MICROS as u64 {
+                        debug!("sntp: clock stepped by {} us", error);
+                        drift_ppb = 0;
+                    } else if elapsed >= MIN_DRIFT_INTERVAL {
+                        // Correct half of the drift measured since the last sample, to filter out network jitter.
+                        drift_ppb += error * 1_000_000_000 / elapsed.as_micros() as i64 / 2;
+                        drift_ppb = drift_ppb.clamp(-MAX_DRIFT_PPB, MAX_DRIFT_PPB);
+                    }
+                    ClockState {
+                        reference: timestamp,
+                        drift_ppb,
+                    }
+                }
+            };
+            debug!("sntp: clock synchronized, drift {} ppb", new.drift_ppb);
+            s.set(Some(new));
+        })
+    }
+}
+
+impl<M: RawMutex> Default for Clock<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An SNTP client.
+pub struct Sntp<'a, D: Driver + 'static> {
+    stack: &'a Stack<D>,
+    socket: UdpSocket<'a>,
+    servers: &'a [&'a str],
+}
+
+impl<'a, D: Driver + 'static> Sntp<'a, D> {
+    /// Create a new SNTP client, with the given servers to use when none is handed out by DHCP.
+    ///
+    /// The servers can be host names or IP addresses.
+    pub fn new(
+        stack: &'a Stack<D>,
+        servers: &'a [&'a str],
+        rx_meta: &'a mut [PacketMetadata],
+        rx_buffer: &'a mut [u8],
+        tx_meta: &'a mut [PacketMetadata],
+        tx_buffer: &'a mut [u8],
+    ) -> Self {
+        let mut socket = UdpSocket::new(stack, rx_meta, rx_buffer, tx_meta, tx_buffer);
+        unwrap!(socket.bind(0));
+        Self { stack, socket, servers }
+    }
+
+    /// Get the time from `server`, which can be a host name or an IP address.
+    pub async fn query(&self, server: &str) -> Result<Timestamp, Error> {
+        let dns = DnsSocket::new(self.stack);
+        let qtypes = [
+            #[cfg(feature = "proto-ipv4")]
+            DnsQueryType::A,
+            #[cfg(feature = "proto-ipv6")]
+            DnsQueryType::Aaaa,
+        ];
+
+        let mut result = Err(crate::dns::Error::Failed);
+        for qtype in qtypes {
+            result = dns.query(server, qtype).await;
+            if let Some(addr) = result.as_ref().ok().and_then(|addrs| addrs.first()) {
+                return self.query_addr(*addr).await;
+            }
+        }
+        Err(result.err().unwrap_or(crate::dns::Error::Failed).into())
+    }
+
+    /// Get the time from the server at `addr`.
+    pub async fn query_addr(&self, addr: IpAddress) -> Result<Timestamp, Error> {
+        let server = IpEndpoint::new(addr, NTP_PORT);
+
+        // The transmit timestamp only has to be unique: the server copies it to the originate
+        // timestamp of the response, which is used to match it to the query and skip stale responses.
+        let sent_at = Instant::now();
+        let cookie = sent_at.as_micros().to_be_bytes();
+
+        let mut packet = [0; PACKET_LEN];
+        packet[0] = VERSION << 3 | MODE_CLIENT;
+        packet[40..48].copy_from_slice(&cookie);
+        self.socket.send_to(&packet, server).await?;
+
+        let received_at = with_timeout(QUERY_TIMEOUT, async {
+            loop {
+                if let Ok((n, from)) = self.socket.recv_from(&mut packet).await {
+                    if from == server && n == PACKET_LEN && packet[24..32] == cookie {
+                        break Instant::now();
+                    }
+                }
+            }
+        })
+        .await
+        .map_err(|_| Error::Timeout)?;
+
+        let leap = packet[0] >> 6;
+        let mode = packet[0] & 0x07;
+        let stratum = packet[1];
+        if mode != MODE_SERVER {
+            return Err(Error::InvalidResponse);
+        }
+        // Stratum 0 is a "kiss-o'-death" message.
+        if leap == LEAP_UNSYNCHRONIZED || stratum == 0 || stratum > 15 {
+            return Err(Error::Unsynchronized);
+        }
+
+        let server_received = ntp_to_unix_micros(&packet[32..40]);
+        let server_sent = ntp_to_unix_micros(&packet[40..48]);
+        let (Some(server_received), Some(server_sent)) = (server_received, server_sent) else {
+            return Err(Error::InvalidResponse);
+        };
+
+        // The server's time when it sent the response, plus half of the network round-trip delay.
+        let round_trip = (received_at - sent_at).as_micros();
+        let processing = server_sent.saturating_sub(server_received);
+        let delay = round_trip.saturating_sub(processing) / 2;
+
+        Ok(Timestamp {
+            unix_micros: server_sent + delay,
+            instant: received_at,
+        })
+    }
+
+    /// Get the time from the first server that responds, among the ones handed out by DHCP then the configured ones.
+    pub async fn query_any(&self) -> Result<Timestamp, Error> {
+        let mut result = Err(Error::NoServer);
+
+        #[cfg(feature = "dhcpv4")]
+        for addr in self.stack.dhcp_ntp_servers() {
+            result = self.query_addr(addr.into()).await;
+            if result.is_ok() {
+                return result;
+            }
+        }
+
+        for server in self.servers {
+            result = self.query(server).await;
+            if result.is_ok() {
+                return result;
+            }
+        }
+
+        result
+    }
+
+    /// Keep `clock` synchronized, querying the servers every `interval`.
+    pub async fn run<M: RawMutex>(&self, clock: &Clock<M>, interval: Duration) -> ! {
+        loop {
+            self.stack.wait_config_up().await;
+
+            match self.query_any().await {
+                Ok(timestamp) => {
+                    clock.update(timestamp);
+                    Timer::after(interval).await;
+                }
+                Err(e) => {
+                    warn!("sntp: query failed: {:?}", e);
+                    Timer::after(RETRY_INTERVAL).await;
+                }
+            }
+        }
+    }
+}
+
+/// Convert an NTP timestamp to microseconds since the UNIX epoch.
+///
+/// Returns `None` if the timestamp is 0, meaning it's not set.
+fn ntp_to_unix_micros(ts: &[u8]) -> Option<u64> {
+    let secs = u32::from_be_bytes(ts[0..4].try_into().unwrap()) as u64;
+    let frac = u32::from_be_bytes(ts[4..8].try_into().unwrap()) as u64;
+    if secs == 0 && frac == 0 {
+        return None;
+    }
+
+    // Timestamps with the most significant bit cleared are in NTP era 1, starting in 2036.
+    let secs = if secs & 0x8000_0000 == 0 {
+        secs + (1 << 32)
+    } else {
+        secs
+    };
+    Some(secs.saturating_sub(NTP_UNIX_OFFSET) * 1_000_000 + ((frac * 1_000_000) >> 32))
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    const UNIX_MICROS: u64 = 1_700_000_000_000_000;
+
+    fn sample(clock: &Clock<NoopRawMutex>, secs: u64, unix_micros: u64) {
+        clock.update(Timestamp {
+            unix_micros,
+            instant: Instant::from_secs(secs),
+        });
+    }
+
+    #[test]
+    fn drift() {
+        let clock = Clock::<NoopRawMutex>::new();
+        sample(&clock, 0, UNIX_MICROS);
+        // The servers are 100 ppm ahead, half of it is corrected.
+        sample(&clock, 1000, UNIX_MICROS + 1_000_100_000);
+        let now = clock.to_unix(Instant::from_secs(2000)).unwrap();
+        assert_eq!(now, UNIX_MICROS + 2_000_150_000);
+    }
+
+    #[test]
+    fn large_offset() {
+        let clock = Clock::<NoopRawMutex>::new();
+        sample(&clock, 0, UNIX_MICROS);
+        // A year ahead, the clock is stepped without touching the drift.
+        let year = 365 * 24 * 3600 * 1_000_000;
+        sample(&clock, 1000, UNIX_MICROS + 1_000_000_000 + year);
+        assert_eq!(
+            clock.to_unix(Instant::from_secs(2000)),
+            Some(UNIX_MICROS + 2_000_000_000 + year)
+        );
+        // A year behind.
+        sample(&clock, 3000, UNIX_MICROS + 3_000_000_000);
+        assert_eq!(
+            clock.to_unix(Instant::from_secs(4000)),
+            Some(UNIX_MICROS + 4_000_000_000)
+        );
+    }
+
+    #[test]
+    fn long_interval() {
+        let clock = Clock::<NoopRawMutex>::new();
+        sample(&clock, 0, UNIX_MICROS);
+        sample(&clock, 1000, UNIX_MICROS + 1_000_500_000);
+        // Ten years later, extrapolating with the maximum drift doesn't overflow.
+        let secs = 10 * 365 * 24 * 3600;
+        assert!(clock.to_unix(Instant::from_secs(secs)).unwrap() > UNIX_MICROS + secs * 1_000_000);
+    }
+}
@@ -0,0 +1,402 @@
+//! Interface statistics.
+
+use core::cell::Cell;
+#[cfg(feature = "tcp")]
+use core::cell::RefCell;
+
+use embassy_net_driver as driver;
+use smoltcp::phy::Medium;
+#[cfg(feature = "proto-ipv4")]
+use smoltcp::wire::Ipv4Packet;
+#[cfg(all(feature = "proto-ipv6", feature = "tcp"))]
+use smoltcp::wire::Ipv6Packet;
+#[cfg(feature = "medium-ethernet")]
+use smoltcp::wire::{EthernetFrame, EthernetProtocol};
+#[cfg(feature = "tcp")]
+use smoltcp::wire::{IpAddress, IpProtocol, TcpPacket, TcpSeqNumber};
+
+/// Statistics of a network interface.
+///
+/// The counters start at zero when the [`Stack`](crate::Stack) is created, and wrap around on overflow.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub struct Stats {
+    /// Number of packets received from the driver.
+    pub rx_packets: u32,
+    /// Number of bytes received from the driver, including link-layer headers.
+    pub rx_bytes: u64,
+    /// Number of packets sent to the driver.
+    pub tx_packets: u32,
+    /// Number of bytes sent to the driver, including link-layer headers.
+    pub tx_bytes: u64,
+    /// Number of received IPv4 packets with an invalid header checksum.
+    ///
+    /// Only the packets checked by the stack are counted, drivers verifying checksums in hardware report the
+    /// invalid ones in [`driver::Stats::rx_errors`].
+    pub rx_checksum_errors: u32,
+    /// Number of TCP segments retransmitted.
+    #[cfg(feature = "tcp")]
+    pub tcp_retransmits: u32,
+    /// Number of TCP segments received with the RST flag set.
+    #[cfg(feature = "tcp")]
+    pub tcp_resets_received: u32,
+    /// Number of TCP segments sent with the RST flag set.
+    #[cfg(feature = "tcp")]
+    pub tcp_resets_sent: u32,
+    /// Number of times a socket couldn't be created because the socket pool was exhausted.
+    ///
+    /// Only [`TcpClient`](crate::tcp::client::TcpClient) connections are counted, since the other sockets
+    /// panic when the socket set is full.
+    #[cfg(feature = "tcp")]
+    pub socket_pool_exhausted: u32,
+    /// Statistics reported by the driver, such as packets dropped because its buffers were full.
+    pub driver: driver::Stats,
+}
+
+/// Number of TCP connections whose sequence numbers are tracked to detect retransmits.
+#[cfg(feature = "tcp")]
+const TCP_FLOWS: usize = 8;
+
+#[cfg(feature = "tcp")]
+#[derive(Clone, Copy)]
+struct TcpFlow {
+    remote: IpAddress,
+    local_port: u16,
+    remote_port: u16,
+    /// Sequence number following the highest one sent so far.
+    next_seq: TcpSeqNumber,
+}
+
+/// The counters of a [`Stack`](crate::Stack), updated by the device adapter as packets go through it.
+///
+/// Only the fixed-size headers are looked at, packets aren't parsed past the TCP header.
+pub(crate) struct Counters {
+    rx_packets: Cell<u32>,
+    rx_bytes: Cell<u64>,
+    tx_packets: Cell<u32>,
+    tx_bytes: Cell<u64>,
+    rx_checksum_errors: Cell<u32>,
+    #[cfg(feature = "tcp")]
+    tcp_retransmits: Cell<u32>,
+    #[cfg(feature = "tcp")]
+    tcp_resets_received: Cell<u32>,
+    #[cfg(feature = "tcp")]
+    tcp_resets_sent: Cell<u32>,
+    #[cfg(feature = "tcp")]
+    socket_pool_exhausted: Cell<u32>,
+    #[cfg(feature = "tcp")]
+    flows: RefCell<[Option<TcpFlow>; TCP_FLOWS]>,
+    #[cfg(feature = "tcp")]
+    next_flow: Cell<usize>,
+}
+
+fn inc(c: &Cell<u32>) {
+    c.set(c.get().wrapping_add(1));
+}
+
+impl Counters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            rx_packets: Cell::new(0),
+            rx_bytes: Cell::new(0),
+            tx_packets: Cell::new(0),
+            tx_bytes: Cell::new(0),
+            rx_checksum_errors: Cell::new(0),
+            #[cfg(feature = "tcp")]
+            tcp_retransmits: Cell::new(0),
+            #[cfg(feature = "tcp")]
+            tcp_resets_received: Cell::new(0),
+            #[cfg(feature = "tcp")]
+            tcp_resets_sent: Cell::new(0),
+            #[cfg(feature = "tcp")]
+            socket_pool_exhausted: Cell::new(0),
+            #[cfg(feature = "tcp")]
+            flows: RefCell::new([None; TCP_FLOWS]),
+            #[cfg(feature = "tcp")]
+            next_flow: Cell::new(0),
+        }
+    }
+
+    /// Get a snapshot of the counters, along with the driver statistics.
+    pub(crate) fn snapshot(&self, driver: driver::Stats) -> Stats {
+        Stats {
+            rx_packets: self.rx_packets.get(),
+            rx_bytes: self.rx_bytes.get(),
+            tx_packets: self.tx_packets.get(),
+            tx_bytes: self.tx_bytes.get(),
+            rx_checksum_errors: self.rx_checksum_errors.get(),
+            #[cfg(feature = "tcp")]
+            tcp_retransmits: self.tcp_retransmits.get(),
+            #[cfg(feature = "tcp")]
+            tcp_resets_received: self.tcp_resets_received.get(),
+            #[cfg(feature = "tcp")]
+            tcp_resets_sent: self.tcp_resets_sent.get(),
+            #[cfg(feature = "tcp")]
+            socket_pool_exhausted: self.socket_pool_exhausted.get(),
+            driver,
+        }
+    }
+
+    #[cfg(feature = "tcp")]
+    pub(crate) fn socket_pool_exhausted(&self) {
+        inc(&self.socket_pool_exhausted);
+    }
+
+    /// Account for a packet received from the driver.
+    ///
+    /// If `verify_ipv4` is set, the IPv4 header checksum is verified here instead of by smoltcp, so invalid
+    /// packets can be counted. Returns `false` if the packet must be dropped.
+    #[cfg_attr(not(feature = "proto-ipv4"), allow(unused_variables))]
+    pub(crate) fn rx(&self, medium: Medium, verify_ipv4: bool, buf: &[u8]) -> bool {
+        inc(&self.rx_packets);
+        self.rx_bytes.set(self.rx_bytes.get().wrapping_add(buf.len() as u64));
+
+        let Some(ip) = ip_packet(medium, buf) else {
+            return true;
+        };
+        match ip.first().map(|b| b >> 4) {
+            #[cfg(feature = "proto-ipv4")]
+            Some(4) => {
+                let Ok(packet) = Ipv4Packet::new_checked(ip) else {
+                    return true;
+                };
+                if verify_ipv4 && !packet.verify_checksum() {
+                    inc(&self.rx_checksum_errors);
+                    return false;
+                }
+                #[cfg(feature = "tcp")]
+                if packet.next_header() == IpProtocol::Tcp && packet.frag_offset() == 0 {
+                    self.inspect_tcp(packet.src_addr().into(), packet.payload(), false);
+                }
+            }
+            #[cfg(all(feature = "proto-ipv6", feature = "tcp"))]
+            Some(6) => {
+                if let Ok(packet) = Ipv6Packet::new_checked(ip) {
+                    if packet.next_header() == IpProtocol::Tcp {
+                        self.inspect_tcp(packet.src_addr().into(), packet.payload(), false);
+                    }
+                }
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Account for a packet sent to the driver.
+    #[cfg_attr(not(feature = "tcp"), allow(unused_variables))]
+    pub(crate) fn tx(&self, medium: Medium, buf: &[u8]) {
+        inc(&self.tx_packets);
+        self.tx_bytes.set(self.tx_bytes.get().wrapping_add(buf.len() as u64));
+
+        #[cfg(feature = "tcp")]
+        if let Some(ip) = ip_packet(medium, buf) {
+            match ip.first().map(|b| b >> 4) {
+                #[cfg(feature = "proto-ipv4")]
+                Some(4) => {
+                    if let Ok(packet) = Ipv4Packet::new_checked(ip) {
+                        if packet.next_header() == IpProtocol::Tcp && packet.frag_offset() == 0 {
+                            self.inspect_tcp(packet.dst_addr().into(), packet.payload(), true);
+                        }
+                    }
+                }
+                #[cfg(feature = "proto-ipv6")]
+                Some(6) => {
+                    if let Ok(packet) = Ipv6Packet::new_checked(ip) {
+                        if packet.next_header() == IpProtocol::Tcp {
+                            self.inspect_tcp(packet.dst_addr().into(), packet.payload(), true);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Count the resets and retransmits in a TCP segment. Segments behind IPv6 extension headers aren't counted.
+    #[cfg(feature = "tcp")]
+    fn inspect_tcp(&self, remote: IpAddress, buf: &[u8], tx: bool) {
+        let Ok(segment) = TcpPacket::new_checked(buf) else {
+            return;
+        };
+
+        if segment.rst() {
+            inc(if tx {
+                &self.tcp_resets_sent
+            } else {
+                &self.tcp_resets_received
+            });
+            return;
+        }
+        if !tx {
+            return;
+        }
+
+        let len = segment.segment_len();
+        if len == 0 {
+            // Pure ACKs and window probes don't consume sequence numbers.
+            return;
+        }
+        let seq = segment.seq_number();
+        let end = seq + len;
+
+        let mut flows = self.flows.borrow_mut();
+        let flow = flows
+            .iter_mut()
+            .flatten()
+            .find(|f| f.remote == remote && f.local_port == segment.src_port() && f.remote_port == segment.dst_port());
+        match flow {
+            // A new SYN on a known flow is a new connection reusing the same ports, unless it's the same SYN again.
+            Some(f) if segment.syn() && !segment.ack() && end != f.next_seq => f.next_seq = end,
+            Some(f) => {
+                if seq < f.next_seq {
+                    inc(&self.tcp_retransmits);
+                }
+                if end > f.next_seq {
+                    f.next_seq = end;
+                }
+            }
+            None => {
+                let i = self.next_flow.get();
+                self.next_flow.set((i + 1) % TCP_FLOWS);
+                flows[i] = Some(TcpFlow {
+                    remote,
+                    local_port: segment.src_port(),
+                    remote_port: segment.dst_port(),
+                    next_seq: end,
+                });
+            }
+        }
+    }
+}
+
+/// Whether the IPv4 header checksum of the packets received on `medium` can be verified by [`Counters::rx`].
+pub(crate) fn verifies_ipv4(medium: Medium) -> bool {
+    match medium {
+        #[cfg(feature = "medium-ethernet")]
+        Medium::Ethernet => cfg!(feature = "proto-ipv4"),
+        #[cfg(feature = "medium-ip")]
+        Medium::Ip => cfg!(feature = "proto-ipv4"),
+        #[allow(unreachable_patterns)]
+        _ => false,
+    }
+}
+
+/// Get the IP packet of a frame, if it carries one.
+#[cfg_attr(
+    not(any(feature = "medium-ethernet", feature = "medium-ip")),
+    allow(unused_variables)
+)]
+fn ip_packet(medium: Medium, buf: &[u8]) -> Option<&[u8]> {
+    match medium {
+        #[cfg(feature = "medium-ethernet")]
+        Medium::Ethernet => match EthernetFrame::new_checked(buf) {
+            Ok(frame) if matches!(frame.ethertype(), EthernetProtocol::Ipv4 | EthernetProtocol::Ipv6) => {
+                Some(frame.payload())
+            }
+            _ => None,
+        },
+        #[cfg(feature = "medium-ip")]
+        Medium::Ip => Some(buf),
+        // 6LoWPAN compresses the headers, don't bother decompressing them.
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "medium-ip", feature = "proto-ipv4", feature = "tcp"))]
+mod tests {
+    use smoltcp::phy::ChecksumCapabilities;
+    use smoltcp::wire::{Ipv4Address, Ipv4Repr, TcpControl, TcpRepr};
+
+    use super::*;
+
+    const LOCAL: Ipv4Address = Ipv4Address([192, 168, 1, 2]);
+    const REMOTE: Ipv4Address = Ipv4Address([192, 168, 1, 1]);
+
+    fn segment(src: Ipv4Address, dst: Ipv4Address, control: TcpControl, seq: i32, payload: &[u8]) -> Vec<u8> {
+        let tcp = TcpRepr {
+            src_port: 1234,
+            dst_port: 80,
+            control,
+            seq_number: TcpSeqNumber(seq),
+            ack_number: None,
+            window_len: 1024,
+            window_scale: None,
+            max_seg_size: None,
+            sack_permitted: false,
+            sack_ranges: [None; 3],
+            payload,
+        };
+        let ip = Ipv4Repr {
+            src_addr: src,
+            dst_addr: dst,
+            next_header: IpProtocol::Tcp,
+            payload_len: tcp.buffer_len(),
+            hop_limit: 64,
+        };
+        let caps = ChecksumCapabilities::default();
+        let mut buf = vec![0; ip.buffer_len() + tcp.buffer_len()];
+        let mut packet = Ipv4Packet::new_unchecked(&mut buf[..]);
+        ip.emit(&mut packet, &caps);
+        tcp.emit(
+            &mut TcpPacket::new_unchecked(packet.payload_mut()),
+            &src.into(),
+            &dst.into(),
+            &caps,
+        );
+        buf
+    }
+
+    #[test]
+    fn ipv4_checksum() {
+        let c = Counters::new();
+        let mut buf = segment(REMOTE, LOCAL, TcpControl::None, 0, b"hello");
+        assert!(c.rx(Medium::Ip, true, &buf));
+
+        buf[8] -= 1; // hop limit, not covered by the TCP checksum
+        assert!(!c.rx(Medium::Ip, true, &buf));
+        // Verified by the driver.
+        assert!(c.rx(Medium::Ip, false, &buf));
+
+        let stats = c.snapshot(Default::default());
+        assert_eq!(stats.rx_packets, 3);
+        assert_eq!(stats.rx_bytes, 3 * buf.len() as u64);
+        assert_eq!(stats.rx_checksum_errors, 1);
+    }
+
+    #[test]
+    fn tcp_resets() {
+        let c = Counters::new();
+        c.rx(Medium::Ip, true, &segment(REMOTE, LOCAL, TcpControl::Rst, 0, b""));
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::Rst, 0, b""));
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::Rst, 0, b""));
+
+        let stats = c.snapshot(Default::default());
+        assert_eq!(stats.tcp_resets_received, 1);
+        assert_eq!(stats.tcp_resets_sent, 2);
+        assert_eq!(stats.tcp_retransmits, 0);
+    }
+
+    #[test]
+    fn tcp_retransmits() {
+        let c = Counters::new();
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::Syn, 100, b""));
+        // The SYN again.
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::Syn, 100, b""));
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::None, 101, b"hello"));
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::None, 106, b"world"));
+        // Retransmit of the first data segment, then new data.
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::None, 101, b"hello"));
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::None, 111, b"!"));
+        // Received segments don't count.
+        c.rx(Medium::Ip, true, &segment(REMOTE, LOCAL, TcpControl::None, 0, b"hi"));
+        c.rx(Medium::Ip, true, &segment(REMOTE, LOCAL, TcpControl::None, 0, b"hi"));
+        assert_eq!(c.snapshot(Default::default()).tcp_retransmits, 2);
+
+        // A new connection on the same ports.
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::Syn, 5, b""));
+        c.tx(Medium::Ip, &segment(LOCAL, REMOTE, TcpControl::None, 6, b"hello"));
+        assert_eq!(c.snapshot(Default::default()).tcp_retransmits, 2);
+    }
+}
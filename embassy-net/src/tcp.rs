@@ -2,24 +2,27 @@
 //!
 //! # Listening
 //!
-//! `embassy-net` does not have a `TcpListener`. Instead, individual `TcpSocket`s can be put into
-//! listening mode by calling [`TcpSocket::accept`].
+//! Individual `TcpSocket`s can be put into listening mode by calling [`TcpSocket::accept`].
 //!
 //! Incoming connections when no socket is listening are rejected. To accept many incoming
-//! connections, create many sockets and put them all into listening mode.
+//! connections, either create many sockets and put them all into listening mode, or use a
+//! [`TcpListener`], which keeps a backlog of listening sockets backed by a [`TcpClientState`]
+//! buffer pool.
 
 use core::cell::RefCell;
 use core::future::poll_fn;
 use core::mem;
-use core::task::Poll;
+use core::task::{Context, Poll};
 
 use embassy_net_driver::Driver;
 use embassy_time::Duration;
+use heapless::Vec;
 use smoltcp::iface::{Interface, SocketHandle};
 use smoltcp::socket::tcp;
 pub use smoltcp::socket::tcp::State;
 use smoltcp::wire::{IpEndpoint, IpListenEndpoint};
 
+pub use self::client::{TcpClientState, TcpConnection};
 use crate::time::duration_to_smoltcp;
 use crate::{SocketStack, Stack};
 
@@ -362,6 +365,113 @@ impl<'a> Drop for TcpSocket<'a> {
     }
 }
 
+/// A TCP listener, keeping a backlog of sockets listening on the same local endpoint.
+///
+/// The listener takes its socket buffers from a [`TcpClientState`] pool. Every free slot of the
+/// pool is turned into a socket in listening mode, so up to `N` incoming connections can be
+/// established concurrently, even while the application is busy with a previous one.
+///
+/// Accepted connections are returned as [`TcpConnection`]s. Their buffers go back to the pool
+/// when they're dropped, and the listener then uses them to listen again.
+pub struct TcpListener<'d, D: Driver, const N: usize, const TX_SZ: usize = 1024, const RX_SZ: usize = 1024> {
+    stack: &'d Stack<D>,
+    state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
+    endpoint: IpListenEndpoint,
+    backlog: Vec<TcpConnection<'d, N, TX_SZ, RX_SZ>, N>,
+}
+
+impl<'d, D: Driver, const N: usize, const TX_SZ: usize, const RX_SZ: usize> TcpListener<'d, D, N, TX_SZ, RX_SZ> {
+    /// Create a new `TcpListener` on the given local endpoint.
+    ///
+    /// No socket is put into listening mode until [`accept`](Self::accept) is first called.
+    pub fn new<T>(stack: &'d Stack<D>, state: &'d TcpClientState<N, TX_SZ, RX_SZ>, local_endpoint: T) -> Self
+    where
+        T: Into<IpListenEndpoint>,
+    {
+        Self {
+            stack,
+            state,
+            endpoint: local_endpoint.into(),
+            backlog: Vec::new(),
+        }
+    }
+
+    /// Get the local endpoint the listener accepts connections on.
+    pub fn local_endpoint(&self) -> IpListenEndpoint {
+        self.endpoint
+    }
+
+    /// Get the number of sockets currently listening for connections.
+    pub fn backlog_len(&self) -> usize {
+        self.backlog.len()
+    }
+
+    /// Accept a connection from a remote host.
+    ///
+    /// This puts all free buffers of the pool into listening mode, and waits until one of the
+    /// listening sockets receives a connection. If all buffers are used by connections that
+    /// were accepted previously, it waits until one of them is dropped.
+    pub async fn accept(&mut self) -> Result<TcpConnection<'d, N, TX_SZ, RX_SZ>, AcceptError> {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    /// Accept a connection from a remote host.
+    ///
+    /// When no connection is established yet, this method will return `Poll::Pending` and
+    /// register the current task to be notified when one is.
+    pub fn poll_accept(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<TcpConnection<'d, N, TX_SZ, RX_SZ>, AcceptError>> {
+        if let Err(e) = self.fill_backlog() {
+            return Poll::Ready(Err(e));
+        }
+
+        let endpoint = self.endpoint;
+        for n in 0..self.backlog.len() {
+            let established = self.backlog[n].socket.io.with_mut(|s, _| match s.state() {
+                tcp::State::Established | tcp::State::CloseWait => true,
+                tcp::State::Listen | tcp::State::SynSent | tcp::State::SynReceived => {
+                    s.register_send_waker(cx.waker());
+                    false
+                }
+                _ => {
+                    // The connection died before being accepted, e.g. it was reset by the
+                    // remote host. Listen again with the same buffers.
+                    s.abort();
+                    // The endpoint was accepted by `listen` before, and the socket is closed now.
+                    unwrap!(s.listen(endpoint).ok());
+                    s.register_send_waker(cx.waker());
+                    false
+                }
+            });
+            if established {
+                return Poll::Ready(Ok(self.backlog.swap_remove(n)));
+            }
+        }
+
+        // Get woken up when a connection is dropped, so its buffers can be used to listen again.
+        self.state.register_free_waker(cx.waker());
+        Poll::Pending
+    }
+
+    fn fill_backlog(&mut self) -> Result<(), AcceptError> {
+        while !self.backlog.is_full() {
+            let Ok(mut conn) = TcpConnection::new(self.stack, self.state) else {
+                // Pool exhausted.
+                break;
+            };
+            match conn.socket.io.with_mut(|s, _| s.listen(self.endpoint)) {
+                Ok(()) => {}
+                Err(tcp::ListenError::InvalidState) => return Err(AcceptError::InvalidState),
+                Err(tcp::ListenError::Unaddressable) => return Err(AcceptError::InvalidPort),
+            }
+            unwrap!(self.backlog.push(conn).ok());
+        }
+        Ok(())
+    }
+}
+
 // =======================
 
 #[derive(Copy, Clone)]
@@ -584,10 +694,13 @@ mod embedded_io_impls {
 
 /// TCP client compatible with `embedded-nal-async` traits.
 pub mod client {
-    use core::cell::{Cell, UnsafeCell};
+    use core::cell::{Cell, RefCell, UnsafeCell};
     use core::mem::MaybeUninit;
+    use core::ops::{Deref, DerefMut};
     use core::ptr::NonNull;
+    use core::task::Waker;
 
+    use embassy_sync::waitqueue::MultiWakerRegistration;
     use embedded_nal_async::IpAddr;
 
     use super::*;
@@ -628,7 +741,8 @@ pub mod client {
                 IpAddr::V6(_) => panic!("ipv6 support not enabled"),
             };
             let remote_endpoint = (addr, remote.port());
-            let mut socket = TcpConnection::new(&self.stack, self.state)?;
+            let mut socket = TcpConnection::new(&self.stack, self.state)
+                .inspect_err(|_| self.stack.socket.borrow().stats.socket_pool_exhausted())?;
             socket
                 .socket
                 .connect(remote_endpoint)
@@ -638,15 +752,20 @@ pub mod client {
         }
     }
 
-    /// Opened TCP connection in a [`TcpClient`].
+    /// Opened TCP connection in a [`TcpClient`] or [`TcpListener`](super::TcpListener).
+    ///
+    /// The underlying [`TcpSocket`] can be accessed through `Deref`.
     pub struct TcpConnection<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> {
-        socket: TcpSocket<'d>,
+        pub(super) socket: TcpSocket<'d>,
         state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
         bufs: NonNull<([u8; TX_SZ], [u8; RX_SZ])>,
     }
 
     impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> TcpConnection<'d, N, TX_SZ, RX_SZ> {
-        fn new<D: Driver>(stack: &'d Stack<D>, state: &'d TcpClientState<N, TX_SZ, RX_SZ>) -> Result<Self, Error> {
+        pub(super) fn new<D: Driver>(
+            stack: &'d Stack<D>,
+            state: &'d TcpClientState<N, TX_SZ, RX_SZ>,
+        ) -> Result<Self, Error> {
             let mut bufs = state.pool.alloc().ok_or(Error::ConnectionReset)?;
             Ok(Self {
                 socket: unsafe { TcpSocket::new(stack, &mut bufs.as_mut().1, &mut bufs.as_mut().0) },
@@ -662,6 +781,21 @@ pub mod client {
                 self.socket.close();
                 self.state.pool.free(self.bufs);
             }
+            self.state.free_waker.borrow_mut().wake();
+        }
+    }
+
+    impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> Deref for TcpConnection<'d, N, TX_SZ, RX_SZ> {
+        type Target = TcpSocket<'d>;
+
+        fn deref(&self) -> &Self::Target {
+            &self.socket
+        }
+    }
+
+    impl<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize> DerefMut for TcpConnection<'d, N, TX_SZ, RX_SZ> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            &mut self.socket
         }
     }
 
@@ -691,15 +825,24 @@ pub mod client {
         }
     }
 
-    /// State for TcpClient
+    /// State for TcpClient and TcpListener
     pub struct TcpClientState<const N: usize, const TX_SZ: usize, const RX_SZ: usize> {
         pool: Pool<([u8; TX_SZ], [u8; RX_SZ]), N>,
+        free_waker: RefCell<MultiWakerRegistration<N>>,
     }
 
     impl<const N: usize, const TX_SZ: usize, const RX_SZ: usize> TcpClientState<N, TX_SZ, RX_SZ> {
         /// Create a new `TcpClientState`.
         pub const fn new() -> Self {
-            Self { pool: Pool::new() }
+            Self {
+                pool: Pool::new(),
+                free_waker: RefCell::new(MultiWakerRegistration::new()),
+            }
+        }
+
+        /// Register a waker to be woken when a connection's buffers are returned to the pool.
+        pub(super) fn register_free_waker(&self, waker: &Waker) {
+            self.free_waker.borrow_mut().register(waker)
         }
     }
 
@@ -743,3 +886,159 @@ pub mod client {
         }
     }
 }
+
+#[cfg(all(test, feature = "medium-ip", feature = "proto-ipv4"))]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    use embassy_futures::join::join;
+    use embassy_futures::yield_now;
+
+    use super::*;
+    use crate::loopback::{self, ADDR_A};
+
+    const PORT: u16 = 1234;
+
+    /// A waker counting how many times it's woken.
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl CountingWaker {
+        fn count(&self) -> usize {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Poll `listener` once, expecting no connection to be accepted yet.
+    fn poll_accept_pending<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize>(
+        listener: &mut TcpListener<'d, loopback::Loopback, N, TX_SZ, RX_SZ>,
+        waker: &Waker,
+    ) {
+        assert!(listener.poll_accept(&mut Context::from_waker(waker)).is_pending());
+    }
+
+    /// Accept a connection, registering only `waker` to be woken.
+    async fn accept_with<'d, const N: usize, const TX_SZ: usize, const RX_SZ: usize>(
+        listener: &mut TcpListener<'d, loopback::Loopback, N, TX_SZ, RX_SZ>,
+        waker: &Waker,
+    ) -> TcpConnection<'d, N, TX_SZ, RX_SZ> {
+        loop {
+            if let Poll::Ready(conn) = listener.poll_accept(&mut Context::from_waker(waker)) {
+                return conn.unwrap();
+            }
+            yield_now().await;
+        }
+    }
+
+    #[test]
+    fn backlog() {
+        let stacks = loopback::stacks::<4>();
+        let state = TcpClientState::<2, 64, 64>::new();
+        let mut listener = TcpListener::new(&stacks.0, &state, PORT);
+        assert_eq!(listener.backlog_len(), 0);
+
+        loopback::run(&stacks, async {
+            let [rx1, tx1, rx2, tx2, rx3, tx3] = &mut [[0; 64]; 6];
+            let mut client1 = TcpSocket::new(&stacks.1, rx1, tx1);
+            let mut client2 = TcpSocket::new(&stacks.1, rx2, tx2);
+            let mut client3 = TcpSocket::new(&stacks.1, rx3, tx3);
+
+            let (conn1, connected) = join(listener.accept(), client1.connect((ADDR_A, PORT))).await;
+            connected.unwrap();
+            let conn1 = conn1.unwrap();
+            assert_eq!(conn1.remote_endpoint(), client1.local_endpoint());
+            assert_eq!(listener.backlog_len(), 1);
+
+            // The second connection is established by the backlog, before it's accepted.
+            client2.connect((ADDR_A, PORT)).await.unwrap();
+            // With all the buffers used, nothing listens anymore.
+            assert_eq!(
+                client3.connect((ADDR_A, PORT)).await,
+                Err(ConnectError::ConnectionReset)
+            );
+
+            let conn2 = listener.accept().await.unwrap();
+            assert_eq!(conn2.remote_endpoint(), client2.local_endpoint());
+            assert_eq!(listener.backlog_len(), 0);
+        });
+    }
+
+    #[test]
+    fn relisten_after_abort() {
+        let stacks = loopback::stacks::<3>();
+        let state = TcpClientState::<1, 64, 64>::new();
+        let mut listener = TcpListener::new(&stacks.0, &state, PORT);
+
+        loopback::run(&stacks, async {
+            let [rx1, tx1, rx2, tx2] = &mut [[0; 64]; 4];
+            let mut client1 = TcpSocket::new(&stacks.1, rx1, tx1);
+            let mut client2 = TcpSocket::new(&stacks.1, rx2, tx2);
+
+            poll_accept_pending(&mut listener, &Arc::new(CountingWaker::default()).into());
+            assert_eq!(listener.backlog_len(), 1);
+
+            // The connection is reset before it's accepted.
+            client1.connect((ADDR_A, PORT)).await.unwrap();
+            client1.abort();
+            client1.flush().await.unwrap();
+            while listener.backlog[0].state() != State::Closed {
+                yield_now().await;
+            }
+
+            // Its buffers listen again, and accept the next connection.
+            let (conn, connected) = join(listener.accept(), client2.connect((ADDR_A, PORT))).await;
+            connected.unwrap();
+            assert_eq!(conn.unwrap().remote_endpoint(), client2.local_endpoint());
+        });
+    }
+
+    #[test]
+    fn free_waker_wakes_all() {
+        let stacks = loopback::stacks::<4>();
+        let state = TcpClientState::<2, 64, 64>::new();
+        let mut listener1 = TcpListener::new(&stacks.0, &state, PORT);
+        let mut listener2 = TcpListener::new(&stacks.0, &state, PORT + 1);
+        let (count1, count2) = (Arc::new(CountingWaker::default()), Arc::new(CountingWaker::default()));
+        let (waker1, waker2) = (count1.clone().into(), count2.clone().into());
+
+        loopback::run(&stacks, async {
+            let [rx1, tx1, rx2, tx2] = &mut [[0; 64]; 4];
+            let mut client1 = TcpSocket::new(&stacks.1, rx1, tx1);
+            let mut client2 = TcpSocket::new(&stacks.1, rx2, tx2);
+
+            // Use all the buffers for connections of the first listener.
+            poll_accept_pending(&mut listener1, &waker1);
+            client1.connect((ADDR_A, PORT)).await.unwrap();
+            client2.connect((ADDR_A, PORT)).await.unwrap();
+            let conn1 = accept_with(&mut listener1, &waker1).await;
+            let conn2 = accept_with(&mut listener1, &waker1).await;
+
+            // Both listeners wait for buffers to be freed.
+            poll_accept_pending(&mut listener1, &waker1);
+            poll_accept_pending(&mut listener2, &waker2);
+            assert_eq!(listener1.backlog_len(), 0);
+            assert_eq!(listener2.backlog_len(), 0);
+            // The first listener's waker was also woken by its sockets' connections.
+            let woken = (count1.count(), count2.count());
+            assert_eq!(woken.1, 0);
+
+            drop(conn1);
+            assert_eq!((count1.count(), count2.count()), (woken.0 + 1, woken.1 + 1));
+
+            // Whichever listener polls first gets the buffers.
+            poll_accept_pending(&mut listener2, &waker2);
+            poll_accept_pending(&mut listener1, &waker1);
+            assert_eq!(listener1.backlog_len(), 0);
+            assert_eq!(listener2.backlog_len(), 1);
+            drop(conn2);
+        });
+    }
+}
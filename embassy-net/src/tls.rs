@@ -0,0 +1,311 @@
+//! TLS connections, using [`embedded-tls`](embedded_tls).
+//!
+//! [`TlsConnection`] runs TLS 1.3 over any transport implementing [`Read`] and [`Write`], such as
+//! a [`TcpSocket`](crate::tcp::TcpSocket) or a [`TcpConnection`](crate::tcp::TcpConnection), and
+//! implements [`Read`] and [`Write`] itself.
+//!
+//! This is a TLS client only: `embedded-tls` doesn't implement the server side of the handshake,
+//! so there is no server mode. A connection accepted with
+//! [`TcpSocket::accept`](crate::tcp::TcpSocket::accept) can be used as transport, but the
+//! handshake still has to be initiated from this end.
+//!
+//! # Buffers
+//!
+//! The record buffers are provided by the caller, like the buffers of TCP sockets. The read buffer
+//! must fit a whole record, which is up to [`MAX_RECORD_LEN`] bytes unless a smaller maximum
+//! fragment length is negotiated with [`TlsConfig::with_max_fragment_length`]. The write buffer
+//! only bounds the size of the records sent, and can be smaller.
+//!
+//! # Authentication
+//!
+//! The server is authenticated either with a pre-shared key, set with [`TlsConfig::with_psk`], or
+//! with its certificate. Certificates are checked by the [`TlsVerifier`] given to
+//! [`TlsConnection::open`], which gets the CA set with [`TlsConfig::with_ca`]. [`NoVerify`]
+//! accepts any certificate, so it must only be used with PSK or for testing.
+//!
+//! ## Example
+//! ```ignore
+//! let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+//! socket.connect(remote_endpoint).await?;
+//!
+//! let config = TlsConfig::new()
+//!     .with_server_name("broker.example.com")
+//!     .with_psk(&psk, &[identity]);
+//! let mut tls = TlsConnection::new(socket, &mut read_record_buffer, &mut write_record_buffer);
+//! tls.open::<_, NoVerify>(&config, &mut rng).await?;
+//! tls.write_all(b"hello").await?;
+//! tls.flush().await?;
+//! ```
+
+use embedded_io_async::{ErrorType, Read, Write};
+use embedded_tls::TlsContext;
+pub use embedded_tls::{
+    Aes128GcmSha256, Aes256GcmSha384, Certificate, MaxFragmentLength, NoVerify, TlsCipherSuite, TlsConfig, TlsError,
+    TlsVerifier,
+};
+use rand_core::{CryptoRng, RngCore};
+
+/// Maximum length of a TLS record, including its header and the encryption overhead.
+///
+/// This is the size of the read record buffer needed to talk to any server.
+pub const MAX_RECORD_LEN: usize = 16640;
+
+/// A TLS connection over a transport `S`, using the cipher suite `C`.
+pub struct TlsConnection<'a, S, C = Aes128GcmSha256>
+where
+    S: Read + Write + 'a,
+    C: TlsCipherSuite + 'static,
+{
+    inner: embedded_tls::TlsConnection<'a, S, C>,
+}
+
+impl<'a, S, C> TlsConnection<'a, S, C>
+where
+    S: Read + Write + 'a,
+    C: TlsCipherSuite + 'static,
+{
+    /// Create a new TLS connection over `transport`, which must already be connected.
+    ///
+    /// See the [module documentation](self) for the sizes of the record buffers. Nothing is sent
+    /// until [`open`](Self::open) is called.
+    pub fn new(transport: S, read_record_buffer: &'a mut [u8], write_record_buffer: &'a mut [u8]) -> Self {
+        Self {
+            inner: embedded_tls::TlsConnection::new(transport, read_record_buffer, write_record_buffer),
+        }
+    }
+
+    /// Do the TLS handshake, checking the server certificate, if any, with `V`.
+    ///
+    /// `rng` must be a cryptographically secure random number generator.
+    pub async fn open<'v, RNG, V>(&mut self, config: &'v TlsConfig<'v, C>, rng: &'v mut RNG) -> Result<(), TlsError>
+    where
+        RNG: CryptoRng + RngCore + 'v,
+        V: TlsVerifier<'v, C>,
+    {
+        self.inner.open::<RNG, V>(TlsContext::new(config, rng)).await
+    }
+
+    /// Send a close notification, and get the transport back.
+    ///
+    /// The transport is returned along with the error if sending fails.
+    pub async fn close(self) -> Result<S, (S, TlsError)> {
+        self.inner.close().await
+    }
+}
+
+impl<'a, S, C> ErrorType for TlsConnection<'a, S, C>
+where
+    S: Read + Write + 'a,
+    C: TlsCipherSuite + 'static,
+{
+    type Error = TlsError;
+}
+
+impl<'a, S, C> Read for TlsConnection<'a, S, C>
+where
+    S: Read + Write + 'a,
+    C: TlsCipherSuite + 'static,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.inner.read(buf).await
+    }
+}
+
+impl<'a, S, C> Write for TlsConnection<'a, S, C>
+where
+    S: Read + Write + 'a,
+    C: TlsCipherSuite + 'static,
+{
+    /// Write data to the current record, which is sent when full or on [`flush`](Write::flush).
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.inner.write(buf).await
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.inner.flush().await
+    }
+}
+
+#[cfg(all(test, feature = "medium-ip", feature = "proto-ipv4"))]
+mod tests {
+    use std::fs::File;
+    use std::io::ErrorKind as IoErrorKind;
+    use std::net::{TcpListener, TcpStream};
+    use std::process::{Command, Stdio};
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use embassy_futures::join::join;
+    use embassy_futures::yield_now;
+    use embedded_nal_async::TcpConnect;
+
+    use super::*;
+    use crate::loopback::{self, ADDR_A, ADDR_B};
+    use crate::tcp::client::{TcpClient, TcpClientState};
+    use crate::tcp::TcpSocket;
+
+    const PSK: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+    const PSK_HEX: &str = "000102030405060708090a0b0c0d0e0f";
+    const PSK_IDENTITY: &[u8] = b"embassy";
+
+    struct OsRng(File);
+
+    impl RngCore for OsRng {
+        fn next_u32(&mut self) -> u32 {
+            rand_core::impls::next_u32_via_fill(self)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            rand_core::impls::next_u64_via_fill(self)
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            std::io::Read::read_exact(&mut self.0, dest).unwrap();
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    impl CryptoRng for OsRng {}
+
+    fn os_rng() -> OsRng {
+        OsRng(File::open("/dev/urandom").unwrap())
+    }
+
+    /// The handshake goes through a `TcpSocket`, and fails when the server doesn't speak TLS.
+    #[test]
+    fn handshake_error() {
+        let stacks = loopback::stacks::<2>();
+        let (mut rx_a, mut tx_a, mut rx_b, mut tx_b) = ([0; 4096], [0; 4096], [0; 4096], [0; 4096]);
+        let mut client = TcpSocket::new(&stacks.0, &mut rx_a, &mut tx_a);
+        let mut server = TcpSocket::new(&stacks.1, &mut rx_b, &mut tx_b);
+
+        let mut read_record_buffer = [0; MAX_RECORD_LEN];
+        let mut write_record_buffer = [0; 4096];
+        let mut rng = os_rng();
+        let config = TlsConfig::new().with_psk(&PSK, &[PSK_IDENTITY]);
+
+        loopback::run(&stacks, async {
+            let (accepted, connected) = join(server.accept(443), client.connect((ADDR_B, 443))).await;
+            accepted.unwrap();
+            connected.unwrap();
+
+            let mut tls: TlsConnection<_> =
+                TlsConnection::new(client, &mut read_record_buffer, &mut write_record_buffer);
+            let (opened, ()) = join(tls.open::<_, NoVerify>(&config, &mut rng), async {
+                // The client hello is a handshake record.
+                let mut buf = [0; 5];
+                server.read_exact(&mut buf).await.unwrap();
+                assert_eq!(buf[0], 0x16);
+
+                server.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await.unwrap();
+                server.flush().await.unwrap();
+                server.close();
+            })
+            .await;
+            assert!(opened.is_err());
+        });
+    }
+
+    /// Run a handshake with a PSK against `openssl s_server`, which sends back each line reversed.
+    ///
+    /// The client goes through a `TcpConnection` of one stack, and the other stack forwards the
+    /// connection to the server. Skipped if `openssl` can't be run.
+    #[test]
+    fn openssl_s_server() {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let server = Command::new("openssl")
+            .args(["s_server", "-tls1_3", "-nocert", "-rev", "-naccept", "1", "-quiet"])
+            .args(["-accept", &std::format!("127.0.0.1:{}", port)])
+            .args(["-psk_identity", "embassy", "-psk", PSK_HEX])
+            .stdout(Stdio::null())
+            .spawn();
+        let Ok(mut server) = server else {
+            std::eprintln!("openssl not found, skipping");
+            return;
+        };
+
+        let upstream = (0..50)
+            .find_map(|_| {
+                sleep(Duration::from_millis(100));
+                TcpStream::connect(("127.0.0.1", port)).ok()
+            })
+            .expect("openssl s_server didn't start");
+        upstream.set_nonblocking(true).unwrap();
+
+        let stacks = loopback::stacks::<2>();
+        let state = TcpClientState::<1, 4096, 4096>::new();
+        let client = TcpClient::new(&stacks.0, &state);
+        let (mut rx_b, mut tx_b) = ([0; 4096], [0; 4096]);
+        let mut proxy = TcpSocket::new(&stacks.1, &mut rx_b, &mut tx_b);
+
+        let mut read_record_buffer = [0; MAX_RECORD_LEN];
+        let mut write_record_buffer = [0; 4096];
+        let mut rng = os_rng();
+        let config = TlsConfig::new().with_psk(&PSK, &[PSK_IDENTITY]);
+
+        loopback::run(&stacks, async {
+            let remote = embedded_nal_async::SocketAddr::new(ADDR_B.0.into(), 443);
+            let (accepted, connection) = join(proxy.accept(443), client.connect(remote)).await;
+            accepted.unwrap();
+
+            let mut tls: TlsConnection<_> =
+                TlsConnection::new(connection.unwrap(), &mut read_record_buffer, &mut write_record_buffer);
+            let exchange = async {
+                tls.open::<_, NoVerify>(&config, &mut rng).await.unwrap();
+                tls.write_all(b"hello\n").await.unwrap();
+                tls.flush().await.unwrap();
+
+                let mut buf = [0; 16];
+                let mut len = 0;
+                while !buf[..len].ends_with(b"\n") {
+                    let n = tls.read(&mut buf[len..]).await.unwrap();
+                    assert_ne!(n, 0, "connection closed");
+                    len += n;
+                }
+                assert_eq!(&buf[..len], b"olleh\n");
+            };
+
+            // Forward the connection between the second stack and the server.
+            let (mut reader, mut writer) = proxy.split();
+            let to_server = async {
+                let mut buf = [0; 1024];
+                loop {
+                    let n = reader.read(&mut buf).await.unwrap();
+                    if n == 0 {
+                        break;
+                    }
+                    let mut sent = 0;
+                    while sent < n {
+                        match std::io::Write::write(&mut &upstream, &buf[sent..n]) {
+                            Ok(m) => sent += m,
+                            Err(e) if e.kind() == IoErrorKind::WouldBlock => yield_now().await,
+                            Err(e) => panic!("{}", e),
+                        }
+                    }
+                }
+            };
+            let from_server = async {
+                let mut buf = [0; 1024];
+                loop {
+                    match std::io::Read::read(&mut &upstream, &mut buf) {
+                        Ok(0) => break,
+                        Ok(n) => writer.write_all(&buf[..n]).await.unwrap(),
+                        Err(e) if e.kind() == IoErrorKind::WouldBlock => yield_now().await,
+                        Err(e) => panic!("{}", e),
+                    }
+                }
+            };
+
+            match embassy_futures::select::select3(exchange, to_server, from_server).await {
+                embassy_futures::select::Either3::First(()) => {}
+                _ => panic!("the server closed the connection"),
+            }
+        });
+
+        server.kill().ok();
+    }
+}
@@ -0,0 +1,566 @@
+//! VLAN (IEEE 802.1Q) sub-interfaces.
+//!
+//! A [`Runner`] takes an Ethernet [`Driver`] and splits it into several [`Device`]s, one per VLAN,
+//! each of which can back its own [`Stack`](crate::Stack). The runner receives the frames of the
+//! underlying driver and hands them to the device of their VLAN, stripping the tag, and tags the
+//! frames sent by the devices.
+//!
+//! Tags are inserted and stripped in software, unless the driver can do it, as reported in
+//! its [`VlanCapabilities`](embassy_net_driver::VlanCapabilities). Untagged frames and frames of
+//! other VLANs are dropped.
+//!
+//! Multicast groups joined on the sub-interfaces are not passed on to the underlying driver. If it
+//! filters received frames by MAC address, it must be configured to let the multicast frames through.
+//!
+//! ## Example
+//! ```ignore
+//! static STATE_10: StaticCell<vlan::State<1514, 4, 4>> = StaticCell::new();
+//! static STATE_20: StaticCell<vlan::State<1514, 4, 4>> = StaticCell::new();
+//!
+//! let mut runner = vlan::Runner::<_, 1514, 2>::new(device);
+//! let device_10 = runner.add(10, STATE_10.init(vlan::State::new()));
+//! let device_20 = runner.add(20, STATE_20.init(vlan::State::new()));
+//! spawner.spawn(vlan_task(runner)).unwrap();
+//!
+//! let stack_10 = Stack::new(device_10, config_10, resources_10, seed);
+//! let stack_20 = Stack::new(device_20, config_20, resources_20, seed);
+//! ```
+
+use core::future::poll_fn;
+use core::task::{Context, Poll};
+
+use embassy_net_driver::{Capabilities, Driver, HardwareAddress, LinkState, RxMeta, RxToken, Stats, TxMeta, TxToken};
+use embassy_net_driver_channel as ch;
+use heapless::Vec;
+
+/// EtherType of 802.1Q tagged frames.
+const TPID: [u8; 2] = [0x81, 0x00];
+/// Length of an 802.1Q tag.
+const TAG_LEN: usize = 4;
+/// Offset of the tag in a frame, right after the destination and source addresses.
+const TAG_OFFSET: usize = 12;
+/// Length of the addresses and tag written in front of the rest of a frame, when tagging in software.
+const HEADER_LEN: usize = TAG_OFFSET + TAG_LEN;
+
+/// Mask of the VLAN ID in a tag control information field.
+const VID_MASK: u16 = 0x0fff;
+
+/// A sub-interface device, to create a [`Stack`](crate::Stack) with.
+pub struct Device<'d, const MTU: usize> {
+    inner: ch::Device<'d, MTU, HEADER_LEN>,
+    max_transmission_unit: usize,
+}
+
+impl<'d, const MTU: usize> Driver for Device<'d, MTU> {
+    type RxToken<'a> = ch::RxToken<'a, MTU> where Self: 'a;
+    type TxToken<'a> = ch::TxToken<'a, MTU, HEADER_LEN> where Self: 'a;
+
+    fn receive(&mut self, cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        self.inner.receive(cx)
+    }
+
+    fn transmit(&mut self, cx: &mut Context) -> Option<Self::TxToken<'_>> {
+        self.inner.transmit(cx)
+    }
+
+    fn link_state(&mut self, cx: &mut Context) -> LinkState {
+        self.inner.link_state(cx)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let mut caps = self.inner.capabilities();
+        caps.max_transmission_unit = self.max_transmission_unit;
+        caps
+    }
+
+    fn hardware_address(&self) -> HardwareAddress {
+        self.inner.hardware_address()
+    }
+
+    fn stats(&self) -> Stats {
+        self.inner.stats()
+    }
+}
+
+/// The buffers of a sub-interface.
+///
+/// `MTU` is the size of the frame buffers, which must fit the largest frame of the underlying driver.
+/// `N_RX` and `N_TX` are the number of frames that can be queued in each direction.
+pub struct State<const MTU: usize, const N_RX: usize, const N_TX: usize> {
+    inner: ch::State<MTU, N_RX, N_TX, HEADER_LEN>,
+}
+
+impl<const MTU: usize, const N_RX: usize, const N_TX: usize> State<MTU, N_RX, N_TX> {
+    /// Create a new `State`.
+    pub const fn new() -> Self {
+        Self {
+            inner: ch::State::new(),
+        }
+    }
+}
+
+impl<const MTU: usize, const N_RX: usize, const N_TX: usize> Default for State<MTU, N_RX, N_TX> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+struct Interface<'d, const MTU: usize> {
+    vlan_id: u16,
+    runner: ch::Runner<'d, MTU, HEADER_LEN>,
+}
+
+/// Runner splitting a driver into VLAN sub-interfaces.
+///
+/// Up to `N` sub-interfaces can be added with [`add`](Self::add). They don't get or send any frames
+/// until [`run`](Self::run) is running.
+pub struct Runner<'d, D: Driver, const MTU: usize, const N: usize> {
+    driver: D,
+    /// Whether the driver inserts the tags.
+    insertion: bool,
+    /// Largest frame the driver can transmit.
+    max_frame_len: usize,
+    link_state: Option<LinkState>,
+    interfaces: Vec<Interface<'d, MTU>, N>,
+}
+
+impl<'d, D: Driver, const MTU: usize, const N: usize> Runner<'d, D, MTU, N> {
+    /// Create a new runner for an Ethernet driver.
+    pub fn new(driver: D) -> Self {
+        assert!(
+            matches!(driver.hardware_address(), HardwareAddress::Ethernet(_)),
+            "VLANs need an Ethernet driver"
+        );
+        let caps = driver.capabilities();
+        Self {
+            driver,
+            insertion: caps.vlan.insertion,
+            max_frame_len: caps.max_transmission_unit,
+            link_state: None,
+            interfaces: Vec::new(),
+        }
+    }
+
+    /// Add a sub-interface for the VLAN with ID `vlan_id`, and get its device.
+    ///
+    /// The sub-interface has the same MAC address as the underlying driver. When tags are inserted in
+    /// software, its MTU is 4 bytes less than `MTU`, to leave room for the tag.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `vlan_id` isn't between 1 and 4094, if there already is a sub-interface for it, or
+    /// if there are already `N` sub-interfaces.
+    pub fn add<const N_RX: usize, const N_TX: usize>(
+        &mut self,
+        vlan_id: u16,
+        state: &'d mut State<MTU, N_RX, N_TX>,
+    ) -> Device<'d, MTU> {
+        assert!((1..=4094).contains(&vlan_id), "invalid VLAN ID");
+        assert!(
+            self.interfaces.iter().all(|i| i.vlan_id != vlan_id),
+            "VLAN already added"
+        );
+
+        let (runner, inner) = ch::new(&mut state.inner, self.driver.hardware_address());
+        if self.interfaces.push(Interface { vlan_id, runner }).is_err() {
+            panic!("too many VLANs");
+        }
+        // Have the link state of the new sub-interface set.
+        self.link_state = None;
+        Device {
+            inner,
+            max_transmission_unit: if self.insertion { MTU } else { MTU - TAG_LEN },
+        }
+    }
+
+    /// Get the underlying driver.
+    pub fn driver(&mut self) -> &mut D {
+        &mut self.driver
+    }
+
+    /// Run the sub-interfaces.
+    pub async fn run(mut self) -> ! {
+        poll_fn(|cx| {
+            self.poll(cx);
+            Poll::<()>::Pending
+        })
+        .await;
+        unreachable!()
+    }
+
+    fn poll(&mut self, cx: &mut Context<'_>) {
+        let link_state = self.driver.link_state(cx);
+        if self.link_state != Some(link_state) {
+            self.link_state = Some(link_state);
+            for i in &mut self.interfaces {
+                i.runner.set_link_state(link_state);
+            }
+        }
+
+        while let Some((rx, _)) = self.driver.receive(cx) {
+            let meta = rx.meta();
+            rx.consume(|frame| receive(&mut self.interfaces, meta, frame));
+        }
+
+        for i in &mut self.interfaces {
+            // Send until the sub-interface has nothing left, which registers the waker, or the
+            // driver is full. The packets returned at once stop where the queue wraps around.
+            while let Poll::Ready(bufs) = i.runner.poll_tx_bufs(cx) {
+                let len = bufs.len();
+                let mut n = 0;
+                let mut dropped = 0;
+                for buf in bufs.iter_mut() {
+                    let frame_len = buf.len() + if self.insertion { 0 } else { TAG_LEN };
+                    if frame_len > self.max_frame_len || buf.len() < TAG_OFFSET {
+                        dropped += 1;
+                        n += 1;
+                        continue;
+                    }
+                    let Some(mut tx) = self.driver.transmit(cx) else {
+                        break;
+                    };
+                    let tci = i.vlan_id;
+                    if self.insertion {
+                        let mut meta = TxMeta::default();
+                        meta.vlan_tci = Some(tci);
+                        tx.set_meta(meta);
+                        tx.consume(frame_len, |b| b.copy_from_slice(buf.packet()));
+                    } else {
+                        // The header holds the addresses followed by the tag, and replaces the
+                        // addresses of the packet.
+                        let mut header = [0; HEADER_LEN];
+                        header[..TAG_OFFSET].copy_from_slice(&buf.packet()[..TAG_OFFSET]);
+                        header[TAG_OFFSET..][..2].copy_from_slice(&TPID);
+                        header[TAG_OFFSET + 2..].copy_from_slice(&tci.to_be_bytes());
+                        *buf.header_mut() = header;
+
+                        let (header, packet) = buf.frame();
+                        tx.consume(frame_len, |b| {
+                            b[..HEADER_LEN].copy_from_slice(header);
+                            b[HEADER_LEN..].copy_from_slice(&packet[TAG_OFFSET..]);
+                        });
+                    }
+                    n += 1;
+                }
+                i.runner.tx_done_many(n);
+                if dropped != 0 {
+                    i.runner
+                        .update_stats(|s| s.tx_dropped = s.tx_dropped.wrapping_add(dropped));
+                }
+                if n < len {
+                    // The driver is full, and wakes the runner when it isn't anymore.
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Hand a frame received from the driver to the sub-interface of its VLAN.
+fn receive<const MTU: usize>(interfaces: &mut [Interface<'_, MTU>], meta: RxMeta, frame: &[u8]) {
+    let (tci, untagged_len) = match meta.vlan_tci {
+        // Already stripped by the driver.
+        Some(tci) => (tci, frame.len()),
+        None => {
+            if frame.len() < TAG_OFFSET + TAG_LEN || frame[TAG_OFFSET..][..2] != TPID {
+                return;
+            }
+            let tci = u16::from_be_bytes([frame[TAG_OFFSET + 2], frame[TAG_OFFSET + 3]]);
+            (tci, frame.len() - TAG_LEN)
+        }
+    };
+    let Some(i) = interfaces.iter_mut().find(|i| i.vlan_id == tci & VID_MASK) else {
+        return;
+    };
+
+    if untagged_len > MTU {
+        i.runner.update_stats(|s| s.rx_errors = s.rx_errors.wrapping_add(1));
+        return;
+    }
+    // The frame is counted as dropped if the sub-interface has no free buffer.
+    let Some(buf) = i.runner.try_rx_buf() else {
+        return;
+    };
+    if untagged_len == frame.len() {
+        buf[..untagged_len].copy_from_slice(frame);
+    } else {
+        buf[..TAG_OFFSET].copy_from_slice(&frame[..TAG_OFFSET]);
+        buf[TAG_OFFSET..untagged_len].copy_from_slice(&frame[TAG_OFFSET + TAG_LEN..]);
+    }
+    i.runner.rx_done(untagged_len);
+}
+
+#[cfg(test)]
+mod tests {
+    use core::task::{RawWaker, RawWakerVTable, Waker};
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    use embassy_net_driver::Capabilities;
+
+    use super::*;
+
+    const MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 1];
+
+    struct FakeDriver {
+        caps: Capabilities,
+        rx: VecDeque<(Vec<u8>, RxMeta)>,
+        tx: Vec<(Vec<u8>, TxMeta)>,
+    }
+
+    impl FakeDriver {
+        fn new(insertion: bool, max_frame_len: usize) -> Self {
+            let mut caps = Capabilities::default();
+            caps.max_transmission_unit = max_frame_len;
+            caps.vlan.insertion = insertion;
+            caps.vlan.stripping = insertion;
+            Self {
+                caps,
+                rx: VecDeque::new(),
+                tx: Vec::new(),
+            }
+        }
+    }
+
+    struct FakeRxToken(Vec<u8>, RxMeta);
+
+    impl RxToken for FakeRxToken {
+        fn consume<R, F: FnOnce(&mut [u8]) -> R>(mut self, f: F) -> R {
+            f(&mut self.0)
+        }
+
+        fn meta(&self) -> RxMeta {
+            self.1
+        }
+    }
+
+    struct FakeTxToken<'a> {
+        tx: &'a mut Vec<(Vec<u8>, TxMeta)>,
+        meta: TxMeta,
+    }
+
+    impl<'a> TxToken for FakeTxToken<'a> {
+        fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+            let mut frame = std::vec![0; len];
+            let r = f(&mut frame);
+            self.tx.push((frame, self.meta));
+            r
+        }
+
+        fn set_meta(&mut self, meta: TxMeta) {
+            self.meta = meta;
+        }
+    }
+
+    impl Driver for FakeDriver {
+        type RxToken<'a> = FakeRxToken;
+        type TxToken<'a> = FakeTxToken<'a>;
+
+        fn receive(&mut self, _cx: &mut Context) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+            let (frame, meta) = self.rx.pop_front()?;
+            let tx = FakeTxToken {
+                tx: &mut self.tx,
+                meta: TxMeta::default(),
+            };
+            Some((FakeRxToken(frame, meta), tx))
+        }
+
+        fn transmit(&mut self, _cx: &mut Context) -> Option<Self::TxToken<'_>> {
+            Some(FakeTxToken {
+                tx: &mut self.tx,
+                meta: TxMeta::default(),
+            })
+        }
+
+        fn link_state(&mut self, _cx: &mut Context) -> LinkState {
+            LinkState::Up
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            self.caps.clone()
+        }
+
+        fn hardware_address(&self) -> HardwareAddress {
+            HardwareAddress::Ethernet(MAC)
+        }
+    }
+
+    fn noop_waker() -> Waker {
+        const VTABLE: RawWakerVTable =
+            RawWakerVTable::new(|_| RawWaker::new(core::ptr::null(), &VTABLE), |_| {}, |_| {}, |_| {});
+        unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) }
+    }
+
+    /// An untagged IPv4 frame.
+    fn frame(payload: &[u8]) -> Vec<u8> {
+        let mut f = Vec::new();
+        f.extend_from_slice(&[0xff; 6]);
+        f.extend_from_slice(&MAC);
+        f.extend_from_slice(&[0x08, 0x00]);
+        f.extend_from_slice(payload);
+        f
+    }
+
+    fn tagged(tci: u16, payload: &[u8]) -> Vec<u8> {
+        let mut f = frame(payload);
+        let mut tag = Vec::from(TPID);
+        tag.extend_from_slice(&tci.to_be_bytes());
+        f.splice(TAG_OFFSET..TAG_OFFSET, tag);
+        f
+    }
+
+    fn rx_frame<const MTU: usize>(device: &mut Device<'_, MTU>, cx: &mut Context) -> Option<Vec<u8>> {
+        let (rx, _) = device.receive(cx)?;
+        Some(rx.consume(|f| f.to_vec()))
+    }
+
+    fn tx_frame<const MTU: usize>(device: &mut Device<'_, MTU>, cx: &mut Context, frame: &[u8]) {
+        let tx = device.transmit(cx).unwrap();
+        tx.consume(frame.len(), |b| b.copy_from_slice(frame));
+    }
+
+    #[test]
+    fn receive_strips_tag() {
+        let waker = noop_waker();
+        let cx = &mut Context::from_waker(&waker);
+        let mut state_10 = State::<1518, 2, 2>::new();
+        let mut state_20 = State::<1518, 2, 2>::new();
+        let mut runner = Runner::<_, 1518, 2>::new(FakeDriver::new(false, 1518));
+        let mut device_10 = runner.add(10, &mut state_10);
+        let mut device_20 = runner.add(20, &mut state_20);
+
+        // Priority 5, VLAN 20.
+        runner
+            .driver()
+            .rx
+            .push_back((tagged(0xa014, b"hello"), RxMeta::default()));
+        // Untagged, priority-tagged, and of another VLAN.
+        runner.driver().rx.push_back((frame(b"untagged"), RxMeta::default()));
+        runner.driver().rx.push_back((tagged(0, b"vlan 0"), RxMeta::default()));
+        runner
+            .driver()
+            .rx
+            .push_back((tagged(30, b"vlan 30"), RxMeta::default()));
+        runner.poll(cx);
+
+        assert_eq!(rx_frame(&mut device_20, cx), Some(frame(b"hello")));
+        assert_eq!(rx_frame(&mut device_20, cx), None);
+        assert_eq!(rx_frame(&mut device_10, cx), None);
+    }
+
+    #[test]
+    fn receive_stripped_by_driver() {
+        let waker = noop_waker();
+        let cx = &mut Context::from_waker(&waker);
+        let mut state = State::<1514, 2, 2>::new();
+        let mut runner = Runner::<_, 1514, 1>::new(FakeDriver::new(true, 1514));
+        let mut device = runner.add(10, &mut state);
+
+        let mut meta = RxMeta::default();
+        meta.vlan_tci = Some(0x200a);
+        runner.driver().rx.push_back((frame(b"hello"), meta));
+        meta.vlan_tci = Some(11);
+        runner.driver().rx.push_back((frame(b"vlan 11"), meta));
+        runner.poll(cx);
+
+        assert_eq!(rx_frame(&mut device, cx), Some(frame(b"hello")));
+        assert_eq!(rx_frame(&mut device, cx), None);
+    }
+
+    #[test]
+    fn receive_too_large() {
+        let waker = noop_waker();
+        let cx = &mut Context::from_waker(&waker);
+        let mut state = State::<64, 2, 2>::new();
+        let mut runner = Runner::<_, 64, 1>::new(FakeDriver::new(false, 64));
+        let mut device = runner.add(10, &mut state);
+
+        runner.driver().rx.push_back((tagged(10, &[0; 51]), RxMeta::default()));
+        runner.driver().rx.push_back((tagged(10, &[0; 50]), RxMeta::default()));
+        runner.poll(cx);
+
+        assert_eq!(rx_frame(&mut device, cx), Some(frame(&[0; 50])));
+        assert_eq!(rx_frame(&mut device, cx), None);
+        assert_eq!(device.stats().rx_errors, 1);
+    }
+
+    #[test]
+    fn transmit_inserts_tag() {
+        let waker = noop_waker();
+        let cx = &mut Context::from_waker(&waker);
+        let mut state_10 = State::<1518, 2, 2>::new();
+        let mut state_20 = State::<1518, 2, 2>::new();
+        let mut runner = Runner::<_, 1518, 2>::new(FakeDriver::new(false, 1518));
+        let mut device_10 = runner.add(10, &mut state_10);
+        let mut device_20 = runner.add(20, &mut state_20);
+        assert_eq!(device_10.capabilities().max_transmission_unit, 1514);
+
+        tx_frame(&mut device_10, cx, &frame(b"ten"));
+        tx_frame(&mut device_20, cx, &frame(b"twenty"));
+        runner.poll(cx);
+
+        let tx = &runner.driver().tx;
+        assert_eq!(tx.len(), 2);
+        assert_eq!(tx[0].0, tagged(10, b"ten"));
+        assert_eq!(tx[0].1.vlan_tci, None);
+        assert_eq!(tx[1].0, tagged(20, b"twenty"));
+    }
+
+    #[test]
+    fn transmit_wraps_around() {
+        let waker = noop_waker();
+        let cx = &mut Context::from_waker(&waker);
+        let mut state = State::<1518, 2, 2>::new();
+        let mut runner = Runner::<_, 1518, 1>::new(FakeDriver::new(false, 1518));
+        let mut device = runner.add(10, &mut state);
+
+        tx_frame(&mut device, cx, &frame(b"one"));
+        runner.poll(cx);
+        // The second frame is at the end of the queue, the third one wraps around to the start.
+        tx_frame(&mut device, cx, &frame(b"two"));
+        tx_frame(&mut device, cx, &frame(b"three"));
+        runner.poll(cx);
+
+        let tx = &runner.driver().tx;
+        assert_eq!(tx.len(), 3);
+        assert_eq!(tx[0].0, tagged(10, b"one"));
+        assert_eq!(tx[1].0, tagged(10, b"two"));
+        assert_eq!(tx[2].0, tagged(10, b"three"));
+    }
+
+    #[test]
+    fn transmit_inserted_by_driver() {
+        let waker = noop_waker();
+        let cx = &mut Context::from_waker(&waker);
+        let mut state = State::<1514, 2, 2>::new();
+        let mut runner = Runner::<_, 1514, 1>::new(FakeDriver::new(true, 1514));
+        let mut device = runner.add(10, &mut state);
+        assert_eq!(device.capabilities().max_transmission_unit, 1514);
+
+        tx_frame(&mut device, cx, &frame(b"ten"));
+        runner.poll(cx);
+
+        let tx = &runner.driver().tx;
+        assert_eq!(tx.len(), 1);
+        assert_eq!(tx[0].0, frame(b"ten"));
+        assert_eq!(tx[0].1.vlan_tci, Some(10));
+    }
+
+    #[test]
+    fn transmit_too_large() {
+        let waker = noop_waker();
+        let cx = &mut Context::from_waker(&waker);
+        let mut state = State::<64, 2, 2>::new();
+        let mut runner = Runner::<_, 64, 1>::new(FakeDriver::new(false, 62));
+        let mut device = runner.add(10, &mut state);
+
+        tx_frame(&mut device, cx, &frame(&[0; 45]));
+        tx_frame(&mut device, cx, &frame(&[0; 44]));
+        runner.poll(cx);
+
+        let tx = &runner.driver().tx;
+        assert_eq!(tx.len(), 1);
+        assert_eq!(tx[0].0, tagged(10, &[0; 44]));
+        assert_eq!(device.stats().tx_dropped, 1);
+    }
+}
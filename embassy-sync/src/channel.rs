@@ -17,6 +17,15 @@
 //! messages that it can store, and if this limit is reached, trying to send
 //! another message will result in an error being returned.
 //!
+//! # Closing
+//!
+//! A channel never closes unless asked to. It is closed by [`Channel::close`], or when the last
+//! [`CountedSender`] or the last [`CountedReceiver`] of the channel is dropped. Counted senders
+//! and receivers then fail with an error: senders right away, receivers once the messages left in
+//! the channel are received. The `try_*` methods of the other handles fail too, but their `send`
+//! and `receive` futures can't report it, and never complete. The message of a pending `send`
+//! is dropped with its future.
+//!
 
 use core::cell::RefCell;
 use core::future::Future;
@@ -258,7 +267,7 @@ impl<'ch, T> Future for DynamicReceiveFuture<'ch, T> {
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
         match self.channel.try_receive_with_context(Some(cx)) {
             Ok(v) => Poll::Ready(v),
-            Err(TryReceiveError::Empty) => Poll::Pending,
+            Err(TryReceiveError::Empty | TryReceiveError::Closed) => Poll::Pending,
         }
     }
 }
@@ -283,7 +292,7 @@ where
         match self.message.take() {
             Some(m) => match self.channel.try_send_with_context(m, Some(cx)) {
                 Ok(..) => Poll::Ready(()),
-                Err(TrySendError::Full(m)) => {
+                Err(TrySendError::Full(m) | TrySendError::Closed(m)) => {
                     self.message = Some(m);
                     Poll::Pending
                 }
@@ -309,7 +318,7 @@ impl<'ch, T> Future for DynamicSendFuture<'ch, T> {
         match self.message.take() {
             Some(m) => match self.channel.try_send_with_context(m, Some(cx)) {
                 Ok(..) => Poll::Ready(()),
-                Err(TrySendError::Full(m)) => {
+                Err(TrySendError::Full(m) | TrySendError::Closed(m)) => {
                     self.message = Some(m);
                     Poll::Pending
                 }
@@ -321,6 +330,199 @@ impl<'ch, T> Future for DynamicSendFuture<'ch, T> {
 
 impl<'ch, T> Unpin for DynamicSendFuture<'ch, T> {}
 
+/// Send-only access to a [`Channel`] or a [`PriorityChannel`](crate::priority_channel::PriorityChannel),
+/// counted to close the channel when the last one is dropped.
+///
+/// Sending fails once the channel is closed. See the [module documentation](self) for closing.
+pub struct CountedSender<'ch, T> {
+    channel: &'ch dyn DynamicChannel<T>,
+}
+
+impl<'ch, T> CountedSender<'ch, T> {
+    pub(crate) fn new(channel: &'ch dyn DynamicChannel<T>) -> Self {
+        channel.update_count(&mut |c| c.senders += 1);
+        Self { channel }
+    }
+
+    /// Sends a value, waiting until there is capacity.
+    ///
+    /// If the channel is closed, the value is given back in the error.
+    pub fn send(&self, message: T) -> CountedSendFuture<'ch, T> {
+        CountedSendFuture {
+            channel: self.channel,
+            message: Some(message),
+        }
+    }
+
+    /// Attempt to immediately send a message.
+    ///
+    /// See [`Channel::try_send()`]
+    pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_send_with_context(message, None)
+    }
+
+    /// Allows a poll_fn to poll until the channel is ready to send, or closed
+    ///
+    /// See [`Channel::poll_ready_to_send()`]
+    pub fn poll_ready_to_send(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.channel.poll_ready_to_send(cx)
+    }
+
+    /// Close the channel.
+    ///
+    /// See [`Channel::close()`]
+    pub fn close(&self) {
+        self.channel.close()
+    }
+
+    /// Returns whether the channel is closed.
+    pub fn is_closed(&self) -> bool {
+        self.channel.is_closed()
+    }
+}
+
+impl<'ch, T> Clone for CountedSender<'ch, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.channel)
+    }
+}
+
+impl<'ch, T> Drop for CountedSender<'ch, T> {
+    fn drop(&mut self) {
+        self.channel.update_count(&mut |c| c.senders -= 1);
+    }
+}
+
+/// Receive-only access to a [`Channel`] or a [`PriorityChannel`](crate::priority_channel::PriorityChannel),
+/// counted to close the channel when the last one is dropped.
+///
+/// Receiving fails once the channel is closed and empty. See the [module documentation](self) for closing.
+pub struct CountedReceiver<'ch, T> {
+    channel: &'ch dyn DynamicChannel<T>,
+}
+
+impl<'ch, T> CountedReceiver<'ch, T> {
+    pub(crate) fn new(channel: &'ch dyn DynamicChannel<T>) -> Self {
+        channel.update_count(&mut |c| c.receivers += 1);
+        Self { channel }
+    }
+
+    /// Receive the next value.
+    ///
+    /// If there are no messages in the channel's buffer, this method will wait until a message
+    /// is sent, or the channel is closed.
+    pub fn receive(&self) -> CountedReceiveFuture<'_, T> {
+        CountedReceiveFuture { channel: self.channel }
+    }
+
+    /// Attempt to immediately receive the next value.
+    ///
+    /// See [`Channel::try_receive()`]
+    pub fn try_receive(&self) -> Result<T, TryReceiveError> {
+        self.channel.try_receive_with_context(None)
+    }
+
+    /// Allows a poll_fn to poll until the channel is ready to receive, or closed
+    ///
+    /// See [`Channel::poll_ready_to_receive()`]
+    pub fn poll_ready_to_receive(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.channel.poll_ready_to_receive(cx)
+    }
+
+    /// Poll the channel for the next item
+    pub fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<Result<T, Closed>> {
+        match self.channel.try_receive_with_context(Some(cx)) {
+            Ok(v) => Poll::Ready(Ok(v)),
+            Err(TryReceiveError::Empty) => Poll::Pending,
+            Err(TryReceiveError::Closed) => Poll::Ready(Err(Closed)),
+        }
+    }
+
+    /// Close the channel.
+    ///
+    /// See [`Channel::close()`]
+    pub fn close(&self) {
+        self.channel.close()
+    }
+
+    /// Returns whether the channel is closed.
+    pub fn is_closed(&self) -> bool {
+        self.channel.is_closed()
+    }
+}
+
+impl<'ch, T> Clone for CountedReceiver<'ch, T> {
+    fn clone(&self) -> Self {
+        Self::new(self.channel)
+    }
+}
+
+impl<'ch, T> Drop for CountedReceiver<'ch, T> {
+    fn drop(&mut self) {
+        self.channel.update_count(&mut |c| c.receivers -= 1);
+    }
+}
+
+/// Future returned by [`CountedReceiver::receive`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct CountedReceiveFuture<'ch, T> {
+    channel: &'ch dyn DynamicChannel<T>,
+}
+
+impl<'ch, T> Future for CountedReceiveFuture<'ch, T> {
+    type Output = Result<T, Closed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.channel.try_receive_with_context(Some(cx)) {
+            Ok(v) => Poll::Ready(Ok(v)),
+            Err(TryReceiveError::Empty) => Poll::Pending,
+            Err(TryReceiveError::Closed) => Poll::Ready(Err(Closed)),
+        }
+    }
+}
+
+/// Future returned by [`CountedSender::send`].
+#[must_use = "futures do nothing unless you `.await` or poll them"]
+pub struct CountedSendFuture<'ch, T> {
+    channel: &'ch dyn DynamicChannel<T>,
+    message: Option<T>,
+}
+
+impl<'ch, T> Future for CountedSendFuture<'ch, T> {
+    type Output = Result<(), SendError<T>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.message.take() {
+            Some(m) => match self.channel.try_send_with_context(m, Some(cx)) {
+                Ok(..) => Poll::Ready(Ok(())),
+                Err(TrySendError::Full(m)) => {
+                    self.message = Some(m);
+                    Poll::Pending
+                }
+                Err(TrySendError::Closed(m)) => Poll::Ready(Err(SendError(m))),
+            },
+            None => panic!("Message cannot be None"),
+        }
+    }
+}
+
+impl<'ch, T> Unpin for CountedSendFuture<'ch, T> {}
+
+/// Numbers of counted senders and receivers of a channel.
+pub(crate) struct HandleCounts {
+    pub(crate) senders: usize,
+    pub(crate) receivers: usize,
+}
+
+impl HandleCounts {
+    pub(crate) const fn new() -> Self {
+        Self {
+            senders: 0,
+            receivers: 0,
+        }
+    }
+}
+
 pub(crate) trait DynamicChannel<T> {
     fn try_send_with_context(&self, message: T, cx: Option<&mut Context<'_>>) -> Result<(), TrySendError<T>>;
 
@@ -330,29 +532,54 @@ pub(crate) trait DynamicChannel<T> {
     fn poll_ready_to_receive(&self, cx: &mut Context<'_>) -> Poll<()>;
 
     fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<T>;
+
+    fn close(&self);
+    fn is_closed(&self) -> bool;
+
+    /// Update the counts of counted senders and receivers, closing the channel if either drops to zero.
+    fn update_count(&self, f: &mut dyn FnMut(&mut HandleCounts));
 }
 
 /// Error returned by [`try_receive`](Channel::try_receive).
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum TryReceiveError {
     /// A message could not be received because the channel is empty.
     Empty,
+    /// A message could not be received because the channel is empty and closed.
+    Closed,
 }
 
 /// Error returned by [`try_send`](Channel::try_send).
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum TrySendError<T> {
     /// The data could not be sent on the channel because the channel is
     /// currently full and sending would require blocking.
     Full(T),
+    /// The data could not be sent on the channel because the channel is closed.
+    Closed(T),
 }
 
+/// Error returned by [`CountedReceiver::receive`] when the channel is closed and empty.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Closed;
+
+/// Error returned by [`CountedSender::send`] when the channel is closed, with the message that
+/// couldn't be sent.
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SendError<T>(pub T);
+
 struct ChannelState<T, const N: usize> {
     queue: Deque<T, N>,
     receiver_waker: WakerRegistration,
     senders_waker: WakerRegistration,
+    closed: bool,
+    counts: HandleCounts,
 }
 
 impl<T, const N: usize> ChannelState<T, N> {
@@ -361,6 +588,22 @@ impl<T, const N: usize> ChannelState<T, N> {
             queue: Deque::new(),
             receiver_waker: WakerRegistration::new(),
             senders_waker: WakerRegistration::new(),
+            closed: false,
+            counts: HandleCounts::new(),
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.receiver_waker.wake();
+        self.senders_waker.wake();
+    }
+
+    fn update_count(&mut self, f: &mut dyn FnMut(&mut HandleCounts)) {
+        let (senders, receivers) = (self.counts.senders, self.counts.receivers);
+        f(&mut self.counts);
+        if (senders != 0 && self.counts.senders == 0) || (receivers != 0 && self.counts.receivers == 0) {
+            self.close();
         }
     }
 
@@ -375,6 +618,8 @@ impl<T, const N: usize> ChannelState<T, N> {
 
         if let Some(message) = self.queue.pop_front() {
             Ok(message)
+        } else if self.closed {
+            Err(TryReceiveError::Closed)
         } else {
             if let Some(cx) = cx {
                 self.receiver_waker.register(cx.waker());
@@ -399,7 +644,7 @@ impl<T, const N: usize> ChannelState<T, N> {
     fn poll_ready_to_receive(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         self.receiver_waker.register(cx.waker());
 
-        if !self.queue.is_empty() {
+        if !self.queue.is_empty() || self.closed {
             Poll::Ready(())
         } else {
             Poll::Pending
@@ -411,6 +656,10 @@ impl<T, const N: usize> ChannelState<T, N> {
     }
 
     fn try_send_with_context(&mut self, message: T, cx: Option<&mut Context<'_>>) -> Result<(), TrySendError<T>> {
+        if self.closed {
+            return Err(TrySendError::Closed(message));
+        }
+
         match self.queue.push_back(message) {
             Ok(()) => {
                 self.receiver_waker.wake();
@@ -428,7 +677,7 @@ impl<T, const N: usize> ChannelState<T, N> {
     fn poll_ready_to_send(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         self.senders_waker.register(cx.waker());
 
-        if !self.queue.is_full() {
+        if !self.queue.is_full() || self.closed {
             Poll::Ready(())
         } else {
             Poll::Pending
@@ -507,10 +756,40 @@ where
         Receiver { channel: self }
     }
 
+    /// Get a counted sender for this channel.
+    ///
+    /// The channel is closed when the last counted sender is dropped.
+    pub fn counted_sender(&self) -> CountedSender<'_, T> {
+        CountedSender::new(self)
+    }
+
+    /// Get a counted receiver for this channel.
+    ///
+    /// The channel is closed when the last counted receiver is dropped.
+    pub fn counted_receiver(&self) -> CountedReceiver<'_, T> {
+        CountedReceiver::new(self)
+    }
+
+    /// Close the channel.
+    ///
+    /// Sending fails from now on, and receiving fails once the messages left in the channel
+    /// are received. A closed channel can't be opened again.
+    pub fn close(&self) {
+        self.lock(|c| c.close())
+    }
+
+    /// Returns whether the channel is closed.
+    pub fn is_closed(&self) -> bool {
+        self.lock(|c| c.closed)
+    }
+
     /// Send a value, waiting until there is capacity.
     ///
     /// Sending completes when the value has been pushed to the channel's queue.
     /// This doesn't mean the value has been received yet.
+    ///
+    /// It never completes if the channel is closed, use [`try_send`](Self::try_send) or a
+    /// [`CountedSender`] to detect it.
     pub fn send(&self, message: T) -> SendFuture<'_, M, T, N> {
         SendFuture {
             channel: self,
@@ -528,6 +807,8 @@ where
     /// If the channel capacity has been reached, i.e., the channel has `n`
     /// buffered values where `n` is the argument passed to [`Channel`], then an
     /// error is returned.
+    ///
+    /// If the channel is closed, an error is returned.
     pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
         self.lock(|c| c.try_send(message))
     }
@@ -535,7 +816,8 @@ where
     /// Receive the next value.
     ///
     /// If there are no messages in the channel's buffer, this method will
-    /// wait until a message is sent.
+    /// wait until a message is sent. It never completes if the channel is
+    /// closed and empty, use a [`CountedReceiver`] to detect it.
     pub fn receive(&self) -> ReceiveFuture<'_, M, T, N> {
         ReceiveFuture { channel: self }
     }
@@ -543,7 +825,7 @@ where
     /// Attempt to immediately receive a message.
     ///
     /// This method will either receive a message from the channel immediately or return an error
-    /// if the channel is empty.
+    /// if the channel is empty, telling whether it is closed.
     pub fn try_receive(&self) -> Result<T, TryReceiveError> {
         self.lock(|c| c.try_receive())
     }
@@ -574,6 +856,18 @@ where
     fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<T> {
         Channel::poll_receive(self, cx)
     }
+
+    fn close(&self) {
+        Channel::close(self)
+    }
+
+    fn is_closed(&self) -> bool {
+        Channel::is_closed(self)
+    }
+
+    fn update_count(&self, f: &mut dyn FnMut(&mut HandleCounts)) {
+        self.lock(|c| c.update_count(f))
+    }
 }
 
 #[cfg(test)]
@@ -705,4 +999,76 @@ mod tests {
         send_task_1.unwrap().await;
         send_task_2.unwrap().await;
     }
+
+    #[test]
+    fn close_drains_then_fails() {
+        let c = Channel::<NoopRawMutex, u32, 3>::new();
+        assert!(c.try_send(1).is_ok());
+        c.close();
+        assert!(c.is_closed());
+        assert_eq!(c.try_send(2), Err(TrySendError::Closed(2)));
+        assert_eq!(c.try_receive(), Ok(1));
+        assert_eq!(c.try_receive(), Err(TryReceiveError::Closed));
+    }
+
+    #[test]
+    fn plain_handles_stay_pending_when_closed() {
+        use futures_util::FutureExt;
+
+        let c = Channel::<NoopRawMutex, u32, 3>::new();
+        c.close();
+
+        let mut send = c.send(1);
+        assert!((&mut send).now_or_never().is_none());
+        assert!(send.message.is_some());
+        assert!(c.sender().send(2).now_or_never().is_none());
+        assert!(c.receive().now_or_never().is_none());
+        assert!(c.receiver().receive().now_or_never().is_none());
+    }
+
+    #[test]
+    fn dropping_counted_receivers_closes() {
+        use futures_util::FutureExt;
+
+        let c = Channel::<NoopRawMutex, u32, 3>::new();
+        let s = c.counted_sender();
+        let r = c.counted_receiver();
+        let r2 = r.clone();
+        drop(r);
+        assert!(!c.is_closed());
+        assert_eq!(s.send(1).now_or_never(), Some(Ok(())));
+        drop(r2);
+        assert!(s.is_closed());
+        assert_eq!(s.send(2).now_or_never(), Some(Err(SendError(2))));
+    }
+
+    #[test]
+    fn dropping_counted_senders_closes() {
+        use futures_util::FutureExt;
+
+        let c = Channel::<NoopRawMutex, u32, 3>::new();
+        let s = c.counted_sender();
+        let r = c.counted_receiver();
+        assert!(s.try_send(1).is_ok());
+        drop(s);
+        assert!(r.is_closed());
+        assert_eq!(r.receive().now_or_never(), Some(Ok(1)));
+        assert_eq!(r.receive().now_or_never(), Some(Err(Closed)));
+    }
+
+    #[futures_test::test]
+    async fn receiver_woken_on_close() {
+        let executor = ThreadPool::new().unwrap();
+
+        static CHANNEL: StaticCell<Channel<CriticalSectionRawMutex, u32, 3>> = StaticCell::new();
+        let c = &*CHANNEL.init(Channel::new());
+        let r = c.counted_receiver();
+        assert!(executor
+            .spawn(async move {
+                Delay::new(Duration::from_millis(100)).await;
+                c.close();
+            })
+            .is_ok());
+        assert_eq!(r.receive().await, Err(Closed));
+    }
 }
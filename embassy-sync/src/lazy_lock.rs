@@ -0,0 +1,202 @@
+//! A value initialized on first access.
+use core::cell::{Cell, UnsafeCell};
+use core::mem::ManuallyDrop;
+use core::ops::Deref;
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex as BlockingMutex;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// `f` is there.
+    Uninit,
+    /// `f` was taken and is running, or panicked. Neither field is there.
+    Initializing,
+    /// `value` is there.
+    Init,
+}
+
+union Data<T, F> {
+    value: ManuallyDrop<T>,
+    f: ManuallyDrop<F>,
+}
+
+/// A value initialized on first access.
+///
+/// The initializer runs while the blocking [`RawMutex`](crate::blocking_mutex::raw::RawMutex) is
+/// locked, so it must be short when using
+/// [`CriticalSectionRawMutex`](crate::blocking_mutex::raw::CriticalSectionRawMutex). For values
+/// that need async initialization, use a [`OnceLock`](crate::once_lock::OnceLock) instead.
+///
+/// Accessing the value from the initializer, or after the initializer panicked, panics.
+///
+/// ```
+/// use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+/// use embassy_sync::lazy_lock::LazyLock;
+///
+/// static VALUE: LazyLock<CriticalSectionRawMutex, u32> = LazyLock::new(|| 20);
+///
+/// assert_eq!(*VALUE, 20);
+/// ```
+pub struct LazyLock<M: RawMutex, T, F = fn() -> T> {
+    state: BlockingMutex<M, Cell<State>>,
+    data: UnsafeCell<Data<T, F>>,
+}
+
+unsafe impl<M: RawMutex + Send, T: Send, F: Send> Send for LazyLock<M, T, F> {}
+unsafe impl<M: RawMutex + Sync, T: Send + Sync, F: Send> Sync for LazyLock<M, T, F> {}
+
+impl<M: RawMutex, T, F: FnOnce() -> T> LazyLock<M, T, F> {
+    /// Create a new `LazyLock`, initialized with `init_fn` on first access.
+    pub const fn new(init_fn: F) -> Self {
+        Self {
+            state: BlockingMutex::new(Cell::new(State::Uninit)),
+            data: UnsafeCell::new(Data {
+                f: ManuallyDrop::new(init_fn),
+            }),
+        }
+    }
+
+    /// Get a reference to the value, initializing it if it isn't yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called from the initializer, or if the initializer panicked.
+    pub fn get(&self) -> &T {
+        self.state.lock(|state| match state.get() {
+            State::Init => {}
+            State::Initializing => panic!("LazyLock accessed during initialization, or its initializer panicked"),
+            State::Uninit => {
+                // Mark `f` as taken first, so it's neither taken again nor dropped if it panics
+                // or accesses the `LazyLock`.
+                state.set(State::Initializing);
+                // Safety: the value isn't initialized, so `f` is there and nobody has a reference to it.
+                let f = unsafe { ManuallyDrop::take(&mut (*self.data.get()).f) };
+                let value = f();
+                // Safety: `f` is gone, and nobody has a reference to the data.
+                unsafe { (*self.data.get()).value = ManuallyDrop::new(value) };
+                state.set(State::Init);
+            }
+        });
+
+        // Safety: the value is initialized.
+        unsafe { &(*self.data.get()).value }
+    }
+
+    /// Consumes the `LazyLock`, returning the value, initializing it if it isn't yet.
+    pub fn into_inner(self) -> T {
+        self.get();
+        let mut this = ManuallyDrop::new(self);
+        // Safety: the value is initialized, and `this` isn't dropped.
+        unsafe { ManuallyDrop::take(&mut this.data.get_mut().value) }
+    }
+}
+
+impl<M: RawMutex, T, F: FnOnce() -> T> Deref for LazyLock<M, T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.get()
+    }
+}
+
+impl<M: RawMutex, T, F> Drop for LazyLock<M, T, F> {
+    fn drop(&mut self) {
+        let data = self.data.get_mut();
+        // Safety: `state` tells which field is there.
+        unsafe {
+            match self.state.get_mut().get() {
+                State::Uninit => ManuallyDrop::drop(&mut data.f),
+                State::Initializing => {}
+                State::Init => ManuallyDrop::drop(&mut data.value),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    use super::*;
+    use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+
+    #[test]
+    fn initializes_once() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        static VALUE: LazyLock<CriticalSectionRawMutex, u32> = LazyLock::new(|| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            20
+        });
+
+        assert_eq!(CALLS.load(Ordering::Relaxed), 0);
+        assert_eq!(*VALUE, 20);
+        assert_eq!(*VALUE.get(), 20);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn into_inner() {
+        let lazy = LazyLock::<NoopRawMutex, u32, _>::new(|| 3);
+        assert_eq!(lazy.into_inner(), 3);
+    }
+
+    #[test]
+    fn drops_value_or_initializer() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Droppable;
+        impl Drop for Droppable {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        // The initializer owns a value, dropped with it.
+        let d = Droppable;
+        drop(LazyLock::<NoopRawMutex, u32, _>::new(move || {
+            let _d = d;
+            1
+        }));
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+
+        let lazy = LazyLock::<NoopRawMutex, Droppable>::new(|| Droppable);
+        lazy.get();
+        drop(lazy);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn panicking_initializer() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Droppable;
+        impl Drop for Droppable {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let d = Droppable;
+        let lazy = LazyLock::<NoopRawMutex, u32, _>::new(move || {
+            let _d = d;
+            panic!("init failed")
+        });
+        let lazy = AssertUnwindSafe(lazy);
+        assert!(catch_unwind(|| *lazy.get()).is_err());
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+
+        // Poisoned, and dropped without dropping the initializer again.
+        assert!(catch_unwind(|| *lazy.get()).is_err());
+        drop(lazy);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "LazyLock accessed during initialization")]
+    fn reentrant_access() {
+        static VALUE: LazyLock<CriticalSectionRawMutex, u32> = LazyLock::new(|| *VALUE + 1);
+        VALUE.get();
+    }
+}
@@ -0,0 +1,305 @@
+//! A cell that is written once, and can be awaited until then.
+use core::cell::{RefCell, UnsafeCell};
+use core::future::{poll_fn, Future};
+use core::mem::MaybeUninit;
+use core::task::Poll;
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex as BlockingMutex;
+use crate::waitqueue::WakerRegistration;
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Init {
+    No,
+    /// A task is running the initializer given to [`OnceLock::get_or_init_async`].
+    InProgress,
+    Done,
+}
+
+struct State {
+    init: Init,
+    waker: WakerRegistration,
+}
+
+/// A cell that is written once, and can be awaited until then.
+///
+/// This is useful for static resources that are initialized at run time, possibly asynchronously,
+/// such as a network stack that must wait for its driver: the tasks using the resource wait for it
+/// with [`get`](Self::get) until some task sets it with [`init`](Self::init).
+///
+/// Like [`Mutex`](crate::mutex::Mutex), the cell is generic over a blocking
+/// [`RawMutex`](crate::blocking_mutex::raw::RawMutex), which guards its state, and only keeps the
+/// waker of one waiting task. Several tasks can wait at the same time, but they keep waking each
+/// other until the cell is initialized.
+///
+/// ```
+/// use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+/// use embassy_sync::once_lock::OnceLock;
+/// # use futures_executor::block_on;
+///
+/// static VALUE: OnceLock<CriticalSectionRawMutex, u32> = OnceLock::new();
+///
+/// # let test = async {
+/// assert!(VALUE.try_get().is_none());
+/// assert!(VALUE.init(20).is_ok());
+/// assert_eq!(VALUE.init(30), Err(30));
+/// assert_eq!(*VALUE.get().await, 20);
+/// # };
+/// # block_on(test);
+/// ```
+pub struct OnceLock<M: RawMutex, T> {
+    state: BlockingMutex<M, RefCell<State>>,
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<M: RawMutex + Send, T: Send> Send for OnceLock<M, T> {}
+unsafe impl<M: RawMutex + Sync, T: Send + Sync> Sync for OnceLock<M, T> {}
+
+impl<M: RawMutex, T> OnceLock<M, T> {
+    /// Create a new, uninitialized `OnceLock`.
+    pub const fn new() -> Self {
+        Self {
+            state: BlockingMutex::new(RefCell::new(State {
+                init: Init::No,
+                waker: WakerRegistration::new(),
+            })),
+            data: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Get a reference to the value, waiting until it is initialized.
+    pub async fn get(&self) -> &T {
+        poll_fn(|cx| {
+            self.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                if s.init == Init::Done {
+                    Poll::Ready(())
+                } else {
+                    s.waker.register(cx.waker());
+                    Poll::Pending
+                }
+            })
+        })
+        .await;
+
+        // Safety: the value is initialized.
+        unsafe { self.get_unchecked() }
+    }
+
+    /// Get a reference to the value, if it is initialized.
+    pub fn try_get(&self) -> Option<&T> {
+        if self.is_initialized() {
+            // Safety: the value is initialized.
+            Some(unsafe { self.get_unchecked() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the value is initialized.
+    pub fn is_initialized(&self) -> bool {
+        self.state.lock(|s| s.borrow().init == Init::Done)
+    }
+
+    /// Initialize the value, and wake the task waiting for it.
+    ///
+    /// Returns the value back if the `OnceLock` is already initialized, or being initialized by
+    /// [`get_or_init_async`](Self::get_or_init_async).
+    pub fn init(&self, value: T) -> Result<(), T> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.init != Init::No {
+                return Err(value);
+            }
+            // Safety: the value is not initialized, so nobody has a reference to it.
+            unsafe { (*self.data.get()).write(value) };
+            s.init = Init::Done;
+            s.waker.wake();
+            Ok(())
+        })
+    }
+
+    /// Get a reference to the value, initializing it with `f` if it isn't yet.
+    ///
+    /// Only one task runs its initializer, the others wait for the value. If the future returned
+    /// by `f` is dropped before completing, the value stays uninitialized, and another task can
+    /// initialize it.
+    pub async fn get_or_init_async<F, Fut>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let initialized = poll_fn(|cx| {
+            self.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                match s.init {
+                    Init::Done => Poll::Ready(true),
+                    Init::No => {
+                        s.init = Init::InProgress;
+                        Poll::Ready(false)
+                    }
+                    Init::InProgress => {
+                        s.waker.register(cx.waker());
+                        Poll::Pending
+                    }
+                }
+            })
+        })
+        .await;
+
+        if !initialized {
+            let guard = InitGuard { once: self };
+            let value = f().await;
+            core::mem::forget(guard);
+
+            self.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                // Safety: the initialization is in progress in this task, so nobody has a reference to the value.
+                unsafe { (*self.data.get()).write(value) };
+                s.init = Init::Done;
+                s.waker.wake();
+            });
+        }
+
+        // Safety: the value is initialized.
+        unsafe { self.get_unchecked() }
+    }
+
+    /// Consumes the `OnceLock`, returning the value if it is initialized.
+    pub fn into_inner(self) -> Option<T> {
+        let this = core::mem::ManuallyDrop::new(self);
+        if this.state.lock(|s| s.borrow().init == Init::Done) {
+            // Safety: the value is initialized, and `this` isn't dropped.
+            Some(unsafe { this.data.get().read().assume_init() })
+        } else {
+            None
+        }
+    }
+
+    /// Safety: the value must be initialized.
+    unsafe fn get_unchecked(&self) -> &T {
+        (*self.data.get()).assume_init_ref()
+    }
+}
+
+impl<M: RawMutex, T> Drop for OnceLock<M, T> {
+    fn drop(&mut self) {
+        if self.state.get_mut().get_mut().init == Init::Done {
+            // Safety: the value is initialized.
+            unsafe { self.data.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+/// Reverts an initialization in progress if the initializer is dropped.
+struct InitGuard<'a, M: RawMutex, T> {
+    once: &'a OnceLock<M, T>,
+}
+
+impl<'a, M: RawMutex, T> Drop for InitGuard<'a, M, T> {
+    fn drop(&mut self) {
+        self.once.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.init = Init::No;
+            // Let a waiting task initialize the value instead.
+            s.waker.wake();
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures_util::future::pending;
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+
+    #[test]
+    fn init_once() {
+        let once = OnceLock::<NoopRawMutex, u32>::new();
+        assert!(!once.is_initialized());
+        assert_eq!(once.try_get(), None);
+
+        assert_eq!(once.init(1), Ok(()));
+        assert_eq!(once.init(2), Err(2));
+        assert_eq!(once.try_get(), Some(&1));
+        assert_eq!(once.into_inner(), Some(1));
+    }
+
+    #[test]
+    fn get_waits_for_init() {
+        let once = OnceLock::<NoopRawMutex, u32>::new();
+
+        let mut fut = pin!(once.get());
+        assert!(fut.as_mut().now_or_never().is_none());
+        once.init(3).unwrap();
+        assert_eq!(fut.now_or_never(), Some(&3));
+    }
+
+    #[futures_test::test]
+    async fn get_or_init_async_runs_once() {
+        let once = OnceLock::<NoopRawMutex, u32>::new();
+        let calls = AtomicUsize::new(0);
+
+        let init = || async {
+            calls.fetch_add(1, Ordering::Relaxed);
+            4
+        };
+        assert_eq!(*once.get_or_init_async(init).await, 4);
+        assert_eq!(*once.get_or_init_async(init).await, 4);
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn cancelled_initializer() {
+        let once = OnceLock::<NoopRawMutex, u32>::new();
+
+        {
+            let mut fut = pin!(once.get_or_init_async(pending::<u32>));
+            assert!(fut.as_mut().now_or_never().is_none());
+            // Being initialized.
+            assert_eq!(once.init(5), Err(5));
+            let mut other = pin!(once.get_or_init_async(|| async { 6 }));
+            assert!(other.as_mut().now_or_never().is_none());
+        }
+
+        assert!(!once.is_initialized());
+        assert_eq!(once.get_or_init_async(|| async { 7 }).now_or_never(), Some(&7));
+    }
+
+    #[test]
+    fn drops_value() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Droppable;
+        impl Drop for Droppable {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        drop(OnceLock::<NoopRawMutex, Droppable>::new());
+        assert_eq!(DROPS.load(Ordering::Relaxed), 0);
+
+        let once = OnceLock::<NoopRawMutex, Droppable>::new();
+        assert!(once.init(Droppable).is_ok());
+        drop(once);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    #[futures_test::test]
+    async fn waiting_tasks_are_woken() {
+        use futures_executor::ThreadPool;
+        use futures_util::task::SpawnExt;
+
+        static ONCE: OnceLock<CriticalSectionRawMutex, u32> = OnceLock::new();
+
+        let executor = ThreadPool::new().unwrap();
+        let h = executor.spawn_with_handle(async { *ONCE.get().await }).unwrap();
+        ONCE.init(8).unwrap();
+        assert_eq!(h.await, 8);
+    }
+}
@@ -2,6 +2,8 @@
 //!
 //! Similar to a [`Channel`](crate::channel::Channel), however [`PriorityChannel`] sifts higher priority items to the front of the queue.
 //! Priority is determined by the `Ord` trait. Priority behavior is determined by the [`Kind`](heapless::binary_heap::Kind) parameter of the channel.
+//!
+//! It can be closed like a [`Channel`](crate::channel::Channel), see [closing](crate::channel#closing).
 
 use core::cell::RefCell;
 use core::future::Future;
@@ -13,7 +15,10 @@ use heapless::BinaryHeap;
 
 use crate::blocking_mutex::raw::RawMutex;
 use crate::blocking_mutex::Mutex;
-use crate::channel::{DynamicChannel, DynamicReceiver, DynamicSender, TryReceiveError, TrySendError};
+use crate::channel::{
+    CountedReceiver, CountedSender, DynamicChannel, DynamicReceiver, DynamicSender, HandleCounts, TryReceiveError,
+    TrySendError,
+};
 use crate::waitqueue::WakerRegistration;
 
 /// Send-only access to a [`PriorityChannel`].
@@ -207,7 +212,7 @@ where
         match self.message.take() {
             Some(m) => match self.channel.try_send_with_context(m, Some(cx)) {
                 Ok(..) => Poll::Ready(()),
-                Err(TrySendError::Full(m)) => {
+                Err(TrySendError::Full(m) | TrySendError::Closed(m)) => {
                     self.message = Some(m);
                     Poll::Pending
                 }
@@ -229,6 +234,8 @@ struct ChannelState<T, K, const N: usize> {
     queue: BinaryHeap<T, K, N>,
     receiver_waker: WakerRegistration,
     senders_waker: WakerRegistration,
+    closed: bool,
+    counts: HandleCounts,
 }
 
 impl<T, K, const N: usize> ChannelState<T, K, N>
@@ -241,6 +248,22 @@ where
             queue: BinaryHeap::new(),
             receiver_waker: WakerRegistration::new(),
             senders_waker: WakerRegistration::new(),
+            closed: false,
+            counts: HandleCounts::new(),
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.receiver_waker.wake();
+        self.senders_waker.wake();
+    }
+
+    fn update_count(&mut self, f: &mut dyn FnMut(&mut HandleCounts)) {
+        let (senders, receivers) = (self.counts.senders, self.counts.receivers);
+        f(&mut self.counts);
+        if (senders != 0 && self.counts.senders == 0) || (receivers != 0 && self.counts.receivers == 0) {
+            self.close();
         }
     }
 
@@ -255,6 +278,8 @@ where
 
         if let Some(message) = self.queue.pop() {
             Ok(message)
+        } else if self.closed {
+            Err(TryReceiveError::Closed)
         } else {
             if let Some(cx) = cx {
                 self.receiver_waker.register(cx.waker());
@@ -279,7 +304,7 @@ where
     fn poll_ready_to_receive(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         self.receiver_waker.register(cx.waker());
 
-        if !self.queue.is_empty() {
+        if !self.queue.is_empty() || self.closed {
             Poll::Ready(())
         } else {
             Poll::Pending
@@ -291,6 +316,10 @@ where
     }
 
     fn try_send_with_context(&mut self, message: T, cx: Option<&mut Context<'_>>) -> Result<(), TrySendError<T>> {
+        if self.closed {
+            return Err(TrySendError::Closed(message));
+        }
+
         match self.queue.push(message) {
             Ok(()) => {
                 self.receiver_waker.wake();
@@ -308,7 +337,7 @@ where
     fn poll_ready_to_send(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         self.senders_waker.register(cx.waker());
 
-        if !self.queue.len() == self.queue.capacity() {
+        if self.queue.len() < self.queue.capacity() || self.closed {
             Poll::Ready(())
         } else {
             Poll::Pending
@@ -393,10 +422,40 @@ where
         Receiver { channel: self }
     }
 
+    /// Get a counted sender for this channel.
+    ///
+    /// The channel is closed when the last counted sender is dropped.
+    pub fn counted_sender(&self) -> CountedSender<'_, T> {
+        CountedSender::new(self)
+    }
+
+    /// Get a counted receiver for this channel.
+    ///
+    /// The channel is closed when the last counted receiver is dropped.
+    pub fn counted_receiver(&self) -> CountedReceiver<'_, T> {
+        CountedReceiver::new(self)
+    }
+
+    /// Close the channel.
+    ///
+    /// Sending fails from now on, and receiving fails once the messages left in the channel
+    /// are received. A closed channel can't be opened again.
+    pub fn close(&self) {
+        self.lock(|c| c.close())
+    }
+
+    /// Returns whether the channel is closed.
+    pub fn is_closed(&self) -> bool {
+        self.lock(|c| c.closed)
+    }
+
     /// Send a value, waiting until there is capacity.
     ///
     /// Sending completes when the value has been pushed to the channel's queue.
     /// This doesn't mean the value has been received yet.
+    ///
+    /// It never completes if the channel is closed, use [`try_send`](Self::try_send) or a
+    /// [`CountedSender`] to detect it.
     pub fn send(&self, message: T) -> SendFuture<'_, M, T, K, N> {
         SendFuture {
             channel: self,
@@ -414,6 +473,8 @@ where
     /// If the channel capacity has been reached, i.e., the channel has `n`
     /// buffered values where `n` is the argument passed to [`PriorityChannel`], then an
     /// error is returned.
+    ///
+    /// If the channel is closed, an error is returned.
     pub fn try_send(&self, message: T) -> Result<(), TrySendError<T>> {
         self.lock(|c| c.try_send(message))
     }
@@ -421,7 +482,8 @@ where
     /// Receive the next value.
     ///
     /// If there are no messages in the channel's buffer, this method will
-    /// wait until a message is sent.
+    /// wait until a message is sent. It never completes if the channel is
+    /// closed and empty, use a [`CountedReceiver`] to detect it.
     pub fn receive(&self) -> ReceiveFuture<'_, M, T, K, N> {
         ReceiveFuture { channel: self }
     }
@@ -429,7 +491,7 @@ where
     /// Attempt to immediately receive a message.
     ///
     /// This method will either receive a message from the channel immediately or return an error
-    /// if the channel is empty.
+    /// if the channel is empty, telling whether it is closed.
     pub fn try_receive(&self) -> Result<T, TryReceiveError> {
         self.lock(|c| c.try_receive())
     }
@@ -462,6 +524,18 @@ where
     fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<T> {
         PriorityChannel::poll_receive(self, cx)
     }
+
+    fn close(&self) {
+        PriorityChannel::close(self)
+    }
+
+    fn is_closed(&self) -> bool {
+        PriorityChannel::is_closed(self)
+    }
+
+    fn update_count(&self, f: &mut dyn FnMut(&mut HandleCounts)) {
+        self.lock(|c| c.update_count(f))
+    }
 }
 
 #[cfg(test)]
@@ -610,4 +684,31 @@ mod tests {
         send_task_1.unwrap().await;
         send_task_2.unwrap().await;
     }
+
+    #[test]
+    fn ready_to_send_until_full() {
+        let c = PriorityChannel::<NoopRawMutex, u32, Max, 2>::new();
+        let cx = &mut futures_test::task::noop_context();
+        assert!(c.poll_ready_to_send(cx).is_ready());
+        assert!(c.try_send(1).is_ok());
+        assert!(c.poll_ready_to_send(cx).is_ready());
+        assert!(c.try_send(2).is_ok());
+        assert!(c.poll_ready_to_send(cx).is_pending());
+    }
+
+    #[test]
+    fn close_drains_then_fails() {
+        use futures_util::FutureExt;
+
+        let c = PriorityChannel::<NoopRawMutex, u32, Max, 3>::new();
+        let s = c.counted_sender();
+        let r = c.counted_receiver();
+        assert!(c.try_send(1).is_ok());
+        assert!(c.try_send(2).is_ok());
+        drop(s);
+        assert_eq!(c.try_send(3), Err(TrySendError::Closed(3)));
+        assert_eq!(r.receive().now_or_never(), Some(Ok(2)));
+        assert_eq!(r.receive().now_or_never(), Some(Ok(1)));
+        assert_eq!(r.receive().now_or_never(), Some(Err(crate::channel::Closed)));
+    }
 }
@@ -0,0 +1,665 @@
+//! Async read-write lock.
+//!
+//! This module provides a lock that can be held by several readers at the same time, or by a
+//! single writer.
+use core::cell::{RefCell, UnsafeCell};
+use core::future::{poll_fn, Future};
+use core::ops::{Deref, DerefMut};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex as BlockingMutex;
+use crate::waitqueue::MultiWakerRegistration;
+
+/// Error returned by [`RwLock::try_read`] and [`RwLock::try_write`]
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TryLockError;
+
+/// Number of waiting readers, and of waiting writers, whose wakers are kept.
+const WAKERS: usize = 4;
+
+struct State {
+    /// Number of read guards.
+    readers: usize,
+    /// Whether there is a write guard.
+    writer: bool,
+    /// Number of tasks waiting to write.
+    writers_waiting: usize,
+    read_wakers: MultiWakerRegistration<WAKERS>,
+    write_wakers: MultiWakerRegistration<WAKERS>,
+}
+
+impl State {
+    fn can_read(&self) -> bool {
+        !self.writer && self.writers_waiting == 0
+    }
+
+    fn can_write(&self) -> bool {
+        !self.writer && self.readers == 0
+    }
+
+    /// Wake the tasks that may be able to lock now: the writers if there are any, else the readers.
+    fn wake(&mut self) {
+        if self.writers_waiting != 0 {
+            self.write_wakers.wake();
+        } else {
+            self.read_wakers.wake();
+        }
+    }
+}
+
+/// Async read-write lock.
+///
+/// The lock can be held by any number of readers, or by a single writer. It prefers writers: once
+/// a task waits to write, new readers wait until it is done, so writers aren't starved by a
+/// steady flow of readers.
+///
+/// Like [`Mutex`](crate::mutex::Mutex), the lock is generic over a blocking
+/// [`RawMutex`](crate::blocking_mutex::raw::RawMutex), which guards the internal state only while
+/// locking and unlocking. The wakers of up to 4 waiting readers and 4 waiting writers are kept.
+/// More tasks can wait at the same time, but then they wake each other until the lock is available.
+pub struct RwLock<M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    state: BlockingMutex<M, RefCell<State>>,
+    inner: UnsafeCell<T>,
+}
+
+unsafe impl<M: RawMutex + Send, T: ?Sized + Send> Send for RwLock<M, T> {}
+unsafe impl<M: RawMutex + Sync, T: ?Sized + Send + Sync> Sync for RwLock<M, T> {}
+
+impl<M, T> RwLock<M, T>
+where
+    M: RawMutex,
+{
+    /// Create a new read-write lock with the given value.
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: UnsafeCell::new(value),
+            state: BlockingMutex::new(RefCell::new(State {
+                readers: 0,
+                writer: false,
+                writers_waiting: 0,
+                read_wakers: MultiWakerRegistration::new(),
+                write_wakers: MultiWakerRegistration::new(),
+            })),
+        }
+    }
+}
+
+impl<M, T> RwLock<M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    /// Lock for reading.
+    ///
+    /// This will wait while the lock is held by a writer, or tasks are waiting to write.
+    pub async fn read(&self) -> RwLockReadGuard<'_, M, T> {
+        poll_fn(|cx| {
+            let ready = self.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                if s.can_read() {
+                    s.readers += 1;
+                    true
+                } else {
+                    s.read_wakers.register(cx.waker());
+                    false
+                }
+            });
+
+            if ready {
+                Poll::Ready(RwLockReadGuard { rwlock: self })
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Lock for writing.
+    ///
+    /// This will wait until the lock isn't held anymore.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, M, T> {
+        WriteLock {
+            rwlock: self,
+            waiting: false,
+        }
+        .await
+    }
+
+    /// Attempt to immediately lock for reading.
+    ///
+    /// If the lock is held by a writer, or tasks are waiting to write, this will return an error
+    /// instead of waiting.
+    pub fn try_read(&self) -> Result<RwLockReadGuard<'_, M, T>, TryLockError> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.can_read() {
+                s.readers += 1;
+                Ok(())
+            } else {
+                Err(TryLockError)
+            }
+        })?;
+
+        Ok(RwLockReadGuard { rwlock: self })
+    }
+
+    /// Attempt to immediately lock for writing.
+    ///
+    /// If the lock is held, this will return an error instead of waiting.
+    pub fn try_write(&self) -> Result<RwLockWriteGuard<'_, M, T>, TryLockError> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.can_write() {
+                s.writer = true;
+                Ok(())
+            } else {
+                Err(TryLockError)
+            }
+        })?;
+
+        Ok(RwLockWriteGuard { rwlock: self })
+    }
+
+    /// Consumes this lock, returning the underlying data.
+    pub fn into_inner(self) -> T
+    where
+        T: Sized,
+    {
+        self.inner.into_inner()
+    }
+
+    /// Returns a mutable reference to the underlying data.
+    ///
+    /// Since this call borrows the RwLock mutably, no actual locking needs to
+    /// take place -- the mutable borrow statically guarantees no locks exist.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+}
+
+/// Future locking a [`RwLock`] for writing, counted as a waiting writer until it's done or dropped.
+struct WriteLock<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    rwlock: &'a RwLock<M, T>,
+    waiting: bool,
+}
+
+impl<'a, M, T> Future for WriteLock<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Output = RwLockWriteGuard<'a, M, T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let ready = this.rwlock.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.can_write() {
+                s.writer = true;
+                if this.waiting {
+                    s.writers_waiting -= 1;
+                    this.waiting = false;
+                }
+                true
+            } else {
+                if !this.waiting {
+                    s.writers_waiting += 1;
+                    this.waiting = true;
+                }
+                s.write_wakers.register(cx.waker());
+                false
+            }
+        });
+
+        if ready {
+            Poll::Ready(RwLockWriteGuard { rwlock: this.rwlock })
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl<'a, M, T> Drop for WriteLock<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        if self.waiting {
+            self.rwlock.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                s.writers_waiting -= 1;
+                // This writer may have been woken to take the lock, pass it on. If it was the last
+                // one, readers held back by it can go now.
+                if s.can_write() {
+                    s.wake();
+                } else if s.can_read() {
+                    s.read_wakers.wake();
+                }
+            })
+        }
+    }
+}
+
+fn unlock_read(state: &BlockingMutex<impl RawMutex, RefCell<State>>) {
+    state.lock(|s| {
+        let mut s = unwrap!(s.try_borrow_mut());
+        s.readers -= 1;
+        if s.readers == 0 {
+            // Readers only wait while writers do.
+            s.write_wakers.wake();
+        }
+    })
+}
+
+fn unlock_write(state: &BlockingMutex<impl RawMutex, RefCell<State>>) {
+    state.lock(|s| {
+        let mut s = unwrap!(s.try_borrow_mut());
+        s.writer = false;
+        s.wake();
+    })
+}
+
+/// Async read-write lock read guard.
+///
+/// Owning an instance of this type indicates having
+/// successfully locked the lock for reading, and grants shared access to the contents.
+///
+/// Dropping it unlocks the lock.
+pub struct RwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    rwlock: &'a RwLock<M, T>,
+}
+
+impl<'a, M, T> RwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    /// Returns a guard giving access to a part of the locked data, such as a field.
+    pub fn map<U: ?Sized>(this: Self, fun: impl FnOnce(&T) -> &U) -> MappedRwLockReadGuard<'a, M, U> {
+        let rwlock = this.rwlock;
+        let value = fun(unsafe { &*(rwlock.inner.get() as *const T) });
+        // Don't run the `drop` method for RwLockReadGuard. The ownership of the underlying
+        // locked state is being moved to the returned MappedRwLockReadGuard.
+        core::mem::forget(this);
+        MappedRwLockReadGuard {
+            state: &rwlock.state,
+            value,
+        }
+    }
+}
+
+impl<'a, M, T> Drop for RwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        unlock_read(&self.rwlock.state)
+    }
+}
+
+impl<'a, M, T> Deref for RwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: the RwLockReadGuard represents shared access to the contents
+        // of the lock, so it's OK to get it.
+        unsafe { &*(self.rwlock.inner.get() as *const T) }
+    }
+}
+
+/// Async read-write lock write guard.
+///
+/// Owning an instance of this type indicates having
+/// successfully locked the lock for writing, and grants exclusive access to the contents.
+///
+/// Dropping it unlocks the lock.
+pub struct RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    rwlock: &'a RwLock<M, T>,
+}
+
+impl<'a, M, T> RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    /// Returns a guard giving access to a part of the locked data, such as a field.
+    pub fn map<U: ?Sized>(this: Self, fun: impl FnOnce(&mut T) -> &mut U) -> MappedRwLockWriteGuard<'a, M, U> {
+        let rwlock = this.rwlock;
+        let value: *mut U = fun(unsafe { &mut *rwlock.inner.get() });
+        // Don't run the `drop` method for RwLockWriteGuard. The ownership of the underlying
+        // locked state is being moved to the returned MappedRwLockWriteGuard.
+        core::mem::forget(this);
+        MappedRwLockWriteGuard {
+            state: &rwlock.state,
+            value,
+        }
+    }
+}
+
+impl<'a, M, T> Drop for RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        unlock_write(&self.rwlock.state)
+    }
+}
+
+impl<'a, M, T> Deref for RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: the RwLockWriteGuard represents exclusive access to the contents
+        // of the lock, so it's OK to get it.
+        unsafe { &*(self.rwlock.inner.get() as *const T) }
+    }
+}
+
+impl<'a, M, T> DerefMut for RwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: the RwLockWriteGuard represents exclusive access to the contents
+        // of the lock, so it's OK to get it.
+        unsafe { &mut *(self.rwlock.inner.get()) }
+    }
+}
+
+/// Read guard giving access to a part of the data of a [`RwLock`], returned by
+/// [`RwLockReadGuard::map`].
+///
+/// Dropping it unlocks the lock.
+pub struct MappedRwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    state: &'a BlockingMutex<M, RefCell<State>>,
+    value: &'a T,
+}
+
+impl<'a, M, T> MappedRwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    /// Returns a guard giving access to a part of the mapped data.
+    pub fn map<U: ?Sized>(this: Self, fun: impl FnOnce(&T) -> &U) -> MappedRwLockReadGuard<'a, M, U> {
+        let state = this.state;
+        let value = fun(this.value);
+        // Don't run the `drop` method, the ownership of the locked state is being moved to the
+        // returned guard.
+        core::mem::forget(this);
+        MappedRwLockReadGuard { state, value }
+    }
+}
+
+impl<'a, M, T> Drop for MappedRwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        unlock_read(self.state)
+    }
+}
+
+impl<'a, M, T> Deref for MappedRwLockReadGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+/// Write guard giving access to a part of the data of a [`RwLock`], returned by
+/// [`RwLockWriteGuard::map`].
+///
+/// Dropping it unlocks the lock.
+pub struct MappedRwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    state: &'a BlockingMutex<M, RefCell<State>>,
+    value: *mut T,
+}
+
+unsafe impl<'a, M: RawMutex + Sync, T: ?Sized + Send> Send for MappedRwLockWriteGuard<'a, M, T> {}
+unsafe impl<'a, M: RawMutex + Sync, T: ?Sized + Sync> Sync for MappedRwLockWriteGuard<'a, M, T> {}
+
+impl<'a, M, T> MappedRwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    /// Returns a guard giving access to a part of the mapped data.
+    pub fn map<U: ?Sized>(this: Self, fun: impl FnOnce(&mut T) -> &mut U) -> MappedRwLockWriteGuard<'a, M, U> {
+        let state = this.state;
+        let value = fun(unsafe { &mut *this.value });
+        // Don't run the `drop` method, the ownership of the locked state is being moved to the
+        // returned guard.
+        core::mem::forget(this);
+        MappedRwLockWriteGuard { state, value }
+    }
+}
+
+impl<'a, M, T> Drop for MappedRwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn drop(&mut self) {
+        unlock_write(self.state)
+    }
+}
+
+impl<'a, M, T> Deref for MappedRwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        // Safety: the guard represents exclusive access to the contents of the lock.
+        unsafe { &*self.value }
+    }
+}
+
+impl<'a, M, T> DerefMut for MappedRwLockWriteGuard<'a, M, T>
+where
+    M: RawMutex,
+    T: ?Sized,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // Safety: the guard represents exclusive access to the contents of the lock.
+        unsafe { &mut *self.value }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures_test::task::new_count_waker;
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+
+    #[futures_test::test]
+    async fn readers_share_the_lock() {
+        let rwlock = RwLock::<NoopRawMutex, u32>::new(5);
+
+        let r0 = rwlock.read().await;
+        let r1 = rwlock.read().await;
+        assert_eq!(*r0 + *r1, 10);
+        assert!(rwlock.try_write().is_err());
+
+        drop(r0);
+        assert!(rwlock.try_write().is_err());
+        drop(r1);
+        assert!(rwlock.try_write().is_ok());
+    }
+
+    #[futures_test::test]
+    async fn writer_is_exclusive() {
+        let rwlock = RwLock::<NoopRawMutex, u32>::new(5);
+
+        let mut w = rwlock.write().await;
+        *w += 1;
+        assert!(rwlock.try_read().is_err());
+        assert!(rwlock.try_write().is_err());
+
+        drop(w);
+        assert_eq!(*rwlock.try_read().unwrap(), 6);
+    }
+
+    #[test]
+    fn waiting_writer_holds_back_readers() {
+        let rwlock = RwLock::<NoopRawMutex, u32>::new(0);
+        let r = rwlock.try_read().unwrap();
+
+        let mut w = pin!(rwlock.write());
+        assert!(w.as_mut().now_or_never().is_none());
+        assert!(rwlock.try_read().is_err());
+
+        drop(r);
+        let w = w.now_or_never().unwrap();
+        drop(w);
+        assert!(rwlock.try_read().is_ok());
+    }
+
+    #[test]
+    fn cancelled_writer_lets_readers_in() {
+        let rwlock = RwLock::<NoopRawMutex, u32>::new(0);
+        let _r = rwlock.try_read().unwrap();
+
+        {
+            let mut w = pin!(rwlock.write());
+            assert!(w.as_mut().now_or_never().is_none());
+            assert!(rwlock.try_read().is_err());
+        }
+
+        assert!(rwlock.try_read().is_ok());
+    }
+
+    #[test]
+    fn waiting_readers_dont_wake_each_other() {
+        let rwlock = RwLock::<NoopRawMutex, u32>::new(0);
+        let w = rwlock.try_write().unwrap();
+
+        let mut readers = [pin!(rwlock.read()), pin!(rwlock.read()), pin!(rwlock.read())];
+        let wakers = [new_count_waker(), new_count_waker(), new_count_waker()];
+        for _ in 0..2 {
+            for (r, (waker, _)) in readers.iter_mut().zip(&wakers) {
+                assert!(r.as_mut().poll(&mut Context::from_waker(waker)).is_pending());
+            }
+        }
+        assert!(wakers.iter().all(|(_, count)| count.get() == 0));
+
+        drop(w);
+        assert!(wakers.iter().all(|(_, count)| count.get() == 1));
+        for (r, (waker, _)) in readers.iter_mut().zip(&wakers) {
+            assert!(r.as_mut().poll(&mut Context::from_waker(waker)).is_ready());
+        }
+    }
+
+    #[test]
+    fn writers_go_before_readers() {
+        let rwlock = RwLock::<NoopRawMutex, u32>::new(0);
+        let r = rwlock.try_read().unwrap();
+
+        let mut w = pin!(rwlock.write());
+        let mut r2 = pin!(rwlock.read());
+        let (write_waker, write_count) = new_count_waker();
+        let (read_waker, read_count) = new_count_waker();
+        assert!(w.as_mut().poll(&mut Context::from_waker(&write_waker)).is_pending());
+        assert!(r2.as_mut().poll(&mut Context::from_waker(&read_waker)).is_pending());
+
+        drop(r);
+        assert_eq!((write_count.get(), read_count.get()), (1, 0));
+        let Poll::Ready(w) = w.as_mut().poll(&mut Context::from_waker(&write_waker)) else {
+            panic!("writer not ready");
+        };
+
+        drop(w);
+        assert_eq!(read_count.get(), 1);
+        assert!(r2.as_mut().poll(&mut Context::from_waker(&read_waker)).is_ready());
+    }
+
+    #[futures_test::test]
+    async fn map_guards() {
+        let rwlock = RwLock::<NoopRawMutex, (u32, u32)>::new((1, 2));
+
+        {
+            let mut w = RwLockWriteGuard::map(rwlock.write().await, |v| &mut v.1);
+            *w = 3;
+            assert!(rwlock.try_read().is_err());
+        }
+
+        let r = RwLockReadGuard::map(rwlock.read().await, |v| &v.1);
+        assert_eq!(*r, 3);
+        assert!(rwlock.try_write().is_err());
+        drop(r);
+        assert!(rwlock.try_write().is_ok());
+    }
+
+    #[futures_test::test]
+    async fn waiting_tasks_are_woken() {
+        use futures_executor::ThreadPool;
+        use futures_util::task::SpawnExt;
+        use static_cell::StaticCell;
+
+        static RWLOCK: StaticCell<RwLock<CriticalSectionRawMutex, u32>> = StaticCell::new();
+        let rwlock: &'static _ = RWLOCK.init(RwLock::new(0));
+
+        let executor = ThreadPool::new().unwrap();
+        let w = rwlock.write().await;
+        let h0 = executor.spawn_with_handle(async move { *rwlock.read().await }).unwrap();
+        let h1 = executor
+            .spawn_with_handle(async move {
+                *rwlock.write().await += 1;
+            })
+            .unwrap();
+
+        drop(w);
+        h1.await;
+        let v = h0.await;
+        assert!(v == 0 || v == 1);
+        assert_eq!(*rwlock.read().await, 1);
+    }
+}
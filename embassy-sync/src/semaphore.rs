@@ -0,0 +1,521 @@
+//! Async counting semaphores.
+//!
+//! A semaphore holds a number of permits, which tasks acquire before using a shared resource and
+//! release when they are done, limiting how many of them use it at the same time. Permits are
+//! released when the [`SemaphoreReleaser`] returned when acquiring them is dropped.
+//!
+//! Two implementations of the [`Semaphore`] trait are provided:
+//!
+//! - [`GreedySemaphore`] grants permits to whichever task asks for them while they are available.
+//!   A task waiting for many permits can be starved by tasks acquiring a few at a time.
+//! - [`FairSemaphore`] grants permits in the order they were requested, to up to `N` waiting
+//!   tasks. A task waiting for many permits holds back the tasks that come after it.
+use core::cell::RefCell;
+use core::future::{poll_fn, Future};
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use heapless::Vec;
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::waitqueue::WakerRegistration;
+
+/// Error returned by [`Semaphore::acquire`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AcquireError {
+    /// The semaphore is closed.
+    Closed,
+    /// The wait queue of a [`FairSemaphore`] is full.
+    WaitQueueFull,
+}
+
+/// Error returned by [`Semaphore::try_acquire`].
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TryAcquireError {
+    /// The semaphore is closed.
+    Closed,
+    /// Not enough permits are available, or other tasks are waiting for them.
+    NoPermits,
+}
+
+/// An async counting semaphore.
+pub trait Semaphore: Sized {
+    /// Acquire `permits` permits, waiting until they are available.
+    ///
+    /// The permits are released when the returned [`SemaphoreReleaser`] is dropped.
+    async fn acquire(&self, permits: usize) -> Result<SemaphoreReleaser<'_, Self>, AcquireError>;
+
+    /// Acquire `permits` permits if they are available right away.
+    fn try_acquire(&self, permits: usize) -> Result<SemaphoreReleaser<'_, Self>, TryAcquireError>;
+
+    /// Add `permits` permits to the semaphore, waking the tasks they are enough for.
+    ///
+    /// This is also how [`SemaphoreReleaser`]s release their permits.
+    fn add_permits(&self, permits: usize);
+
+    /// Returns the number of permits available.
+    fn available_permits(&self) -> usize;
+
+    /// Close the semaphore.
+    ///
+    /// The tasks waiting for permits, and the following attempts to acquire some, fail with
+    /// [`AcquireError::Closed`]. The permits already acquired are unaffected.
+    fn close(&self);
+
+    /// Returns whether the semaphore is closed.
+    fn is_closed(&self) -> bool;
+}
+
+/// Permits acquired from a [`Semaphore`].
+///
+/// Dropping it releases the permits.
+#[must_use = "the permits are released right away if the releaser is dropped"]
+pub struct SemaphoreReleaser<'a, S: Semaphore> {
+    semaphore: &'a S,
+    permits: usize,
+}
+
+impl<'a, S: Semaphore> SemaphoreReleaser<'a, S> {
+    /// Returns the number of permits held.
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keep the permits acquired, instead of releasing them on drop.
+    ///
+    /// The permits are removed from the semaphore for good, unless given back with
+    /// [`Semaphore::add_permits`]. Returns their number.
+    pub fn disarm(self) -> usize {
+        let permits = self.permits;
+        core::mem::forget(self);
+        permits
+    }
+}
+
+impl<'a, S: Semaphore> Drop for SemaphoreReleaser<'a, S> {
+    fn drop(&mut self) {
+        self.semaphore.add_permits(self.permits);
+    }
+}
+
+struct GreedyState {
+    permits: usize,
+    closed: bool,
+    waker: WakerRegistration,
+}
+
+/// A semaphore granting permits to any task asking for them while they are available.
+///
+/// Like [`Mutex`](crate::mutex::Mutex), it only keeps the waker of one waiting task. Several tasks
+/// can wait at the same time, but they keep waking each other until permits are available.
+pub struct GreedySemaphore<M: RawMutex> {
+    state: Mutex<M, RefCell<GreedyState>>,
+}
+
+impl<M: RawMutex> GreedySemaphore<M> {
+    /// Create a new semaphore with `permits` permits.
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::const_new(
+                M::INIT,
+                RefCell::new(GreedyState {
+                    permits,
+                    closed: false,
+                    waker: WakerRegistration::new(),
+                }),
+            ),
+        }
+    }
+
+    fn poll_acquire(
+        &self,
+        permits: usize,
+        cx: Option<&mut Context<'_>>,
+    ) -> Poll<Result<SemaphoreReleaser<'_, Self>, AcquireError>> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.closed {
+                Poll::Ready(Err(AcquireError::Closed))
+            } else if s.permits >= permits {
+                s.permits -= permits;
+                Poll::Ready(Ok(SemaphoreReleaser {
+                    semaphore: self,
+                    permits,
+                }))
+            } else {
+                if let Some(cx) = cx {
+                    s.waker.register(cx.waker());
+                }
+                Poll::Pending
+            }
+        })
+    }
+}
+
+impl<M: RawMutex> Semaphore for GreedySemaphore<M> {
+    async fn acquire(&self, permits: usize) -> Result<SemaphoreReleaser<'_, Self>, AcquireError> {
+        poll_fn(|cx| self.poll_acquire(permits, Some(cx))).await
+    }
+
+    fn try_acquire(&self, permits: usize) -> Result<SemaphoreReleaser<'_, Self>, TryAcquireError> {
+        match self.poll_acquire(permits, None) {
+            Poll::Ready(Ok(releaser)) => Ok(releaser),
+            Poll::Ready(Err(_)) => Err(TryAcquireError::Closed),
+            Poll::Pending => Err(TryAcquireError::NoPermits),
+        }
+    }
+
+    fn add_permits(&self, permits: usize) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.permits += permits;
+            s.waker.wake();
+        })
+    }
+
+    fn available_permits(&self) -> usize {
+        self.state.lock(|s| s.borrow().permits)
+    }
+
+    fn close(&self) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.closed = true;
+            s.waker.wake();
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock(|s| s.borrow().closed)
+    }
+}
+
+struct Waiter {
+    id: usize,
+    permits: usize,
+    waker: Option<Waker>,
+}
+
+struct FairState<const N: usize> {
+    permits: usize,
+    closed: bool,
+    /// The tasks waiting for permits, in the order they asked for them.
+    queue: Vec<Waiter, N>,
+    next_id: usize,
+}
+
+impl<const N: usize> FairState<N> {
+    /// Wake the first waiting task if there are enough permits for it.
+    fn wake_front(&mut self) {
+        let permits = self.permits;
+        if let Some(w) = self.queue.first_mut().filter(|w| w.permits <= permits) {
+            if let Some(waker) = w.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    fn position(&self, id: usize) -> Option<usize> {
+        self.queue.iter().position(|w| w.id == id)
+    }
+}
+
+/// A semaphore granting permits in the order they were requested.
+///
+/// Up to `N` tasks can wait for permits. A task waiting for more permits than available holds
+/// back all the tasks that come after it, so large requests are not starved.
+pub struct FairSemaphore<M: RawMutex, const N: usize> {
+    state: Mutex<M, RefCell<FairState<N>>>,
+}
+
+impl<M: RawMutex, const N: usize> FairSemaphore<M, N> {
+    /// Create a new semaphore with `permits` permits.
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::const_new(
+                M::INIT,
+                RefCell::new(FairState {
+                    permits,
+                    closed: false,
+                    queue: Vec::new(),
+                    next_id: 0,
+                }),
+            ),
+        }
+    }
+}
+
+impl<M: RawMutex, const N: usize> Semaphore for FairSemaphore<M, N> {
+    async fn acquire(&self, permits: usize) -> Result<SemaphoreReleaser<'_, Self>, AcquireError> {
+        FairAcquire {
+            semaphore: self,
+            permits,
+            id: None,
+        }
+        .await
+    }
+
+    fn try_acquire(&self, permits: usize) -> Result<SemaphoreReleaser<'_, Self>, TryAcquireError> {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.closed {
+                Err(TryAcquireError::Closed)
+            } else if s.queue.is_empty() && s.permits >= permits {
+                s.permits -= permits;
+                Ok(SemaphoreReleaser {
+                    semaphore: self,
+                    permits,
+                })
+            } else {
+                Err(TryAcquireError::NoPermits)
+            }
+        })
+    }
+
+    fn add_permits(&self, permits: usize) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.permits += permits;
+            s.wake_front();
+        })
+    }
+
+    fn available_permits(&self) -> usize {
+        self.state.lock(|s| s.borrow().permits)
+    }
+
+    fn close(&self) {
+        self.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.closed = true;
+            for w in &mut s.queue {
+                if let Some(waker) = w.waker.take() {
+                    waker.wake();
+                }
+            }
+        })
+    }
+
+    fn is_closed(&self) -> bool {
+        self.state.lock(|s| s.borrow().closed)
+    }
+}
+
+/// Future acquiring permits from a [`FairSemaphore`], leaving the wait queue when dropped.
+struct FairAcquire<'a, M: RawMutex, const N: usize> {
+    semaphore: &'a FairSemaphore<M, N>,
+    permits: usize,
+    /// ID of the entry in the wait queue, once queued.
+    id: Option<usize>,
+}
+
+impl<'a, M: RawMutex, const N: usize> Future for FairAcquire<'a, M, N> {
+    type Output = Result<SemaphoreReleaser<'a, FairSemaphore<M, N>>, AcquireError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        this.semaphore.state.lock(|s| {
+            let mut s = s.borrow_mut();
+            let pos = this.id.and_then(|id| s.position(id));
+
+            if s.closed {
+                if let Some(pos) = pos {
+                    s.queue.remove(pos);
+                }
+                this.id = None;
+                return Poll::Ready(Err(AcquireError::Closed));
+            }
+
+            match pos {
+                // First time polled, only skip the queue if it's empty.
+                None if s.queue.is_empty() && s.permits >= this.permits => {}
+                None => {
+                    let id = s.next_id;
+                    let waiter = Waiter {
+                        id,
+                        permits: this.permits,
+                        waker: Some(cx.waker().clone()),
+                    };
+                    if s.queue.push(waiter).is_err() {
+                        return Poll::Ready(Err(AcquireError::WaitQueueFull));
+                    }
+                    s.next_id = s.next_id.wrapping_add(1);
+                    this.id = Some(id);
+                    return Poll::Pending;
+                }
+                Some(0) if s.permits >= this.permits => {
+                    s.queue.remove(0);
+                    this.id = None;
+                }
+                Some(pos) => {
+                    match &mut s.queue[pos].waker {
+                        Some(w) if w.will_wake(cx.waker()) => {}
+                        w => *w = Some(cx.waker().clone()),
+                    }
+                    return Poll::Pending;
+                }
+            }
+
+            s.permits -= this.permits;
+            // The next task might be satisfied by the remaining permits.
+            s.wake_front();
+            Poll::Ready(Ok(SemaphoreReleaser {
+                semaphore: this.semaphore,
+                permits: this.permits,
+            }))
+        })
+    }
+}
+
+impl<'a, M: RawMutex, const N: usize> Drop for FairAcquire<'a, M, N> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.semaphore.state.lock(|s| {
+                let mut s = s.borrow_mut();
+                if let Some(pos) = s.position(id) {
+                    s.queue.remove(pos);
+                    // The tasks that were held back by this one might be able to go now.
+                    if pos == 0 {
+                        s.wake_front();
+                    }
+                }
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+
+    #[futures_test::test]
+    async fn greedy_acquire_and_release() {
+        let semaphore = GreedySemaphore::<NoopRawMutex>::new(3);
+
+        let a = semaphore.acquire(2).await.unwrap();
+        assert_eq!(a.permits(), 2);
+        assert_eq!(semaphore.available_permits(), 1);
+        assert_eq!(semaphore.try_acquire(2).err(), Some(TryAcquireError::NoPermits));
+
+        drop(a);
+        assert_eq!(semaphore.available_permits(), 3);
+        let _b = semaphore.try_acquire(3).unwrap();
+        assert_eq!(semaphore.available_permits(), 0);
+    }
+
+    #[test]
+    fn greedy_waits_for_permits() {
+        let semaphore = GreedySemaphore::<NoopRawMutex>::new(1);
+        let a = semaphore.try_acquire(1).unwrap();
+
+        let mut fut = pin!(semaphore.acquire(1));
+        assert!(fut.as_mut().now_or_never().is_none());
+        drop(a);
+        assert_eq!(fut.now_or_never().unwrap().unwrap().permits(), 1);
+    }
+
+    #[test]
+    fn disarm_and_add_permits() {
+        let semaphore = GreedySemaphore::<NoopRawMutex>::new(2);
+
+        assert_eq!(semaphore.try_acquire(2).unwrap().disarm(), 2);
+        assert_eq!(semaphore.available_permits(), 0);
+
+        semaphore.add_permits(5);
+        assert_eq!(semaphore.available_permits(), 5);
+    }
+
+    #[test]
+    fn close() {
+        let semaphore = FairSemaphore::<NoopRawMutex, 2>::new(1);
+        let a = semaphore.try_acquire(1).unwrap();
+
+        let mut fut = pin!(semaphore.acquire(1));
+        assert!(fut.as_mut().now_or_never().is_none());
+
+        semaphore.close();
+        assert!(semaphore.is_closed());
+        assert_eq!(fut.now_or_never().unwrap().err(), Some(AcquireError::Closed));
+        assert_eq!(semaphore.try_acquire(0).err(), Some(TryAcquireError::Closed));
+
+        // Permits can still be released.
+        drop(a);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn fair_large_request_is_not_starved() {
+        let semaphore = FairSemaphore::<NoopRawMutex, 2>::new(2);
+        let a = semaphore.try_acquire(1).unwrap();
+
+        // Waits for 2 permits, only 1 is available.
+        let mut large = pin!(semaphore.acquire(2));
+        assert!(large.as_mut().now_or_never().is_none());
+
+        // Smaller requests queue behind it, even though there's a permit for them.
+        assert_eq!(semaphore.try_acquire(1).err(), Some(TryAcquireError::NoPermits));
+        let mut small = pin!(semaphore.acquire(1));
+        assert!(small.as_mut().now_or_never().is_none());
+
+        drop(a);
+        let large = large.now_or_never().unwrap().unwrap();
+        assert!(small.as_mut().now_or_never().is_none());
+
+        drop(large);
+        assert_eq!(small.now_or_never().unwrap().unwrap().permits(), 1);
+    }
+
+    #[test]
+    fn fair_wait_queue_full() {
+        let semaphore = FairSemaphore::<NoopRawMutex, 1>::new(0);
+
+        let mut a = pin!(semaphore.acquire(1));
+        assert!(a.as_mut().now_or_never().is_none());
+        let b = semaphore.acquire(1).now_or_never();
+        assert_eq!(b.unwrap().err(), Some(AcquireError::WaitQueueFull));
+    }
+
+    #[test]
+    fn fair_cancelled_waiter_leaves_queue() {
+        let semaphore = FairSemaphore::<NoopRawMutex, 2>::new(1);
+
+        {
+            let mut large = pin!(semaphore.acquire(2));
+            assert!(large.as_mut().now_or_never().is_none());
+            let mut small = pin!(semaphore.acquire(1));
+            assert!(small.as_mut().now_or_never().is_none());
+        }
+
+        assert_eq!(semaphore.try_acquire(1).unwrap().permits(), 1);
+    }
+
+    #[futures_test::test]
+    async fn fair_waiters_are_woken() {
+        use futures_executor::ThreadPool;
+        use futures_util::task::SpawnExt;
+        use static_cell::StaticCell;
+
+        static SEMAPHORE: StaticCell<FairSemaphore<CriticalSectionRawMutex, 4>> = StaticCell::new();
+        let semaphore: &'static _ = SEMAPHORE.init(FairSemaphore::new(0));
+
+        let executor = ThreadPool::new().unwrap();
+        let h0 = executor
+            .spawn_with_handle(async move { semaphore.acquire(1).await.unwrap().disarm() })
+            .unwrap();
+        let h1 = executor
+            .spawn_with_handle(async move { semaphore.acquire(2).await.unwrap().disarm() })
+            .unwrap();
+
+        semaphore.add_permits(3);
+        assert_eq!(h0.await + h1.await, 3);
+        assert_eq!(semaphore.available_permits(), 0);
+    }
+}
@@ -0,0 +1,417 @@
+//! A synchronization primitive for broadcasting the latest value to several tasks.
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::{Context, Poll};
+
+use crate::blocking_mutex::raw::RawMutex;
+use crate::blocking_mutex::Mutex;
+use crate::waitqueue::MultiWakerRegistration;
+
+/// Broadcast of the latest value, with change notification.
+///
+/// A `Watch` holds a single value, which any number of [`Sender`]s can replace or modify in place.
+/// Up to `N` [`Receiver`]s can read it, and wait for it to change. Unlike a
+/// [`Signal`](crate::signal::Signal), receiving doesn't consume the value, and all the waiting
+/// receivers are woken. Unlike a [`PubSubChannel`](crate::pubsub::PubSubChannel), intermediate
+/// values are not queued: a receiver that is too slow only sees the latest one.
+///
+/// It is useful for distributing "state", such as a configuration or the state of a link, to all
+/// the tasks that need it.
+///
+/// ```
+/// # use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+/// # use embassy_sync::watch::Watch;
+/// # use futures_executor::block_on;
+/// # let test = async {
+/// static WATCH: Watch<CriticalSectionRawMutex, u32, 2> = Watch::new();
+///
+/// let sender = WATCH.sender();
+/// let mut receiver = WATCH.receiver().unwrap();
+///
+/// sender.send(10);
+/// assert_eq!(receiver.changed().await, 10);
+///
+/// // Only notify the receivers if the value actually changed.
+/// sender.send_if_modified(|value| {
+///     if *value == Some(10) {
+///         return false;
+///     }
+///     *value = Some(10);
+///     true
+/// });
+/// assert_eq!(receiver.try_changed(), None);
+///
+/// // The current value can always be read.
+/// assert_eq!(receiver.get().await, 10);
+/// # };
+/// # block_on(test);
+/// ```
+pub struct Watch<M: RawMutex, T: Clone, const N: usize> {
+    inner: Mutex<M, RefCell<WatchState<T, N>>>,
+}
+
+struct WatchState<T: Clone, const N: usize> {
+    data: Option<T>,
+    /// Incremented on every change. Receivers remember the ID of the last value they saw.
+    current_id: u64,
+    wakers: MultiWakerRegistration<N>,
+    receiver_count: usize,
+}
+
+impl<T: Clone, const N: usize> WatchState<T, N> {
+    fn changed(&mut self) {
+        self.current_id += 1;
+        self.wakers.wake();
+    }
+}
+
+impl<M: RawMutex, T: Clone, const N: usize> Watch<M, T, N> {
+    /// Create a new `Watch`, without a value.
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::const_new(
+                M::INIT,
+                RefCell::new(WatchState {
+                    data: None,
+                    current_id: 0,
+                    wakers: MultiWakerRegistration::new(),
+                    receiver_count: 0,
+                }),
+            ),
+        }
+    }
+
+    /// Create a new `Watch`, with an initial value.
+    ///
+    /// The initial value counts as a change for the receivers, see [`Receiver::changed`].
+    pub const fn new_with(data: T) -> Self {
+        Self {
+            inner: Mutex::const_new(
+                M::INIT,
+                RefCell::new(WatchState {
+                    data: Some(data),
+                    current_id: 1,
+                    wakers: MultiWakerRegistration::new(),
+                    receiver_count: 0,
+                }),
+            ),
+        }
+    }
+
+    /// Create a new sender.
+    ///
+    /// There can be any number of senders.
+    pub fn sender(&self) -> Sender<'_, M, T, N> {
+        Sender { watch: self }
+    }
+
+    /// Create a new receiver.
+    ///
+    /// Returns `None` if there already are `N` receivers.
+    pub fn receiver(&self) -> Option<Receiver<'_, M, T, N>> {
+        self.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+            if s.receiver_count < N {
+                s.receiver_count += 1;
+                Some(Receiver { watch: self, at_id: 0 })
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Get a clone of the current value, if any.
+    pub fn try_get(&self) -> Option<T> {
+        self.inner.lock(|s| s.borrow().data.clone())
+    }
+
+    /// Returns whether the `Watch` has a value.
+    pub fn contains_value(&self) -> bool {
+        self.inner.lock(|s| s.borrow().data.is_some())
+    }
+
+    fn poll_get(&self, at_id: &mut u64, changed: bool, cx: Option<&mut Context<'_>>) -> Poll<T> {
+        self.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+            match &s.data {
+                Some(data) if !changed || s.current_id > *at_id => {
+                    let data = data.clone();
+                    *at_id = s.current_id;
+                    Poll::Ready(data)
+                }
+                _ => {
+                    if let Some(cx) = cx {
+                        s.wakers.register(cx.waker());
+                    }
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl<M: RawMutex, T: Clone, const N: usize> Default for Watch<M, T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A sender of a [`Watch`].
+pub struct Sender<'a, M: RawMutex, T: Clone, const N: usize> {
+    watch: &'a Watch<M, T, N>,
+}
+
+impl<'a, M: RawMutex, T: Clone, const N: usize> Clone for Sender<'a, M, T, N> {
+    fn clone(&self) -> Self {
+        Self { watch: self.watch }
+    }
+}
+
+impl<'a, M: RawMutex, T: Clone, const N: usize> Sender<'a, M, T, N> {
+    /// Replace the value, and notify the receivers.
+    pub fn send(&self, val: T) {
+        self.watch.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+            s.data = Some(val);
+            s.changed();
+        })
+    }
+
+    /// Remove the value.
+    ///
+    /// The receivers are not notified, they wait for the next value sent.
+    pub fn clear(&self) {
+        self.watch.inner.lock(|s| s.borrow_mut().data = None)
+    }
+
+    /// Modify the value in place, and notify the receivers.
+    pub fn send_modify<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Option<T>),
+    {
+        self.send_if_modified(|data| {
+            f(data);
+            true
+        });
+    }
+
+    /// Modify the value in place, and notify the receivers if `f` returns `true`.
+    ///
+    /// This avoids waking the receivers when the value didn't actually change.
+    pub fn send_if_modified<F>(&self, f: F) -> bool
+    where
+        F: FnOnce(&mut Option<T>) -> bool,
+    {
+        self.watch.inner.lock(|s| {
+            let mut s = s.borrow_mut();
+            let modified = f(&mut s.data);
+            if modified {
+                s.changed();
+            }
+            modified
+        })
+    }
+
+    /// Get a clone of the current value, if any.
+    pub fn try_get(&self) -> Option<T> {
+        self.watch.try_get()
+    }
+}
+
+/// A receiver of a [`Watch`].
+///
+/// Dropping it frees its slot for another receiver.
+pub struct Receiver<'a, M: RawMutex, T: Clone, const N: usize> {
+    watch: &'a Watch<M, T, N>,
+    /// ID of the last value seen.
+    at_id: u64,
+}
+
+impl<'a, M: RawMutex, T: Clone, const N: usize> Receiver<'a, M, T, N> {
+    /// Get a clone of the current value, waiting until there is one.
+    ///
+    /// The value is marked as seen.
+    pub async fn get(&mut self) -> T {
+        poll_fn(|cx| self.watch.poll_get(&mut self.at_id, false, Some(cx))).await
+    }
+
+    /// Get a clone of the current value, if any.
+    ///
+    /// The value is marked as seen.
+    pub fn try_get(&mut self) -> Option<T> {
+        match self.watch.poll_get(&mut self.at_id, false, None) {
+            Poll::Ready(data) => Some(data),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Wait for a value this receiver hasn't seen yet, and get a clone of it.
+    ///
+    /// The value of the `Watch` when the receiver is created counts as not seen, so the first
+    /// call returns immediately if there is one. If several values are sent in the meantime, only
+    /// the latest is returned.
+    pub async fn changed(&mut self) -> T {
+        poll_fn(|cx| self.watch.poll_get(&mut self.at_id, true, Some(cx))).await
+    }
+
+    /// Get a clone of the value if this receiver hasn't seen it yet.
+    pub fn try_changed(&mut self) -> Option<T> {
+        match self.watch.poll_get(&mut self.at_id, true, None) {
+            Poll::Ready(data) => Some(data),
+            Poll::Pending => None,
+        }
+    }
+
+    /// Returns whether the `Watch` has a value.
+    pub fn contains_value(&self) -> bool {
+        self.watch.contains_value()
+    }
+}
+
+impl<'a, M: RawMutex, T: Clone, const N: usize> Drop for Receiver<'a, M, T, N> {
+    fn drop(&mut self) {
+        self.watch.inner.lock(|s| s.borrow_mut().receiver_count -= 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::FutureExt;
+
+    use super::*;
+    use crate::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+
+    #[futures_test::test]
+    async fn all_receivers_get_the_value() {
+        let watch = Watch::<NoopRawMutex, u32, 2>::new();
+        let sender = watch.sender();
+        let mut rcv0 = watch.receiver().unwrap();
+        let mut rcv1 = watch.receiver().unwrap();
+
+        assert_eq!(rcv0.try_get(), None);
+        sender.send(42);
+
+        assert_eq!(rcv0.changed().await, 42);
+        assert_eq!(rcv1.changed().await, 42);
+        assert_eq!(rcv0.try_changed(), None);
+        assert_eq!(rcv1.try_changed(), None);
+
+        // Reading doesn't consume the value.
+        assert_eq!(rcv0.get().await, 42);
+        assert_eq!(watch.try_get(), Some(42));
+    }
+
+    #[futures_test::test]
+    async fn only_the_latest_value_is_seen() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new();
+        let sender = watch.sender();
+        let mut rcv = watch.receiver().unwrap();
+
+        sender.send(1);
+        sender.send(2);
+        sender.send(3);
+        assert_eq!(rcv.changed().await, 3);
+        assert_eq!(rcv.try_changed(), None);
+    }
+
+    #[futures_test::test]
+    async fn initial_value_counts_as_changed() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new_with(7);
+        let mut rcv = watch.receiver().unwrap();
+
+        assert_eq!(rcv.try_changed(), Some(7));
+        assert_eq!(rcv.try_changed(), None);
+    }
+
+    #[futures_test::test]
+    async fn get_marks_as_seen() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new();
+        let sender = watch.sender();
+        let mut rcv = watch.receiver().unwrap();
+
+        sender.send(1);
+        assert_eq!(rcv.get().await, 1);
+        assert_eq!(rcv.try_changed(), None);
+    }
+
+    #[test]
+    fn send_if_modified() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new_with(5);
+        let sender = watch.sender();
+        let mut rcv = watch.receiver().unwrap();
+        assert_eq!(rcv.try_changed(), Some(5));
+
+        assert!(!sender.send_if_modified(|v| v.replace(5) != Some(5)));
+        assert_eq!(rcv.try_changed(), None);
+
+        assert!(sender.send_if_modified(|v| v.replace(6) != Some(6)));
+        assert_eq!(rcv.try_changed(), Some(6));
+
+        sender.send_modify(|v| *v = v.map(|v| v + 1));
+        assert_eq!(rcv.try_changed(), Some(7));
+    }
+
+    #[test]
+    fn clear() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new_with(5);
+        let sender = watch.sender();
+        let mut rcv = watch.receiver().unwrap();
+
+        sender.clear();
+        assert!(!rcv.contains_value());
+        assert_eq!(rcv.try_get(), None);
+        assert!(rcv.get().now_or_never().is_none());
+
+        sender.send(6);
+        assert_eq!(rcv.try_changed(), Some(6));
+    }
+
+    #[test]
+    fn changed_waits_for_a_new_value() {
+        let watch = Watch::<NoopRawMutex, u32, 1>::new();
+        let sender = watch.sender();
+        let mut rcv = watch.receiver().unwrap();
+
+        sender.send(1);
+        assert_eq!(rcv.try_changed(), Some(1));
+
+        {
+            let mut fut = core::pin::pin!(rcv.changed());
+            assert!(fut.as_mut().now_or_never().is_none());
+            sender.send(2);
+            assert_eq!(fut.now_or_never(), Some(2));
+        }
+    }
+
+    #[test]
+    fn receiver_slots() {
+        let watch = Watch::<CriticalSectionRawMutex, u32, 2>::new();
+
+        let rcv0 = watch.receiver().unwrap();
+        let _rcv1 = watch.receiver().unwrap();
+        assert!(watch.receiver().is_none());
+
+        drop(rcv0);
+        assert!(watch.receiver().is_some());
+    }
+
+    #[futures_test::test]
+    async fn waiting_receivers_are_woken() {
+        use futures_executor::ThreadPool;
+        use futures_util::task::SpawnExt;
+        use static_cell::StaticCell;
+
+        static WATCH: StaticCell<Watch<CriticalSectionRawMutex, u32, 2>> = StaticCell::new();
+        let watch: &'static _ = WATCH.init(Watch::new());
+
+        let executor = ThreadPool::new().unwrap();
+        let mut rcv0 = watch.receiver().unwrap();
+        let mut rcv1 = watch.receiver().unwrap();
+        let h0 = executor.spawn_with_handle(async move { rcv0.changed().await }).unwrap();
+        let h1 = executor.spawn_with_handle(async move { rcv1.changed().await }).unwrap();
+
+        watch.sender().send(3);
+        assert_eq!(h0.await, 3);
+        assert_eq!(h1.await, 3);
+    }
+}
@@ -133,6 +133,59 @@ impl<'a, M: RawMutex, T> Sender<'a, M, T> {
     pub fn send_done(&mut self) {
         self.channel.state.lock(|s| s.borrow_mut().push_done())
     }
+
+    /// Attempts to send several values over the channel.
+    ///
+    /// Returns the free slots that are contiguous in the buffer, which might be fewer than all
+    /// the free slots. The slice is empty if the channel is full.
+    pub fn try_send_many(&mut self) -> &mut [T] {
+        let (i, n) = self.channel.state.lock(|s| s.borrow().push_slots());
+        unsafe { core::slice::from_raw_parts_mut(self.channel.buf.add(i), n) }
+    }
+
+    /// Attempts to send several values over the channel.
+    ///
+    /// See [`try_send_many`](Self::try_send_many), this is ready as soon as there is one free slot.
+    pub fn poll_send_many(&mut self, cx: &mut Context) -> Poll<&mut [T]> {
+        let (i, n) = self.channel.state.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            let (i, n) = s.push_slots();
+            if n == 0 {
+                s.receive_waker.register(cx.waker());
+            }
+            (i, n)
+        });
+        match n {
+            0 => Poll::Pending,
+            n => Poll::Ready(unsafe { core::slice::from_raw_parts_mut(self.channel.buf.add(i), n) }),
+        }
+    }
+
+    /// Asynchronously send several values over the channel.
+    ///
+    /// See [`try_send_many`](Self::try_send_many), this waits until there is at least one free slot.
+    pub async fn send_many(&mut self) -> &mut [T] {
+        let (i, n) = poll_fn(|cx| {
+            self.channel.state.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                match s.push_slots() {
+                    (_, 0) => {
+                        s.receive_waker.register(cx.waker());
+                        Poll::Pending
+                    }
+                    slots => Poll::Ready(slots),
+                }
+            })
+        })
+        .await;
+        unsafe { core::slice::from_raw_parts_mut(self.channel.buf.add(i), n) }
+    }
+
+    /// Notify the channel that the sending of the first `n` values returned by
+    /// [`try_send_many`](Self::try_send_many) or [`send_many`](Self::send_many) has been finalized.
+    pub fn send_done_many(&mut self, n: usize) {
+        self.channel.state.lock(|s| s.borrow_mut().push_done_many(n))
+    }
 }
 
 /// Receive-only access to a [`Channel`].
@@ -193,6 +246,59 @@ impl<'a, M: RawMutex, T> Receiver<'a, M, T> {
     pub fn receive_done(&mut self) {
         self.channel.state.lock(|s| s.borrow_mut().pop_done())
     }
+
+    /// Attempts to receive several values over the channel.
+    ///
+    /// Returns the values that are contiguous in the buffer, which might be fewer than all
+    /// the values in the channel. The slice is empty if the channel is empty.
+    pub fn try_receive_many(&mut self) -> &mut [T] {
+        let (i, n) = self.channel.state.lock(|s| s.borrow().pop_slots());
+        unsafe { core::slice::from_raw_parts_mut(self.channel.buf.add(i), n) }
+    }
+
+    /// Attempts to asynchronously receive several values over the channel.
+    ///
+    /// See [`try_receive_many`](Self::try_receive_many), this is ready as soon as there is one value.
+    pub fn poll_receive_many(&mut self, cx: &mut Context) -> Poll<&mut [T]> {
+        let (i, n) = self.channel.state.lock(|s| {
+            let s = &mut *s.borrow_mut();
+            let (i, n) = s.pop_slots();
+            if n == 0 {
+                s.send_waker.register(cx.waker());
+            }
+            (i, n)
+        });
+        match n {
+            0 => Poll::Pending,
+            n => Poll::Ready(unsafe { core::slice::from_raw_parts_mut(self.channel.buf.add(i), n) }),
+        }
+    }
+
+    /// Asynchronously receive several values over the channel.
+    ///
+    /// See [`try_receive_many`](Self::try_receive_many), this waits until there is at least one value.
+    pub async fn receive_many(&mut self) -> &mut [T] {
+        let (i, n) = poll_fn(|cx| {
+            self.channel.state.lock(|s| {
+                let s = &mut *s.borrow_mut();
+                match s.pop_slots() {
+                    (_, 0) => {
+                        s.send_waker.register(cx.waker());
+                        Poll::Pending
+                    }
+                    slots => Poll::Ready(slots),
+                }
+            })
+        })
+        .await;
+        unsafe { core::slice::from_raw_parts_mut(self.channel.buf.add(i), n) }
+    }
+
+    /// Notify the channel that the receiving of the first `n` values returned by
+    /// [`try_receive_many`](Self::try_receive_many) or [`receive_many`](Self::receive_many) has been finalized.
+    pub fn receive_done_many(&mut self, n: usize) {
+        self.channel.state.lock(|s| s.borrow_mut().pop_done_many(n))
+    }
 }
 
 struct State {
@@ -244,6 +350,46 @@ impl State {
         self.send_waker.wake();
     }
 
+    /// Index and number of the contiguous free slots.
+    fn push_slots(&self) -> (usize, usize) {
+        let end = if self.back < self.front { self.front } else { self.len };
+        match self.is_full() {
+            true => (self.back, 0),
+            false => (self.back, end - self.back),
+        }
+    }
+
+    fn push_done_many(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        assert!(n <= self.push_slots().1);
+        self.back = (self.back + n) % self.len;
+        if self.back == self.front {
+            self.full = true;
+        }
+        self.send_waker.wake();
+    }
+
+    /// Index and number of the contiguous filled slots.
+    fn pop_slots(&self) -> (usize, usize) {
+        let end = if self.front < self.back { self.back } else { self.len };
+        match self.is_empty() {
+            true => (self.front, 0),
+            false => (self.front, end - self.front),
+        }
+    }
+
+    fn pop_done_many(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        assert!(n <= self.pop_slots().1);
+        self.front = (self.front + n) % self.len;
+        self.full = false;
+        self.receive_waker.wake();
+    }
+
     fn pop_index(&mut self) -> Option<usize> {
         match self.is_empty() {
             true => None,
@@ -258,3 +404,43 @@ impl State {
         self.receive_waker.wake();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocking_mutex::raw::NoopRawMutex;
+
+    #[test]
+    fn send_receive_many() {
+        let mut buf = [0u32; 4];
+        let mut c = Channel::<NoopRawMutex, u32>::new(&mut buf);
+        let (mut tx, mut rx) = c.split();
+
+        assert!(rx.try_receive_many().is_empty());
+        let slots = tx.try_send_many();
+        assert_eq!(slots.len(), 4);
+        slots[..3].copy_from_slice(&[1, 2, 3]);
+        tx.send_done_many(3);
+
+        assert_eq!(rx.try_receive_many(), &[1, 2, 3]);
+        rx.receive_done_many(2);
+        assert_eq!(rx.try_receive_many(), &[3]);
+
+        // The free slots wrap around, only the contiguous ones are returned.
+        assert_eq!(tx.try_send_many().len(), 1);
+        *tx.try_send().unwrap() = 4;
+        tx.send_done();
+        let slots = tx.try_send_many();
+        assert_eq!(slots.len(), 2);
+        slots.copy_from_slice(&[5, 6]);
+        tx.send_done_many(2);
+        assert!(tx.try_send_many().is_empty());
+        assert!(tx.try_send().is_none());
+
+        assert_eq!(rx.try_receive_many(), &[3, 4]);
+        rx.receive_done_many(2);
+        assert_eq!(rx.try_receive_many(), &[5, 6]);
+        rx.receive_done_many(2);
+        assert!(rx.try_receive_many().is_empty());
+    }
+}
@@ -6,7 +6,7 @@ use clap::Parser;
 use embassy_executor::{Executor, Spawner};
 use embassy_net::tcp::TcpSocket;
 use embassy_net::{Config, Ipv4Address, Ipv4Cidr, Stack, StackResources};
-use embassy_net_tuntap::TunTapDevice;
+use embassy_net_tuntap::{self as tuntap, TunTapDevice};
 use embassy_time::Duration;
 use embedded_io_async::Write;
 use heapless::Vec;
@@ -20,6 +20,9 @@ struct Opts {
     /// TAP device name
     #[clap(long, default_value = "tap0")]
     tap: String,
+    /// use a TUN device (IP medium) instead of a TAP device, implies --static-ip
+    #[clap(long)]
+    tun: bool,
     /// use a static IP instead of DHCP
     #[clap(long)]
     static_ip: bool,
@@ -35,10 +38,15 @@ async fn main_task(spawner: Spawner) {
     let opts: Opts = Opts::parse();
 
     // Init network device
-    let device = TunTapDevice::new(&opts.tap).unwrap();
+    let tuntap_config = if opts.tun {
+        tuntap::Config::tun()
+    } else {
+        tuntap::Config::tap()
+    };
+    let device = TunTapDevice::with_config(&opts.tap, tuntap_config).unwrap();
 
     // Choose between dhcp or static ip
-    let config = if opts.static_ip {
+    let config = if opts.static_ip || opts.tun {
         Config::ipv4_static(embassy_net::StaticConfigV4 {
             address: Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 2), 24),
             dns_servers: Vec::new(),
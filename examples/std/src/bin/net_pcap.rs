@@ -0,0 +1,94 @@
+#![feature(type_alias_impl_trait)]
+
+use std::default::Default;
+
+use clap::Parser;
+use embassy_executor::{Executor, Spawner};
+use embassy_net::pcap::{self, PcapDriver, PcapFile};
+use embassy_net::{Config, Stack, StackResources};
+use embassy_net_tuntap::TunTapDevice;
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::pipe::Pipe;
+use embassy_time::{Duration, Timer};
+use log::*;
+use rand_core::{OsRng, RngCore};
+use static_cell::{make_static, StaticCell};
+
+const CAPTURE_SIZE: usize = 8192;
+
+type CaptureDevice = PcapDriver<'static, TunTapDevice, NoopRawMutex, CAPTURE_SIZE>;
+
+#[derive(Parser)]
+#[clap(version = "1.0")]
+struct Opts {
+    /// TAP device name
+    #[clap(long, default_value = "tap0")]
+    tap: String,
+    /// File to write the capture to
+    #[clap(long, default_value = "capture.pcap")]
+    output: String,
+}
+
+#[embassy_executor::task]
+async fn net_task(stack: &'static Stack<CaptureDevice>) -> ! {
+    stack.run().await
+}
+
+#[embassy_executor::task]
+async fn capture_task(pipe: &'static Pipe<NoopRawMutex, CAPTURE_SIZE>, file: PcapFile) {
+    if let Err(e) = pcap::run(pipe, file).await {
+        warn!("capture write error: {:?}", e);
+    }
+}
+
+#[embassy_executor::task]
+async fn main_task(spawner: Spawner) {
+    let opts: Opts = Opts::parse();
+
+    // Init network device, capturing its traffic
+    let pipe = &*make_static!(Pipe::new());
+    let device = PcapDriver::new(TunTapDevice::new(&opts.tap).unwrap(), pipe);
+    let file = PcapFile::create(&opts.output).unwrap();
+    spawner.spawn(capture_task(pipe, file)).unwrap();
+
+    // Generate random seed
+    let mut seed = [0; 8];
+    OsRng.fill_bytes(&mut seed);
+    let seed = u64::from_le_bytes(seed);
+
+    // Init network stack
+    let stack = &*make_static!(Stack::new(
+        device,
+        Config::dhcpv4(Default::default()),
+        make_static!(StackResources::<3>::new()),
+        seed
+    ));
+
+    // Launch network task
+    spawner.spawn(net_task(stack)).unwrap();
+
+    // The DHCP exchange ends up in the capture.
+    stack.wait_config_up().await;
+    info!("IP config: {:?}", stack.config_v4());
+    info!("writing capture to {}", opts.output);
+
+    loop {
+        Timer::after(Duration::from_secs(10)).await;
+        info!("stats: {:?}", stack.stats());
+    }
+}
+
+static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+
+fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Debug)
+        .filter_module("async_io", log::LevelFilter::Info)
+        .format_timestamp_nanos()
+        .init();
+
+    let executor = EXECUTOR.init(Executor::new());
+    executor.run(|spawner| {
+        spawner.spawn(main_task(spawner)).unwrap();
+    });
+}
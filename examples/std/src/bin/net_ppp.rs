@@ -6,6 +6,14 @@
 //!     RUST_LOG=trace cargo run --bin net_ppp -- --device pty2
 //!     ping 192.168.7.10
 //!     nc 192.168.7.10 1234
+//!
+//! To also test IPv6, add `+ipv6` to the pppd options and pass `--ipv6`. pppd prints the link-local
+//! addresses it negotiated, ping ours through the ppp interface with `ping fe80::...%ppp0`.
+//!
+//! With `debug`, pppd logs our LCP Echo-Requests with the magic number it acked.
+//!
+//! The link is dropped when pppd doesn't answer LCP Echo-Requests for 30 seconds, you can check this
+//! by stopping it with `kill -STOP`.
 
 #![feature(type_alias_impl_trait)]
 #![allow(async_fn_in_trait)]
@@ -17,8 +25,10 @@ use async_io::Async;
 use clap::Parser;
 use embassy_executor::{Executor, Spawner};
 use embassy_net::tcp::TcpSocket;
-use embassy_net::{Config, ConfigV4, Ipv4Address, Ipv4Cidr, Stack, StackResources};
-use embassy_net_ppp::Runner;
+use embassy_net::{
+    Config, ConfigV4, ConfigV6, Ipv4Address, Ipv4Cidr, Ipv6Address, Ipv6Cidr, Stack, StackResources, StaticConfigV6,
+};
+use embassy_net_ppp::{Keepalive, Options, Runner};
 use embedded_io_async::Write;
 use futures::io::BufReader;
 use heapless::Vec;
@@ -35,6 +45,9 @@ struct Opts {
     /// Serial port device name
     #[clap(short, long)]
     device: String,
+    /// Negotiate IPv6 too
+    #[clap(long)]
+    ipv6: bool,
 }
 
 #[embassy_executor::task]
@@ -47,6 +60,7 @@ async fn ppp_task(
     stack: &'static Stack<embassy_net_ppp::Device<'static>>,
     mut runner: Runner<'static>,
     port: SerialPort,
+    ipv6: bool,
 ) -> ! {
     let port = Async::new(port).unwrap();
     let port = BufReader::new(port);
@@ -57,25 +71,47 @@ async fn ppp_task(
         password: b"mypass",
     };
 
-    runner
-        .run(port, config, |ipv4| {
-            let Some(addr) = ipv4.address else {
-                warn!("PPP did not provide an IP address.");
-                return;
-            };
-            let mut dns_servers = Vec::new();
-            for s in ipv4.dns_servers.iter().flatten() {
-                let _ = dns_servers.push(Ipv4Address::from_bytes(&s.0));
-            }
-            let config = ConfigV4::Static(embassy_net::StaticConfigV4 {
-                address: Ipv4Cidr::new(Ipv4Address::from_bytes(&addr.0), 0),
-                gateway: None,
-                dns_servers,
-            });
-            stack.set_config_v4(config);
-        })
-        .await
-        .unwrap();
+    let mut options = Options::default();
+    options.ipv6 = ipv6;
+    options.keepalive = Some(Keepalive::default());
+
+    let quality = runner.link_quality();
+    let on_ipv6_up = |ipv6: embassy_net_ppp::Ipv6Status| {
+        let address = Ipv6Address::from_bytes(&ipv6.link_local_address());
+        info!("IPv6 up, address {}", address);
+        stack.set_config_v6(ConfigV6::Static(StaticConfigV6 {
+            address: Ipv6Cidr::new(address, 64),
+            gateway: Some(Ipv6Address::from_bytes(&ipv6.peer_link_local_address())),
+            dns_servers: Vec::new(),
+        }));
+    };
+
+    let res = runner
+        .run_with_options(
+            port,
+            config,
+            &options,
+            |ipv4| {
+                let Some(addr) = ipv4.address else {
+                    warn!("PPP did not provide an IP address.");
+                    return;
+                };
+                let mut dns_servers = Vec::new();
+                for s in ipv4.dns_servers.iter().flatten() {
+                    let _ = dns_servers.push(Ipv4Address::from_bytes(&s.0));
+                }
+                let config = ConfigV4::Static(embassy_net::StaticConfigV4 {
+                    address: Ipv4Cidr::new(Ipv4Address::from_bytes(&addr.0), 0),
+                    gateway: None,
+                    dns_servers,
+                });
+                stack.set_config_v4(config);
+            },
+            on_ipv6_up,
+        )
+        .await;
+    info!("link quality: {:?}", quality.get());
+    res.unwrap();
     unreachable!()
 }
 
@@ -106,7 +142,7 @@ async fn main_task(spawner: Spawner) {
 
     // Launch network task
     spawner.spawn(net_task(stack)).unwrap();
-    spawner.spawn(ppp_task(stack, runner, port)).unwrap();
+    spawner.spawn(ppp_task(stack, runner, port, opts.ipv6)).unwrap();
 
     // Then we can use it!
     let mut rx_buffer = [0; 4096];
@@ -0,0 +1,130 @@
+#![feature(type_alias_impl_trait)]
+
+// Connect to a local TLS 1.3 server, authenticated with a pre-shared key, such as:
+//
+//     openssl s_server -accept 4433 -tls1_3 -nocert -psk_identity embassy -psk 000102030405060708090a0b0c0d0e0f
+
+use clap::Parser;
+use embassy_executor::{Executor, Spawner};
+use embassy_net::tcp::TcpSocket;
+use embassy_net::tls::{NoVerify, TlsConfig, TlsConnection, MAX_RECORD_LEN};
+use embassy_net::{Config, Ipv4Address, Ipv4Cidr, Stack, StackResources};
+use embassy_net_tuntap::TunTapDevice;
+use embassy_time::Duration;
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+use log::*;
+use rand_core::{OsRng, RngCore};
+use static_cell::{make_static, StaticCell};
+
+const PSK: [u8; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+const PSK_IDENTITY: &[u8] = b"embassy";
+
+#[derive(Parser)]
+#[clap(version = "1.0")]
+struct Opts {
+    /// TAP device name
+    #[clap(long, default_value = "tap0")]
+    tap: String,
+    /// TLS server port
+    #[clap(long, default_value = "4433")]
+    port: u16,
+}
+
+#[embassy_executor::task]
+async fn net_task(stack: &'static Stack<TunTapDevice>) -> ! {
+    stack.run().await
+}
+
+#[embassy_executor::task]
+async fn main_task(spawner: Spawner) {
+    let opts: Opts = Opts::parse();
+
+    // Init network device
+    let device = TunTapDevice::new(&opts.tap).unwrap();
+
+    let config = Config::ipv4_static(embassy_net::StaticConfigV4 {
+        address: Ipv4Cidr::new(Ipv4Address::new(192, 168, 69, 2), 24),
+        dns_servers: Vec::new(),
+        gateway: Some(Ipv4Address::new(192, 168, 69, 1)),
+    });
+
+    // Generate random seed
+    let mut seed = [0; 8];
+    OsRng.fill_bytes(&mut seed);
+    let seed = u64::from_le_bytes(seed);
+
+    // Init network stack
+    let stack = &*make_static!(Stack::new(
+        device,
+        config,
+        make_static!(StackResources::<3>::new()),
+        seed
+    ));
+
+    // Launch network task
+    spawner.spawn(net_task(stack)).unwrap();
+
+    let mut rx_buffer = [0; 4096];
+    let mut tx_buffer = [0; 4096];
+    let mut socket = TcpSocket::new(stack, &mut rx_buffer, &mut tx_buffer);
+    socket.set_timeout(Some(Duration::from_secs(10)));
+
+    let remote_endpoint = (Ipv4Address::new(192, 168, 69, 1), opts.port);
+    info!("connecting to {:?}...", remote_endpoint);
+    if let Err(e) = socket.connect(remote_endpoint).await {
+        warn!("connect error: {:?}", e);
+        return;
+    }
+
+    let mut read_record_buffer = [0; MAX_RECORD_LEN];
+    let mut write_record_buffer = [0; 4096];
+    let config = TlsConfig::new().with_psk(&PSK, &[PSK_IDENTITY]);
+    let mut tls: TlsConnection<_> = TlsConnection::new(socket, &mut read_record_buffer, &mut write_record_buffer);
+
+    // The server is authenticated by the PSK, there is no certificate to verify.
+    if let Err(e) = tls.open::<_, NoVerify>(&config, &mut OsRng).await {
+        warn!("TLS handshake error: {:?}", e);
+        return;
+    }
+    info!("TLS connection established!");
+
+    if let Err(e) = tls.write_all(b"Hello!\n").await {
+        warn!("write error: {:?}", e);
+        return;
+    }
+    if let Err(e) = tls.flush().await {
+        warn!("flush error: {:?}", e);
+        return;
+    }
+
+    let mut buf = [0; 1024];
+    loop {
+        match tls.read(&mut buf).await {
+            Ok(0) => {
+                info!("connection closed");
+                break;
+            }
+            Ok(n) => info!("received: {:?}", core::str::from_utf8(&buf[..n])),
+            Err(e) => {
+                warn!("read error: {:?}", e);
+                break;
+            }
+        }
+    }
+}
+
+static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+
+fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Debug)
+        .filter_module("async_io", log::LevelFilter::Info)
+        .format_timestamp_nanos()
+        .init();
+
+    let executor = EXECUTOR.init(Executor::new());
+    executor.run(|spawner| {
+        spawner.spawn(main_task(spawner)).unwrap();
+    });
+}